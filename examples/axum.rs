@@ -5,30 +5,13 @@ use axum::{
     routing::get,
     Json, Router,
 };
+use eve_oauth2::models::{CallbackResult, Character};
+use eve_oauth2::tower_sessions::{save_login, verify_login};
 use eve_oauth2::{create_login_url, get_access_token, validate_token};
-use oauth2::TokenResponse;
-use serde::{Deserialize, Serialize};
 use std::env;
 use time::Duration;
 use tower_sessions::{cookie::SameSite, Expiry, MemoryStore, Session, SessionManagerLayer};
 
-const STATE_KEY: &str = "state";
-
-#[derive(Deserialize)]
-struct CallbackParams {
-    state: String,
-    code: String,
-}
-
-#[derive(Serialize)]
-struct Character {
-    character_id: i32,
-    character_name: String,
-}
-
-#[derive(Default, Deserialize, Serialize, Debug)]
-struct State(String);
-
 #[tokio::main]
 async fn main() {
     let _ = dotenv::dotenv();
@@ -68,18 +51,24 @@ async fn login(session: Session) -> Redirect {
 
     let auth_data = create_login_url(client_id, client_secret, redirect_url, scopes);
 
-    session
-        .insert(STATE_KEY, State(auth_data.state))
-        .await
-        .unwrap();
+    save_login(&session, &auth_data).await.unwrap();
 
     Redirect::temporary(&auth_data.login_url)
 }
 
-async fn callback(session: Session, params: Query<CallbackParams>) -> Response {
-    let state: State = session.get(STATE_KEY).await.unwrap().unwrap_or_default();
+async fn callback(session: Session, params: Query<CallbackResult>) -> Response {
+    let (code, callback_state) = match params.0 {
+        CallbackResult::Success { code, state } => (code, state),
+        CallbackResult::Error { .. } => {
+            return (
+                StatusCode::BAD_REQUEST,
+                "The login was cancelled, please try again.",
+            )
+                .into_response()
+        }
+    };
 
-    if state.0 != params.0.state {
+    if verify_login(&session, &callback_state).await.is_err() {
         return (
             StatusCode::BAD_REQUEST,
             "There was an issue logging you in, please try again.",
@@ -92,17 +81,37 @@ async fn callback(session: Session, params: Query<CallbackParams>) -> Response {
     let client_secret = env::var("ESI_CLIENT_SECRET")
         .expect("ESI_CLIENT_SECRET not set, please set it in your .env!");
 
-    let token = get_access_token(client_id, client_secret, params.0.code).await;
-    let token_claims = validate_token(token.access_token().secret().to_string()).await;
-
-    let id_str = token_claims.claims.sub.split(':').collect::<Vec<&str>>()[2];
+    let token = match get_access_token(client_id, client_secret, code).await {
+        Ok(token) => token,
+        Err(err) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to get access token: {}", err),
+            )
+                .into_response()
+        }
+    };
 
-    let character_id: i32 = id_str.parse().expect("Failed to parse id to i32");
-    let character_name: String = token_claims.claims.name;
+    let token_claims = match validate_token(token.access_token().to_string()).await {
+        Ok(token_claims) => token_claims,
+        Err(err) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to validate access token: {}", err),
+            )
+                .into_response()
+        }
+    };
 
-    let character = Character {
-        character_id,
-        character_name,
+    let character = match Character::try_from(token_claims.claims) {
+        Ok(character) => character,
+        Err(err) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to get character: {}", err),
+            )
+                .into_response()
+        }
     };
 
     (StatusCode::OK, Json(character)).into_response()
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS eve_oauth2_tokens (
+    character_id BIGINT PRIMARY KEY,
+    access_token TEXT NOT NULL,
+    refresh_token TEXT NOT NULL,
+    expires_at BIGINT NOT NULL,
+    scopes TEXT[] NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1 @@
+ALTER TABLE eve_oauth2_tokens ADD COLUMN IF NOT EXISTS owner_hash TEXT;
@@ -0,0 +1,254 @@
+//! Integration with the actix-web framework, enabled with the `actix` feature
+//!
+//! Add an [`EveSsoClient`] to your app data & take [`EveClaims`] as a parameter of protected handlers.
+//! Wrap routes in [`RequireScopes`] to require scopes of every request to them.
+//!
+//! ```no_run
+//! use actix_web::{web, App};
+//! use eve_oauth2::actix::{EveClaims, RequireScopes};
+//! use eve_oauth2::EveSsoClient;
+//!
+//! async fn character(EveClaims(claims): EveClaims) -> String {
+//!     claims.name
+//! }
+//!
+//! let client = EveSsoClient::new("client_id".to_string(), None);
+//! let app = App::new().app_data(web::Data::new(client)).service(
+//!     web::resource("/wallet")
+//!         .wrap(RequireScopes::new(["esi-wallet.read_character_wallet.v1"]))
+//!         .route(web::get().to(character)),
+//! );
+//! ```
+
+use std::fmt;
+use std::future::{ready, Future, Ready};
+use std::rc::Rc;
+
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use actix_web::http::StatusCode;
+use actix_web::{web, FromRequest, HttpMessage, HttpRequest, HttpResponse, ResponseError};
+use futures_util::future::LocalBoxFuture;
+
+use crate::error::{Error, ErrorClass};
+use crate::http;
+use crate::models::EveJwtClaims;
+use crate::validation::TokenValidation;
+use crate::EveSsoClient;
+
+/// Extracts the bearer token of the Authorization header & validates it with the [`EveSsoClient`] of the app data
+///
+/// Requests without a valid token are rejected with 401 Unauthorized. Claims validated by [`RequireScopes`] are
+/// reused instead of validating the token again, other [`EveJwtClaims`] in the request's extensions are ignored.
+#[derive(Debug, Clone)]
+pub struct EveClaims(pub EveJwtClaims);
+
+impl FromRequest for EveClaims {
+    type Error = EveAuthError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        if let Some(ValidatedClaims(claims)) = req.extensions().get::<ValidatedClaims>() {
+            let claims = claims.clone();
+
+            return Box::pin(async move { Ok(EveClaims(claims)) });
+        }
+
+        let validated = validate(req, TokenValidation::default());
+
+        Box::pin(async move { validated.await.map(EveClaims) })
+    }
+}
+
+/// Claims validated by [`RequireScopes`], other middleware can't insert this type into the request's extensions
+#[derive(Debug, Clone)]
+struct ValidatedClaims(EveJwtClaims);
+
+/// Middleware rejecting requests without a valid token granting the scopes with 401 Unauthorized or 403 Forbidden
+///
+/// The validated claims are available to the handlers through [`EveClaims`].
+#[derive(Debug, Clone)]
+pub struct RequireScopes {
+    scopes: Rc<Vec<String>>,
+}
+
+impl RequireScopes {
+    /// Creates the middleware requiring the scopes, an empty list only requires a valid token
+    pub fn new(scopes: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            scopes: Rc::new(scopes.into_iter().map(Into::into).collect()),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequireScopes
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = RequireScopesMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequireScopesMiddleware {
+            service: Rc::new(service),
+            scopes: self.scopes.clone(),
+        }))
+    }
+}
+
+/// Service created by [`RequireScopes`]
+pub struct RequireScopesMiddleware<S> {
+    service: Rc<S>,
+    scopes: Rc<Vec<String>>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequireScopesMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let validated = validate(
+            req.request(),
+            TokenValidation::new().require_scopes(self.scopes.iter()),
+        );
+
+        Box::pin(async move {
+            match validated.await {
+                Ok(claims) => {
+                    req.extensions_mut().insert(ValidatedClaims(claims));
+
+                    service
+                        .call(req)
+                        .await
+                        .map(ServiceResponse::map_into_left_body)
+                }
+                Err(err) => Ok(req.error_response(err).map_into_right_body()),
+            }
+        })
+    }
+}
+
+/// Error of requests which couldn't be authenticated
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum EveAuthError {
+    /// The request has no bearer token in the Authorization header, responds with 401 Unauthorized
+    MissingToken,
+    /// No [`EveSsoClient`] was added to the app data, responds with 500 Internal Server Error
+    MissingClient,
+    /// Validating the token failed, responds with 403 Forbidden if the token is missing required scopes,
+    /// 503 Service Unavailable if EVE Online SSO couldn't be reached to retrieve the EVE JWT keys & 401 Unauthorized
+    /// otherwise
+    InvalidToken(Error),
+}
+
+impl fmt::Display for EveAuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EveAuthError::MissingToken => write!(f, "Missing bearer token"),
+            EveAuthError::MissingClient => write!(f, "No EveSsoClient was added to the app data"),
+            EveAuthError::InvalidToken(err @ Error::MissingScopes(_)) => write!(f, "{}", err),
+            EveAuthError::InvalidToken(_) => write!(f, "Invalid bearer token"),
+        }
+    }
+}
+
+impl ResponseError for EveAuthError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            EveAuthError::MissingToken => StatusCode::UNAUTHORIZED,
+            EveAuthError::MissingClient => StatusCode::INTERNAL_SERVER_ERROR,
+            EveAuthError::InvalidToken(Error::MissingScopes(_)) => StatusCode::FORBIDDEN,
+            EveAuthError::InvalidToken(err) => match err.classify() {
+                ErrorClass::Transient | ErrorClass::RateLimited => StatusCode::SERVICE_UNAVAILABLE,
+                _ => StatusCode::UNAUTHORIZED,
+            },
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+
+        match self {
+            EveAuthError::InvalidToken(Error::MissingScopes(missing_scopes)) => {
+                response
+                    .insert_header((WWW_AUTHENTICATE, http::insufficient_scope(missing_scopes)));
+            }
+            _ if self.status_code() == StatusCode::UNAUTHORIZED => {
+                response.insert_header((WWW_AUTHENTICATE, "Bearer"));
+            }
+            _ => {}
+        }
+
+        response.body(self.to_string())
+    }
+}
+
+fn validate(
+    req: &HttpRequest,
+    validation: TokenValidation,
+) -> impl Future<Output = Result<EveJwtClaims, EveAuthError>> {
+    let client = req
+        .app_data::<web::Data<EveSsoClient>>()
+        .map(|client| client.get_ref().clone());
+    let token = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(http::bearer_token)
+        .map(str::to_string);
+
+    async move {
+        let client = client.ok_or(EveAuthError::MissingClient)?;
+        let token = token.ok_or(EveAuthError::MissingToken)?;
+
+        client
+            .validate_token_with(token, &validation)
+            .await
+            .map(|token_data| token_data.claims)
+            .map_err(EveAuthError::InvalidToken)
+    }
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    async fn extract(req: HttpRequest) -> Result<EveClaims, EveAuthError> {
+        EveClaims::from_request(&req, &mut Payload::None).await
+    }
+
+    #[tokio::test]
+    async fn claims_extractor_only_reuses_claims_validated_by_require_scopes() {
+        let client = web::Data::new(EveSsoClient::new("client_id", None));
+        let claims = EveJwtClaims::test().client_id("client_id").build();
+
+        let req = TestRequest::default()
+            .app_data(client.clone())
+            .to_http_request();
+        req.extensions_mut().insert(claims.clone());
+        assert!(matches!(
+            extract(req).await,
+            Err(EveAuthError::MissingToken)
+        ));
+
+        let req = TestRequest::default().app_data(client).to_http_request();
+        req.extensions_mut().insert(ValidatedClaims(claims));
+        assert_eq!(extract(req).await.unwrap().0.azp, "client_id");
+    }
+}
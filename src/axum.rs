@@ -0,0 +1,487 @@
+//! Integration with the axum web framework, enabled with the `axum` feature
+//!
+//! Add an [`EveSsoClient`] to your router's state & take [`EveClaims`] as a parameter of protected routes.
+//! [`router`] creates the login & callback routes of the login flow.
+//!
+//! ```no_run
+//! use axum::{routing::get, Router};
+//! use eve_oauth2::axum::EveClaims;
+//! use eve_oauth2::EveSsoClient;
+//!
+//! async fn character(EveClaims(claims): EveClaims) -> String {
+//!     claims.name
+//! }
+//!
+//! let client = EveSsoClient::new("client_id".to_string(), None);
+//! let app: Router = Router::new()
+//!     .route("/character", get(character))
+//!     .with_state(client);
+//! ```
+
+use std::future::Future;
+use std::sync::Arc;
+
+use ::axum::async_trait;
+use ::axum::extract::{FromRef, FromRequestParts, Query};
+use ::axum::http::request::Parts;
+use ::axum::response::{IntoResponse, Redirect, Response};
+use ::axum::routing::get;
+use ::axum::Router;
+
+use crate::error::{Error, ErrorClass};
+use crate::http::header::{AUTHORIZATION, COOKIE, SET_COOKIE, WWW_AUTHENTICATE};
+use crate::http::{self, HeaderMap, HeaderValue, StatusCode};
+use crate::models::{CallbackResult, Character, EveJwtClaims};
+use crate::state::{MemoryStateStore, StateStore, DEFAULT_STATE_TTL};
+#[cfg(feature = "tower")]
+use crate::tower::ValidatedClaims;
+#[cfg(feature = "tower")]
+use crate::validation::TokenValidation;
+use crate::{verify_state, EveSsoClient, EveTokenResponse};
+
+/// Cookie binding the state of a login to the browser which started it
+const STATE_COOKIE: &str = "eve_oauth2_state";
+
+/// Extracts the bearer token of the Authorization header & validates it with the [`EveSsoClient`] of the state
+///
+/// Requests without a valid token are rejected with 401 Unauthorized. Claims validated by `RequireEveAuth` of the
+/// `tower` feature are reused instead of validating the token again, their `azp` still has to match the client_id as
+/// the layer may accept tokens of any client. Other [`EveJwtClaims`] in the request's extensions are ignored.
+#[derive(Debug, Clone)]
+pub struct EveClaims(pub EveJwtClaims);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for EveClaims
+where
+    EveSsoClient: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = EveAuthRejection;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let client = EveSsoClient::from_ref(state);
+
+        #[cfg(feature = "tower")]
+        if let Some(ValidatedClaims(claims)) = parts.extensions.get::<ValidatedClaims>() {
+            TokenValidation::default()
+                .with_default_client_id(client.client_id())
+                .check(claims)
+                .map_err(EveAuthRejection::InvalidToken)?;
+
+            return Ok(EveClaims(claims.clone()));
+        }
+
+        let token = bearer_token(&parts.headers).ok_or(EveAuthRejection::MissingToken)?;
+
+        let token_data = client
+            .validate_token(token)
+            .await
+            .map_err(EveAuthRejection::InvalidToken)?;
+
+        Ok(EveClaims(token_data.claims))
+    }
+}
+
+/// Rejection of requests which couldn't be authenticated
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum EveAuthRejection {
+    /// The request has no bearer token in the Authorization header, responds with 401 Unauthorized
+    MissingToken,
+    /// Validating the token failed, responds with 503 Service Unavailable if EVE Online SSO couldn't be reached
+    /// to retrieve the EVE JWT keys & 401 Unauthorized otherwise
+    InvalidToken(Error),
+}
+
+impl IntoResponse for EveAuthRejection {
+    fn into_response(self) -> Response {
+        match self {
+            EveAuthRejection::MissingToken => unauthorized("Missing bearer token"),
+            EveAuthRejection::InvalidToken(err) => match err.classify() {
+                ErrorClass::Transient | ErrorClass::RateLimited => (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "Failed to validate the token",
+                )
+                    .into_response(),
+                _ => unauthorized("Invalid bearer token"),
+            },
+        }
+    }
+}
+
+/// Configuration of the login & callback routes created by [`router`]
+#[derive(Clone)]
+pub struct LoginConfig {
+    client: EveSsoClient,
+    redirect_url: String,
+    scopes: Vec<String>,
+    state_store: Arc<dyn StateStore>,
+    login_path: String,
+    callback_path: String,
+    cookie_path: String,
+}
+
+impl LoginConfig {
+    /// Creates the configuration using the routes `/login` & `/callback` & a [`MemoryStateStore`]
+    ///
+    /// The memory store holds at most [`MemoryStateStore::DEFAULT_MAX_STATES`] pending logins, further logins are
+    /// rejected until states expire or are used. redirect_url is the callback URL of your EVE developer application, it has to point to the callback route.
+    pub fn new(client: EveSsoClient, redirect_url: impl Into<String>) -> Self {
+        Self {
+            client,
+            redirect_url: redirect_url.into(),
+            scopes: Vec::new(),
+            state_store: Arc::new(MemoryStateStore::new()),
+            login_path: "/login".to_string(),
+            callback_path: "/callback".to_string(),
+            cookie_path: "/".to_string(),
+        }
+    }
+
+    /// Sets the scopes requested by the login
+    pub fn scopes(mut self, scopes: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.scopes = scopes.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the store the states are kept in, e.g. a [`crate::state::RedisStateStore`] for multiple instances
+    pub fn state_store(mut self, state_store: impl StateStore + 'static) -> Self {
+        self.state_store = Arc::new(state_store);
+        self
+    }
+
+    /// Sets the path of the route redirecting to EVE Online SSO
+    pub fn login_path(mut self, login_path: impl Into<String>) -> Self {
+        self.login_path = login_path.into();
+        self
+    }
+
+    /// Sets the path of the callback route
+    pub fn callback_path(mut self, callback_path: impl Into<String>) -> Self {
+        self.callback_path = callback_path.into();
+        self
+    }
+
+    /// Sets the `Path` of the state cookie, defaults to `/`
+    ///
+    /// Set it to the full path of the callback route to only send the cookie to the callback, including the prefix of
+    /// the router when it's nested, e.g. `/auth/callback`.
+    pub fn cookie_path(mut self, cookie_path: impl Into<String>) -> Self {
+        self.cookie_path = cookie_path.into();
+        self
+    }
+}
+
+/// Creates the login & callback routes of the login flow
+///
+/// The login route redirects to EVE Online SSO after putting the state into the store & into an HttpOnly cookie
+/// binding it to the browser. The callback route requires the state of the cookie to match the state of the callback,
+/// takes the state from the store, retrieves & validates the access token & responds with the response of on_success.
+///
+/// ```no_run
+/// use axum::Router;
+/// use eve_oauth2::axum::{router, LoginConfig};
+/// use eve_oauth2::models::Character;
+/// use eve_oauth2::{EveSsoClient, EveTokenResponse};
+///
+/// let client = EveSsoClient::new("client_id".to_string(), Some("client_secret".to_string()));
+/// let config = LoginConfig::new(client, "http://localhost:8000/callback").scopes(["publicData"]);
+///
+/// let app: Router = router(config, |character: Character, _token: EveTokenResponse| async move {
+///     format!("Logged in as {}", character.name)
+/// });
+/// ```
+pub fn router<S, F, Fut>(config: LoginConfig, on_success: F) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+    F: Fn(Character, EveTokenResponse) -> Fut + Clone + Send + Sync + 'static,
+    Fut: Future + Send + 'static,
+    Fut::Output: IntoResponse,
+{
+    let config = Arc::new(config);
+    let login_config = config.clone();
+    let callback_config = config.clone();
+
+    Router::new()
+        .route(
+            &config.login_path,
+            get(move || async move { login(&login_config).await }),
+        )
+        .route(
+            &config.callback_path,
+            get(
+                move |headers: HeaderMap, Query(callback_result): Query<CallbackResult>| async move {
+                    callback(&callback_config, &headers, callback_result, on_success).await
+                },
+            ),
+        )
+}
+
+async fn login(config: &LoginConfig) -> Response {
+    let login_url = config
+        .client
+        .start_login_with_store(
+            &config.state_store,
+            &config.redirect_url,
+            config.scopes.clone(),
+        )
+        .await;
+
+    match login_url {
+        Ok(login) => (
+            [(
+                SET_COOKIE,
+                state_cookie(config, &login.state, DEFAULT_STATE_TTL.as_secs()),
+            )],
+            Redirect::to(&login.login_url),
+        )
+            .into_response(),
+        Err(_err) => {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(error = %_err, "Failed to create EVE login URL");
+
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to start the login, please try again.",
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn callback<F, Fut>(
+    config: &LoginConfig,
+    headers: &HeaderMap,
+    callback_result: CallbackResult,
+    on_success: F,
+) -> Response
+where
+    F: Fn(Character, EveTokenResponse) -> Fut,
+    Fut: Future,
+    Fut::Output: IntoResponse,
+{
+    let clear_cookie = [(SET_COOKIE, state_cookie(config, "", 0))];
+
+    let Ok((code, state)) = callback_result.into_result() else {
+        return (
+            StatusCode::BAD_REQUEST,
+            clear_cookie,
+            "The login was cancelled, please try again.",
+        )
+            .into_response();
+    };
+
+    // The state has to come from the browser which started the login, otherwise an attacker could complete their own
+    // login in the victim's browser
+    let expected = cookie(headers, STATE_COOKIE).unwrap_or_default();
+    if !verify_state(expected, &encode_cookie_value(&state)) {
+        return (
+            StatusCode::BAD_REQUEST,
+            clear_cookie,
+            "The login was started in another browser, please try again.",
+        )
+            .into_response();
+    }
+
+    let result = async {
+        let token = config
+            .client
+            .verify_callback(&config.state_store, code, state)
+            .await?;
+        let token_data = config.client.validate_token(token.access_token()).await?;
+
+        Ok::<_, Error>((Character::try_from(token_data.claims)?, token))
+    }
+    .await;
+
+    match result {
+        Ok((character, token)) => {
+            (clear_cookie, on_success(character, token).await).into_response()
+        }
+        Err(Error::InvalidState(_)) => (
+            StatusCode::BAD_REQUEST,
+            clear_cookie,
+            "The login has expired, please try again.",
+        )
+            .into_response(),
+        Err(_err) => {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(error = %_err, "Failed to complete EVE login");
+
+            (
+                StatusCode::BAD_GATEWAY,
+                clear_cookie,
+                "Failed to log you in, please try again.",
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Set-Cookie header of the state cookie, with `Secure` for https redirect URLs
+fn state_cookie(config: &LoginConfig, state: &str, max_age: u64) -> String {
+    let secure = if config.redirect_url.starts_with("https://") {
+        "; Secure"
+    } else {
+        ""
+    };
+
+    format!(
+        "{}={}; Path={}; Max-Age={}; HttpOnly; SameSite=Lax{}",
+        STATE_COOKIE,
+        encode_cookie_value(state),
+        config.cookie_path,
+        max_age,
+        secure
+    )
+}
+
+/// Percent-encodes the state so custom states can't break out of the cookie value
+fn encode_cookie_value(value: &str) -> String {
+    url::form_urlencoded::byte_serialize(value.as_bytes()).collect()
+}
+
+fn cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|header| header.to_str().ok())
+        .flat_map(|header| header.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(cookie_name, _)| *cookie_name == name)
+        .map(|(_, value)| value)
+}
+
+fn unauthorized(message: &'static str) -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        [(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"))],
+        message,
+    )
+        .into_response()
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    http::bearer_token(headers.get(AUTHORIZATION)?.to_str().ok()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> LoginConfig {
+        let client = EveSsoClient::new("client_id", Some("client_secret".to_string()));
+
+        LoginConfig::new(client, "https://example.com/callback")
+    }
+
+    async fn never_called(_character: Character, _token: EveTokenResponse) -> &'static str {
+        unreachable!("the login must not complete")
+    }
+
+    #[test]
+    fn state_cookie_is_http_only_same_site_and_secure() {
+        let cookie = state_cookie(&config(), "a b;c", 600);
+
+        assert_eq!(
+            cookie,
+            "eve_oauth2_state=a+b%3Bc; Path=/; Max-Age=600; HttpOnly; SameSite=Lax; Secure"
+        );
+    }
+
+    #[test]
+    fn state_cookie_path_is_configurable() {
+        let config = LoginConfig::new(
+            EveSsoClient::new("client_id", None),
+            "http://localhost:8000/auth/callback",
+        )
+        .cookie_path("/auth/callback");
+
+        assert_eq!(
+            state_cookie(&config, "", 0),
+            "eve_oauth2_state=; Path=/auth/callback; Max-Age=0; HttpOnly; SameSite=Lax"
+        );
+    }
+
+    #[test]
+    fn finds_cookie_among_others() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            COOKIE,
+            HeaderValue::from_static("session=1; eve_oauth2_state=abc"),
+        );
+
+        assert_eq!(cookie(&headers, STATE_COOKIE), Some("abc"));
+        assert_eq!(cookie(&headers, "missing"), None);
+    }
+
+    #[tokio::test]
+    async fn callback_rejects_state_without_matching_cookie() {
+        let config = config();
+        config
+            .state_store
+            .put("state".to_string(), Default::default(), DEFAULT_STATE_TTL)
+            .await
+            .unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(COOKIE, HeaderValue::from_static("eve_oauth2_state=other"));
+
+        let callback_result = CallbackResult::Success {
+            code: "code".to_string(),
+            state: "state".to_string(),
+        };
+        let response = callback(&config, &headers, callback_result, never_called).await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(config
+            .state_store
+            .take("state".to_string())
+            .await
+            .unwrap()
+            .is_some());
+    }
+
+    #[cfg(feature = "test-utils")]
+    async fn extract(extensions: impl FnOnce(&mut Parts)) -> Result<EveClaims, EveAuthRejection> {
+        let client = EveSsoClient::new("client_id", None);
+        let (mut parts, ()) = ::axum::http::Request::new(()).into_parts();
+        extensions(&mut parts);
+
+        EveClaims::from_request_parts(&mut parts, &client).await
+    }
+
+    #[cfg(feature = "test-utils")]
+    #[tokio::test]
+    async fn claims_extractor_ignores_claims_inserted_by_other_layers() {
+        let result = extract(|parts| {
+            parts
+                .extensions
+                .insert(EveJwtClaims::test().client_id("client_id").build());
+        })
+        .await;
+
+        assert!(matches!(result, Err(EveAuthRejection::MissingToken)));
+    }
+
+    #[cfg(all(feature = "tower", feature = "test-utils"))]
+    #[tokio::test]
+    async fn claims_extractor_checks_azp_of_claims_validated_by_require_eve_auth() {
+        let result = extract(|parts| {
+            let claims = EveJwtClaims::test().client_id("client_id").build();
+            parts.extensions.insert(ValidatedClaims(claims));
+        })
+        .await;
+        assert_eq!(result.unwrap().0.azp, "client_id");
+
+        let result = extract(|parts| {
+            let claims = EveJwtClaims::test().client_id("other_client").build();
+            parts.extensions.insert(ValidatedClaims(claims));
+        })
+        .await;
+        assert!(matches!(
+            result,
+            Err(EveAuthRejection::InvalidToken(Error::InvalidClaims(_)))
+        ));
+    }
+}
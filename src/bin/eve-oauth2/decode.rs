@@ -0,0 +1,95 @@
+use clap::Args;
+use eve_oauth2::validation::TokenValidation;
+
+use crate::{read_arg, CliResult};
+
+#[derive(Args)]
+pub struct DecodeArgs {
+    /// Access token to decode, `-` reads it from stdin
+    token: String,
+    /// Validate the signature, expiry & issuer against the EVE JWT keys of EVE Online SSO
+    #[arg(long)]
+    validate: bool,
+    /// Require the token to be issued to the client id when validating
+    #[arg(long, requires = "validate")]
+    client_id: Option<String>,
+    /// Print the header & claims as JSON instead of a table
+    #[arg(long)]
+    json: bool,
+}
+
+pub async fn run(args: DecodeArgs) -> CliResult {
+    let token = read_arg(args.token)?;
+
+    if args.json {
+        let token_data = eve_oauth2::decode_unverified(&token)?;
+
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&serde_json::json!({
+                "header": token_data.header,
+                "claims": token_data.claims,
+            }))?
+        );
+    } else {
+        print!("{}", eve_oauth2::describe_token(&token)?);
+    }
+
+    if args.validate {
+        let validation = match args.client_id {
+            Some(client_id) => TokenValidation::new().require_client_id(client_id),
+            None => TokenValidation::new(),
+        };
+
+        match eve_oauth2::validate_token_with(token, &validation).await {
+            Ok(_) => eprintln!("Token is valid"),
+            Err(err) => return Err(format!("Token is invalid: {}", err).into()),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use crate::{Cli, Command};
+
+    #[test]
+    fn client_id_requires_validation() {
+        assert!(
+            Cli::try_parse_from(["eve-oauth2", "decode", "token", "--client-id", "client_id"])
+                .is_err()
+        );
+
+        let cli = Cli::try_parse_from([
+            "eve-oauth2",
+            "decode",
+            "-",
+            "--validate",
+            "--client-id",
+            "client_id",
+            "--json",
+        ])
+        .unwrap();
+
+        let Command::Decode(args) = cli.command else {
+            panic!("Expected the decode command");
+        };
+        assert_eq!(args.token, "-");
+        assert!(args.validate);
+        assert_eq!(args.client_id.as_deref(), Some("client_id"));
+        assert!(args.json);
+    }
+
+    #[tokio::test]
+    async fn fails_on_tokens_which_arent_jwts() {
+        let cli = Cli::try_parse_from(["eve-oauth2", "decode", "not a token"]).unwrap();
+
+        let Command::Decode(args) = cli.command else {
+            panic!("Expected the decode command");
+        };
+        assert!(super::run(args).await.is_err());
+    }
+}
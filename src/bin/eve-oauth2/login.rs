@@ -0,0 +1,94 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::Args;
+use eve_oauth2::loopback::LocalCallbackServer;
+
+use crate::{write_tokens, CliResult, ClientArgs};
+
+#[derive(Args)]
+pub struct LoginArgs {
+    #[command(flatten)]
+    client: ClientArgs,
+    /// Scope to request, repeat for multiple scopes
+    #[arg(short, long = "scope", value_name = "SCOPE")]
+    scopes: Vec<String>,
+    /// Port of the loopback callback, the callback URL of your EVE developer application must be
+    /// `http://127.0.0.1:<PORT>/callback`
+    #[arg(short, long, default_value_t = 8080)]
+    port: u16,
+    /// Print the login URL instead of opening the browser
+    #[arg(long)]
+    no_browser: bool,
+    /// Seconds to wait for the login
+    #[arg(long, default_value_t = 300)]
+    timeout: u64,
+    /// Write the tokens to the file instead of printing them
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+pub async fn run(args: LoginArgs) -> CliResult {
+    let client = args.client.client();
+    let server = LocalCallbackServer::bind(args.port)
+        .await?
+        .timeout(Duration::from_secs(args.timeout));
+
+    let auth = match args.client.client_secret {
+        Some(_) => client.try_create_login_url(server.redirect_url(), args.scopes)?,
+        None => client.try_create_login_url_pkce(server.redirect_url(), args.scopes)?,
+    };
+
+    eprintln!("Log in at {}", auth.login_url);
+
+    if !args.no_browser {
+        if let Err(err) = eve_oauth2::open_login_url(&auth) {
+            eprintln!("Failed to open the browser: {}", err);
+        }
+    }
+
+    let code = server.wait_for_code(&auth).await?;
+
+    let token = match auth.code_verifier {
+        Some(code_verifier) => client.get_access_token_pkce(code, code_verifier).await?,
+        None => client.get_access_token(code).await?,
+    };
+    let tokens = client.validate_token_response(&token).await?;
+
+    write_tokens(&tokens, args.output)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use crate::{Cli, Command};
+
+    #[test]
+    fn parses_repeated_scopes_and_defaults() {
+        let cli = Cli::try_parse_from([
+            "eve-oauth2",
+            "login",
+            "--client-id",
+            "client_id",
+            "--scope",
+            "publicData",
+            "-s",
+            "esi-wallet.read_character_wallet.v1",
+        ])
+        .unwrap();
+
+        let Command::Login(args) = cli.command else {
+            panic!("Expected the login command");
+        };
+        assert_eq!(args.client.client_id, "client_id");
+        assert_eq!(
+            args.scopes,
+            ["publicData", "esi-wallet.read_character_wallet.v1"]
+        );
+        assert_eq!(args.port, 8080);
+        assert_eq!(args.timeout, 300);
+        assert!(!args.no_browser);
+        assert!(args.output.is_none());
+    }
+}
@@ -0,0 +1,206 @@
+//! Command line tool for EVE Online SSO, built with the `cli` feature
+//!
+//! ```text
+//! cargo install eve_oauth2 --features cli
+//! eve-oauth2 login --client-id <CLIENT_ID> --scope esi-wallet.read_character_wallet.v1
+//! eve-oauth2 decode <ACCESS_TOKEN>
+//! eve-oauth2 refresh --client-id <CLIENT_ID> --refresh-token <REFRESH_TOKEN>
+//! eve-oauth2 revoke --client-id <CLIENT_ID> --refresh-token <REFRESH_TOKEN>
+//! ```
+
+mod decode;
+mod login;
+mod refresh;
+mod revoke;
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{Args, Parser, Subcommand};
+use eve_oauth2::models::EveTokens;
+use eve_oauth2::EveSsoClient;
+
+#[derive(Parser)]
+#[command(
+    name = "eve-oauth2",
+    version,
+    about = "Log in with EVE Online SSO & manage tokens"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Logs in a character in the browser & prints its tokens
+    Login(login::LoginArgs),
+    /// Prints the header & claims of an access token, optionally validating it
+    Decode(decode::DecodeArgs),
+    /// Exchanges a refresh token for a new token pair & prints it
+    Refresh(refresh::RefreshArgs),
+    /// Revokes a refresh token
+    Revoke(revoke::RevokeArgs),
+}
+
+/// Credentials of your EVE developer application
+#[derive(Args)]
+struct ClientArgs {
+    /// Client id of your EVE developer application
+    #[arg(long, env = "ESI_CLIENT_ID")]
+    client_id: String,
+    /// Client secret of your EVE developer application, the PKCE flow is used without it
+    #[arg(long, env = "ESI_CLIENT_SECRET", hide_env_values = true)]
+    client_secret: Option<String>,
+}
+
+impl ClientArgs {
+    fn client(&self) -> EveSsoClient {
+        let client = EveSsoClient::builder(&self.client_id).user_agent(
+            "eve-oauth2",
+            env!("CARGO_PKG_VERSION"),
+            env!("CARGO_PKG_REPOSITORY"),
+        );
+
+        match &self.client_secret {
+            Some(client_secret) => client.client_secret(client_secret.as_str()).build(),
+            None => client.build(),
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let result = match cli.command {
+        Command::Login(args) => login::run(args).await,
+        Command::Decode(args) => decode::run(args).await,
+        Command::Refresh(args) => refresh::run(args).await,
+        Command::Revoke(args) => revoke::run(args).await,
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+type CliResult = Result<(), Box<dyn Error>>;
+
+/// Returns the argument, or reads it from stdin if it's `-`
+fn read_arg(value: String) -> Result<String, Box<dyn Error>> {
+    if value != "-" {
+        return Ok(value);
+    }
+
+    let mut value = String::new();
+    std::io::stdin().read_to_string(&mut value)?;
+
+    Ok(value.trim().to_string())
+}
+
+/// Prints the tokens as JSON, or writes them to the output file
+fn write_tokens(tokens: &EveTokens, output: Option<PathBuf>) -> CliResult {
+    let json = serde_json::to_string_pretty(tokens)?;
+
+    match output {
+        Some(path) => {
+            create_private_file(&path)?.write_all(json.as_bytes())?;
+            eprintln!(
+                "Wrote the tokens of character {} to {}",
+                tokens.character_id,
+                path.display()
+            );
+        }
+        None => println!("{}", json),
+    }
+
+    Ok(())
+}
+
+/// Creates or truncates the file readable only by the current user, refusing to follow a symlink at the path
+#[cfg(unix)]
+fn create_private_file(path: &Path) -> Result<File, Box<dyn Error>> {
+    use std::fs::{OpenOptions, Permissions};
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+    if path
+        .symlink_metadata()
+        .is_ok_and(|metadata| metadata.file_type().is_symlink())
+    {
+        return Err(format!("Refusing to write tokens to symlink {}", path.display()).into());
+    }
+
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path)?;
+
+    // The mode only applies to new files, restrict an existing file as well
+    file.set_permissions(Permissions::from_mode(0o600))?;
+
+    Ok(file)
+}
+
+#[cfg(not(unix))]
+fn create_private_file(path: &Path) -> Result<File, Box<dyn Error>> {
+    Ok(File::create(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+
+    #[test]
+    fn cli_is_valid() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn client_args_use_pkce_without_a_client_secret() {
+        let args = ClientArgs {
+            client_id: "client_id".to_string(),
+            client_secret: None,
+        };
+
+        let login = args
+            .client()
+            .create_login_url_pkce("http://127.0.0.1:8080/callback", ["publicData"]);
+        assert!(login.code_verifier.is_some());
+        assert!(login.login_url.contains("client_id=client_id"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn private_file_is_owner_only_and_refuses_symlinks() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("eve-oauth2-cli-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("tokens.json");
+        let link = dir.join("link.json");
+
+        std::fs::write(&path, "{}").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        create_private_file(&path).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        std::os::unix::fs::symlink(&path, &link).unwrap();
+        assert!(create_private_file(&link).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,32 @@
+use std::path::PathBuf;
+
+use clap::Args;
+
+use crate::{read_arg, write_tokens, CliResult, ClientArgs};
+
+#[derive(Args)]
+pub struct RefreshArgs {
+    #[command(flatten)]
+    client: ClientArgs,
+    /// Refresh token to exchange, `-` reads it from stdin
+    #[arg(long, env = "ESI_REFRESH_TOKEN", hide_env_values = true)]
+    refresh_token: String,
+    /// Scope to limit the new access token to, repeat for multiple scopes, defaults to all granted scopes
+    #[arg(short, long = "scope", value_name = "SCOPE")]
+    scopes: Vec<String>,
+    /// Write the tokens to the file instead of printing them
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+pub async fn run(args: RefreshArgs) -> CliResult {
+    let client = args.client.client();
+    let refresh_token = read_arg(args.refresh_token)?;
+
+    let token = client
+        .refresh_with_scopes(refresh_token, args.scopes)
+        .await?;
+    let tokens = client.validate_token_response(&token).await?;
+
+    write_tokens(&tokens, args.output)
+}
@@ -0,0 +1,22 @@
+use clap::Args;
+
+use crate::{read_arg, CliResult, ClientArgs};
+
+#[derive(Args)]
+pub struct RevokeArgs {
+    #[command(flatten)]
+    client: ClientArgs,
+    /// Refresh token to revoke, `-` reads it from stdin
+    #[arg(long, env = "ESI_REFRESH_TOKEN", hide_env_values = true)]
+    refresh_token: String,
+}
+
+pub async fn run(args: RevokeArgs) -> CliResult {
+    let client = args.client.client();
+    let refresh_token = read_arg(args.refresh_token)?;
+
+    client.revoke_token(refresh_token).await?;
+    eprintln!("Revoked the refresh token");
+
+    Ok(())
+}
@@ -0,0 +1,288 @@
+//! Blocking versions of the EVE Online SSO functions for applications which don't run an async runtime
+//!
+//! Enabled with the `blocking` feature, uses `reqwest::blocking` for all requests to EVE Online SSO.
+
+use std::sync::OnceLock;
+
+use jsonwebtoken::TokenData;
+use oauth2::{AuthorizationCode, PkceCodeVerifier, RefreshToken};
+use serde::de::DeserializeOwned;
+
+use crate::error::{Error, ResponseStatus};
+use crate::http::{Endpoint, HttpError, HttpRequest, HttpResponse};
+use crate::metrics::{self, Operation};
+use crate::models::{EveJwtClaims, EveJwtKeys, EveSsoMetaData};
+use crate::token::EveTokenResponse;
+#[cfg(feature = "tracing")]
+use crate::trace_token_result;
+use crate::validation::TokenValidation;
+use crate::{
+    cache_eve_jwt_keys, cache_sso_metadata, cached_eve_jwt_keys, cached_sso_metadata,
+    convert_scopes, decode_token, eve_client, SSO_META_DATA_TTL, SSO_META_DATA_URL,
+};
+
+pub use crate::{create_login_url, create_login_url_pkce};
+
+static HTTP_CLIENT: OnceLock<reqwest::blocking::Client> = OnceLock::new();
+
+/// Handles callback from EVE Online SSO
+///
+/// Takes client_id & client_secret variables which you get from your EVE developer application (https://developers.eveonline.com/).
+///
+/// Redirect code is pulled from the GET request URL when the user is redirected to your callback route
+///
+/// Returns the token which you can you retrieve the claims from using validate_token
+/// ```no_run
+/// # fn example(client_id: String, client_secret: String, code: String) {
+/// use eve_oauth2::blocking::{get_access_token, validate_token};
+///
+/// let token = get_access_token(client_id, client_secret, code).unwrap();
+/// let token_claims = validate_token(token.access_token()).unwrap();
+/// # }
+/// ```
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(client_id = client_id.as_ref())))]
+pub fn get_access_token(
+    client_id: impl AsRef<str>,
+    client_secret: impl Into<String>,
+    code: impl Into<String>,
+) -> Result<EveTokenResponse, Error> {
+    let status = ResponseStatus::default();
+
+    let token = eve_client(client_id.as_ref().to_string(), Some(client_secret.into()))
+        .exchange_code(AuthorizationCode::new(code.into()))
+        .request(&|request| execute_token(request, &status))
+        .map(EveTokenResponse::from_oauth2)
+        .map_err(|err| Error::from_token_request(err, |err| err, status));
+
+    #[cfg(feature = "tracing")]
+    trace_token_result("authorization code exchange", &token);
+
+    metrics::record(Operation::CodeExchange, &token);
+
+    token
+}
+
+/// Handles callback from EVE Online SSO for logins created with `create_login_url_pkce`
+///
+/// Takes the code_verifier returned alongside the login URL instead of a client secret.
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(client_id = client_id.as_ref())))]
+pub fn get_access_token_pkce(
+    client_id: impl AsRef<str>,
+    code: impl Into<String>,
+    code_verifier: impl Into<String>,
+) -> Result<EveTokenResponse, Error> {
+    let status = ResponseStatus::default();
+
+    let token = eve_client(client_id.as_ref().to_string(), None)
+        .exchange_code(AuthorizationCode::new(code.into()))
+        .set_pkce_verifier(PkceCodeVerifier::new(code_verifier.into()))
+        .request(&|request| execute_token(request, &status))
+        .map(EveTokenResponse::from_oauth2)
+        .map_err(|err| Error::from_token_request(err, |err| err, status));
+
+    #[cfg(feature = "tracing")]
+    trace_token_result("PKCE authorization code exchange", &token);
+
+    metrics::record(Operation::CodeExchange, &token);
+
+    token
+}
+
+/// Retrieves a new access token using the refresh token of a previous token response
+///
+/// EVE Online SSO rotates refresh tokens, make sure to store the refresh token of the returned token response
+/// as the one provided will no longer be usable.
+pub fn refresh(
+    client_id: impl AsRef<str>,
+    client_secret: impl Into<String>,
+    refresh_token: impl Into<String>,
+) -> Result<EveTokenResponse, Error> {
+    refresh_with_scopes(
+        client_id,
+        client_secret,
+        refresh_token,
+        Vec::<String>::new(),
+    )
+}
+
+/// Retrieves a new access token limited to a subset of the scopes originally granted to the refresh token
+///
+/// All originally granted scopes are requested if scopes is empty. As with `refresh`, store the refresh token
+/// of the returned token response.
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(client_id = client_id.as_ref())))]
+pub fn refresh_with_scopes(
+    client_id: impl AsRef<str>,
+    client_secret: impl Into<String>,
+    refresh_token: impl Into<String>,
+    scopes: impl IntoIterator<Item = impl Into<String>>,
+) -> Result<EveTokenResponse, Error> {
+    let status = ResponseStatus::default();
+
+    let token = eve_client(client_id.as_ref().to_string(), Some(client_secret.into()))
+        .exchange_refresh_token(&RefreshToken::new(refresh_token.into()))
+        .add_scopes(convert_scopes(scopes))
+        .request(&|request| execute_token(request, &status))
+        .map(EveTokenResponse::from_oauth2)
+        .map_err(|err| Error::from_token_request(err, |err| err, status));
+
+    #[cfg(feature = "tracing")]
+    trace_token_result("refresh token exchange", &token);
+
+    metrics::record(Operation::Refresh, &token);
+
+    token
+}
+
+/// Validates a token which can be retrieved using `get_access_token`
+///
+/// On successful validation it will return the EVE JWT claims
+pub fn validate_token(token: impl AsRef<str>) -> Result<TokenData<EveJwtClaims>, Error> {
+    validate_token_with(token, &TokenValidation::default())
+}
+
+/// Validates a token & applies the additional checks of the validation options, e.g. required scopes
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+pub fn validate_token_with(
+    token: impl AsRef<str>,
+    validation: &TokenValidation,
+) -> Result<TokenData<EveJwtClaims>, Error> {
+    let result =
+        get_eve_jwt_keys().and_then(|keys| decode_token(token.as_ref(), keys.keys, validation));
+
+    metrics::record(Operation::Validation, &result);
+
+    result
+}
+
+fn get_eve_jwt_keys() -> Result<EveJwtKeys, Error> {
+    let client = http_client();
+
+    let res = get_sso_metadata(client)?;
+
+    if let Some(keys) = cached_eve_jwt_keys(&res.jwks_uri) {
+        #[cfg(feature = "tracing")]
+        tracing::trace!("Using cached EVE JWT keys");
+
+        return Ok(keys);
+    }
+
+    let keys: EveJwtKeys = get_json(client, &res.jwks_uri, Endpoint::Jwks)?;
+
+    #[cfg(feature = "tracing")]
+    tracing::debug!(jwks_uri = %res.jwks_uri, keys = keys.keys.len(), "Retrieved EVE JWT keys");
+
+    cache_eve_jwt_keys(&res.jwks_uri, &keys);
+
+    Ok(keys)
+}
+
+fn get_sso_metadata(client: &reqwest::blocking::Client) -> Result<EveSsoMetaData, Error> {
+    if let Some(metadata) = cached_sso_metadata(SSO_META_DATA_TTL) {
+        return Ok(metadata);
+    }
+
+    let metadata: EveSsoMetaData = get_json(client, SSO_META_DATA_URL, Endpoint::Metadata)?;
+
+    cache_sso_metadata(&metadata);
+
+    Ok(metadata)
+}
+
+fn get_json<T: DeserializeOwned>(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    endpoint: Endpoint,
+) -> Result<T, Error> {
+    let response = metrics::timed_blocking(endpoint, || client.get(url).send())
+        .map_err(|err| Error::Http(HttpError::new(err)))?;
+
+    let status_code = response.status();
+    let headers = response.headers().to_owned();
+    let body = response
+        .bytes()
+        .map_err(|err| Error::Http(HttpError::new(err)))?;
+
+    if !status_code.is_success() {
+        return Err(Error::from_status(status_code, &headers, &body));
+    }
+
+    serde_json::from_slice(&body).map_err(|err| Error::unexpected_body(err, &body))
+}
+
+/// Sends a token request & records the status of unsuccessful responses for [`Error::from_token_request`]
+fn execute_token(
+    request: oauth2::HttpRequest,
+    status: &ResponseStatus,
+) -> Result<oauth2::HttpResponse, HttpError> {
+    let response = metrics::timed_blocking(Endpoint::Token, || send(request));
+
+    status.record(&response);
+
+    response.map(HttpResponse::into_oauth2)
+}
+
+fn send(request: oauth2::HttpRequest) -> Result<HttpResponse, HttpError> {
+    let request = HttpRequest::from_oauth2(request)?;
+
+    let response = http_client()
+        .request(request.method, request.url)
+        .headers(request.headers)
+        .body(request.body)
+        .send()
+        .map_err(HttpError::new)?;
+
+    let status_code = response.status();
+    let headers = response.headers().to_owned();
+    let body = response.bytes().map_err(HttpError::new)?.to_vec();
+
+    Ok(HttpResponse {
+        status_code,
+        headers,
+        body,
+    })
+}
+
+/// Returns the `reqwest::blocking::Client` shared by all requests, redirects are disabled to prevent SSRF vulnerabilities
+fn http_client() -> &'static reqwest::blocking::Client {
+    HTTP_CLIENT.get_or_init(|| {
+        reqwest::blocking::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .user_agent(concat!("eve_oauth2/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .expect("Failed to build reqwest client")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn shared_client_does_not_follow_redirects() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/metadata", listener.local_addr().unwrap());
+
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let len = stream.read(&mut request).unwrap();
+
+            stream
+                .write_all(
+                    b"HTTP/1.1 302 Found\r\nLocation: http://169.254.169.254/\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                )
+                .unwrap();
+
+            String::from_utf8_lossy(&request[..len]).to_lowercase()
+        });
+
+        let result = get_json::<EveSsoMetaData>(http_client(), &url, Endpoint::Metadata);
+        assert!(matches!(result, Err(Error::Status { status: 302, .. })));
+
+        let request = server.join().unwrap();
+        assert!(request.contains("user-agent: eve_oauth2/"));
+    }
+}
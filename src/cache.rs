@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use jsonwebtoken::TokenData;
+use sha2::{Digest, Sha256};
+use web_time::{SystemTime, UNIX_EPOCH};
+
+use crate::models::EveJwtClaims;
+
+const DEFAULT_MAX_AGE: Duration = Duration::from_secs(1200);
+
+/// Cache of validated access tokens, enabled with the `token-cache` feature
+///
+/// The same access token is usually presented on every request during its lifetime. Set a cache with
+/// [`crate::EveSsoClient::token_cache`] to return the claims of tokens validated before without verifying their
+/// signature again. Entries are keyed by the SHA-256 hash of the token, so the cache doesn't hold the tokens
+/// themselves, & expire with the token or after the max age, whichever comes first. The least recently used entry is
+/// evicted once the cache is full.
+///
+/// The validation options of each call, e.g. required scopes, are still checked against the cached claims. Clones
+/// share the same entries.
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use eve_oauth2::{EveSsoClient, TokenCache};
+///
+/// let client = EveSsoClient::builder("client_id")
+///     .token_cache(TokenCache::new(10_000).max_age(Duration::from_secs(300)))
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct TokenCache {
+    inner: Arc<Mutex<Entries>>,
+    capacity: usize,
+    max_age: Duration,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    entries: HashMap<[u8; 32], Entry>,
+    /// Incremented on every use to find the least recently used entry
+    clock: u64,
+}
+
+#[derive(Debug)]
+struct Entry {
+    token_data: TokenData<EveJwtClaims>,
+    /// Unix timestamp in seconds after which the entry is no longer used
+    expires_at: u64,
+    last_used: u64,
+}
+
+impl TokenCache {
+    /// Creates a cache holding up to capacity tokens for at most 20 minutes each, the lifetime of EVE access tokens
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Entries::default())),
+            capacity,
+            max_age: DEFAULT_MAX_AGE,
+        }
+    }
+
+    /// Sets how long a validated token is cached at most, tokens are never cached past their expiry
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Number of cached tokens, including expired tokens which weren't evicted yet
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+
+    /// Whether no tokens are cached
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all cached tokens, e.g. after the EVE JWT keys were rotated
+    pub fn clear(&self) {
+        self.inner.lock().unwrap().entries.clear();
+    }
+
+    /// Returns the claims of the token if it was validated before & hasn't expired
+    pub(crate) fn get(&self, token: &str) -> Option<TokenData<EveJwtClaims>> {
+        let key = hash(token);
+        let mut inner = self.inner.lock().unwrap();
+
+        inner.clock += 1;
+        let clock = inner.clock;
+
+        match inner.entries.get_mut(&key) {
+            Some(entry) if entry.expires_at > now() => {
+                entry.last_used = clock;
+
+                Some(entry.token_data.clone())
+            }
+            Some(_) => {
+                inner.entries.remove(&key);
+
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Caches the claims of a validated token until it expires or the max age has passed
+    pub(crate) fn insert(&self, token: &str, token_data: &TokenData<EveJwtClaims>) {
+        let now = now();
+        let expires_at = token_data
+            .claims
+            .exp
+            .min(now.saturating_add(self.max_age.as_secs()));
+
+        if self.capacity == 0 || expires_at <= now {
+            return;
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+
+        if inner.entries.len() >= self.capacity {
+            inner.entries.retain(|_, entry| entry.expires_at > now);
+        }
+
+        if inner.entries.len() >= self.capacity {
+            let least_recently_used = inner
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| *key);
+
+            if let Some(key) = least_recently_used {
+                inner.entries.remove(&key);
+            }
+        }
+
+        inner.clock += 1;
+        let last_used = inner.clock;
+
+        inner.entries.insert(
+            hash(token),
+            Entry {
+                token_data: token_data.clone(),
+                expires_at,
+                last_used,
+            },
+        );
+    }
+}
+
+fn hash(token: &str) -> [u8; 32] {
+    Sha256::digest(token.as_bytes()).into()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use jsonwebtoken::Header;
+
+    use super::*;
+
+    fn token_data(claims: EveJwtClaims) -> TokenData<EveJwtClaims> {
+        TokenData {
+            header: Header::default(),
+            claims,
+        }
+    }
+
+    fn expires_at(cache: &TokenCache, token: &str) -> u64 {
+        cache.inner.lock().unwrap().entries[&hash(token)].expires_at
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_token_at_capacity() {
+        let cache = TokenCache::new(2);
+
+        cache.insert(
+            "a",
+            &token_data(EveJwtClaims::test().character_id(1).build()),
+        );
+        cache.insert(
+            "b",
+            &token_data(EveJwtClaims::test().character_id(2).build()),
+        );
+        assert!(cache.get("a").is_some());
+
+        cache.insert(
+            "c",
+            &token_data(EveJwtClaims::test().character_id(3).build()),
+        );
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get("b").is_none());
+        assert_eq!(cache.get("a").unwrap().claims.character_id().unwrap().0, 1);
+        assert_eq!(cache.get("c").unwrap().claims.character_id().unwrap().0, 3);
+    }
+
+    #[test]
+    fn expires_with_the_token_or_after_the_max_age() {
+        let cache = TokenCache::new(10).max_age(Duration::from_secs(60));
+        let now = now();
+
+        cache.insert(
+            "long",
+            &token_data(EveJwtClaims::test().expires_at(now + 1200).build()),
+        );
+        assert!((now + 60..=now + 61).contains(&expires_at(&cache, "long")));
+
+        cache.insert(
+            "short",
+            &token_data(EveJwtClaims::test().expires_at(now + 30).build()),
+        );
+        assert_eq!(expires_at(&cache, "short"), now + 30);
+
+        cache.insert(
+            "expired",
+            &token_data(EveJwtClaims::test().expires_at(now).build()),
+        );
+        assert!(cache.get("expired").is_none());
+        assert_eq!(cache.len(), 2);
+
+        // Entries past their expiry are removed when looked up
+        cache
+            .inner
+            .lock()
+            .unwrap()
+            .entries
+            .get_mut(&hash("short"))
+            .unwrap()
+            .expires_at = now;
+        assert!(cache.get("short").is_none());
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn disabled_caches_hold_no_tokens() {
+        let claims = EveJwtClaims::test().build();
+
+        let cache = TokenCache::new(0);
+        cache.insert("token", &token_data(claims.clone()));
+        assert!(cache.is_empty());
+
+        let cache = TokenCache::new(10).max_age(Duration::ZERO);
+        cache.insert("token", &token_data(claims));
+        assert!(cache.is_empty());
+    }
+
+    #[cfg(all(feature = "testing", feature = "reqwest"))]
+    #[tokio::test]
+    async fn validation_options_are_checked_on_cache_hits() {
+        use crate::error::Error;
+        use crate::testing::MockSso;
+        use crate::validation::TokenValidation;
+
+        let sso = MockSso::start().unwrap();
+        let cache = TokenCache::new(10);
+        let client = sso.client("client_id").token_cache(cache.clone());
+        let token = sso
+            .signer()
+            .token()
+            .client_id("client_id")
+            .scopes(["publicData"])
+            .sign();
+
+        client.validate_token(token.clone()).await.unwrap();
+        assert_eq!(cache.len(), 1);
+
+        let err = client
+            .validate_token_with(
+                token.clone(),
+                &TokenValidation::new().require_scopes(["esi-mail.read_mail.v1"]),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::MissingScopes(_)), "{:?}", err);
+
+        let err = client
+            .validate_token_with(
+                token.clone(),
+                &TokenValidation::new().require_client_id("other_client"),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidClaims(_)), "{:?}", err);
+
+        assert!(client
+            .validate_token_with(
+                token,
+                &TokenValidation::new().require_scopes(["publicData"])
+            )
+            .await
+            .is_ok());
+    }
+}
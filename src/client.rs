@@ -0,0 +1,2613 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::{DecodingKey, TokenData};
+use oauth2::{
+    AsyncHttpClient, AuthType, AuthorizationCode, CsrfToken, PkceCodeVerifier, RefreshToken,
+};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "token-cache")]
+use crate::cache::TokenCache;
+use crate::error::{Error, ErrorClass, ResponseStatus};
+use crate::events::{AuditEvent, AuditEventKind, AuditSink, NoopAuditSink, TokenEvent};
+use crate::http::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE, USER_AGENT};
+use crate::http::{
+    BoxFuture, Endpoint, HeaderMap, HeaderValue, HttpClient, HttpError, HttpRequest, HttpResponse,
+    Method, Url,
+};
+use crate::login::LoginUrlBuilder;
+use crate::metrics::{self, Operation};
+use crate::models::{
+    Character, EsiVerifyResponse, EveJwtClaims, EveJwtKeys, EveSsoMetaData, EveTokens, SsoEndpoints,
+};
+#[cfg(feature = "tokio")]
+use crate::rate_limit::RateLimiter;
+use crate::retry::{NoRetry, RetryPolicy};
+use crate::scopes::{check_scopes, missing_scopes, ScopeCheck, ScopeSet};
+use crate::secret::SecretString;
+use crate::state::{PendingLogin, StateSigner, StateStore, DEFAULT_STATE_TTL};
+use crate::store::TokenStore;
+use crate::token::EveTokenResponse;
+#[cfg(feature = "tracing")]
+use crate::trace_token_result;
+use crate::validation::TokenValidation;
+use crate::{
+    cache_eve_jwt_keys, cache_sso_metadata, cached_eve_jwt_keys, cached_sso_metadata,
+    convert_scopes, decode_token, decode_tokens, decode_unverified, decoding_key, eve_client_with,
+    AuthenticationData, EveOAuthClient, ESI_URL, SSO_META_DATA_TTL, SSO_META_DATA_URL,
+};
+
+/// Smallest number of tokens validated per blocking task by batch validation, fewer aren't worth a task of their own
+#[cfg(feature = "tokio")]
+const MIN_TOKENS_PER_TASK: usize = 16;
+
+/// Client for EVE Online SSO which sends all requests using the provided [`HttpClient`]
+///
+/// The client is cheap to clone & can be shared between your routes.
+#[derive(Clone)]
+pub struct EveSsoClient {
+    client_id: String,
+    client_secret: Option<SecretString>,
+    http_client: Arc<dyn HttpClient>,
+    user_agent: Option<HeaderValue>,
+    token_auth_method: TokenAuthMethod,
+    endpoints: SsoEndpoints,
+    metadata_ttl: Duration,
+    fallback_metadata_url: Option<String>,
+    fallback_jwt_keys: Option<EveJwtKeys>,
+    state_signer: Option<StateSigner>,
+    state_generator: Option<Arc<StateGenerator>>,
+    token_event_hooks: Vec<Arc<TokenEventHook>>,
+    audit_sink: Arc<dyn AuditSink>,
+    retry_policy: Arc<dyn RetryPolicy>,
+    #[cfg(feature = "tokio")]
+    rate_limiter: Option<RateLimiter>,
+    scope_check: ScopeCheck,
+    esi_verify_fallback: bool,
+    esi_url: Url,
+    #[cfg(feature = "esi")]
+    pub(crate) affiliations: crate::esi::AffiliationCache,
+    #[cfg(feature = "tokio")]
+    blocking_validation: bool,
+    #[cfg(feature = "token-cache")]
+    token_cache: Option<TokenCache>,
+}
+
+type TokenEventHook = dyn Fn(&TokenEvent<'_>) + Send + Sync;
+
+type StateGenerator = dyn Fn() -> String + Send + Sync;
+
+/// How the client credentials are sent to the token endpoint, EVE Online SSO supports both methods
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TokenAuthMethod {
+    /// HTTP Basic authentication in the Authorization header (`client_secret_basic`)
+    #[default]
+    ClientSecretBasic,
+    /// client_id & client_secret in the request body (`client_secret_post`)
+    ClientSecretPost,
+}
+
+/// Builder for an [`EveSsoClient`], created with [`EveSsoClient::builder`]
+///
+/// ```no_run
+/// use eve_oauth2::EveSsoClient;
+///
+/// let client = EveSsoClient::builder("client_id")
+///     .client_secret("client_secret")
+///     .user_agent("My Corp Tool", "1.0.0", "admin@example.com")
+///     .build();
+/// ```
+///
+/// The builder wraps an [`EveSsoClient`] with the default settings, its setters are the client's setters.
+pub struct EveSsoClientBuilder {
+    client: EveSsoClient,
+    http_client: Option<Arc<dyn HttpClient>>,
+}
+
+impl EveSsoClientBuilder {
+    /// Sets the client secret of your EVE developer application, not required for the PKCE login flow
+    ///
+    /// The secret is kept in a [`SecretString`] & only exposed when it's sent to EVE Online SSO.
+    pub fn client_secret(mut self, client_secret: impl Into<SecretString>) -> Self {
+        self.client.client_secret = Some(client_secret.into());
+        self
+    }
+
+    /// Sets the [`HttpClient`] used to send requests, required when the `reqwest` feature is disabled
+    pub fn http_client(mut self, http_client: impl HttpClient + 'static) -> Self {
+        self.http_client = Some(Arc::new(http_client));
+        self
+    }
+
+    /// Sets the User-Agent header sent with all requests to EVE Online SSO, see [`EveSsoClient::user_agent`]
+    pub fn user_agent(self, app_name: &str, version: &str, contact: &str) -> Self {
+        self.map(|client| client.user_agent(app_name, version, contact))
+    }
+
+    /// Sets how the client credentials are sent to the token endpoint, see [`EveSsoClient::token_auth_method`]
+    pub fn token_auth_method(self, token_auth_method: TokenAuthMethod) -> Self {
+        self.map(|client| client.token_auth_method(token_auth_method))
+    }
+
+    /// Sets the endpoints of EVE Online SSO, e.g. to use endpoints discovered from the metadata or a mock server
+    pub fn endpoints(mut self, endpoints: SsoEndpoints) -> Self {
+        self.client.endpoints = endpoints;
+        self
+    }
+
+    /// Sets how long the EVE Online SSO metadata is cached, see [`EveSsoClient::metadata_ttl`]
+    pub fn metadata_ttl(self, ttl: Duration) -> Self {
+        self.map(|client| client.metadata_ttl(ttl))
+    }
+
+    /// Sets the URL the metadata is retrieved from if the well-known endpoint fails, see
+    /// [`EveSsoClient::fallback_metadata_url`]
+    pub fn fallback_metadata_url(self, url: impl Into<String>) -> Self {
+        self.map(|client| client.fallback_metadata_url(url))
+    }
+
+    /// Sets the EVE JWT keys used if they can't be retrieved, see [`EveSsoClient::fallback_jwt_keys`]
+    pub fn fallback_jwt_keys(self, keys: EveJwtKeys) -> Self {
+        self.map(|client| client.fallback_jwt_keys(keys))
+    }
+
+    /// Signs the states of login URLs instead of generating random ones, see [`EveSsoClient::state_signer`]
+    pub fn state_signer(self, state_signer: StateSigner) -> Self {
+        self.map(|client| client.state_signer(state_signer))
+    }
+
+    /// Generates the states of login URLs with the closure instead of randomly, see [`EveSsoClient::state_generator`]
+    pub fn state_generator(self, generator: impl Fn() -> String + Send + Sync + 'static) -> Self {
+        self.map(|client| client.state_generator(generator))
+    }
+
+    /// Registers a hook which is called with every token lifecycle event, see [`EveSsoClient::on_token_event`]
+    pub fn on_token_event(self, hook: impl Fn(&TokenEvent<'_>) + Send + Sync + 'static) -> Self {
+        self.map(|client| client.on_token_event(hook))
+    }
+
+    /// Sets the sink receiving the client's audit events, see [`EveSsoClient::audit_sink`]
+    pub fn audit_sink(self, sink: impl AuditSink + 'static) -> Self {
+        self.map(|client| client.audit_sink(sink))
+    }
+
+    /// Sets the policy deciding which failed requests are retried, see [`EveSsoClient::retry_policy`]
+    pub fn retry_policy(self, policy: impl RetryPolicy + 'static) -> Self {
+        self.map(|client| client.retry_policy(policy))
+    }
+
+    /// Limits the rate of requests to EVE Online SSO, see [`EveSsoClient::rate_limiter`]
+    #[cfg(feature = "tokio")]
+    pub fn rate_limiter(self, rate_limiter: RateLimiter) -> Self {
+        self.map(|client| client.rate_limiter(rate_limiter))
+    }
+
+    /// Checks the scopes of login URLs against the known ESI scopes, see [`EveSsoClient::scope_check`]
+    pub fn scope_check(self, scope_check: ScopeCheck) -> Self {
+        self.map(|client| client.scope_check(scope_check))
+    }
+
+    /// Falls back to ESI's `/verify` endpoint when the EVE JWT keys can't be retrieved, see
+    /// [`EveSsoClient::esi_verify_fallback`]
+    pub fn esi_verify_fallback(self, enabled: bool) -> Self {
+        self.map(|client| client.esi_verify_fallback(enabled))
+    }
+
+    /// Sets the base URL of ESI, see [`EveSsoClient::esi_url`]
+    pub fn esi_url(self, url: Url) -> Self {
+        self.map(|client| client.esi_url(url))
+    }
+
+    /// Verifies the signatures of tokens on tokio's blocking thread pool, see [`EveSsoClient::blocking_validation`]
+    #[cfg(feature = "tokio")]
+    pub fn blocking_validation(self, enabled: bool) -> Self {
+        self.map(|client| client.blocking_validation(enabled))
+    }
+
+    /// Caches validated tokens, see [`EveSsoClient::token_cache`]
+    #[cfg(feature = "token-cache")]
+    pub fn token_cache(self, cache: TokenCache) -> Self {
+        self.map(|client| client.token_cache(cache))
+    }
+
+    /// Creates the client, using `reqwest` to send requests if no [`HttpClient`] was set
+    ///
+    /// Panics if no [`HttpClient`] was set & the `reqwest` feature is disabled.
+    pub fn build(self) -> EveSsoClient {
+        #[cfg(feature = "reqwest")]
+        let http_client = self
+            .http_client
+            .unwrap_or_else(|| Arc::new(crate::http::default_http_client()));
+
+        #[cfg(not(feature = "reqwest"))]
+        let http_client = self
+            .http_client
+            .expect("EveSsoClientBuilder::http_client is required without the reqwest feature");
+
+        EveSsoClient {
+            http_client,
+            ..self.client
+        }
+    }
+
+    fn map(mut self, f: impl FnOnce(EveSsoClient) -> EveSsoClient) -> Self {
+        self.client = f(self.client);
+        self
+    }
+}
+
+/// Placeholder of the builder's client until [`EveSsoClientBuilder::build`] sets the [`HttpClient`]
+struct UnsetHttpClient;
+
+impl HttpClient for UnsetHttpClient {
+    fn execute(&self, _request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, HttpError>> {
+        Box::pin(async { Err(HttpError::new("No HttpClient set")) })
+    }
+}
+
+impl EveSsoClient {
+    /// Creates a new client using `reqwest` to send requests
+    ///
+    /// Takes client_id & client_secret variables which you get from your EVE developer application (https://developers.eveonline.com/).
+    /// client_secret can be `None` if you only use the PKCE login flow, it's kept in a [`SecretString`].
+    #[cfg(feature = "reqwest")]
+    pub fn new(client_id: impl Into<String>, client_secret: Option<String>) -> Self {
+        Self::with_http_client(client_id, client_secret, crate::http::default_http_client())
+    }
+
+    /// Creates a builder to configure the client, see [`EveSsoClientBuilder`]
+    pub fn builder(client_id: impl Into<String>) -> EveSsoClientBuilder {
+        EveSsoClientBuilder {
+            client: Self::from_parts(client_id.into(), None, Arc::new(UnsetHttpClient)),
+            http_client: None,
+        }
+    }
+
+    /// Creates a new client using your own [`HttpClient`] implementation to send requests
+    pub fn with_http_client(
+        client_id: impl Into<String>,
+        client_secret: Option<String>,
+        http_client: impl HttpClient + 'static,
+    ) -> Self {
+        Self::from_parts(
+            client_id.into(),
+            client_secret.map(SecretString::from),
+            Arc::new(http_client),
+        )
+    }
+
+    /// Creates a client with the default settings
+    fn from_parts(
+        client_id: String,
+        client_secret: Option<SecretString>,
+        http_client: Arc<dyn HttpClient>,
+    ) -> Self {
+        Self {
+            client_id,
+            client_secret,
+            http_client,
+            user_agent: None,
+            token_auth_method: TokenAuthMethod::default(),
+            endpoints: SsoEndpoints::default(),
+            metadata_ttl: SSO_META_DATA_TTL,
+            fallback_metadata_url: None,
+            fallback_jwt_keys: None,
+            state_signer: None,
+            state_generator: None,
+            token_event_hooks: Vec::new(),
+            audit_sink: Arc::new(NoopAuditSink),
+            retry_policy: Arc::new(NoRetry),
+            #[cfg(feature = "tokio")]
+            rate_limiter: None,
+            scope_check: ScopeCheck::default(),
+            esi_verify_fallback: false,
+            esi_url: Url::parse(ESI_URL).expect("Invalid ESI URL"),
+            #[cfg(feature = "esi")]
+            affiliations: Default::default(),
+            #[cfg(feature = "tokio")]
+            blocking_validation: false,
+            #[cfg(feature = "token-cache")]
+            token_cache: None,
+        }
+    }
+
+    /// Sets the User-Agent header sent with all requests to EVE Online SSO
+    ///
+    /// CCP asks developers to identify their application & provide a way to contact them, e.g.
+    /// `client.user_agent("My Corp Tool", "1.0.0", "admin@example.com")` results in
+    /// `My Corp Tool/1.0.0 (admin@example.com) eve_oauth2/0.2.0`. Characters which aren't allowed in headers, e.g.
+    /// non-ASCII & control characters, are percent-encoded.
+    pub fn user_agent(mut self, app_name: &str, version: &str, contact: &str) -> Self {
+        self.user_agent = Some(user_agent_header(app_name, version, contact));
+        self
+    }
+
+    /// Sets how the client credentials are sent to the token endpoint, HTTP Basic authentication by default
+    ///
+    /// Use [`TokenAuthMethod::ClientSecretPost`] if a proxy strips or rewrites the Authorization header.
+    pub fn token_auth_method(mut self, token_auth_method: TokenAuthMethod) -> Self {
+        self.token_auth_method = token_auth_method;
+        self
+    }
+
+    /// Retrieves the EVE Online SSO metadata & uses the published endpoints for all following requests
+    ///
+    /// Endpoint changes by CCP are picked up without a crate release, call this once when creating the client.
+    ///
+    /// ```no_run
+    /// # async fn example() -> Result<(), eve_oauth2::Error> {
+    /// use eve_oauth2::EveSsoClient;
+    ///
+    /// let client = EveSsoClient::builder("client_id")
+    ///     .client_secret("client_secret")
+    ///     .build()
+    ///     .discover_endpoints()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn discover_endpoints(mut self) -> Result<Self, Error> {
+        let metadata = self.metadata().await?;
+
+        self.endpoints = SsoEndpoints::from_metadata(&metadata)?;
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(endpoints = ?self.endpoints, "Discovered EVE SSO endpoints");
+
+        Ok(self)
+    }
+
+    /// Client id of the EVE developer application
+    pub fn client_id(&self) -> &str {
+        &self.client_id
+    }
+
+    /// Endpoints of EVE Online SSO used by this client
+    pub fn endpoints(&self) -> &SsoEndpoints {
+        &self.endpoints
+    }
+
+    /// Sets how long the EVE Online SSO metadata is cached, 24 hours by default
+    ///
+    /// The cache is shared by all clients, each client applies its own TTL when reading it.
+    pub fn metadata_ttl(mut self, ttl: Duration) -> Self {
+        self.metadata_ttl = ttl;
+        self
+    }
+
+    /// Returns the EVE Online SSO metadata, e.g. to read the revocation endpoint & supported methods
+    ///
+    /// The metadata is cached for the TTL set with [`EveSsoClient::metadata_ttl`], except on wasm targets where it
+    /// is retrieved on every call.
+    pub async fn metadata(&self) -> Result<EveSsoMetaData, Error> {
+        if let Some(metadata) = cached_sso_metadata(self.metadata_ttl) {
+            #[cfg(feature = "tracing")]
+            tracing::trace!("Using cached EVE SSO metadata");
+
+            return Ok(metadata);
+        }
+
+        let metadata: EveSsoMetaData = match self
+            .get_json(SSO_META_DATA_URL, Endpoint::Metadata)
+            .await
+        {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                let Some(url) = &self.fallback_metadata_url else {
+                    return Err(err);
+                };
+
+                #[cfg(feature = "tracing")]
+                tracing::warn!(error = %err, url = %url, "Failed to retrieve EVE SSO metadata, using fallback URL");
+
+                self.get_json(url, Endpoint::Metadata)
+                    .await
+                    .map_err(|_| err)?
+            }
+        };
+
+        cache_sso_metadata(&metadata);
+
+        Ok(metadata)
+    }
+
+    /// Retrieves the metadata from the URL if the well-known endpoint of EVE Online SSO fails, e.g. a mirror of the
+    /// metadata you host yourself
+    ///
+    /// The error of the well-known endpoint is returned if the fallback fails as well.
+    pub fn fallback_metadata_url(mut self, url: impl Into<String>) -> Self {
+        self.fallback_metadata_url = Some(url.into());
+        self
+    }
+
+    /// Validates tokens with a last known good snapshot of the EVE JWT keys if they can't be retrieved, e.g. while
+    /// EVE Online SSO is down
+    ///
+    /// The snapshot is only used when retrieving the keys fails & is never cached, so the live keys are used again as
+    /// soon as they can be retrieved. Tokens signed with keys rotated in after the snapshot was taken fail validation
+    /// while it's used.
+    ///
+    /// ```no_run
+    /// use eve_oauth2::models::EveJwtKeys;
+    /// use eve_oauth2::EveSsoClient;
+    ///
+    /// // Snapshot of https://login.eveonline.com/oauth/jwks shipped with the application
+    /// let keys: EveJwtKeys = serde_json::from_str(&std::fs::read_to_string("jwks.json").unwrap()).unwrap();
+    ///
+    /// let client = EveSsoClient::builder("client_id").fallback_jwt_keys(keys).build();
+    /// ```
+    pub fn fallback_jwt_keys(mut self, keys: EveJwtKeys) -> Self {
+        self.fallback_jwt_keys = Some(keys);
+        self
+    }
+
+    /// Signs the states of login URLs with the signer instead of generating random ones
+    ///
+    /// Signed states expire & can be verified with [`EveSsoClient::verify_signed_state`] without storing them,
+    /// e.g. in stateless handlers. They aren't bound to the browser which started the login, see [`StateSigner`].
+    pub fn state_signer(mut self, state_signer: StateSigner) -> Self {
+        self.state_signer = Some(state_signer);
+        self
+    }
+
+    /// Generates the states of login URLs with the closure instead of randomly, e.g. deterministic states in tests or
+    /// a custom entropy source
+    ///
+    /// States must be unguessable to protect against CSRF, only return predictable states in tests. A
+    /// [`StateSigner`] takes precedence over the generator.
+    ///
+    /// ```no_run
+    /// use std::sync::atomic::{AtomicU64, Ordering};
+    ///
+    /// use eve_oauth2::EveSsoClient;
+    ///
+    /// let counter = AtomicU64::new(0);
+    /// let client = EveSsoClient::new("client_id".to_string(), None)
+    ///     .state_generator(move || format!("test-state-{}", counter.fetch_add(1, Ordering::Relaxed)));
+    /// ```
+    pub fn state_generator(
+        mut self,
+        generator: impl Fn() -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.state_generator = Some(Arc::new(generator));
+        self
+    }
+
+    /// Verifies the state returned to the callback was signed by this client's [`StateSigner`] & hasn't expired
+    ///
+    /// Fails with [`Error::InvalidState`] if the state is invalid or no state signer is set.
+    pub fn verify_signed_state(&self, state: &str) -> Result<(), Error> {
+        self.require_state_signer()?.verify(state)
+    }
+
+    /// Generates a login URL whose signed state carries the payload, e.g. where to send the user after the login
+    ///
+    /// Requires a [`StateSigner`], the PKCE flow is used if the client has no client secret. Get the payload back
+    /// in the callback with [`EveSsoClient::verify_signed_state_payload`].
+    ///
+    /// ```no_run
+    /// # fn example(client: eve_oauth2::EveSsoClient) -> Result<(), eve_oauth2::Error> {
+    /// #[derive(serde::Serialize, serde::Deserialize)]
+    /// struct LoginPayload {
+    ///     return_to: String,
+    /// }
+    ///
+    /// let login = client.create_login_url_with_payload(
+    ///     "http://localhost:8000/callback".to_string(),
+    ///     vec!["publicData".to_string()],
+    ///     &LoginPayload { return_to: "/settings".to_string() },
+    /// )?;
+    ///
+    /// // In the callback
+    /// let payload: LoginPayload = client.verify_signed_state_payload(&login.state)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn create_login_url_with_payload<T: Serialize>(
+        &self,
+        redirect_url: impl AsRef<str>,
+        scopes: impl IntoIterator<Item = impl Into<String>>,
+        payload: &T,
+    ) -> Result<AuthenticationData, Error> {
+        let state = CsrfToken::new(self.require_state_signer()?.sign_with_payload(payload)?);
+        let scopes = self.checked_scopes(scopes)?;
+
+        Ok(match self.client_secret {
+            Some(_) => crate::login_url(
+                self.oauth_client(self.client_secret.as_ref()),
+                redirect_url.as_ref(),
+                scopes,
+                state,
+            ),
+            None => crate::login_url_pkce(
+                self.oauth_client(None),
+                redirect_url.as_ref(),
+                scopes,
+                state,
+            ),
+        })
+    }
+
+    /// Verifies the signed state returned to the callback & returns its payload, see [`EveSsoClient::create_login_url_with_payload`]
+    pub fn verify_signed_state_payload<T: DeserializeOwned>(
+        &self,
+        state: &str,
+    ) -> Result<T, Error> {
+        self.require_state_signer()?.verify_with_payload(state)
+    }
+
+    /// Generates a login URL & puts its state into the store, it expires after 10 minutes & can only be used once
+    ///
+    /// The PKCE flow is used if the client has no client secret, its code verifier is stored alongside the state.
+    /// Complete the login in the callback with [`EveSsoClient::verify_callback`] using the same store.
+    ///
+    /// ```no_run
+    /// # async fn example(client: eve_oauth2::EveSsoClient, code: String, state: String) -> Result<(), eve_oauth2::Error> {
+    /// use eve_oauth2::state::MemoryStateStore;
+    ///
+    /// let store = MemoryStateStore::new();
+    ///
+    /// let login_url = client
+    ///     .create_login_url_with_store(&store, "http://localhost:8000/callback", ["publicData"])
+    ///     .await?;
+    ///
+    /// // In the callback
+    /// let token = client.verify_callback(&store, code, state).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn create_login_url_with_store(
+        &self,
+        store: &impl StateStore,
+        redirect_url: impl AsRef<str>,
+        scopes: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Result<String, Error> {
+        self.start_login_with_store(store, redirect_url, scopes)
+            .await
+            .map(|login| login.login_url)
+    }
+
+    /// Creates a login & puts its state into the store, returning the login so the caller can bind its state to the
+    /// user agent
+    pub(crate) async fn start_login_with_store(
+        &self,
+        store: &impl StateStore,
+        redirect_url: impl AsRef<str>,
+        scopes: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Result<AuthenticationData, Error> {
+        let login = match self.client_secret {
+            Some(_) => self.try_create_login_url(redirect_url, scopes)?,
+            None => self.try_create_login_url_pkce(redirect_url, scopes)?,
+        };
+
+        store
+            .put(
+                login.state.clone(),
+                PendingLogin::new(login.code_verifier.clone()),
+                DEFAULT_STATE_TTL,
+            )
+            .await?;
+
+        Ok(login)
+    }
+
+    /// Takes the state of the callback from the store & retrieves the access token using the code
+    ///
+    /// Fails with [`Error::InvalidState`] if the state is unknown, has expired or was already used.
+    pub async fn verify_callback(
+        &self,
+        store: &impl StateStore,
+        code: impl Into<String>,
+        state: impl Into<String>,
+    ) -> Result<EveTokenResponse, Error> {
+        let login = store.take(state.into()).await?.ok_or_else(|| {
+            Error::InvalidState("State is unknown, has expired or was already used".to_string())
+        })?;
+
+        match login.code_verifier {
+            Some(code_verifier) => self.get_access_token_pkce(code, code_verifier).await,
+            None => self.get_access_token(code).await,
+        }
+    }
+
+    /// Registers a hook which is called with every token lifecycle event of this client
+    ///
+    /// Hooks are called in the order they were registered, keep them short as they run before the request returns.
+    ///
+    /// ```no_run
+    /// use eve_oauth2::events::TokenEvent;
+    /// use eve_oauth2::EveSsoClient;
+    ///
+    /// let client = EveSsoClient::new("client_id".to_string(), Some("client_secret".to_string()))
+    ///     .on_token_event(|event| {
+    ///         if let TokenEvent::RefreshFailed(err) = event {
+    ///             println!("Refresh failed: {}", err);
+    ///         }
+    ///     });
+    /// ```
+    pub fn on_token_event(
+        mut self,
+        hook: impl Fn(&TokenEvent<'_>) + Send + Sync + 'static,
+    ) -> Self {
+        self.token_event_hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Sets the sink receiving an [`AuditEvent`] for every login, refresh & failed validation, see [`AuditSink`]
+    ///
+    /// Events carry the character id & a timestamp. The character id of logins & refreshes is read from the returned
+    /// access token, the id of failed validations from the token's claims without verifying them. The default sink
+    /// discards all events.
+    pub fn audit_sink(mut self, sink: impl AuditSink + 'static) -> Self {
+        self.audit_sink = Arc::new(sink);
+        self
+    }
+
+    /// Sets the policy deciding whether failed requests to EVE Online SSO are retried, requests aren't retried by
+    /// default
+    ///
+    /// The policy is consulted per endpoint, e.g. to retry JWKS & metadata requests but not token requests in
+    /// interactive login flows. See [`crate::retry`] for the provided policies.
+    pub fn retry_policy(mut self, policy: impl RetryPolicy + 'static) -> Self {
+        self.retry_policy = Arc::new(policy);
+        self
+    }
+
+    /// Limits the rate of all requests sent by the client, enabled with the `tokio` feature
+    ///
+    /// Requests exceeding the rate wait until they can be sent, see [`RateLimiter`]. Set it on the client of a
+    /// [`crate::TokenManager`] to throttle bulk refreshes.
+    #[cfg(feature = "tokio")]
+    pub fn rate_limiter(mut self, rate_limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Checks the scopes of login URLs against the catalog of known ESI scopes, scopes aren't checked by default
+    ///
+    /// With [`ScopeCheck::Warn`] unknown scopes are logged with the `tracing` feature & ignored without it. With
+    /// [`ScopeCheck::Deny`] creating a login URL with unknown scopes fails with [`Error::Config`], naming the known
+    /// scopes they're likely typos of; use the `try_` login URL functions to handle the error, the others panic.
+    /// Scopes added to ESI after the crate was released are reported as unknown.
+    ///
+    /// ```no_run
+    /// use eve_oauth2::scopes::ScopeCheck;
+    /// use eve_oauth2::EveSsoClient;
+    ///
+    /// let client = EveSsoClient::builder("client_id")
+    ///     .scope_check(ScopeCheck::Deny)
+    ///     .build();
+    /// ```
+    pub fn scope_check(mut self, scope_check: ScopeCheck) -> Self {
+        self.scope_check = scope_check;
+        self
+    }
+
+    /// Generates a state verification string & authentication URL for EVE Online SSO, see [`crate::create_login_url`]
+    ///
+    /// Panics if a scope is unknown & [`ScopeCheck::Deny`] is set, see [`EveSsoClient::try_create_login_url`].
+    pub fn create_login_url(
+        &self,
+        redirect_url: impl AsRef<str>,
+        scopes: impl IntoIterator<Item = impl Into<String>>,
+    ) -> AuthenticationData {
+        self.try_create_login_url(redirect_url, scopes)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Generates a state verification string & authentication URL for EVE Online SSO
+    ///
+    /// Fails with [`Error::Config`] if a scope is unknown & [`ScopeCheck::Deny`] is set.
+    pub fn try_create_login_url(
+        &self,
+        redirect_url: impl AsRef<str>,
+        scopes: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Result<AuthenticationData, Error> {
+        Ok(crate::login_url(
+            self.oauth_client(self.client_secret.as_ref()),
+            redirect_url.as_ref(),
+            self.checked_scopes(scopes)?,
+            self.new_state(),
+        ))
+    }
+
+    /// Generates a state verification string, PKCE code verifier & authentication URL for EVE Online SSO, see [`crate::create_login_url_pkce`]
+    ///
+    /// Panics if a scope is unknown & [`ScopeCheck::Deny`] is set, see [`EveSsoClient::try_create_login_url_pkce`].
+    pub fn create_login_url_pkce(
+        &self,
+        redirect_url: impl AsRef<str>,
+        scopes: impl IntoIterator<Item = impl Into<String>>,
+    ) -> AuthenticationData {
+        self.try_create_login_url_pkce(redirect_url, scopes)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Generates a state verification string, PKCE code verifier & authentication URL for EVE Online SSO
+    ///
+    /// Fails with [`Error::Config`] if a scope is unknown & [`ScopeCheck::Deny`] is set.
+    pub fn try_create_login_url_pkce(
+        &self,
+        redirect_url: impl AsRef<str>,
+        scopes: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Result<AuthenticationData, Error> {
+        Ok(crate::login_url_pkce(
+            self.oauth_client(None),
+            redirect_url.as_ref(),
+            self.checked_scopes(scopes)?,
+            self.new_state(),
+        ))
+    }
+
+    /// Creates a builder for a login URL with extra query parameters, see [`LoginUrlBuilder`]
+    pub fn login_url_builder(&self, redirect_url: impl Into<String>) -> LoginUrlBuilder<'_> {
+        LoginUrlBuilder::new(self, redirect_url.into())
+    }
+
+    /// Generates a login URL requesting the scopes already granted to a character along with the missing required scopes
+    ///
+    /// Returns `None` if all required scopes are granted. The PKCE flow is used if the client has no client secret,
+    /// handle the state & code verifier of the returned data like any other login.
+    ///
+    /// ```no_run
+    /// # fn example(client: eve_oauth2::EveSsoClient, tokens: eve_oauth2::models::EveTokens) {
+    /// let required = vec!["esi-wallet.read_character_wallet.v1".to_string()];
+    ///
+    /// if let Some(login) = client.create_incremental_login_url(
+    ///     "http://localhost:8000/callback".to_string(),
+    ///     &tokens.scopes,
+    ///     &required,
+    /// ) {
+    ///     // Redirect the user to login.login_url & store login.state
+    /// }
+    /// # }
+    /// ```
+    ///
+    /// Panics if a scope is unknown & [`ScopeCheck::Deny`] is set, see
+    /// [`EveSsoClient::try_create_incremental_login_url`].
+    pub fn create_incremental_login_url(
+        &self,
+        redirect_url: impl AsRef<str>,
+        granted_scopes: &[String],
+        required_scopes: &[String],
+    ) -> Option<AuthenticationData> {
+        self.try_create_incremental_login_url(redirect_url, granted_scopes, required_scopes)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Generates a login URL requesting the scopes already granted to a character along with the missing required scopes
+    ///
+    /// Returns `None` if all required scopes are granted, fails with [`Error::Config`] if a scope is unknown &
+    /// [`ScopeCheck::Deny`] is set.
+    pub fn try_create_incremental_login_url(
+        &self,
+        redirect_url: impl AsRef<str>,
+        granted_scopes: &[String],
+        required_scopes: &[String],
+    ) -> Result<Option<AuthenticationData>, Error> {
+        let missing = missing_scopes(granted_scopes, required_scopes);
+
+        if missing.is_empty() {
+            return Ok(None);
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(missing = ?missing, "Requesting missing scopes");
+
+        let mut scopes = granted_scopes.to_vec();
+        scopes.extend(missing);
+
+        match self.client_secret {
+            Some(_) => self.try_create_login_url(redirect_url, scopes),
+            None => self.try_create_login_url_pkce(redirect_url, scopes),
+        }
+        .map(Some)
+    }
+
+    /// Retrieves the access token using the code from the callback, see [`crate::get_access_token`]
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(client_id = %self.client_id)))]
+    pub async fn get_access_token(
+        &self,
+        code: impl Into<String>,
+    ) -> Result<EveTokenResponse, Error> {
+        let http_client = TokenHttpClient::new(self);
+
+        let token = self
+            .oauth_client(self.client_secret.as_ref())
+            .exchange_code(AuthorizationCode::new(code.into()))
+            .request_async(&http_client)
+            .await
+            .map(EveTokenResponse::from_oauth2)
+            .map_err(|err| Error::from_token_request(err, |err| err, http_client.status));
+
+        #[cfg(feature = "tracing")]
+        trace_token_result("authorization code exchange", &token);
+
+        metrics::record(Operation::CodeExchange, &token);
+
+        self.audit_token(
+            AuditEventKind::Login,
+            AuditEventKind::LoginFailed,
+            &token,
+            None,
+        );
+
+        if let Ok(token) = &token {
+            self.emit(TokenEvent::Obtained(token));
+        }
+
+        token
+    }
+
+    /// Retrieves the access token using the code from the callback & the PKCE code verifier, see [`crate::get_access_token_pkce`]
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(client_id = %self.client_id)))]
+    pub async fn get_access_token_pkce(
+        &self,
+        code: impl Into<String>,
+        code_verifier: impl Into<String>,
+    ) -> Result<EveTokenResponse, Error> {
+        let http_client = TokenHttpClient::new(self);
+
+        let token = self
+            .oauth_client(None)
+            .exchange_code(AuthorizationCode::new(code.into()))
+            .set_pkce_verifier(PkceCodeVerifier::new(code_verifier.into()))
+            .request_async(&http_client)
+            .await
+            .map(EveTokenResponse::from_oauth2)
+            .map_err(|err| Error::from_token_request(err, |err| err, http_client.status));
+
+        #[cfg(feature = "tracing")]
+        trace_token_result("PKCE authorization code exchange", &token);
+
+        metrics::record(Operation::CodeExchange, &token);
+
+        self.audit_token(
+            AuditEventKind::Login,
+            AuditEventKind::LoginFailed,
+            &token,
+            None,
+        );
+
+        if let Ok(token) = &token {
+            self.emit(TokenEvent::Obtained(token));
+        }
+
+        token
+    }
+
+    /// Retrieves a new access token using a refresh token, see [`crate::refresh`]
+    pub async fn refresh(
+        &self,
+        refresh_token: impl Into<String>,
+    ) -> Result<EveTokenResponse, Error> {
+        self.refresh_with_scopes(refresh_token, Vec::<String>::new())
+            .await
+    }
+
+    /// Retrieves a new access token limited to a subset of the originally granted scopes, see [`crate::refresh_with_scopes`]
+    ///
+    /// All originally granted scopes are requested if scopes is empty.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(client_id = %self.client_id)))]
+    pub async fn refresh_with_scopes(
+        &self,
+        refresh_token: impl Into<String>,
+        scopes: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Result<EveTokenResponse, Error> {
+        self.exchange_refresh_token(refresh_token.into(), convert_scopes(scopes), None)
+            .await
+    }
+
+    /// Exchanges the refresh token, character_id is audited if the refresh fails
+    async fn exchange_refresh_token(
+        &self,
+        refresh_token: String,
+        scopes: Vec<oauth2::Scope>,
+        character_id: Option<u64>,
+    ) -> Result<EveTokenResponse, Error> {
+        let http_client = TokenHttpClient::new(self);
+
+        let token = self
+            .oauth_client(self.client_secret.as_ref())
+            .exchange_refresh_token(&RefreshToken::new(refresh_token))
+            .add_scopes(scopes)
+            .request_async(&http_client)
+            .await
+            .map(EveTokenResponse::from_oauth2)
+            .map_err(|err| Error::from_token_request(err, |err| err, http_client.status));
+
+        #[cfg(feature = "tracing")]
+        trace_token_result("refresh token exchange", &token);
+
+        metrics::record(Operation::Refresh, &token);
+
+        self.audit_token(
+            AuditEventKind::Refreshed,
+            AuditEventKind::RefreshFailed,
+            &token,
+            character_id,
+        );
+
+        match &token {
+            Ok(token) => self.emit(TokenEvent::Refreshed(token)),
+            Err(err) => self.emit(TokenEvent::RefreshFailed(err)),
+        }
+
+        token
+    }
+
+    /// Revokes a refresh token at the revocation endpoint of EVE Online SSO, e.g. when a user logs out or unlinks a
+    /// character
+    ///
+    /// The revocation endpoint is looked up from the metadata if the client's endpoints don't set one. Access tokens
+    /// issued with the refresh token stay valid until they expire.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(client_id = %self.client_id)))]
+    pub async fn revoke_token(&self, refresh_token: impl Into<String>) -> Result<(), Error> {
+        let refresh_token = refresh_token.into();
+        let url = match &self.endpoints.revocation_endpoint {
+            Some(url) => url.clone(),
+            None => self
+                .metadata()
+                .await?
+                .revocation_endpoint
+                .ok_or_else(|| {
+                    Error::UnexpectedResponse(
+                        "EVE SSO metadata has no revocation endpoint".to_string(),
+                    )
+                })
+                .and_then(|url| {
+                    Url::parse(&url).map_err(|err| Error::UnexpectedResponse(err.to_string()))
+                })?,
+        };
+
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/x-www-form-urlencoded"),
+        );
+
+        let mut body = url::form_urlencoded::Serializer::new(String::new());
+        body.append_pair("token_type_hint", "refresh_token");
+        body.append_pair("token", &refresh_token);
+
+        match (&self.client_secret, self.token_auth_method) {
+            (Some(client_secret), TokenAuthMethod::ClientSecretBasic) => {
+                let credentials = format!(
+                    "{}:{}",
+                    form_urlencode(&self.client_id),
+                    form_urlencode(client_secret.expose_secret())
+                );
+                let mut authorization =
+                    HeaderValue::from_str(&format!("Basic {}", STANDARD.encode(credentials)))
+                        .map_err(|err| Error::UnexpectedResponse(err.to_string()))?;
+                authorization.set_sensitive(true);
+
+                headers.insert(AUTHORIZATION, authorization);
+            }
+            (Some(client_secret), TokenAuthMethod::ClientSecretPost) => {
+                body.append_pair("client_id", &self.client_id);
+                body.append_pair("client_secret", client_secret.expose_secret());
+            }
+            (None, _) => {
+                body.append_pair("client_id", &self.client_id);
+            }
+        }
+
+        let request = HttpRequest {
+            url,
+            method: Method::POST,
+            headers,
+            body: body.finish().into_bytes(),
+        };
+
+        let response = self.execute(request, Endpoint::Revoke).await?;
+
+        if response.status_code.is_success() {
+            return Ok(());
+        }
+
+        #[derive(Deserialize)]
+        struct ErrorResponse {
+            error: String,
+            error_description: Option<String>,
+        }
+
+        let status = Error::from_status(response.status_code, &response.headers, &response.body);
+        let err = match serde_json::from_slice::<ErrorResponse>(&response.body) {
+            Ok(error_response) => {
+                status.into_sso(error_response.error, error_response.error_description)
+            }
+            Err(_) => status,
+        };
+
+        #[cfg(feature = "tracing")]
+        tracing::warn!(error = %err, "EVE SSO token revocation failed");
+
+        Err(err)
+    }
+
+    /// Validates an access token & returns the EVE JWT claims, see [`crate::validate_token`]
+    ///
+    /// The `azp` claim must match the client_id of this client, use [`EveSsoClient::validate_token_with`] &
+    /// [`TokenValidation::allow_any_client_id`] to accept tokens issued to other EVE applications.
+    pub async fn validate_token(
+        &self,
+        token: impl Into<String>,
+    ) -> Result<TokenData<EveJwtClaims>, Error> {
+        self.validate_token_with(token, &TokenValidation::default())
+            .await
+    }
+
+    /// Validates an access token & applies the additional checks of the validation options, see [`crate::validate_token_with`]
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub async fn validate_token_with(
+        &self,
+        token: impl Into<String>,
+        validation: &TokenValidation,
+    ) -> Result<TokenData<EveJwtClaims>, Error> {
+        let token = token.into();
+        let validation = validation
+            .with_default_client_id(&self.client_id)
+            .into_owned();
+
+        #[cfg(feature = "token-cache")]
+        if let Some(token_data) = self
+            .token_cache
+            .as_ref()
+            .and_then(|cache| cache.get(&token))
+        {
+            #[cfg(feature = "tracing")]
+            tracing::trace!("Using cached validated token");
+
+            let result = validation.check(&token_data.claims).map(|()| token_data);
+
+            metrics::record(Operation::Validation, &result);
+
+            if let Err(err) = &result {
+                self.audit_validation_failure(&token, err);
+            }
+
+            return result;
+        }
+
+        #[cfg(feature = "token-cache")]
+        let cache = self.token_cache.clone();
+
+        let result = match self.get_eve_jwt_keys().await {
+            Ok(keys) => {
+                let token = token.clone();
+
+                self.run_validation(move || {
+                    let result = decode_token(&token, keys.keys, &validation);
+
+                    #[cfg(feature = "token-cache")]
+                    if let (Some(cache), Ok(token_data)) = (&cache, &result) {
+                        cache.insert(&token, token_data);
+                    }
+
+                    result
+                })
+                .await
+                .and_then(|result| result)
+            }
+            Err(err) => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(error = %err, "Failed to retrieve EVE JWT keys");
+
+                Err(err)
+            }
+        };
+
+        metrics::record(Operation::Validation, &result);
+
+        if let Err(err) = &result {
+            self.audit_validation_failure(&token, err);
+        }
+
+        result
+    }
+
+    /// Validates a batch of access tokens with a single retrieval of the EVE JWT keys & returns the result of each token
+    /// in order
+    ///
+    /// Meant for bursts of tokens, e.g. when many clients reconnect at once. With
+    /// [`EveSsoClient::blocking_validation`] enabled the tokens are validated in parallel on tokio's blocking thread
+    /// pool, otherwise one after another on the calling task. Fails as a whole only if the EVE JWT keys can't be
+    /// retrieved or a validation task fails.
+    ///
+    /// ```no_run
+    /// # async fn example(client: eve_oauth2::EveSsoClient, tokens: Vec<String>) -> Result<(), eve_oauth2::Error> {
+    /// for (token, result) in tokens.iter().zip(client.validate_tokens(&tokens).await?) {
+    ///     match result {
+    ///         Ok(token_data) => { /* Accept the connection of token_data.claims.sub */ }
+    ///         Err(err) => { /* Reject the connection */ }
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn validate_tokens(
+        &self,
+        tokens: &[String],
+    ) -> Result<Vec<Result<TokenData<EveJwtClaims>, Error>>, Error> {
+        self.validate_tokens_with(tokens, &TokenValidation::default())
+            .await
+    }
+
+    /// Validates a batch of access tokens & applies the additional checks of the validation options to each, see
+    /// [`EveSsoClient::validate_tokens`]
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(tokens = tokens.len())))]
+    pub async fn validate_tokens_with(
+        &self,
+        tokens: &[String],
+        validation: &TokenValidation,
+    ) -> Result<Vec<Result<TokenData<EveJwtClaims>, Error>>, Error> {
+        let validation = validation
+            .with_default_client_id(&self.client_id)
+            .into_owned();
+
+        let results = match self.get_eve_jwt_keys().await {
+            Ok(keys) => match decoding_key(keys.keys) {
+                Ok(key) => self.run_batch_validation(tokens, key, validation).await,
+                Err(err) => Err(err),
+            },
+            Err(err) => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(error = %err, "Failed to retrieve EVE JWT keys");
+
+                Err(err)
+            }
+        };
+
+        // Every token counts as one validation, whether it failed on its own or with the whole batch
+        match &results {
+            Ok(results) => {
+                for (token, result) in tokens.iter().zip(results) {
+                    metrics::record(Operation::Validation, result);
+
+                    if let Err(err) = result {
+                        self.audit_validation_failure(token, err);
+                    }
+                }
+            }
+            Err(err) => {
+                for token in tokens {
+                    metrics::record(Operation::Validation, &results);
+                    self.audit_validation_failure(token, err);
+                }
+            }
+        }
+
+        results
+    }
+
+    /// Verifies the signatures of tokens on tokio's blocking thread pool with `spawn_blocking` instead of on the async
+    /// executor, enabled with the `tokio` feature, disabled by default
+    ///
+    /// RSA signature verification is CPU-bound, enable this when validating many tokens to keep it from delaying other
+    /// tasks on the executor. Validation must then run inside a tokio runtime.
+    #[cfg(feature = "tokio")]
+    pub fn blocking_validation(mut self, enabled: bool) -> Self {
+        self.blocking_validation = enabled;
+        self
+    }
+
+    /// Returns the claims of tokens validated before from the cache instead of verifying their signature again, enabled
+    /// with the `token-cache` feature, see [`TokenCache`]
+    ///
+    /// Only [`EveSsoClient::validate_token`] & the functions built on it use the cache, batch validation doesn't.
+    #[cfg(feature = "token-cache")]
+    pub fn token_cache(mut self, cache: TokenCache) -> Self {
+        self.token_cache = Some(cache);
+        self
+    }
+
+    /// Falls back to ESI's `/verify` endpoint in [`EveSsoClient::validate_character`] when the EVE JWT keys can't be
+    /// retrieved, e.g. during an outage of EVE Online SSO, disabled by default
+    ///
+    /// ESI only confirms the token is valid, the `azp` claim isn't checked so tokens issued to other EVE applications
+    /// are accepted.
+    pub fn esi_verify_fallback(mut self, enabled: bool) -> Self {
+        self.esi_verify_fallback = enabled;
+        self
+    }
+
+    /// Sets the base URL of ESI used by the `/verify` fallback & [`EveSsoClient::enrich_character`], e.g. to use a mock
+    /// server, `https://esi.evetech.net/` by default
+    pub fn esi_url(mut self, mut url: Url) -> Self {
+        if !url.path().ends_with('/') {
+            url.set_path(&format!("{}/", url.path()));
+        }
+
+        self.esi_url = url;
+        self
+    }
+
+    /// URL of the ESI route relative to [`EveSsoClient::esi_url`]
+    pub(crate) fn esi_route(&self, route: &str) -> Result<Url, Error> {
+        self.esi_url
+            .join(route)
+            .map_err(|err| Error::Config(format!("Invalid ESI URL {}: {}", self.esi_url, err)))
+    }
+
+    /// Validates an access token & returns the character it was issued for
+    ///
+    /// Falls back to [`EveSsoClient::verify_with_esi`] if the EVE JWT keys can't be retrieved & the fallback is
+    /// enabled with [`EveSsoClient::esi_verify_fallback`].
+    pub async fn validate_character(&self, token: impl Into<String>) -> Result<Character, Error> {
+        let token = token.into();
+
+        let err = match self.validate_token(token.clone()).await {
+            Ok(token_data) => return Character::try_from(token_data.claims),
+            Err(err) => err,
+        };
+
+        if !self.esi_verify_fallback
+            || !matches!(
+                err.classify(),
+                ErrorClass::Transient | ErrorClass::RateLimited
+            )
+        {
+            return Err(err);
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::warn!(error = %err, "Failed to retrieve EVE JWT keys, verifying the token with ESI");
+
+        self.verify_with_esi(token).await
+    }
+
+    /// Verifies an access token with ESI's `/verify` endpoint instead of validating its signature
+    ///
+    /// Sends a request to ESI for every token & only checks that ESI accepts it, prefer
+    /// [`EveSsoClient::validate_token`] which validates tokens locally.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub async fn verify_with_esi(&self, token: impl AsRef<str>) -> Result<Character, Error> {
+        let mut authorization = HeaderValue::from_str(&format!("Bearer {}", token.as_ref()))
+            .map_err(|_| Error::InvalidToken(ErrorKind::InvalidToken.into()))?;
+        authorization.set_sensitive(true);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, authorization);
+
+        let result = self
+            .get_json_with_headers::<EsiVerifyResponse>(
+                self.esi_route("verify/")?.as_str(),
+                headers,
+                Endpoint::Verify,
+            )
+            .await
+            .and_then(Character::try_from);
+
+        metrics::record(Operation::Validation, &result);
+
+        result
+    }
+
+    /// Refreshes the stored tokens of a character & saves the new token pair to the store
+    ///
+    /// EVE Online SSO rotates the refresh token, so the new pair is saved before its access token is validated & a
+    /// failed validation, e.g. while the EVE JWT keys can't be retrieved, doesn't lose the only usable refresh token.
+    /// The validation error is returned with the new pair stored. The returned tokens contain the scopes of the new
+    /// token. If the stored tokens were replaced while refreshing, e.g. by another instance of your application, the
+    /// replaced tokens are returned instead.
+    pub async fn refresh_stored(
+        &self,
+        store: &impl TokenStore,
+        character_id: u64,
+    ) -> Result<EveTokens, Error> {
+        let previous = store
+            .get(character_id)
+            .await?
+            .ok_or(Error::CharacterNotFound(character_id))?;
+
+        let token = self
+            .exchange_refresh_token(
+                previous.refresh_token.expose_secret().to_string(),
+                Vec::new(),
+                Some(character_id),
+            )
+            .await?;
+        let unverified = unverified_tokens(&token, &previous)?;
+
+        if !store
+            .replace(unverified.clone(), previous.refresh_token.into_secret())
+            .await?
+        {
+            return store
+                .get(character_id)
+                .await?
+                .ok_or(Error::CharacterNotFound(character_id));
+        }
+
+        let tokens = self.validate_token_response(&token).await?;
+
+        if tokens != unverified {
+            store
+                .replace(tokens.clone(), unverified.refresh_token.into_secret())
+                .await?;
+        }
+
+        Ok(tokens)
+    }
+
+    /// Validates the access token of a token response & creates the [`EveTokens`] to store for the character
+    pub async fn validate_token_response(
+        &self,
+        token: &EveTokenResponse,
+    ) -> Result<EveTokens, Error> {
+        let token_data = self.validate_token(token.access_token()).await?;
+
+        EveTokens::new(token, &token_data.claims)
+    }
+
+    /// Creates a login URL whose state starts with the prefix, using PKCE if the client has no client secret
+    pub(crate) fn create_prefixed_login_url(
+        &self,
+        prefix: &str,
+        redirect_url: impl AsRef<str>,
+        scopes: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Result<AuthenticationData, Error> {
+        let state = CsrfToken::new(format!("{}{}", prefix, self.new_state().secret()));
+        let scopes = self.checked_scopes(scopes)?;
+
+        Ok(match self.client_secret {
+            Some(_) => crate::login_url(
+                self.oauth_client(self.client_secret.as_ref()),
+                redirect_url.as_ref(),
+                scopes,
+                state,
+            ),
+            None => crate::login_url_pkce(
+                self.oauth_client(None),
+                redirect_url.as_ref(),
+                scopes,
+                state,
+            ),
+        })
+    }
+
+    /// Creates a login URL with the extra query parameters, using PKCE if the client has no client secret unless
+    /// pkce is set
+    pub(crate) fn login_url_with(
+        &self,
+        redirect_url: &str,
+        scopes: Vec<String>,
+        pkce: Option<bool>,
+        extra_params: &[(String, String)],
+    ) -> Result<AuthenticationData, Error> {
+        let pkce = pkce.unwrap_or(self.client_secret.is_none());
+        let client_secret = if pkce {
+            None
+        } else {
+            self.client_secret.as_ref()
+        };
+
+        Ok(crate::login_url_with(
+            self.oauth_client(client_secret),
+            redirect_url,
+            self.checked_scopes(scopes)?,
+            self.new_state(),
+            pkce,
+            extra_params,
+        ))
+    }
+
+    /// Collects the scopes of a login URL & applies the scope check
+    fn checked_scopes(
+        &self,
+        scopes: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Result<ScopeSet, Error> {
+        let scopes = scopes.into_iter().collect();
+        check_scopes(self.scope_check, &scopes)?;
+
+        Ok(scopes)
+    }
+
+    fn require_state_signer(&self) -> Result<&StateSigner, Error> {
+        self.state_signer
+            .as_ref()
+            .ok_or_else(|| Error::InvalidState("No state signer is set".to_string()))
+    }
+
+    fn new_state(&self) -> CsrfToken {
+        match (&self.state_signer, &self.state_generator) {
+            (Some(state_signer), _) => CsrfToken::new(state_signer.sign()),
+            (None, Some(generator)) => CsrfToken::new(generator()),
+            (None, None) => CsrfToken::new_random(),
+        }
+    }
+
+    /// Creates the `oauth2` client, exposing the client secret for the request it's created for
+    fn oauth_client(&self, client_secret: Option<&SecretString>) -> EveOAuthClient {
+        let auth_type = match self.token_auth_method {
+            TokenAuthMethod::ClientSecretBasic => AuthType::BasicAuth,
+            TokenAuthMethod::ClientSecretPost => AuthType::RequestBody,
+        };
+
+        eve_client_with(
+            &self.endpoints,
+            self.client_id.clone(),
+            client_secret.map(|client_secret| client_secret.expose_secret().to_string()),
+        )
+        .set_auth_type(auth_type)
+    }
+
+    /// Runs the validation on the blocking thread pool if [`EveSsoClient::blocking_validation`] is enabled
+    async fn run_validation<T: Send + 'static>(
+        &self,
+        validate: impl FnOnce() -> T + Send + 'static,
+    ) -> Result<T, Error> {
+        #[cfg(feature = "tokio")]
+        if self.blocking_validation {
+            return spawn_validation(validate).await;
+        }
+
+        Ok(validate())
+    }
+
+    /// Validates the tokens in chunks spread over tokio's blocking thread pool with
+    /// [`EveSsoClient::blocking_validation`], otherwise on the calling task
+    async fn run_batch_validation(
+        &self,
+        tokens: &[String],
+        key: DecodingKey,
+        validation: TokenValidation,
+    ) -> Result<Vec<Result<TokenData<EveJwtClaims>, Error>>, Error> {
+        #[cfg(feature = "tokio")]
+        if self.blocking_validation {
+            let threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+            let chunk_size = tokens.len().div_ceil(threads).max(MIN_TOKENS_PER_TASK);
+            let (key, validation) = (Arc::new(key), Arc::new(validation));
+
+            let tasks: Vec<_> = tokens
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    let (chunk, key, validation) =
+                        (chunk.to_vec(), key.clone(), validation.clone());
+
+                    spawn_validation(move || decode_tokens(&chunk, &key, &validation))
+                })
+                .collect();
+
+            let mut results = Vec::with_capacity(tokens.len());
+            for task in tasks {
+                results.extend(task.await?);
+            }
+
+            return Ok(results);
+        }
+
+        Ok(decode_tokens(tokens, &key, &validation))
+    }
+
+    /// Retrieves the EVE JWT keys, falling back to [`EveSsoClient::fallback_jwt_keys`] if that fails
+    async fn get_eve_jwt_keys(&self) -> Result<EveJwtKeys, Error> {
+        match (self.fetch_eve_jwt_keys().await, &self.fallback_jwt_keys) {
+            (Err(_err), Some(keys)) => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(error = %_err, "Failed to retrieve EVE JWT keys, using fallback keys");
+
+                Ok(keys.clone())
+            }
+            (result, _) => result,
+        }
+    }
+
+    async fn fetch_eve_jwt_keys(&self) -> Result<EveJwtKeys, Error> {
+        let jwks_uri = match &self.endpoints.jwks_uri {
+            Some(jwks_uri) => jwks_uri.to_string(),
+            None => self.metadata().await?.jwks_uri,
+        };
+
+        if let Some(keys) = cached_eve_jwt_keys(&jwks_uri) {
+            #[cfg(feature = "tracing")]
+            tracing::trace!("Using cached EVE JWT keys");
+
+            return Ok(keys);
+        }
+
+        let keys: EveJwtKeys = self.get_json(&jwks_uri, Endpoint::Jwks).await?;
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(jwks_uri = %jwks_uri, keys = keys.keys.len(), "Retrieved EVE JWT keys");
+
+        cache_eve_jwt_keys(&jwks_uri, &keys);
+
+        Ok(keys)
+    }
+
+    async fn get_json<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        endpoint: Endpoint,
+    ) -> Result<T, Error> {
+        self.get_json_with_headers(url, HeaderMap::new(), endpoint)
+            .await
+    }
+
+    async fn get_json_with_headers<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        mut headers: HeaderMap,
+        endpoint: Endpoint,
+    ) -> Result<T, Error> {
+        headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
+
+        let request = HttpRequest {
+            url: Url::parse(url).map_err(|err| Error::UnexpectedResponse(err.to_string()))?,
+            method: Method::GET,
+            headers,
+            body: Vec::new(),
+        };
+
+        let response = self.execute(request, endpoint).await?;
+
+        if !response.status_code.is_success() {
+            return Err(Error::from_status(
+                response.status_code,
+                &response.headers,
+                &response.body,
+            ));
+        }
+
+        serde_json::from_slice(&response.body)
+            .map_err(|err| Error::unexpected_body(err, &response.body))
+    }
+
+    /// Records an audit event
+    pub(crate) fn audit(
+        &self,
+        kind: AuditEventKind,
+        character_id: Option<u64>,
+        error: Option<&Error>,
+    ) {
+        self.audit_sink
+            .record(&AuditEvent::new(kind, character_id, error));
+    }
+
+    /// Records the audit event of a token exchange, reading the character id from a returned access token
+    fn audit_token(
+        &self,
+        success: AuditEventKind,
+        failure: AuditEventKind,
+        token: &Result<EveTokenResponse, Error>,
+        character_id: Option<u64>,
+    ) {
+        match token {
+            Ok(token) => self.audit(
+                success,
+                character_id_unverified(token.access_token()).or(character_id),
+                None,
+            ),
+            Err(err) => self.audit(failure, character_id, Some(err)),
+        }
+    }
+
+    fn audit_validation_failure(&self, token: &str, err: &Error) {
+        self.audit(
+            AuditEventKind::ValidationFailed,
+            character_id_unverified(token),
+            Some(err),
+        );
+    }
+
+    fn emit(&self, event: TokenEvent<'_>) {
+        for hook in &self.token_event_hooks {
+            hook(&event);
+        }
+    }
+
+    /// Sends a request using the HTTP client with the configured User-Agent & rate limit, retrying it as the retry
+    /// policy decides
+    pub(crate) fn execute(
+        &self,
+        mut request: HttpRequest,
+        endpoint: Endpoint,
+    ) -> BoxFuture<'_, Result<HttpResponse, HttpError>> {
+        if let Some(user_agent) = &self.user_agent {
+            request.headers.insert(USER_AGENT, user_agent.clone());
+        }
+
+        Box::pin(async move {
+            let mut attempt = 1;
+
+            loop {
+                #[cfg(feature = "tokio")]
+                if let Some(rate_limiter) = &self.rate_limiter {
+                    rate_limiter.acquire().await;
+                }
+
+                let result =
+                    metrics::timed(endpoint, self.http_client.execute(request.clone())).await;
+
+                match self
+                    .retry_policy
+                    .retry_delay(endpoint, attempt, result.as_ref())
+                {
+                    Some(delay) => self.retry_policy.sleep(delay).await,
+                    None => return result,
+                }
+
+                attempt += 1;
+            }
+        })
+    }
+}
+
+/// Sends the token requests of `oauth2` using the client's [`HttpClient`] & records the status of unsuccessful
+/// responses for [`Error::from_token_request`]
+struct TokenHttpClient<'a> {
+    client: &'a EveSsoClient,
+    status: ResponseStatus,
+}
+
+impl<'a> TokenHttpClient<'a> {
+    fn new(client: &'a EveSsoClient) -> Self {
+        Self {
+            client,
+            status: ResponseStatus::default(),
+        }
+    }
+}
+
+impl<'c> AsyncHttpClient<'c> for TokenHttpClient<'_> {
+    type Error = HttpError;
+    type Future = BoxFuture<'c, Result<oauth2::HttpResponse, HttpError>>;
+
+    fn call(&'c self, request: oauth2::HttpRequest) -> Self::Future {
+        Box::pin(async move {
+            let response = self
+                .client
+                .execute(HttpRequest::from_oauth2(request)?, Endpoint::Token)
+                .await;
+
+            self.status.record(&response);
+
+            response.map(HttpResponse::into_oauth2)
+        })
+    }
+}
+
+/// Spawns the validation on tokio's blocking thread pool right away, fails with [`Error::ValidationTask`] if the task
+/// panics or is cancelled
+#[cfg(feature = "tokio")]
+fn spawn_validation<T: Send + 'static>(
+    validate: impl FnOnce() -> T + Send + 'static,
+) -> impl std::future::Future<Output = Result<T, Error>> {
+    #[cfg(feature = "tracing")]
+    let span = tracing::Span::current();
+
+    let task = tokio::task::spawn_blocking(move || {
+        #[cfg(feature = "tracing")]
+        let _entered = span.enter();
+
+        validate()
+    });
+
+    async move {
+        task.await
+            .map_err(|err| Error::ValidationTask(err.to_string()))
+    }
+}
+
+/// Creates the tokens of a refresh response from the unverified claims of its access token, so they can be saved
+/// before the access token is validated
+///
+/// Falls back to the character, scopes & owner hash of the stored tokens if the access token can't be decoded, with
+/// `expires_at` set to 0 so the tokens are refreshed again before use.
+fn unverified_tokens(token: &EveTokenResponse, previous: &EveTokens) -> Result<EveTokens, Error> {
+    let tokens = decode_unverified(token.access_token())
+        .and_then(|token_data| EveTokens::new(token, &token_data.claims));
+
+    match tokens {
+        Ok(tokens) if tokens.character_id == previous.character_id => Ok(tokens),
+        _ => Ok(EveTokens {
+            character_id: previous.character_id,
+            access_token: token.access_token().into(),
+            refresh_token: token
+                .refresh_token()
+                .ok_or(Error::MissingRefreshToken)?
+                .into(),
+            expires_at: 0,
+            scopes: previous.scopes.clone(),
+            owner_hash: previous.owner_hash.clone(),
+        }),
+    }
+}
+
+/// Encodes a client credential for HTTP Basic authentication as required by RFC 6749
+fn form_urlencode(value: &str) -> String {
+    url::form_urlencoded::byte_serialize(value.as_bytes()).collect()
+}
+
+fn user_agent_header(app_name: &str, version: &str, contact: &str) -> HeaderValue {
+    let user_agent = format!(
+        "{}/{} ({}) eve_oauth2/{}",
+        header_safe(app_name),
+        header_safe(version),
+        header_safe(contact),
+        env!("CARGO_PKG_VERSION")
+    );
+
+    HeaderValue::from_str(&user_agent).expect("User-Agent contains only visible ASCII characters")
+}
+
+/// Percent-encodes all bytes except visible ASCII characters & spaces, so the value is valid in a header
+fn header_safe(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b' '..=b'~' if byte != b'%' => char::from(byte).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Reads the character id from the claims of an access token without validating it, for audit events
+fn character_id_unverified(token: &str) -> Option<u64> {
+    decode_unverified(token)
+        .ok()?
+        .claims
+        .character_id()
+        .ok()
+        .map(|character_id| character_id.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNKNOWN_SCOPE: &str = "esi-wallet.read_character_walet.v1";
+
+    fn denying_client() -> EveSsoClient {
+        EveSsoClient::builder("client_id")
+            .client_secret("client_secret")
+            .state_signer(StateSigner::new("0123456789abcdef0123456789abcdef"))
+            .scope_check(ScopeCheck::Deny)
+            .build()
+    }
+
+    #[test]
+    fn deny_fails_login_urls_with_unknown_scopes() {
+        let client = denying_client();
+        let redirect_url = "https://example.com/callback";
+
+        assert!(client
+            .try_create_login_url(redirect_url, ["publicData"])
+            .is_ok());
+        assert!(matches!(
+            client.try_create_login_url(redirect_url, [UNKNOWN_SCOPE]),
+            Err(Error::Config(_))
+        ));
+        assert!(matches!(
+            client.try_create_login_url_pkce(redirect_url, [UNKNOWN_SCOPE]),
+            Err(Error::Config(_))
+        ));
+        assert!(matches!(
+            client
+                .login_url_builder(redirect_url)
+                .scope(UNKNOWN_SCOPE)
+                .try_build(),
+            Err(Error::Config(_))
+        ));
+        assert!(matches!(
+            client.create_login_url_with_payload(redirect_url, [UNKNOWN_SCOPE], &"payload"),
+            Err(Error::Config(_))
+        ));
+    }
+
+    #[test]
+    fn incremental_login_urls_request_granted_and_missing_scopes() {
+        let client = denying_client();
+        let redirect_url = "https://example.com/callback";
+        let granted = vec!["publicData".to_string()];
+        let wallet = "esi-wallet.read_character_wallet.v1".to_string();
+
+        assert!(client
+            .try_create_incremental_login_url(redirect_url, &granted, &granted)
+            .unwrap()
+            .is_none());
+
+        let login = client
+            .try_create_incremental_login_url(redirect_url, &granted, std::slice::from_ref(&wallet))
+            .unwrap()
+            .unwrap();
+        let url = url::Url::parse(&login.login_url).unwrap();
+        let scope = url
+            .query_pairs()
+            .find(|(name, _)| name == "scope")
+            .unwrap()
+            .1;
+        let mut scopes = scope.split(' ').collect::<Vec<_>>();
+        scopes.sort_unstable();
+        assert_eq!(scopes, [wallet.as_str(), "publicData"]);
+        assert!(login.code_verifier.is_none());
+
+        let public = EveSsoClient::builder("client_id").build();
+        let login = public
+            .try_create_incremental_login_url(redirect_url, &granted, &[wallet])
+            .unwrap()
+            .unwrap();
+        assert!(login.code_verifier.is_some());
+
+        assert!(matches!(
+            client.try_create_incremental_login_url(
+                redirect_url,
+                &granted,
+                &[UNKNOWN_SCOPE.to_string()]
+            ),
+            Err(Error::Config(_))
+        ));
+    }
+
+    /// HTTP client recording the requests it was sent & answering every request with the same response
+    #[derive(Clone)]
+    struct RecordingHttpClient {
+        requests: Arc<std::sync::Mutex<Vec<HttpRequest>>>,
+        status_code: crate::http::StatusCode,
+        body: &'static str,
+        unavailable_url: Option<&'static str>,
+    }
+
+    impl RecordingHttpClient {
+        fn new(status_code: crate::http::StatusCode, body: &'static str) -> Self {
+            Self {
+                requests: Arc::default(),
+                status_code,
+                body,
+                unavailable_url: None,
+            }
+        }
+
+        /// Answers requests to the URL with 503 Service Unavailable
+        fn unavailable(mut self, url: &'static str) -> Self {
+            self.unavailable_url = Some(url);
+            self
+        }
+
+        fn requests(&self) -> std::sync::MutexGuard<'_, Vec<HttpRequest>> {
+            self.requests.lock().unwrap()
+        }
+    }
+
+    impl HttpClient for RecordingHttpClient {
+        fn execute(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, HttpError>> {
+            let status_code = match self.unavailable_url {
+                Some(url) if request.url.as_str() == url => {
+                    crate::http::StatusCode::SERVICE_UNAVAILABLE
+                }
+                _ => self.status_code,
+            };
+            self.requests().push(request);
+
+            let mut headers = HeaderMap::new();
+            headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+
+            Box::pin(std::future::ready(Ok(HttpResponse {
+                status_code,
+                headers,
+                body: self.body.as_bytes().to_vec(),
+            })))
+        }
+    }
+
+    #[tokio::test]
+    async fn token_auth_method_selects_where_the_credentials_are_sent() {
+        for (token_auth_method, basic_auth) in [
+            (TokenAuthMethod::ClientSecretBasic, true),
+            (TokenAuthMethod::ClientSecretPost, false),
+        ] {
+            let http_client = RecordingHttpClient::new(
+                crate::http::StatusCode::BAD_REQUEST,
+                r#"{"error":"invalid_grant"}"#,
+            );
+            let client = EveSsoClient::builder("client_id")
+                .client_secret("client_secret")
+                .http_client(http_client.clone())
+                .token_auth_method(token_auth_method)
+                .build();
+
+            assert!(client.refresh("refresh_token").await.is_err());
+
+            let requests = http_client.requests();
+            let body = String::from_utf8(requests[0].body.clone()).unwrap();
+            assert_eq!(
+                requests[0].headers.contains_key(AUTHORIZATION),
+                basic_auth,
+                "{token_auth_method:?}"
+            );
+            assert_eq!(
+                body.contains("client_secret=client_secret"),
+                !basic_auth,
+                "{token_auth_method:?}"
+            );
+            assert!(body.contains("refresh_token=refresh_token"));
+        }
+    }
+
+    #[tokio::test]
+    async fn builder_settings_apply_to_token_requests() {
+        let http_client = RecordingHttpClient::new(
+            crate::http::StatusCode::BAD_REQUEST,
+            r#"{"error":"invalid_grant"}"#,
+        );
+        let client = EveSsoClient::builder("client_id")
+            .client_secret("client_secret")
+            .http_client(http_client.clone())
+            .user_agent("My Corp Tool", "1.0.0", "admin@example.com")
+            .token_auth_method(TokenAuthMethod::ClientSecretPost)
+            .endpoints(SsoEndpoints {
+                token_endpoint: Url::parse("https://sso.example.com/token").unwrap(),
+                ..SsoEndpoints::default()
+            })
+            .build();
+
+        client.get_access_token("code").await.unwrap_err();
+
+        let request = &http_client.requests()[0];
+        assert_eq!(request.url.as_str(), "https://sso.example.com/token");
+        assert!(request.headers[USER_AGENT]
+            .to_str()
+            .unwrap()
+            .starts_with("My Corp Tool/1.0.0 (admin@example.com) eve_oauth2/"));
+        let body = String::from_utf8(request.body.clone()).unwrap();
+        assert!(body.contains("client_secret=client_secret"), "{}", body);
+        assert!(body.contains("code=code"), "{}", body);
+    }
+
+    #[test]
+    fn requests_are_sent_with_the_http_client_without_a_runtime() {
+        use std::future::Future;
+        use std::task::{Context, Poll, Waker};
+
+        let http_client = RecordingHttpClient::new(crate::http::StatusCode::OK, METADATA);
+        let client = EveSsoClient::with_http_client("client_id", None, http_client.clone())
+            .metadata_ttl(Duration::ZERO);
+
+        // The futures of the client only wait on the HttpClient, which completes immediately here
+        let metadata = std::pin::pin!(client.metadata());
+        let Poll::Ready(metadata) = metadata.poll(&mut Context::from_waker(Waker::noop())) else {
+            panic!("metadata is pending without a runtime");
+        };
+
+        assert_eq!(metadata.unwrap().jwks_uri, "https://sso.example.com/jwks");
+        assert_eq!(http_client.requests()[0].url.as_str(), SSO_META_DATA_URL);
+    }
+
+    const METADATA: &str = r#"{
+        "authorization_endpoint": "https://sso.example.com/authorize",
+        "jwks_uri": "https://sso.example.com/jwks",
+        "revocation_endpoint": "https://sso.example.com/revoke",
+        "token_endpoint": "https://sso.example.com/token"
+    }"#;
+
+    #[tokio::test]
+    async fn discovered_endpoints_are_used_for_login_urls() {
+        let http_client = RecordingHttpClient::new(crate::http::StatusCode::OK, METADATA);
+        let client = EveSsoClient::builder("client_id")
+            .http_client(http_client.clone())
+            .metadata_ttl(Duration::ZERO)
+            .build();
+        assert_eq!(client.endpoints(), &SsoEndpoints::default());
+
+        let client = client.discover_endpoints().await.unwrap();
+
+        assert_eq!(http_client.requests()[0].url.as_str(), SSO_META_DATA_URL);
+        assert_eq!(
+            client.endpoints().token_endpoint.as_str(),
+            "https://sso.example.com/token"
+        );
+        assert_eq!(
+            client.endpoints().jwks_uri.as_ref().map(Url::as_str),
+            Some("https://sso.example.com/jwks")
+        );
+        assert!(client
+            .create_login_url_pkce("https://example.com/callback", ["publicData"])
+            .login_url
+            .starts_with("https://sso.example.com/authorize?"));
+    }
+
+    #[tokio::test]
+    async fn discovering_invalid_endpoints_fails() {
+        let http_client = RecordingHttpClient::new(
+            crate::http::StatusCode::OK,
+            r#"{
+                "authorization_endpoint": "not a url",
+                "jwks_uri": "https://sso.example.com/jwks",
+                "token_endpoint": "https://sso.example.com/token"
+            }"#,
+        );
+        let client = EveSsoClient::builder("client_id")
+            .http_client(http_client)
+            .metadata_ttl(Duration::ZERO)
+            .build();
+
+        assert!(matches!(
+            client.discover_endpoints().await,
+            Err(Error::UnexpectedResponse(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn metadata_is_cached_for_the_ttl() {
+        let http_client = RecordingHttpClient::new(crate::http::StatusCode::OK, METADATA);
+        let uncached = EveSsoClient::builder("client_id")
+            .http_client(http_client.clone())
+            .metadata_ttl(Duration::ZERO)
+            .build();
+
+        uncached.metadata().await.unwrap();
+        uncached.metadata().await.unwrap();
+        assert_eq!(http_client.requests().len(), 2);
+
+        // The cache is shared by all clients, the metadata retrieved above is within the TTL of this client
+        let cached = EveSsoClient::builder("client_id")
+            .http_client(http_client.clone())
+            .metadata_ttl(Duration::from_secs(3600))
+            .build();
+
+        cached.metadata().await.unwrap();
+        assert_eq!(http_client.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn metadata_falls_back_to_the_fallback_url() {
+        const FALLBACK_URL: &str = "https://mirror.example.com/metadata";
+
+        let http_client = RecordingHttpClient::new(crate::http::StatusCode::OK, METADATA)
+            .unavailable(SSO_META_DATA_URL);
+        let client = EveSsoClient::builder("client_id")
+            .http_client(http_client.clone())
+            .metadata_ttl(Duration::ZERO)
+            .build();
+        assert!(client.metadata().await.is_err());
+
+        let client = client.fallback_metadata_url(FALLBACK_URL);
+        let metadata = client.metadata().await.unwrap();
+
+        assert_eq!(metadata.jwks_uri, "https://sso.example.com/jwks");
+        let urls: Vec<_> = http_client
+            .requests()
+            .iter()
+            .map(|request| request.url.to_string())
+            .collect();
+        assert_eq!(urls, [SSO_META_DATA_URL, SSO_META_DATA_URL, FALLBACK_URL]);
+    }
+
+    #[tokio::test]
+    async fn metadata_fails_with_the_well_known_error_if_the_fallback_fails() {
+        let http_client = RecordingHttpClient::new(crate::http::StatusCode::OK, "{}")
+            .unavailable(SSO_META_DATA_URL);
+        let client = EveSsoClient::builder("client_id")
+            .http_client(http_client.clone())
+            .metadata_ttl(Duration::ZERO)
+            .fallback_metadata_url("https://mirror.example.com/metadata")
+            .build();
+
+        let err = client.metadata().await.unwrap_err();
+
+        assert!(
+            matches!(err, Error::Status { status: 503, .. }),
+            "{:?}",
+            err
+        );
+        assert_eq!(http_client.requests().len(), 2);
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn validation_falls_back_to_the_jwt_key_snapshot() {
+        use crate::testing::TestTokenSigner;
+
+        const JWKS_URI: &str = "https://sso.example.com/fallback-jwks";
+
+        let signer = TestTokenSigner::shared();
+        let http_client =
+            RecordingHttpClient::new(crate::http::StatusCode::OK, "{}").unavailable(JWKS_URI);
+        let client = EveSsoClient::builder("client_id")
+            .http_client(http_client)
+            .endpoints(SsoEndpoints {
+                jwks_uri: Some(Url::parse(JWKS_URI).unwrap()),
+                ..SsoEndpoints::default()
+            })
+            .build();
+        let token = signer.token().client_id("client_id").sign();
+
+        assert!(client.validate_token(token.clone()).await.is_err());
+
+        let client = client.fallback_jwt_keys(signer.jwt_keys());
+        let token_data = client.validate_token(token).await.unwrap();
+        assert_eq!(token_data.claims.azp, "client_id");
+    }
+
+    #[test]
+    fn login_state_carries_a_signed_payload() {
+        let client = denying_client();
+        let payload = serde_json::json!({ "return_to": "/dashboard" });
+
+        let login = client
+            .create_login_url_with_payload("https://example.com/callback", ["publicData"], &payload)
+            .unwrap();
+        assert!(login.login_url.contains(&format!("state={}", login.state)));
+        assert_eq!(
+            client
+                .verify_signed_state_payload::<serde_json::Value>(&login.state)
+                .unwrap(),
+            payload
+        );
+
+        let unsigned = EveSsoClient::builder("client_id").build();
+        assert!(matches!(
+            unsigned.create_login_url_with_payload(
+                "https://example.com/callback",
+                ["publicData"],
+                &payload
+            ),
+            Err(Error::InvalidState(_))
+        ));
+        assert!(matches!(
+            unsigned.verify_signed_state_payload::<serde_json::Value>(&login.state),
+            Err(Error::InvalidState(_))
+        ));
+    }
+
+    #[test]
+    fn state_generator_creates_the_states_of_login_urls() {
+        let counter = std::sync::atomic::AtomicU64::new(0);
+        let client = EveSsoClient::builder("client_id")
+            .state_generator(move || {
+                format!(
+                    "state-{}",
+                    counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+                )
+            })
+            .build();
+
+        let login = client.create_login_url_pkce("https://example.com/callback", ["publicData"]);
+        assert_eq!(login.state, "state-0");
+        assert!(login.login_url.contains("state=state-0"));
+        assert_eq!(
+            client
+                .create_login_url_pkce("https://example.com/callback", ["publicData"])
+                .state,
+            "state-1"
+        );
+
+        // A state signer takes precedence over the generator
+        let client = client.state_signer(StateSigner::new("0123456789abcdef0123456789abcdef"));
+        let login = client.create_login_url_pkce("https://example.com/callback", ["publicData"]);
+        assert!(client.verify_signed_state(&login.state).is_ok());
+    }
+
+    #[test]
+    fn user_agent_percent_encodes_invalid_header_characters() {
+        let user_agent = user_agent_header("Flotte Übersicht", "1.0\r\n", "100%@example.com");
+
+        assert_eq!(
+            user_agent.to_str().unwrap(),
+            format!(
+                "Flotte %C3%9Cbersicht/1.0%0D%0A (100%25@example.com) eve_oauth2/{}",
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+        assert_eq!(
+            user_agent_header("My Corp Tool", "1.0.0", "admin@example.com"),
+            format!(
+                "My Corp Tool/1.0.0 (admin@example.com) eve_oauth2/{}",
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+    }
+
+    #[test]
+    fn warn_allows_login_urls_with_unknown_scopes() {
+        let client = EveSsoClient::builder("client_id")
+            .scope_check(ScopeCheck::Warn)
+            .build();
+
+        assert!(client
+            .try_create_login_url_pkce("https://example.com/callback", [UNKNOWN_SCOPE])
+            .is_ok());
+    }
+
+    #[cfg(all(feature = "testing", feature = "reqwest"))]
+    #[tokio::test]
+    async fn refresh_stored_keeps_the_rotated_refresh_token_when_validation_fails() {
+        use crate::store::MemoryTokenStore;
+        use crate::testing::MockSso;
+
+        let sso = MockSso::start().unwrap();
+        let client = sso.client("client_id");
+
+        let auth = client.create_login_url("http://localhost:8080/callback", ["publicData"]);
+        let (code, _) = sso
+            .authorize(&auth.login_url)
+            .unwrap()
+            .into_result()
+            .unwrap();
+        let token = client.get_access_token(code).await.unwrap();
+        let store = MemoryTokenStore::new();
+        store
+            .save(client.validate_token_response(&token).await.unwrap())
+            .await
+            .unwrap();
+
+        let mut endpoints = sso.endpoints();
+        endpoints.jwks_uri = Some(Url::parse(&format!("{}/oauth/unavailable", sso.url())).unwrap());
+        let failing = EveSsoClient::builder("client_id")
+            .client_secret("secret")
+            .endpoints(endpoints)
+            .build();
+
+        assert!(failing.refresh_stored(&store, 90000001).await.is_err());
+
+        let stored = store.get(90000001).await.unwrap().unwrap();
+        assert_ne!(
+            stored.refresh_token.expose_secret(),
+            token.refresh_token().unwrap()
+        );
+        assert_eq!(stored.scopes, ["publicData"]);
+
+        let refreshed = client.refresh_stored(&store, 90000001).await.unwrap();
+        assert_ne!(refreshed.refresh_token, stored.refresh_token);
+    }
+
+    #[cfg(all(feature = "testing", feature = "reqwest"))]
+    #[tokio::test]
+    async fn refresh_with_scopes_downgrades_the_access_token() {
+        use crate::testing::MockSso;
+
+        let sso = MockSso::start().unwrap();
+        let client = sso.client("client_id");
+        let scopes = ["publicData", "esi-wallet.read_character_wallet.v1"];
+
+        let auth = client.create_login_url("http://localhost:8080/callback", scopes);
+        let (code, _) = sso
+            .authorize(&auth.login_url)
+            .unwrap()
+            .into_result()
+            .unwrap();
+        let token = client.get_access_token(code).await.unwrap();
+
+        let downgraded = client
+            .refresh_with_scopes(token.refresh_token().unwrap(), ["publicData"])
+            .await
+            .unwrap();
+        let claims = client
+            .validate_token(downgraded.access_token())
+            .await
+            .unwrap()
+            .claims;
+        assert_eq!(claims.scopes().len(), 1);
+        assert!(claims.scopes().contains("publicData"));
+
+        // The rotated refresh token keeps the granted scopes
+        let refreshed = client
+            .refresh(downgraded.refresh_token().unwrap())
+            .await
+            .unwrap();
+        let claims = client
+            .validate_token(refreshed.access_token())
+            .await
+            .unwrap()
+            .claims;
+        assert_eq!(claims.scopes().len(), 2);
+
+        let err = client
+            .refresh_with_scopes(
+                refreshed.refresh_token().unwrap(),
+                ["esi-mail.read_mail.v1"],
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Sso { ref error, .. } if error == "invalid_scope"));
+    }
+
+    #[cfg(all(feature = "testing", feature = "tokio", feature = "reqwest"))]
+    #[tokio::test]
+    async fn blocking_validation_verifies_tokens_on_the_blocking_pool() {
+        use crate::testing::MockSso;
+
+        let sso = MockSso::start().unwrap();
+        let client = sso.client("client_id").blocking_validation(true);
+
+        let token = sso.signer().token().client_id("client_id").sign();
+        let token_data = client.validate_token(token).await.unwrap();
+        assert_eq!(token_data.claims.azp, "client_id");
+
+        let token = sso.signer().token().client_id("other_id").sign();
+        assert!(matches!(
+            client.validate_token(token).await,
+            Err(Error::InvalidClaims(_))
+        ));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn panicking_validation_tasks_fail_with_validation_task() {
+        let err = spawn_validation::<()>(|| panic!("validation panicked"))
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, Error::ValidationTask(_)));
+    }
+
+    #[cfg(all(feature = "testing", feature = "tokio", feature = "reqwest"))]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn blocking_batch_validation_keeps_token_order() {
+        use crate::testing::MockSso;
+
+        let sso = MockSso::start().unwrap();
+        let client = sso.client("client_id").blocking_validation(true);
+
+        let tokens: Vec<String> = (0..40)
+            .map(|i| match i % 3 {
+                0 => "invalid".to_string(),
+                _ => sso
+                    .signer()
+                    .token()
+                    .client_id("client_id")
+                    .character_id(i)
+                    .sign(),
+            })
+            .collect();
+
+        let results = client.validate_tokens(&tokens).await.unwrap();
+
+        assert_eq!(results.len(), tokens.len());
+        for (i, result) in results.into_iter().enumerate() {
+            match result {
+                Ok(token_data) => {
+                    assert_eq!(token_data.claims.character_id().unwrap().0, i as u64)
+                }
+                Err(_) => assert_eq!(i % 3, 0),
+            }
+        }
+    }
+
+    #[cfg(all(feature = "testing", feature = "reqwest"))]
+    #[tokio::test]
+    async fn audit_sink_records_logins_refreshes_and_failures() {
+        use crate::events::AuditEvent;
+        use crate::testing::MockSso;
+
+        let sso = MockSso::start().unwrap();
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let client = sso
+            .client("client_id")
+            .audit_sink(move |event: &AuditEvent| sink.lock().unwrap().push(event.clone()));
+
+        let login = client.create_login_url("http://localhost:8080/callback", ["publicData"]);
+        let (code, _) = sso
+            .authorize(&login.login_url)
+            .unwrap()
+            .into_result()
+            .unwrap();
+        let token = client.get_access_token(code.clone()).await.unwrap();
+        client.get_access_token(code).await.unwrap_err();
+        client
+            .refresh(token.refresh_token().unwrap())
+            .await
+            .unwrap();
+        client.refresh("unknown").await.unwrap_err();
+        let token = sso.signer().token().client_id("other_id").sign();
+        client.validate_token(token).await.unwrap_err();
+        client.validate_token("malformed").await.unwrap_err();
+
+        let events: Vec<_> = events
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|event| (event.kind, event.character_id, event.error))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                (AuditEventKind::Login, Some(90000001), None),
+                (AuditEventKind::LoginFailed, None, Some("sso")),
+                (AuditEventKind::Refreshed, Some(90000001), None),
+                (AuditEventKind::RefreshFailed, None, Some("sso")),
+                (
+                    AuditEventKind::ValidationFailed,
+                    Some(90000001),
+                    Some("invalid_claims")
+                ),
+                (
+                    AuditEventKind::ValidationFailed,
+                    None,
+                    Some("invalid_token")
+                ),
+            ]
+        );
+    }
+
+    #[cfg(all(feature = "testing", feature = "reqwest"))]
+    #[tokio::test]
+    async fn verify_callback_takes_the_state_from_the_store_once() {
+        use crate::state::MemoryStateStore;
+        use crate::testing::MockSso;
+
+        let sso = MockSso::start().unwrap();
+        let store = MemoryStateStore::new();
+
+        for client in [
+            sso.client("client_id"),
+            EveSsoClient::builder("client_id")
+                .endpoints(sso.endpoints())
+                .build(),
+        ] {
+            let login_url = client
+                .create_login_url_with_store(
+                    &store,
+                    "http://localhost:8080/callback",
+                    ["publicData"],
+                )
+                .await
+                .unwrap();
+            let (code, state) = sso.authorize(&login_url).unwrap().into_result().unwrap();
+
+            let token = client
+                .verify_callback(&store, code.clone(), state.clone())
+                .await
+                .unwrap();
+            assert!(client.validate_token_response(&token).await.is_ok());
+
+            assert!(matches!(
+                client.verify_callback(&store, code, state).await,
+                Err(Error::InvalidState(_))
+            ));
+        }
+    }
+
+    #[cfg(all(feature = "testing", feature = "reqwest"))]
+    #[tokio::test]
+    async fn token_event_hooks_are_called_for_every_lifecycle_event() {
+        use std::sync::Mutex;
+
+        use crate::testing::MockSso;
+
+        let sso = MockSso::start().unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let first = events.clone();
+        let second = events.clone();
+        let client = sso
+            .client("client_id")
+            .on_token_event(move |event| {
+                let event = match event {
+                    TokenEvent::Obtained(token) => format!("obtained {}", token.access_token()),
+                    TokenEvent::Refreshed(token) => format!("refreshed {}", token.access_token()),
+                    TokenEvent::RefreshFailed(err) => format!("failed {}", err.kind()),
+                };
+                first.lock().unwrap().push(event);
+            })
+            .on_token_event(move |_| second.lock().unwrap().push("second hook".to_string()));
+
+        let auth = client.create_login_url("http://localhost:8080/callback", ["publicData"]);
+        let (code, _) = sso
+            .authorize(&auth.login_url)
+            .unwrap()
+            .into_result()
+            .unwrap();
+        let token = client.get_access_token(code).await.unwrap();
+        let refreshed = client
+            .refresh(token.refresh_token().unwrap())
+            .await
+            .unwrap();
+        let err = client
+            .refresh(token.refresh_token().unwrap())
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                format!("obtained {}", token.access_token()),
+                "second hook".to_string(),
+                format!("refreshed {}", refreshed.access_token()),
+                "second hook".to_string(),
+                format!("failed {}", err.kind()),
+                "second hook".to_string(),
+            ]
+        );
+    }
+
+    #[cfg(all(feature = "tracing", feature = "testing", feature = "reqwest"))]
+    #[tokio::test]
+    async fn traces_token_requests_without_secrets() {
+        use std::fmt;
+        use std::sync::{Arc, Mutex};
+
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata, Subscriber};
+
+        use crate::testing::MockSso;
+
+        /// Subscriber recording the fields of all spans & events
+        #[derive(Clone, Default)]
+        struct Capture(Arc<Mutex<Vec<String>>>);
+
+        impl Visit for Capture {
+            fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("{}={:?}", field.name(), value));
+            }
+        }
+
+        impl Subscriber for Capture {
+            fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, span: &Attributes<'_>) -> Id {
+                span.record(&mut self.clone());
+                Id::from_u64(1)
+            }
+
+            fn record(&self, _span: &Id, values: &Record<'_>) {
+                values.record(&mut self.clone());
+            }
+
+            fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+            fn event(&self, event: &Event<'_>) {
+                event.record(&mut self.clone());
+            }
+
+            fn enter(&self, _span: &Id) {}
+
+            fn exit(&self, _span: &Id) {}
+        }
+
+        let sso = MockSso::start().unwrap();
+        let client = sso.client("client_id");
+        let auth = client.create_login_url("http://localhost:8080/callback", ["publicData"]);
+        let (code, _) = sso
+            .authorize(&auth.login_url)
+            .unwrap()
+            .into_result()
+            .unwrap();
+
+        let capture = Capture::default();
+        let guard = tracing::subscriber::set_default(capture.clone());
+        let token = client.get_access_token(code.clone()).await.unwrap();
+        let refreshed = client
+            .refresh(token.refresh_token().unwrap())
+            .await
+            .unwrap();
+        drop(guard);
+
+        let fields = capture.0.lock().unwrap();
+        assert!(fields.contains(&"client_id=client_id".to_string()));
+        assert!(fields
+            .iter()
+            .any(|field| field.contains("EVE SSO token request succeeded")));
+        assert!(fields.contains(&"operation=\"refresh token exchange\"".to_string()));
+
+        let secrets = [
+            code.as_str(),
+            token.access_token(),
+            token.refresh_token().unwrap(),
+            refreshed.access_token(),
+            refreshed.refresh_token().unwrap(),
+        ];
+        for field in fields.iter() {
+            assert!(
+                !secrets.iter().any(|secret| field.contains(secret)),
+                "{} leaks a secret",
+                field
+            );
+        }
+    }
+}
@@ -0,0 +1,503 @@
+use std::env::{self, VarError};
+use std::path::Path;
+#[cfg(feature = "toml")]
+use std::path::PathBuf;
+
+#[cfg(feature = "toml")]
+use serde::Deserialize;
+
+use crate::client::{EveSsoClient, EveSsoClientBuilder};
+use crate::error::Error;
+use crate::models::EveServer;
+use crate::secret::SecretString;
+use crate::validate_redirect_url;
+use crate::validation::TokenValidation;
+
+const CLIENT_ID_VAR: &str = "ESI_CLIENT_ID";
+const CLIENT_SECRET_VAR: &str = "ESI_CLIENT_SECRET";
+const CALLBACK_URL_VAR: &str = "ESI_CALLBACK_URL";
+const SCOPES_VAR: &str = "ESI_SCOPES";
+const SERVER_VAR: &str = "ESI_SERVER";
+
+/// Configuration of an EVE developer application
+///
+/// Usually loaded from the environment with [`EveSsoConfig::from_env`]:
+///
+/// ```no_run
+/// # fn example() -> Result<(), eve_oauth2::Error> {
+/// use eve_oauth2::EveSsoConfig;
+///
+/// let config = EveSsoConfig::from_env()?;
+/// let client = config.client();
+///
+/// let auth = client.create_login_url_pkce(&config.callback_url, &config.scopes);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EveSsoConfig {
+    pub client_id: String,
+    /// `None` for applications which only use the PKCE login flow
+    pub client_secret: Option<SecretString>,
+    /// Callback URL of your EVE developer application
+    pub callback_url: String,
+    pub scopes: Vec<String>,
+    /// Server tokens are required to be issued for, `None` accepts tokens of any server
+    pub server: Option<EveServer>,
+}
+
+impl EveSsoConfig {
+    /// Loads the configuration from environment variables
+    ///
+    /// - `ESI_CLIENT_ID`: client id, required
+    /// - `ESI_CLIENT_SECRET`: client secret, optional for the PKCE login flow
+    /// - `ESI_CALLBACK_URL`: callback URL, required & validated with [`validate_redirect_url`]
+    /// - `ESI_SCOPES`: scopes separated by spaces or commas, optional
+    /// - `ESI_SERVER`: `tranquility`, `singularity` or `serenity`, optional
+    ///
+    /// Fails with [`Error::Config`] naming the variable if a required variable is missing, a variable is set but
+    /// blank or the callback URL is invalid.
+    pub fn from_env() -> Result<Self, Error> {
+        Self::from_env_with_prefix("")
+    }
+
+    /// Loads the configuration from environment variables starting with the prefix, e.g. `RECRUITMENT_ESI_CLIENT_ID`
+    /// for the prefix `RECRUITMENT_`, see [`EveSsoConfig::from_env`]
+    pub fn from_env_with_prefix(prefix: &str) -> Result<Self, Error> {
+        PartialConfig::from_env(prefix)?.build(prefix, None)
+    }
+
+    /// Loads the configuration from a TOML file, overriding its values with the environment variables of
+    /// [`EveSsoConfig::from_env`], enabled with the `toml` feature
+    ///
+    /// ```toml
+    /// client_id = "..."
+    /// client_secret = "..."
+    /// callback_url = "http://localhost:8080/callback"
+    /// scopes = ["publicData", "esi-wallet.read_character_wallet.v1"]
+    /// server = "tranquility"
+    /// ```
+    ///
+    /// Fails with [`Error::Config`] if the file can't be read or parsed, has unknown keys or the merged configuration
+    /// is incomplete.
+    #[cfg(feature = "toml")]
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let file = std::fs::read_to_string(path)
+            .map_err(|err| Error::Config(format!("Failed to read {}: {}", path.display(), err)))?;
+        let config: PartialConfig = toml::from_str(&file)
+            .map_err(|err| Error::Config(format!("Failed to parse {}: {}", path.display(), err)))?;
+
+        config
+            .check_blank(path)?
+            .merge(PartialConfig::from_env("")?)
+            .build("", Some(path))
+    }
+
+    /// Loads the configuration from the file at [`EveSsoConfig::default_path`] if it exists, otherwise from the
+    /// environment, enabled with the `toml` feature
+    #[cfg(feature = "toml")]
+    pub fn load() -> Result<Self, Error> {
+        match Self::default_path() {
+            Some(path) if path.is_file() => Self::from_file(path),
+            _ => Self::from_env(),
+        }
+    }
+
+    /// Default location of the config file, `eve_oauth2/config.toml` in the user's config directory, e.g.
+    /// `~/.config/eve_oauth2/config.toml` on Linux
+    ///
+    /// Uses `XDG_CONFIG_HOME` or `~/.config` on Unix & `%APPDATA%` on Windows, `None` if neither is set.
+    #[cfg(feature = "toml")]
+    pub fn default_path() -> Option<PathBuf> {
+        let config_dir = if cfg!(windows) {
+            env::var_os("APPDATA").map(PathBuf::from)
+        } else {
+            env::var_os("XDG_CONFIG_HOME")
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from)
+                .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        };
+
+        config_dir.map(|dir| dir.join("eve_oauth2").join("config.toml"))
+    }
+
+    /// Creates a builder for a client of the application, see [`EveSsoClientBuilder`]
+    pub fn client_builder(&self) -> EveSsoClientBuilder {
+        let builder = EveSsoClient::builder(&self.client_id);
+
+        match &self.client_secret {
+            Some(client_secret) => builder.client_secret(client_secret.clone()),
+            None => builder,
+        }
+    }
+
+    /// Creates a client of the application using `reqwest` to send requests
+    #[cfg(feature = "reqwest")]
+    pub fn client(&self) -> EveSsoClient {
+        self.client_builder().build()
+    }
+
+    /// Validation options requiring tokens of the configured server, if any
+    pub fn validation(&self) -> TokenValidation {
+        match self.server {
+            Some(server) => TokenValidation::new().require_server(server),
+            None => TokenValidation::new(),
+        }
+    }
+}
+
+/// Configuration values of a single source, merged before the required values are checked
+#[derive(Default)]
+#[cfg_attr(feature = "toml", derive(Deserialize))]
+#[cfg_attr(feature = "toml", serde(deny_unknown_fields))]
+struct PartialConfig {
+    client_id: Option<String>,
+    client_secret: Option<SecretString>,
+    callback_url: Option<String>,
+    scopes: Option<Vec<String>>,
+    server: Option<EveServer>,
+}
+
+impl PartialConfig {
+    fn from_env(prefix: &str) -> Result<Self, Error> {
+        let var = |name: &str| env_var(&format!("{}{}", prefix, name));
+
+        Ok(Self {
+            client_id: var(CLIENT_ID_VAR)?,
+            client_secret: var(CLIENT_SECRET_VAR)?.map(SecretString::from),
+            callback_url: var(CALLBACK_URL_VAR)?,
+            scopes: var(SCOPES_VAR)?.map(|scopes| {
+                scopes
+                    .split(|c: char| c.is_whitespace() || c == ',')
+                    .filter(|scope| !scope.is_empty())
+                    .map(String::from)
+                    .collect()
+            }),
+            server: var(SERVER_VAR)?
+                .map(|server| {
+                    server.parse().map_err(|_| {
+                        Error::Config(format!(
+                            "{}{} must be tranquility, singularity or serenity",
+                            prefix, SERVER_VAR
+                        ))
+                    })
+                })
+                .transpose()?,
+        })
+    }
+
+    /// Fails if a value of the config file is blank, matching the checks of the environment variables
+    #[cfg(feature = "toml")]
+    fn check_blank(self, path: &Path) -> Result<Self, Error> {
+        let values = [
+            ("client_id", self.client_id.as_deref()),
+            (
+                "client_secret",
+                self.client_secret.as_ref().map(SecretString::expose_secret),
+            ),
+            ("callback_url", self.callback_url.as_deref()),
+        ];
+
+        for (key, value) in values {
+            if value.is_some_and(|value| value.trim().is_empty()) {
+                return Err(Error::Config(format!(
+                    "{} in {} is blank",
+                    key,
+                    path.display()
+                )));
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// Replaces the values with the values set in the overrides
+    #[cfg(feature = "toml")]
+    fn merge(self, overrides: PartialConfig) -> Self {
+        Self {
+            client_id: overrides.client_id.or(self.client_id),
+            client_secret: overrides.client_secret.or(self.client_secret),
+            callback_url: overrides.callback_url.or(self.callback_url),
+            scopes: overrides.scopes.or(self.scopes),
+            server: overrides.server.or(self.server),
+        }
+    }
+
+    /// Checks the required values are set & the callback URL is valid, path is the config file the values were read from
+    fn build(self, prefix: &str, path: Option<&Path>) -> Result<EveSsoConfig, Error> {
+        let source = |key: &str, var: &str| match path {
+            Some(path) => format!("{} in {} or {}{}", key, path.display(), prefix, var),
+            None => format!("{}{}", prefix, var),
+        };
+
+        let client_id = self.client_id.ok_or_else(|| {
+            Error::Config(format!("{} isn't set", source("client_id", CLIENT_ID_VAR)))
+        })?;
+        let callback_url = self.callback_url.ok_or_else(|| {
+            Error::Config(format!(
+                "{} isn't set",
+                source("callback_url", CALLBACK_URL_VAR)
+            ))
+        })?;
+
+        if let Err(Error::InvalidRedirectUrl(message)) = validate_redirect_url(&callback_url) {
+            return Err(Error::Config(format!(
+                "{} is invalid, {}",
+                source("callback_url", CALLBACK_URL_VAR),
+                message
+            )));
+        }
+
+        Ok(EveSsoConfig {
+            client_id,
+            client_secret: self.client_secret,
+            callback_url,
+            scopes: self.scopes.unwrap_or_default(),
+            server: self.server,
+        })
+    }
+}
+
+/// Reads the environment variable, `None` if it isn't set
+fn env_var(name: &str) -> Result<Option<String>, Error> {
+    match env::var(name) {
+        Ok(value) if value.trim().is_empty() => {
+            Err(Error::Config(format!("{} is set but blank", name)))
+        }
+        Ok(value) => Ok(Some(value.trim().to_string())),
+        Err(VarError::NotPresent) => Ok(None),
+        Err(VarError::NotUnicode(_)) => Err(Error::Config(format!("{} isn't valid unicode", name))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sets the variables with the prefix, tests use their own prefix as they run in parallel
+    fn set_vars(prefix: &str, vars: &[(&str, &str)]) {
+        for (name, value) in vars {
+            env::set_var(format!("{}{}", prefix, name), value);
+        }
+    }
+
+    fn config_error(result: Result<EveSsoConfig, Error>) -> String {
+        match result {
+            Err(Error::Config(message)) => message,
+            result => panic!("expected a config error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn loads_the_config_from_the_environment() {
+        let prefix = "TEST_LOADS_";
+        set_vars(
+            prefix,
+            &[
+                (CLIENT_ID_VAR, " client_id "),
+                (CLIENT_SECRET_VAR, "client_secret"),
+                (CALLBACK_URL_VAR, "http://localhost:8080/callback"),
+                (
+                    SCOPES_VAR,
+                    "publicData, esi-wallet.read_character_wallet.v1\tesi-mail.read_mail.v1,,",
+                ),
+                (SERVER_VAR, "Singularity"),
+            ],
+        );
+
+        let config = EveSsoConfig::from_env_with_prefix(prefix).unwrap();
+
+        assert_eq!(config.client_id, "client_id");
+        assert_eq!(
+            config
+                .client_secret
+                .as_ref()
+                .map(SecretString::expose_secret),
+            Some("client_secret")
+        );
+        assert_eq!(config.callback_url, "http://localhost:8080/callback");
+        assert_eq!(
+            config.scopes,
+            [
+                "publicData",
+                "esi-wallet.read_character_wallet.v1",
+                "esi-mail.read_mail.v1"
+            ]
+        );
+        assert_eq!(config.server, Some(EveServer::Singularity));
+    }
+
+    #[test]
+    fn debug_redacts_the_client_secret() {
+        let config = EveSsoConfig {
+            client_id: "client_id".to_string(),
+            client_secret: Some(SecretString::from("secret_value")),
+            callback_url: "http://localhost:8080/callback".to_string(),
+            scopes: Vec::new(),
+            server: None,
+        };
+
+        let debug = format!("{:?}", config);
+
+        assert!(debug.contains("client_secret: Some(***redacted***)"));
+        assert!(!debug.contains("secret_value"));
+    }
+
+    #[test]
+    fn optional_variables_may_be_unset() {
+        let prefix = "TEST_OPTIONAL_";
+        set_vars(
+            prefix,
+            &[
+                (CLIENT_ID_VAR, "client_id"),
+                (CALLBACK_URL_VAR, "http://localhost:8080/callback"),
+            ],
+        );
+
+        let config = EveSsoConfig::from_env_with_prefix(prefix).unwrap();
+
+        assert!(config.client_secret.is_none());
+        assert!(config.scopes.is_empty());
+        assert!(config.server.is_none());
+    }
+
+    #[test]
+    fn errors_name_the_missing_or_invalid_variable() {
+        let prefix = "TEST_ERRORS_";
+        let load = || EveSsoConfig::from_env_with_prefix(prefix);
+
+        assert_eq!(config_error(load()), "TEST_ERRORS_ESI_CLIENT_ID isn't set");
+
+        set_vars(prefix, &[(CLIENT_ID_VAR, "  ")]);
+        assert_eq!(
+            config_error(load()),
+            "TEST_ERRORS_ESI_CLIENT_ID is set but blank"
+        );
+
+        set_vars(prefix, &[(CLIENT_ID_VAR, "client_id")]);
+        assert_eq!(
+            config_error(load()),
+            "TEST_ERRORS_ESI_CALLBACK_URL isn't set"
+        );
+
+        set_vars(
+            prefix,
+            &[(CALLBACK_URL_VAR, "localhost:8080/callback#fragment")],
+        );
+        assert!(config_error(load()).starts_with("TEST_ERRORS_ESI_CALLBACK_URL is invalid"));
+
+        set_vars(
+            prefix,
+            &[
+                (CALLBACK_URL_VAR, "http://localhost:8080/callback"),
+                (SERVER_VAR, "duality"),
+            ],
+        );
+        assert_eq!(
+            config_error(load()),
+            "TEST_ERRORS_ESI_SERVER must be tranquility, singularity or serenity"
+        );
+
+        set_vars(prefix, &[(SERVER_VAR, "tranquility")]);
+        assert!(load().is_ok());
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn env_values_override_file_values() {
+        let file: PartialConfig = toml::from_str(
+            r#"
+            client_id = "file_client_id"
+            client_secret = "file_client_secret"
+            callback_url = "http://localhost:8080/callback"
+            scopes = ["publicData"]
+            server = "tranquility"
+            "#,
+        )
+        .unwrap();
+        let env = PartialConfig {
+            client_id: Some("env_client_id".to_string()),
+            scopes: Some(vec!["esi-mail.read_mail.v1".to_string()]),
+            ..PartialConfig::default()
+        };
+
+        let config = file.merge(env).build("", None).unwrap();
+
+        assert_eq!(config.client_id, "env_client_id");
+        assert_eq!(
+            config
+                .client_secret
+                .as_ref()
+                .map(SecretString::expose_secret),
+            Some("file_client_secret")
+        );
+        assert_eq!(config.callback_url, "http://localhost:8080/callback");
+        assert_eq!(config.scopes, ["esi-mail.read_mail.v1"]);
+        assert_eq!(config.server, Some(EveServer::Tranquility));
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn rejects_blank_values_and_unknown_keys_in_files() {
+        let path = Path::new("config.toml");
+
+        let file: PartialConfig = toml::from_str(r#"client_id = " ""#).unwrap();
+        assert!(matches!(
+            file.check_blank(path),
+            Err(Error::Config(message)) if message == "client_id in config.toml is blank"
+        ));
+
+        assert!(toml::from_str::<PartialConfig>(r#"client_ide = "client_id""#).is_err());
+
+        let file: PartialConfig = toml::from_str(r#"client_id = "client_id""#).unwrap();
+        assert!(matches!(
+            file.build("", Some(path)),
+            Err(Error::Config(message))
+                if message == "callback_url in config.toml or ESI_CALLBACK_URL isn't set"
+        ));
+    }
+
+    /// The only test setting the unprefixed variables read by `from_file` & `load`
+    #[cfg(feature = "toml")]
+    #[test]
+    fn loads_files_with_env_overrides() {
+        let dir = env::temp_dir().join(format!("eve_oauth2-config-{}", std::process::id()));
+        let path = dir.join("eve_oauth2").join("config.toml");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(
+            &path,
+            r#"
+            client_id = "file_client_id"
+            callback_url = "http://localhost:8080/callback"
+            scopes = ["publicData"]
+            "#,
+        )
+        .unwrap();
+
+        let config = EveSsoConfig::from_file(&path).unwrap();
+        assert_eq!(config.client_id, "file_client_id");
+        assert_eq!(config.scopes, ["publicData"]);
+
+        env::set_var(CLIENT_ID_VAR, "env_client_id");
+        env::set_var("XDG_CONFIG_HOME", &dir);
+        let result = EveSsoConfig::from_file(&path);
+        let default_path = EveSsoConfig::default_path();
+        let loaded = EveSsoConfig::load();
+        env::remove_var(CLIENT_ID_VAR);
+        env::remove_var("XDG_CONFIG_HOME");
+
+        assert_eq!(result.unwrap().client_id, "env_client_id");
+        if cfg!(not(windows)) {
+            assert_eq!(default_path.unwrap(), path);
+            assert_eq!(loaded.unwrap().scopes, ["publicData"]);
+        }
+
+        assert!(matches!(
+            EveSsoConfig::from_file(dir.join("missing.toml")),
+            Err(Error::Config(message)) if message.starts_with("Failed to read")
+        ));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,72 @@
+//! Conversions of timestamps to `time` & `chrono` types, enabled with the `time` & `chrono` features
+
+use std::time::Duration;
+
+use web_time::{SystemTime, UNIX_EPOCH};
+
+/// Duration since the unix epoch of a system time, times before the epoch are clamped to it
+pub(crate) fn since_epoch(time: SystemTime) -> Duration {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default()
+}
+
+/// Converts a duration since the unix epoch, saturating at the latest representable time
+#[cfg(feature = "time")]
+pub(crate) fn offset_date_time(since_epoch: Duration) -> time::OffsetDateTime {
+    i64::try_from(since_epoch.as_secs())
+        .ok()
+        .and_then(|secs| time::OffsetDateTime::from_unix_timestamp(secs).ok())
+        .and_then(|time| time.replace_nanosecond(since_epoch.subsec_nanos()).ok())
+        .unwrap_or(time::PrimitiveDateTime::MAX.assume_utc())
+}
+
+/// Converts a duration since the unix epoch, saturating at the latest representable time
+#[cfg(feature = "chrono")]
+pub(crate) fn date_time_utc(since_epoch: Duration) -> chrono::DateTime<chrono::Utc> {
+    i64::try_from(since_epoch.as_secs())
+        .ok()
+        .and_then(|secs| chrono::DateTime::from_timestamp(secs, since_epoch.subsec_nanos()))
+        .unwrap_or(chrono::DateTime::<chrono::Utc>::MAX_UTC)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn times_before_the_epoch_are_clamped() {
+        assert_eq!(
+            since_epoch(UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+            Duration::from_secs(1_700_000_000)
+        );
+        assert_eq!(
+            since_epoch(UNIX_EPOCH - Duration::from_secs(1)),
+            Duration::ZERO
+        );
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn converts_to_offset_date_times() {
+        let time = offset_date_time(Duration::new(1_700_000_000, 500));
+
+        assert_eq!(time.unix_timestamp(), 1_700_000_000);
+        assert_eq!(time.nanosecond(), 500);
+        assert_eq!(
+            offset_date_time(Duration::from_secs(u64::MAX)),
+            time::PrimitiveDateTime::MAX.assume_utc()
+        );
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn converts_to_chrono_date_times() {
+        let time = date_time_utc(Duration::new(1_700_000_000, 500));
+
+        assert_eq!(time.timestamp(), 1_700_000_000);
+        assert_eq!(time.timestamp_subsec_nanos(), 500);
+        assert_eq!(
+            date_time_utc(Duration::from_secs(u64::MAX)),
+            chrono::DateTime::<chrono::Utc>::MAX_UTC
+        );
+    }
+}
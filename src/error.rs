@@ -0,0 +1,531 @@
+use std::fmt;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use oauth2::basic::BasicErrorResponse;
+use oauth2::RequestTokenError;
+
+use crate::http::header::RETRY_AFTER;
+use crate::http::{HeaderMap, HttpError, HttpResponse, StatusCode};
+
+/// Errors returned when requests to EVE Online SSO or token validation fail
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The request to EVE Online SSO couldn't be completed
+    Http(HttpError),
+    /// EVE Online SSO responded to a token request with an OAuth2 error such as `invalid_grant`
+    Sso {
+        error: String,
+        error_description: Option<String>,
+        /// Raw response with the `debug-responses` feature, read it with [`Error::raw_response`]
+        response: ResponseCapture,
+    },
+    /// EVE Online SSO responded with an unsuccessful HTTP status which isn't an OAuth2 error, e.g. 429 or 503
+    Status {
+        status: u16,
+        /// Delay requested by the `Retry-After` header
+        retry_after: Option<Duration>,
+        /// Raw response with the `debug-responses` feature, read it with [`Error::raw_response`]
+        response: ResponseCapture,
+    },
+    /// EVE Online SSO returned a response which couldn't be understood, the message includes the truncated body with
+    /// the `debug-responses` feature
+    UnexpectedResponse(String),
+    /// The EVE JWT keys don't contain an RS256 key to validate tokens with
+    MissingJwtKey,
+    /// The token failed validation
+    InvalidToken(jsonwebtoken::errors::Error),
+    /// The claims of a validated token don't contain the expected values
+    InvalidClaims(String),
+    /// The token wasn't granted the required scopes, contains the missing scopes
+    MissingScopes(Vec<String>),
+    /// The token response doesn't contain a refresh token
+    MissingRefreshToken,
+    /// No tokens are stored for the character
+    CharacterNotFound(u64),
+    /// The character was transferred to another account, its stored tokens were deleted
+    OwnerChanged(u64),
+    /// The token store failed to complete the operation
+    TokenStore(Box<dyn std::error::Error + Send + Sync>),
+    /// The state returned to the callback is invalid or has expired
+    InvalidState(String),
+    /// The state store failed to complete the operation
+    StateStore(Box<dyn std::error::Error + Send + Sync>),
+    /// The redirect URL can't be used for logins, see [`crate::validate_redirect_url`]
+    InvalidRedirectUrl(String),
+    /// The configuration is missing or invalid, e.g. a required environment variable isn't set
+    Config(String),
+    /// The local callback server failed to listen or timed out waiting for the redirect
+    CallbackServer(std::io::Error),
+    /// The blocking task validating tokens panicked or was cancelled, see [`crate::EveSsoClient::blocking_validation`]
+    ValidationTask(String),
+}
+
+impl Error {
+    /// Short label for the kind of error, also used as the `kind` label of the failure metrics
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Http(_) => "http",
+            Error::Sso { .. } => "sso",
+            Error::Status { .. } => "status",
+            Error::UnexpectedResponse(_) => "unexpected_response",
+            Error::MissingJwtKey => "missing_jwt_key",
+            Error::InvalidToken(_) => "invalid_token",
+            Error::InvalidClaims(_) => "invalid_claims",
+            Error::MissingScopes(_) => "missing_scopes",
+            Error::MissingRefreshToken => "missing_refresh_token",
+            Error::CharacterNotFound(_) => "character_not_found",
+            Error::OwnerChanged(_) => "owner_changed",
+            Error::TokenStore(_) => "token_store",
+            Error::InvalidState(_) => "invalid_state",
+            Error::StateStore(_) => "state_store",
+            Error::InvalidRedirectUrl(_) => "invalid_redirect_url",
+            Error::Config(_) => "config",
+            Error::CallbackServer(_) => "callback_server",
+            Error::ValidationTask(_) => "validation_task",
+        }
+    }
+
+    /// Classifies the error to decide how to react, e.g. whether a failed refresh should be retried
+    /// or the character has to log in again
+    pub fn classify(&self) -> ErrorClass {
+        match self {
+            Error::Sso { error, .. } => match error.as_str() {
+                "invalid_grant" => ErrorClass::RevokedOrExpiredGrant,
+                "temporarily_unavailable" | "server_error" => ErrorClass::Transient,
+                _ => ErrorClass::Other,
+            },
+            Error::Status { status: 429, .. } => ErrorClass::RateLimited,
+            Error::Status { status, .. } if *status == 408 || *status >= 500 => {
+                ErrorClass::Transient
+            }
+            Error::Http(_) => ErrorClass::Transient,
+            Error::OwnerChanged(_) => ErrorClass::RevokedOrExpiredGrant,
+            _ => ErrorClass::Other,
+        }
+    }
+
+    /// Whether retrying the operation later may succeed, i.e. the error is [`ErrorClass::Transient`] or
+    /// [`ErrorClass::RateLimited`]
+    ///
+    /// Honor the `retry_after` of [`Error::Status`] when retrying rate limited requests.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self.classify(),
+            ErrorClass::Transient | ErrorClass::RateLimited
+        )
+    }
+
+    /// Whether the character has to log in again, e.g. because its refresh token was revoked, no tokens are stored
+    /// for it or its token is missing required scopes
+    pub fn requires_reauth(&self) -> bool {
+        self.classify() == ErrorClass::RevokedOrExpiredGrant
+            || matches!(self, Error::CharacterNotFound(_) | Error::MissingScopes(_))
+    }
+
+    /// Whether the error is caused by the application's configuration rather than the request, e.g. an invalid
+    /// redirect URL or client credentials rejected by EVE Online SSO
+    pub fn is_configuration_error(&self) -> bool {
+        match self {
+            Error::Sso { error, .. } => matches!(
+                error.as_str(),
+                "invalid_client" | "unauthorized_client" | "invalid_scope"
+            ),
+            Error::Config(_) | Error::InvalidRedirectUrl(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Converts the error of an oauth2 token request, using `request` to convert the HTTP client's error
+    ///
+    /// Responses with an unsuccessful status which aren't OAuth2 error responses are converted to [`Error::Status`].
+    pub(crate) fn from_token_request<RE: std::error::Error + 'static>(
+        err: RequestTokenError<RE, BasicErrorResponse>,
+        request: impl FnOnce(RE) -> HttpError,
+        status: ResponseStatus,
+    ) -> Self {
+        match err {
+            RequestTokenError::ServerResponse(response) => {
+                let error = response.error().to_string();
+                let error_description = response.error_description().cloned();
+
+                match status.into_error() {
+                    Some(status) => status.into_sso(error, error_description),
+                    None => Error::sso(error, error_description),
+                }
+            }
+            RequestTokenError::Request(err) => Error::Http(request(err)),
+            RequestTokenError::Parse(err, _) => status
+                .into_error()
+                .unwrap_or_else(|| Error::UnexpectedResponse(err.to_string())),
+            RequestTokenError::Other(message) => status
+                .into_error()
+                .unwrap_or(Error::UnexpectedResponse(message)),
+        }
+    }
+
+    /// Creates an [`Error::Sso`] which wasn't returned in a response
+    pub(crate) fn sso(error: String, error_description: Option<String>) -> Self {
+        Error::Sso {
+            error,
+            error_description,
+            response: ResponseCapture::default(),
+        }
+    }
+
+    /// Creates an [`Error::Status`] from an unsuccessful response
+    pub(crate) fn from_status(status_code: StatusCode, headers: &HeaderMap, body: &[u8]) -> Self {
+        let retry_after = headers
+            .get(RETRY_AFTER)
+            .and_then(|retry_after| retry_after.to_str().ok())
+            .and_then(|retry_after| retry_after.parse().ok())
+            .map(Duration::from_secs);
+
+        Error::Status {
+            status: status_code.as_u16(),
+            retry_after,
+            response: ResponseCapture::new(status_code, headers, body),
+        }
+    }
+
+    /// Converts an [`Error::Status`] of an OAuth2 error response to an [`Error::Sso`], keeping the raw response
+    pub(crate) fn into_sso(self, error: String, error_description: Option<String>) -> Self {
+        match self {
+            Error::Status { response, .. } => Error::Sso {
+                error,
+                error_description,
+                response,
+            },
+            _ => Error::sso(error, error_description),
+        }
+    }
+
+    /// Creates an [`Error::UnexpectedResponse`] for a response body which couldn't be parsed
+    pub(crate) fn unexpected_body(err: impl fmt::Display, body: &[u8]) -> Self {
+        #[cfg(feature = "debug-responses")]
+        return Error::UnexpectedResponse(format!("{} in body {}", err, truncated_body(body)));
+
+        #[cfg(not(feature = "debug-responses"))]
+        {
+            let _ = body;
+            Error::UnexpectedResponse(err.to_string())
+        }
+    }
+
+    /// Raw response EVE Online SSO returned with the error, enabled with the `debug-responses` feature
+    ///
+    /// Only [`Error::Status`] & [`Error::Sso`] errors returned in a response carry one.
+    #[cfg(feature = "debug-responses")]
+    pub fn raw_response(&self) -> Option<&RawResponse> {
+        match self {
+            Error::Sso { response, .. } | Error::Status { response, .. } => response.0.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+/// How to react to an [`Error`], returned by [`Error::classify`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorClass {
+    /// The refresh token was revoked or has expired, the character has to log in again
+    RevokedOrExpiredGrant,
+    /// EVE Online SSO couldn't be reached or had a temporary failure, retry later
+    Transient,
+    /// EVE Online SSO is rate limiting requests, retry after backing off
+    RateLimited,
+    /// Any other error, retrying is unlikely to help
+    Other,
+}
+
+/// Raw response attached to [`Error::Sso`] & [`Error::Status`], empty unless the `debug-responses` feature is enabled
+///
+/// The variants have the same fields with & without the feature, so matching them doesn't depend on it. Read the raw
+/// response with [`Error::raw_response`].
+#[derive(Debug, Clone, Default)]
+pub struct ResponseCapture(
+    #[cfg(feature = "debug-responses")] Option<Box<RawResponse>>,
+    #[cfg(not(feature = "debug-responses"))] (),
+);
+
+impl ResponseCapture {
+    fn new(status_code: StatusCode, headers: &HeaderMap, body: &[u8]) -> Self {
+        #[cfg(feature = "debug-responses")]
+        return Self(Some(Box::new(RawResponse::new(status_code, headers, body))));
+
+        #[cfg(not(feature = "debug-responses"))]
+        {
+            let _ = (status_code, headers, body);
+            Self(())
+        }
+    }
+}
+
+/// Status, headers of interest & truncated body of an unsuccessful response from EVE Online SSO, attached to errors
+/// with the `debug-responses` feature
+#[cfg(feature = "debug-responses")]
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct RawResponse {
+    pub status: u16,
+    /// Content-Type, Date, Retry-After & WWW-Authenticate headers of the response
+    pub headers: Vec<(String, String)>,
+    /// Body decoded as UTF-8, truncated to 1024 bytes
+    pub body: String,
+}
+
+#[cfg(feature = "debug-responses")]
+impl RawResponse {
+    fn new(status_code: StatusCode, headers: &HeaderMap, body: &[u8]) -> Self {
+        use crate::http::header::{CONTENT_TYPE, DATE, WWW_AUTHENTICATE};
+
+        let headers = [CONTENT_TYPE, DATE, RETRY_AFTER, WWW_AUTHENTICATE]
+            .into_iter()
+            .filter_map(|name| {
+                let value = headers.get(&name)?.to_str().ok()?.to_string();
+
+                Some((name.to_string(), value))
+            })
+            .collect();
+
+        Self {
+            status: status_code.as_u16(),
+            headers,
+            body: truncated_body(body),
+        }
+    }
+}
+
+#[cfg(feature = "debug-responses")]
+const MAX_BODY_LEN: usize = 1024;
+
+#[cfg(feature = "debug-responses")]
+fn truncated_body(body: &[u8]) -> String {
+    let mut truncated = String::from_utf8_lossy(&body[..body.len().min(MAX_BODY_LEN)]).into_owned();
+
+    if body.len() > MAX_BODY_LEN {
+        truncated.push_str("...");
+    }
+
+    truncated
+}
+
+/// Records the unsuccessful response of a token request, oauth2 only reports that the response couldn't be parsed
+#[derive(Default)]
+pub(crate) struct ResponseStatus(Mutex<Option<Error>>);
+
+impl ResponseStatus {
+    pub(crate) fn record<E>(&self, response: &Result<HttpResponse, E>) {
+        if let Ok(response) = response {
+            if !response.status_code.is_success() {
+                *self.0.lock().unwrap() = Some(Error::from_status(
+                    response.status_code,
+                    &response.headers,
+                    &response.body,
+                ));
+            }
+        }
+    }
+
+    fn into_error(self) -> Option<Error> {
+        self.0.into_inner().unwrap()
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Http(err) => write!(f, "{}", err),
+            Error::Sso {
+                error,
+                error_description: Some(description),
+                ..
+            } => write!(f, "EVE SSO returned error {}: {}", error, description),
+            Error::Sso { error, .. } => write!(f, "EVE SSO returned error {}", error),
+            Error::Status { status, .. } => write!(f, "EVE SSO returned status {}", status),
+            Error::UnexpectedResponse(message) => {
+                write!(f, "Unexpected response from EVE SSO: {}", message)
+            }
+            Error::MissingJwtKey => write!(f, "Failed to find RS256 EveJwtKey"),
+            Error::InvalidToken(err) => write!(f, "Token is invalid: {}", err),
+            Error::InvalidClaims(message) => write!(f, "Token claims are invalid: {}", message),
+            Error::MissingScopes(scopes) => {
+                write!(f, "Token is missing required scopes: {}", scopes.join(", "))
+            }
+            Error::MissingRefreshToken => write!(f, "Token response has no refresh token"),
+            Error::CharacterNotFound(character_id) => {
+                write!(f, "No tokens are stored for character {}", character_id)
+            }
+            Error::OwnerChanged(character_id) => {
+                write!(
+                    f,
+                    "Character {} was transferred to another account",
+                    character_id
+                )
+            }
+            Error::TokenStore(err) => write!(f, "Token store failed: {}", err),
+            Error::InvalidState(message) => write!(f, "State is invalid: {}", message),
+            Error::StateStore(err) => write!(f, "State store failed: {}", err),
+            Error::InvalidRedirectUrl(message) => write!(f, "Redirect URL is invalid: {}", message),
+            Error::Config(message) => write!(f, "Configuration is invalid: {}", message),
+            Error::CallbackServer(err) => write!(f, "Callback server failed: {}", err),
+            Error::ValidationTask(message) => {
+                write!(f, "Token validation task failed: {}", message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Http(err) => Some(err),
+            Error::InvalidToken(err) => Some(err),
+            Error::TokenStore(err) => Some(err.as_ref()),
+            Error::StateStore(err) => Some(err.as_ref()),
+            Error::CallbackServer(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<HttpError> for Error {
+    fn from(err: HttpError) -> Self {
+        Error::Http(err)
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for Error {
+    fn from(err: jsonwebtoken::errors::Error) -> Self {
+        Error::InvalidToken(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(status: u16) -> Error {
+        Error::from_status(
+            StatusCode::from_u16(status).unwrap(),
+            &HeaderMap::new(),
+            b"",
+        )
+    }
+
+    #[test]
+    fn classifies_refresh_failures() {
+        let sso = |error: &str| Error::sso(error.to_string(), None);
+
+        assert_eq!(
+            sso("invalid_grant").classify(),
+            ErrorClass::RevokedOrExpiredGrant
+        );
+        assert_eq!(
+            sso("temporarily_unavailable").classify(),
+            ErrorClass::Transient
+        );
+        assert_eq!(sso("server_error").classify(), ErrorClass::Transient);
+        assert_eq!(sso("invalid_client").classify(), ErrorClass::Other);
+
+        assert_eq!(status(429).classify(), ErrorClass::RateLimited);
+        assert_eq!(status(408).classify(), ErrorClass::Transient);
+        assert_eq!(status(500).classify(), ErrorClass::Transient);
+        assert_eq!(status(503).classify(), ErrorClass::Transient);
+        assert_eq!(status(404).classify(), ErrorClass::Other);
+
+        assert_eq!(
+            Error::Http(HttpError::new("connection refused")).classify(),
+            ErrorClass::Transient
+        );
+        assert_eq!(
+            Error::OwnerChanged(2114794365).classify(),
+            ErrorClass::RevokedOrExpiredGrant
+        );
+        assert_eq!(Error::MissingJwtKey.classify(), ErrorClass::Other);
+    }
+
+    #[test]
+    fn helpers_follow_the_classification() {
+        let sso = |error: &str| Error::sso(error.to_string(), None);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, "30".parse().unwrap());
+        let rate_limited = Error::from_status(StatusCode::TOO_MANY_REQUESTS, &headers, b"");
+        assert!(matches!(
+            rate_limited,
+            Error::Status { retry_after: Some(retry_after), .. } if retry_after == Duration::from_secs(30)
+        ));
+        assert!(rate_limited.is_retryable());
+        assert!(!rate_limited.requires_reauth());
+
+        assert!(status(503).is_retryable());
+        assert!(Error::Http(HttpError::new("connection refused")).is_retryable());
+        assert!(!status(404).is_retryable());
+        assert!(!sso("invalid_grant").is_retryable());
+
+        assert!(sso("invalid_grant").requires_reauth());
+        assert!(Error::CharacterNotFound(2114794365).requires_reauth());
+        assert!(Error::MissingScopes(vec!["publicData".to_string()]).requires_reauth());
+        assert!(!status(500).requires_reauth());
+
+        for error in ["invalid_client", "unauthorized_client", "invalid_scope"] {
+            assert!(sso(error).is_configuration_error(), "{error}");
+        }
+        assert!(Error::Config("missing client id".to_string()).is_configuration_error());
+        assert!(Error::InvalidRedirectUrl("not a url".to_string()).is_configuration_error());
+        assert!(!sso("invalid_grant").is_configuration_error());
+        assert!(!status(500).is_configuration_error());
+    }
+
+    #[test]
+    fn status_errors_keep_their_shape_with_and_without_debug_responses() {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, "30".parse().unwrap());
+
+        let err = Error::from_status(StatusCode::TOO_MANY_REQUESTS, &headers, b"slow down");
+        let Error::Status {
+            status,
+            retry_after,
+            response: _,
+        } = &err
+        else {
+            panic!("expected a status error, got {:?}", err);
+        };
+        assert_eq!(*status, 429);
+        assert_eq!(*retry_after, Some(Duration::from_secs(30)));
+
+        #[cfg(feature = "debug-responses")]
+        assert_eq!(err.raw_response().unwrap().body, "slow down");
+
+        let err = err.into_sso("invalid_grant".to_string(), None);
+        assert!(matches!(&err, Error::Sso { error, .. } if error == "invalid_grant"));
+
+        #[cfg(feature = "debug-responses")]
+        assert_eq!(err.raw_response().unwrap().status, 429);
+    }
+
+    #[cfg(feature = "debug-responses")]
+    #[test]
+    fn raw_responses_keep_headers_of_interest_and_truncate_the_body() {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, "30".parse().unwrap());
+        headers.insert("set-cookie", "session=secret".parse().unwrap());
+        let body = "a".repeat(MAX_BODY_LEN + 1);
+
+        let err = Error::from_status(StatusCode::BAD_GATEWAY, &headers, body.as_bytes());
+        let response = err.raw_response().unwrap();
+
+        assert_eq!(response.status, 502);
+        assert_eq!(
+            response.headers,
+            [("retry-after".to_string(), "30".to_string())]
+        );
+        assert_eq!(response.body.len(), MAX_BODY_LEN + 3);
+        assert!(response.body.ends_with("a..."));
+
+        let err = Error::from_status(StatusCode::BAD_GATEWAY, &headers, &[0xff, b'a']);
+        assert_eq!(err.raw_response().unwrap().body, "\u{fffd}a");
+    }
+}
@@ -0,0 +1,203 @@
+//! Character affiliations from the EVE Swagger Interface (ESI), enabled with the `esi` feature
+//!
+//! ```no_run
+//! # async fn example(client: eve_oauth2::EveSsoClient, token: String) -> Result<(), eve_oauth2::Error> {
+//! let token_data = client.validate_token(token).await?;
+//! let affiliation = client.enrich_character(&token_data.claims).await?;
+//!
+//! println!("Corporation {}, alliance {:?}", affiliation.corporation_id, affiliation.alliance_id);
+//! # Ok(())
+//! # }
+//! ```
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::collections::BTreeMap;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::{Arc, RwLock};
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::http::header::{ACCEPT, CONTENT_TYPE};
+use crate::http::{Endpoint, HeaderMap, HeaderValue, HttpRequest, Method};
+use crate::models::{CharacterId, EveJwtClaims};
+use crate::EveSsoClient;
+
+const AFFILIATION_ROUTE: &str = "latest/characters/affiliation/";
+
+/// Time affiliations are cached for, matching the cache of the ESI endpoint
+#[cfg(not(target_arch = "wasm32"))]
+const AFFILIATION_TTL: Duration = Duration::from_secs(3600);
+
+/// Affiliations retrieved by a client & its clones
+#[derive(Debug, Clone, Default)]
+pub(crate) struct AffiliationCache {
+    #[cfg(not(target_arch = "wasm32"))]
+    affiliations: Arc<RwLock<BTreeMap<CharacterId, (Instant, CharacterAffiliation)>>>,
+}
+
+/// Corporation & alliance a character belongs to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CharacterAffiliation {
+    pub character_id: CharacterId,
+    pub corporation_id: u64,
+    /// `None` if the character's corporation isn't in an alliance
+    pub alliance_id: Option<u64>,
+}
+
+impl EveSsoClient {
+    /// Retrieves the corporation & alliance of the character the claims were issued for
+    ///
+    /// Affiliations are retrieved from [`EveSsoClient::esi_url`] & cached by the client & its clones for an hour, except
+    /// on wasm targets where they're retrieved on every call.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(sub = %claims.sub)))]
+    pub async fn enrich_character(
+        &self,
+        claims: &EveJwtClaims,
+    ) -> Result<CharacterAffiliation, Error> {
+        let character_id = claims.character_id()?;
+
+        if let Some(affiliation) = self.affiliations.get(character_id) {
+            #[cfg(feature = "tracing")]
+            tracing::trace!("Using cached character affiliation");
+
+            return Ok(affiliation);
+        }
+
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+
+        let request = HttpRequest {
+            method: Method::POST,
+            url: self.esi_route(AFFILIATION_ROUTE)?,
+            headers,
+            body: serde_json::to_vec(&[character_id])
+                .map_err(|err| Error::UnexpectedResponse(err.to_string()))?,
+        };
+
+        let response = self.execute(request, Endpoint::Affiliation).await?;
+
+        if !response.status_code.is_success() {
+            return Err(Error::from_status(
+                response.status_code,
+                &response.headers,
+                &response.body,
+            ));
+        }
+
+        let affiliation = serde_json::from_slice::<Vec<CharacterAffiliation>>(&response.body)
+            .map_err(|err| Error::unexpected_body(err, &response.body))?
+            .into_iter()
+            .find(|affiliation| affiliation.character_id == character_id)
+            .ok_or_else(|| {
+                Error::UnexpectedResponse(format!("No affiliation of character {}", character_id))
+            })?;
+
+        self.affiliations.insert(&affiliation);
+
+        Ok(affiliation)
+    }
+}
+
+impl AffiliationCache {
+    fn get(&self, character_id: CharacterId) -> Option<CharacterAffiliation> {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some((retrieved_at, affiliation)) =
+            self.affiliations.read().unwrap().get(&character_id)
+        {
+            if retrieved_at.elapsed() < AFFILIATION_TTL {
+                return Some(affiliation.clone());
+            }
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        let _ = character_id;
+
+        None
+    }
+
+    fn insert(&self, affiliation: &CharacterAffiliation) {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let mut affiliations = self.affiliations.write().unwrap();
+
+            affiliations.retain(|_, (retrieved_at, _)| retrieved_at.elapsed() < AFFILIATION_TTL);
+            affiliations.insert(
+                affiliation.character_id,
+                (Instant::now(), affiliation.clone()),
+            );
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        let _ = affiliation;
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::http::{BoxFuture, HttpClient, HttpError, HttpResponse, StatusCode, Url};
+
+    #[derive(Clone, Default)]
+    struct MockEsi {
+        requests: Arc<AtomicUsize>,
+        url: Arc<Mutex<Option<Url>>>,
+    }
+
+    impl HttpClient for MockEsi {
+        fn execute(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, HttpError>> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            *self.url.lock().unwrap() = Some(request.url);
+
+            Box::pin(async {
+                Ok(HttpResponse {
+                    status_code: StatusCode::OK,
+                    headers: HeaderMap::new(),
+                    body: br#"[{"character_id":2114794365,"corporation_id":98000001}]"#.to_vec(),
+                })
+            })
+        }
+    }
+
+    fn claims() -> EveJwtClaims {
+        serde_json::from_value(serde_json::json!({
+            "scp": null, "jti": "jti", "kid": "JWT-Signature-Key", "sub": "CHARACTER:EVE:2114794365",
+            "azp": "client_id", "tenant": "tranquility", "tier": "live", "region": "world",
+            "aud": ["client_id", "EVE Online"], "name": "Test Character", "owner": "owner",
+            "exp": 0, "iat": 0, "iss": "https://login.eveonline.com"
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn caches_affiliations_per_client_using_its_esi_url() {
+        let esi = MockEsi::default();
+        let client = EveSsoClient::builder("client_id")
+            .http_client(esi.clone())
+            .esi_url(Url::parse("http://127.0.0.1:8080/esi").unwrap())
+            .build();
+
+        let affiliation = client.enrich_character(&claims()).await.unwrap();
+        assert_eq!(affiliation.corporation_id, 98000001);
+        assert_eq!(affiliation.alliance_id, None);
+        assert_eq!(
+            esi.url.lock().unwrap().as_ref().unwrap().as_str(),
+            "http://127.0.0.1:8080/esi/latest/characters/affiliation/"
+        );
+
+        client.clone().enrich_character(&claims()).await.unwrap();
+        assert_eq!(esi.requests.load(Ordering::SeqCst), 1);
+
+        let other = EveSsoClient::builder("client_id")
+            .http_client(esi.clone())
+            .build();
+        other.enrich_character(&claims()).await.unwrap();
+        assert_eq!(esi.requests.load(Ordering::SeqCst), 2);
+    }
+}
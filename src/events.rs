@@ -0,0 +1,133 @@
+use web_time::SystemTime;
+
+use crate::error::{Error, ErrorClass};
+use crate::models::EveTokens;
+use crate::token::EveTokenResponse;
+
+/// Token lifecycle events passed to the hooks registered with [`crate::EveSsoClient::on_token_event`]
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum TokenEvent<'a> {
+    /// A token was obtained by exchanging the authorization code from the callback
+    Obtained(&'a EveTokenResponse),
+    /// A token was refreshed, store the new refresh token as the previous one is no longer usable
+    Refreshed(&'a EveTokenResponse),
+    /// Refreshing a token failed, e.g. because the character's refresh token was revoked
+    RefreshFailed(&'a Error),
+}
+
+/// Events of the characters managed by a [`crate::TokenManager`], sent to the subscribers of
+/// [`crate::TokenManager::subscribe`]
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum ManagedTokenEvent {
+    /// The tokens of a character were refreshed & saved to the store
+    Refreshed(EveTokens),
+    /// Refreshing the tokens of a character failed, error is the message of the returned [`Error`]
+    RefreshFailed {
+        character_id: u64,
+        class: ErrorClass,
+        error: String,
+    },
+    /// The stored tokens of a character were deleted because its refresh token was revoked or has expired
+    Invalidated { character_id: u64 },
+    /// The owner hash of a character changed because it was transferred to another account
+    OwnerChanged { character_id: u64 },
+}
+
+/// Authentication event passed to the [`AuditSink`] of a client, e.g. to keep an audit log for compliance
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct AuditEvent {
+    pub kind: AuditEventKind,
+    /// Character the event concerns, `None` if it's unknown, e.g. for failed logins or malformed tokens
+    pub character_id: Option<u64>,
+    /// [`Error::kind`] of the error of failures
+    pub error: Option<&'static str>,
+    /// Time the event occurred
+    pub timestamp: SystemTime,
+}
+
+impl AuditEvent {
+    pub(crate) fn new(
+        kind: AuditEventKind,
+        character_id: Option<u64>,
+        error: Option<&Error>,
+    ) -> Self {
+        Self {
+            kind,
+            character_id,
+            error: error.map(Error::kind),
+            timestamp: SystemTime::now(),
+        }
+    }
+}
+
+/// Kinds of [`AuditEvent`]s
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum AuditEventKind {
+    /// A character logged in, its authorization code was exchanged for a token
+    Login,
+    /// Exchanging an authorization code failed
+    LoginFailed,
+    /// An access token failed validation, e.g. because it expired or its signature is invalid
+    ValidationFailed,
+    /// A token was refreshed
+    Refreshed,
+    /// Refreshing a token failed, e.g. because the character's refresh token was revoked
+    RefreshFailed,
+    /// The stored tokens of a character were deleted by a [`crate::TokenManager`] because its refresh token was
+    /// revoked or has expired
+    TokensDeleted,
+    /// A [`crate::TokenManager`] noticed the owner hash of a character changed
+    OwnerChanged,
+}
+
+impl AuditEventKind {
+    /// Label of the kind for logs, e.g. `login` or `validation_failed`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditEventKind::Login => "login",
+            AuditEventKind::LoginFailed => "login_failed",
+            AuditEventKind::ValidationFailed => "validation_failed",
+            AuditEventKind::Refreshed => "refreshed",
+            AuditEventKind::RefreshFailed => "refresh_failed",
+            AuditEventKind::TokensDeleted => "tokens_deleted",
+            AuditEventKind::OwnerChanged => "owner_changed",
+        }
+    }
+}
+
+/// Receives the [`AuditEvent`]s of a client, set it with [`crate::EveSsoClientBuilder::audit_sink`]
+///
+/// Records are made before the request returns, hand events off to a channel or buffer if writing them is slow.
+/// Closures taking an `&AuditEvent` implement the trait.
+///
+/// ```no_run
+/// use eve_oauth2::events::AuditEvent;
+/// use eve_oauth2::EveSsoClient;
+///
+/// let client = EveSsoClient::builder("client_id")
+///     .audit_sink(|event: &AuditEvent| {
+///         println!("{:?} {} {:?}", event.timestamp, event.kind.as_str(), event.character_id);
+///     })
+///     .build();
+/// ```
+pub trait AuditSink: Send + Sync {
+    fn record(&self, event: &AuditEvent);
+}
+
+impl<F: Fn(&AuditEvent) + Send + Sync> AuditSink for F {
+    fn record(&self, event: &AuditEvent) {
+        self(event)
+    }
+}
+
+/// Audit sink discarding all events, the default of clients
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopAuditSink;
+
+impl AuditSink for NoopAuditSink {
+    fn record(&self, _event: &AuditEvent) {}
+}
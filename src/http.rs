@@ -0,0 +1,204 @@
+//! HTTP client abstraction used for all requests to EVE Online SSO
+//!
+//! Implement [`HttpClient`] to use the crate with an HTTP client of your choice, for example when your application
+//! runs on an async runtime other than tokio such as async-std or smol. An implementation for `reqwest::Client`
+//! is provided with the default `reqwest` feature.
+//!
+//! ```no_run
+//! use eve_oauth2::http::{BoxFuture, HttpClient, HttpError, HttpRequest, HttpResponse};
+//!
+//! struct MyHttpClient;
+//!
+//! impl HttpClient for MyHttpClient {
+//!     fn execute(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, HttpError>> {
+//!         Box::pin(async move {
+//!             // Send the request using your HTTP client of choice
+//!             # let _ = request;
+//!             # unimplemented!()
+//!         })
+//!     }
+//! }
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+
+pub use ::http::header;
+pub use ::http::{HeaderMap, HeaderValue, Method, StatusCode};
+pub use url::Url;
+
+/// HTTP request to EVE Online SSO
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+    pub method: Method,
+    pub url: Url,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+/// HTTP response from EVE Online SSO
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    pub status_code: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+/// Endpoint of EVE Online SSO or ESI a request is sent to, e.g. to apply a different
+/// [`crate::retry::RetryPolicy`] per endpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Endpoint {
+    /// Token endpoint, used to exchange authorization codes & refresh tokens
+    Token,
+    /// Well-known metadata endpoint
+    Metadata,
+    /// JWKS endpoint publishing the EVE JWT keys
+    Jwks,
+    /// ESI's `/verify` endpoint
+    Verify,
+    /// Revocation endpoint
+    Revoke,
+    /// ESI's character affiliation endpoint
+    #[cfg(feature = "esi")]
+    Affiliation,
+}
+
+impl Endpoint {
+    /// Name of the endpoint, also used as the `endpoint` label of the request duration metric
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Endpoint::Token => "token",
+            Endpoint::Metadata => "metadata",
+            Endpoint::Jwks => "jwks",
+            Endpoint::Verify => "verify",
+            Endpoint::Revoke => "revoke",
+            #[cfg(feature = "esi")]
+            Endpoint::Affiliation => "affiliation",
+        }
+    }
+}
+
+/// Boxed future returned by [`HttpClient::execute`], only requires `Send` on non-wasm targets
+#[cfg(not(target_arch = "wasm32"))]
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Boxed future returned by [`HttpClient::execute`], only requires `Send` on non-wasm targets
+#[cfg(target_arch = "wasm32")]
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+/// Asynchronous HTTP client used to send requests to EVE Online SSO
+pub trait HttpClient: Send + Sync {
+    /// Sends the request & returns the response, non-success status codes must be returned as a response rather than an error
+    fn execute(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, HttpError>>;
+}
+
+/// Error returned by an [`HttpClient`] when a request couldn't be completed
+#[derive(Debug)]
+pub struct HttpError(Box<dyn Error + Send + Sync>);
+
+impl HttpError {
+    pub fn new(err: impl Into<Box<dyn Error + Send + Sync>>) -> Self {
+        Self(err.into())
+    }
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "HTTP request failed: {}", self.0)
+    }
+}
+
+impl Error for HttpError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.0.as_ref())
+    }
+}
+
+#[cfg(feature = "reqwest")]
+impl HttpClient for reqwest::Client {
+    fn execute(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, HttpError>> {
+        Box::pin(async move {
+            let response = self
+                .request(request.method, request.url.as_str())
+                .headers(request.headers)
+                .body(request.body)
+                .send()
+                .await
+                .map_err(HttpError::new)?;
+
+            let status_code = response.status();
+            let headers = response.headers().to_owned();
+            let body = response.bytes().await.map_err(HttpError::new)?.to_vec();
+
+            Ok(HttpResponse {
+                status_code,
+                headers,
+                body,
+            })
+        })
+    }
+}
+
+impl HttpRequest {
+    pub(crate) fn from_oauth2(request: oauth2::HttpRequest) -> Result<Self, HttpError> {
+        let (parts, body) = request.into_parts();
+
+        Ok(HttpRequest {
+            method: parts.method,
+            url: Url::parse(&parts.uri.to_string()).map_err(HttpError::new)?,
+            headers: parts.headers,
+            body,
+        })
+    }
+}
+
+impl HttpResponse {
+    pub(crate) fn into_oauth2(self) -> oauth2::HttpResponse {
+        let mut http_response = oauth2::HttpResponse::new(self.body);
+        *http_response.status_mut() = self.status_code;
+        *http_response.headers_mut() = self.headers;
+
+        http_response
+    }
+}
+
+/// Creates the `reqwest::Client` used by default, redirects are disabled to prevent SSRF vulnerabilities
+#[cfg(feature = "reqwest")]
+pub(crate) fn default_http_client() -> reqwest::Client {
+    let builder = reqwest::Client::builder();
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let builder = builder.redirect(reqwest::redirect::Policy::none());
+
+    builder.build().expect("Failed to build reqwest client")
+}
+
+/// Extracts the token of an `Authorization: Bearer <token>` header value
+#[cfg(any(
+    feature = "actix",
+    feature = "axum",
+    feature = "poem",
+    feature = "rocket",
+    feature = "tower",
+    feature = "warp"
+))]
+pub(crate) fn bearer_token(authorization: &str) -> Option<&str> {
+    let (scheme, token) = authorization.split_once(' ')?;
+
+    scheme
+        .eq_ignore_ascii_case("bearer")
+        .then(|| token.trim())
+        .filter(|token| !token.is_empty())
+}
+
+/// `WWW-Authenticate` challenge of responses to tokens which are missing required scopes, see RFC 6750
+#[cfg(any(feature = "actix", feature = "poem", feature = "tower"))]
+pub(crate) fn insufficient_scope(missing_scopes: &[String]) -> String {
+    format!(
+        "Bearer error=\"insufficient_scope\", scope=\"{}\"",
+        missing_scopes.join(" ")
+    )
+}
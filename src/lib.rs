@@ -1,20 +1,159 @@
+pub mod events;
+pub mod http;
 pub mod models;
+pub mod retry;
+pub mod scopes;
+pub mod state;
+pub mod store;
+pub mod validation;
+
+#[cfg(feature = "actix")]
+pub mod actix;
+#[cfg(feature = "axum")]
+pub mod axum;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+#[cfg(feature = "esi")]
+pub mod esi;
+#[cfg(feature = "loopback")]
+pub mod loopback;
+#[cfg(feature = "openidconnect")]
+pub mod oidc;
+#[cfg(feature = "poem")]
+pub mod poem;
+#[cfg(feature = "reqwest-middleware")]
+pub mod reqwest_middleware;
+#[cfg(feature = "rocket")]
+pub mod rocket;
+#[cfg(feature = "tauri")]
+pub mod tauri;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "tower")]
+pub mod tower;
+#[cfg(feature = "tower-sessions")]
+pub mod tower_sessions;
+#[cfg(feature = "warp")]
+pub mod warp;
+
+#[cfg(feature = "token-cache")]
+mod cache;
+mod client;
+mod config;
+#[cfg(any(feature = "time", feature = "chrono"))]
+mod datetime;
+mod error;
+mod login;
+mod manager;
+mod metrics;
+#[cfg(feature = "tokio")]
+mod rate_limit;
+mod registry;
+mod secret;
+mod token;
+
+#[cfg(feature = "token-cache")]
+pub use cache::TokenCache;
+pub use client::{EveSsoClient, EveSsoClientBuilder, TokenAuthMethod};
+pub use config::EveSsoConfig;
+#[cfg(feature = "debug-responses")]
+pub use error::RawResponse;
+pub use error::{Error, ErrorClass, ResponseCapture};
+pub use login::LoginUrlBuilder;
+pub use manager::{RefreshReport, TokenManager};
+#[cfg(feature = "tokio")]
+pub use rate_limit::RateLimiter;
+pub use registry::EveSsoRegistry;
+pub use secret::SecretString;
+pub use state::verify_state;
+pub use token::EveTokenResponse;
+
+use std::fmt;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::RwLock;
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
 
-use cached::proc_macro::cached;
-use jsonwebtoken::errors::ErrorKind;
 use jsonwebtoken::{DecodingKey, TokenData, Validation};
 use oauth2::basic::BasicClient;
-use oauth2::reqwest::async_http_client;
 use oauth2::{
-    AuthUrl, AuthorizationCode, ClientId, ClientSecret, CsrfToken, EmptyExtraTokenFields,
-    RedirectUrl, Scope, StandardTokenResponse, TokenUrl,
+    AuthUrl, ClientId, ClientSecret, CsrfToken, EndpointNotSet, EndpointSet, PkceCodeChallenge,
+    RedirectUrl, Scope, TokenUrl,
 };
+use serde::{Deserialize, Serialize};
+use web_time::{SystemTime, UNIX_EPOCH};
 
-use models::{EveJwtClaims, EveJwtKey, EveJwtKeys, EveSsoMetaData};
+use models::{
+    CallbackParams, CallbackResult, EveJwtClaims, EveJwtKey, EveJwtKeys, EveSsoMetaData,
+    SsoEndpoints,
+};
+use scopes::ScopeSet;
+use secret::redact;
+use state::DEFAULT_STATE_TTL;
+use validation::TokenValidation;
+
+pub(crate) const ISSUER: &str = "https://login.eveonline.com";
+pub(crate) const AUTHORIZE_URL: &str = "https://login.eveonline.com/v2/oauth/authorize/";
+pub(crate) const TOKEN_URL: &str = "https://login.eveonline.com/v2/oauth/token";
+#[cfg(any(feature = "reqwest-middleware", feature = "tower"))]
+pub(crate) const ESI_HOST: &str = "esi.evetech.net";
+pub(crate) const ESI_URL: &str = "https://esi.evetech.net/";
+pub(crate) const SSO_META_DATA_URL: &str =
+    "https://login.eveonline.com/.well-known/oauth-authorization-server";
 
+/// Login URL & everything needed to complete the login in the callback, store it e.g. in the user's session
+///
+/// created_at & expires_at are unix timestamps in seconds, the login should be completed within 10 minutes. Its `Debug`
+/// output redacts the code verifier.
+#[derive(Clone, Serialize, Deserialize)]
 pub struct AuthenticationData {
     pub login_url: String,
     pub state: String,
+    /// PKCE code verifier, only set for logins created with `create_login_url_pkce`
+    pub code_verifier: Option<String>,
+    /// When the login URL was created
+    pub created_at: u64,
+    /// Recommended expiry of the state & code verifier, reject callbacks arriving later
+    pub expires_at: u64,
+}
+
+impl fmt::Debug for AuthenticationData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthenticationData")
+            .field("login_url", &self.login_url)
+            .field("state", &self.state)
+            .field("code_verifier", &redact(&self.code_verifier))
+            .field("created_at", &self.created_at)
+            .field("expires_at", &self.expires_at)
+            .finish()
+    }
+}
+
+impl AuthenticationData {
+    fn new(login_url: String, state: String, code_verifier: Option<String>) -> Self {
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        Self {
+            login_url,
+            state,
+            code_verifier,
+            created_at,
+            expires_at: created_at + DEFAULT_STATE_TTL.as_secs(),
+        }
+    }
+
+    /// Whether the recommended expiry has passed & the callback should be rejected
+    pub fn is_expired(&self) -> bool {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        now >= Duration::from_secs(self.expires_at)
+    }
 }
 
 /// Generates a state verification string & authentication URL for EVE Online SSO which you use to redirect your user to EVE's login.
@@ -22,40 +161,45 @@ pub struct AuthenticationData {
 ///
 /// Takes client_id & client_secret variables which you get from your EVE developer application (https://developers.eveonline.com/).
 /// redirect_url specifies where your callback is to handle the authorization code, this must match the one in your developer appliacation!
-/// scopes is a vec of scopes which represent the permissions you need from that character such as reading assets or wallet data, these must match the ones in your developer application!
+/// scopes are the scopes, e.g. an array of `&str`s or a `Vec<String>`, which represent the permissions you need from that character such as reading assets or wallet data, these must match the ones in your developer application!
+/// Duplicate scopes are removed & the scopes are sorted, so the login URL doesn't depend on their order.
+///
+/// Panics if redirect_url is invalid, see [`validate_redirect_url`] for the permitted URLs.
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(client_id = client_id.as_ref(), redirect_url = redirect_url.as_ref())))]
 pub fn create_login_url(
-    client_id: String,
-    client_secret: String,
-    redirect_url: String,
-    scopes: Vec<String>,
+    client_id: impl AsRef<str>,
+    client_secret: impl Into<String>,
+    redirect_url: impl AsRef<str>,
+    scopes: impl IntoIterator<Item = impl Into<String>>,
 ) -> AuthenticationData {
-    fn convert_scopes(scopes: Vec<String>) -> Vec<Scope> {
-        scopes.iter().map(|s| Scope::new(s.clone())).collect()
-    }
-
-    let client = BasicClient::new(
-        ClientId::new(client_id),
-        Some(ClientSecret::new(client_secret)),
-        AuthUrl::new("https://login.eveonline.com/v2/oauth/authorize/".to_string())
-            .expect("Failed to create new authorization url"),
-        Some(
-            TokenUrl::new("https://login.eveonline.com/v2/oauth/token".to_string())
-                .expect("Failed to create new EVE oauth token URL"),
-        ),
+    login_url(
+        eve_client(client_id.as_ref().to_string(), Some(client_secret.into())),
+        redirect_url.as_ref(),
+        scopes,
+        CsrfToken::new_random(),
     )
-    .set_redirect_uri(RedirectUrl::new(redirect_url).expect("Failed to set redirect_uri"));
-
-    let scopes = convert_scopes(scopes);
-
-    let (eve_oauth_url, csrf_token) = client
-        .authorize_url(CsrfToken::new_random)
-        .add_scopes(scopes)
-        .url();
+}
 
-    AuthenticationData {
-        login_url: eve_oauth_url.to_string(),
-        state: csrf_token.secret().to_string(),
-    }
+/// Generates a state verification string, PKCE code verifier & authentication URL for EVE Online SSO
+///
+/// Use this for applications which can't keep the client secret private such as browser (wasm) frontends & desktop applications,
+/// your EVE developer application doesn't require a client secret for this flow.
+///
+/// Store the code_verifier alongside the state, you will need it to retrieve the access token with `get_access_token_pkce`.
+/// Native & mobile apps can use a custom URI scheme as redirect_url, e.g. `eveauth-myapp://callback`, panics if
+/// redirect_url is invalid, see [`validate_redirect_url`].
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(client_id = client_id.as_ref(), redirect_url = redirect_url.as_ref())))]
+pub fn create_login_url_pkce(
+    client_id: impl AsRef<str>,
+    redirect_url: impl AsRef<str>,
+    scopes: impl IntoIterator<Item = impl Into<String>>,
+) -> AuthenticationData {
+    login_url_pkce(
+        eve_client(client_id.as_ref().to_string(), None),
+        redirect_url.as_ref(),
+        scopes,
+        CsrfToken::new_random(),
+    )
 }
 
 /// Handles callback from EVE Online SSO
@@ -65,97 +209,482 @@ pub fn create_login_url(
 /// Redirect code is pulled from the GET request URL when the user is redirected to your callback route
 ///
 /// Returns the token which you can you retrieve the claims from using validate_token
+/// ```no_run
+/// # async fn example(client_id: String, client_secret: String, code: String) {
+/// use eve_oauth2::{get_access_token, validate_token};
+///
+/// let token = get_access_token(client_id, client_secret, code).await.unwrap();
+/// let token_claims = validate_token(token.access_token()).await.unwrap();
+/// # }
 /// ```
-/// let token_claims = validate_token(token.access_token().secret().to_string()).await;
-/// ```
+#[cfg(feature = "reqwest")]
 pub async fn get_access_token(
-    client_id: String,
-    client_secret: String,
-    code: String,
-) -> StandardTokenResponse<EmptyExtraTokenFields, oauth2::basic::BasicTokenType> {
-    let client = BasicClient::new(
-        ClientId::new(client_id),
-        Some(ClientSecret::new(client_secret)),
-        AuthUrl::new("https://login.eveonline.com/v2/oauth/authorize/".to_string())
-            .expect("Failed to create new authorization url"),
-        Some(
-            TokenUrl::new("https://login.eveonline.com/v2/oauth/token".to_string())
-                .expect("Failed to create new EVE oauth token URL"),
-        ),
-    );
+    client_id: impl Into<String>,
+    client_secret: impl Into<String>,
+    code: impl Into<String>,
+) -> Result<EveTokenResponse, Error> {
+    EveSsoClient::new(client_id, Some(client_secret.into()))
+        .get_access_token(code)
+        .await
+}
 
-    client
-        .exchange_code(AuthorizationCode::new(code.to_string()))
-        .request_async(async_http_client)
+/// Handles callback from EVE Online SSO for logins created with `create_login_url_pkce`
+///
+/// Takes the code_verifier returned alongside the login URL instead of a client secret.
+#[cfg(feature = "reqwest")]
+pub async fn get_access_token_pkce(
+    client_id: impl Into<String>,
+    code: impl Into<String>,
+    code_verifier: impl Into<String>,
+) -> Result<EveTokenResponse, Error> {
+    EveSsoClient::new(client_id, None)
+        .get_access_token_pkce(code, code_verifier)
+        .await
+}
+
+/// Retrieves a new access token using the refresh token of a previous token response
+///
+/// EVE Online SSO rotates refresh tokens, make sure to store the refresh token of the returned token response
+/// as the one provided will no longer be usable.
+///
+/// Use [`Error::classify`] on failure to decide whether to retry or have the character log in again.
+#[cfg(feature = "reqwest")]
+pub async fn refresh(
+    client_id: impl Into<String>,
+    client_secret: impl Into<String>,
+    refresh_token: impl Into<String>,
+) -> Result<EveTokenResponse, Error> {
+    EveSsoClient::new(client_id, Some(client_secret.into()))
+        .refresh(refresh_token)
+        .await
+}
+
+/// Retrieves a new access token limited to a subset of the scopes originally granted to the refresh token
+///
+/// Use this to mint narrowly scoped access tokens for specific jobs, all originally granted scopes are requested
+/// if scopes is empty. As with `refresh`, store the refresh token of the returned token response.
+#[cfg(feature = "reqwest")]
+pub async fn refresh_with_scopes(
+    client_id: impl Into<String>,
+    client_secret: impl Into<String>,
+    refresh_token: impl Into<String>,
+    scopes: impl IntoIterator<Item = impl Into<String>>,
+) -> Result<EveTokenResponse, Error> {
+    EveSsoClient::new(client_id, Some(client_secret.into()))
+        .refresh_with_scopes(refresh_token, scopes)
         .await
-        .expect("Failed to get token using redirect_code")
 }
 
 /// Validates a token which can be retrieved using `get_access_token`
 ///
 /// On successful validation it will return the EVE JWT claims
-pub async fn validate_token(token: String) -> TokenData<EveJwtClaims> {
-    #[cached(time = 10800)]
-    async fn get_eve_jwt_keys() -> EveJwtKeys {
-        let sso_meta_data_url =
-            "https://login.eveonline.com/.well-known/oauth-authorization-server";
-
-        let res: EveSsoMetaData = reqwest::Client::new()
-            .get(sso_meta_data_url)
-            .send()
-            .await
-            .expect("Failed to get EveSsoMetaData")
-            .json()
-            .await
-            .expect("Failed to deserialize EveSsoMetaData");
-
-        reqwest::Client::new()
-            .get(res.jwks_uri)
-            .send()
-            .await
-            .expect("Failed to get EveJwtKeys")
-            .json()
-            .await
-            .expect("Failed to deserialize EveJwtKeys")
-    }
-
-    let jwk_key =
-        select_key(get_eve_jwt_keys().await.keys).expect("Failed to find RS256 EveJwtKey");
-
-    let jwk_n: String;
-    let jwk_e: String;
-
-    if let EveJwtKey::RS256 {
-        e,
-        kid: _,
-        kty: _,
-        n,
-        r#use: _,
-    } = jwk_key
+///
+/// The EVE JWT keys are cached for 3 hours, except on wasm targets where they are retrieved on every validation.
+#[cfg(feature = "reqwest")]
+pub async fn validate_token(token: impl Into<String>) -> Result<TokenData<EveJwtClaims>, Error> {
+    EveSsoClient::new(String::new(), None)
+        .validate_token(token)
+        .await
+}
+
+/// Validates a token & applies the additional checks of the validation options, e.g. required scopes
+#[cfg(feature = "reqwest")]
+pub async fn validate_token_with(
+    token: impl Into<String>,
+    validation: &TokenValidation,
+) -> Result<TokenData<EveJwtClaims>, Error> {
+    EveSsoClient::new(String::new(), None)
+        .validate_token_with(token, validation)
+        .await
+}
+
+/// Validates a token with EVE JWT keys you retrieved yourself instead of requesting them from EVE Online SSO
+///
+/// Validation doesn't send any requests, e.g. to validate tokens minted by the `TestTokenSigner` of the `testing`
+/// feature in unit tests.
+/// The `azp` claim is only checked if the validation options require a client_id.
+pub fn validate_token_with_keys(
+    token: &str,
+    keys: &EveJwtKeys,
+    validation: &TokenValidation,
+) -> Result<TokenData<EveJwtClaims>, Error> {
+    let result = decode_token(token, keys.keys.clone(), validation);
+
+    metrics::record(metrics::Operation::Validation, &result);
+
+    result
+}
+
+/// Decodes the header & claims of a token WITHOUT verifying its signature, expiry, audience or issuer
+///
+/// Never trust the returned claims for authentication, use `validate_token` for that. This is meant for inspecting
+/// tokens which fail validation, e.g. finding the character of an expired token to ask it to log in again.
+pub fn decode_unverified(token: &str) -> Result<TokenData<EveJwtClaims>, Error> {
+    let mut validation = Validation::new(jsonwebtoken::Algorithm::RS256);
+    validation.insecure_disable_signature_validation();
+    validation.validate_exp = false;
+    validation.validate_aud = false;
+    validation.required_spec_claims.clear();
+
+    Ok(jsonwebtoken::decode(
+        token,
+        &DecodingKey::from_secret(&[]),
+        &validation,
+    )?)
+}
+
+/// Describes a token for support tooling WITHOUT verifying it, see [`models::TokenDescription`]
+///
+/// The description contains the character, scopes grouped by category, expiry countdown, issuer, server & key id &
+/// can be printed or serialized to JSON. Like [`decode_unverified`], don't use it for authentication.
+///
+/// ```no_run
+/// # fn example(token: &str) -> Result<(), eve_oauth2::Error> {
+/// let description = eve_oauth2::describe_token(token)?;
+///
+/// println!("{}", description);
+/// println!("{}", serde_json::to_string_pretty(&description).unwrap());
+/// # Ok(())
+/// # }
+/// ```
+pub fn describe_token(token: &str) -> Result<models::TokenDescription, Error> {
+    let token_data = decode_unverified(token)?;
+
+    Ok(models::TokenDescription::new(
+        &token_data.header,
+        &token_data.claims,
+    ))
+}
+
+/// Schemes which can't be used as redirect URLs as they'd run code or open local content in the browser
+const FORBIDDEN_REDIRECT_SCHEMES: [&str; 6] =
+    ["javascript", "data", "file", "blob", "about", "vbscript"];
+
+/// Validates a redirect URL before it's used in a login URL
+///
+/// Besides `http` & `https` callbacks, custom URI schemes of native & mobile apps such as `eveauth-myapp://callback`
+/// are permitted. Fails with [`Error::InvalidRedirectUrl`] if the URL isn't absolute, has a fragment, uses a scheme
+/// such as `javascript` or `file` or a custom scheme URL has neither a host nor a path.
+///
+/// ```
+/// eve_oauth2::validate_redirect_url("http://localhost:8080/callback")?;
+/// eve_oauth2::validate_redirect_url("eveauth-myapp://callback")?;
+/// eve_oauth2::validate_redirect_url("com.example.app:/callback")?;
+///
+/// assert!(eve_oauth2::validate_redirect_url("/callback").is_err());
+/// assert!(eve_oauth2::validate_redirect_url("eveauth-myapp://callback#fragment").is_err());
+/// assert!(eve_oauth2::validate_redirect_url("javascript:alert(1)").is_err());
+/// # Ok::<(), eve_oauth2::Error>(())
+/// ```
+pub fn validate_redirect_url(redirect_url: &str) -> Result<http::Url, Error> {
+    let url = http::Url::parse(redirect_url)
+        .map_err(|err| Error::InvalidRedirectUrl(format!("{}: {}", redirect_url, err)))?;
+
+    if url.fragment().is_some() {
+        return Err(Error::InvalidRedirectUrl(format!(
+            "{} has a fragment",
+            redirect_url
+        )));
+    }
+
+    if FORBIDDEN_REDIRECT_SCHEMES.contains(&url.scheme()) {
+        return Err(Error::InvalidRedirectUrl(format!(
+            "{} uses the {} scheme",
+            redirect_url,
+            url.scheme()
+        )));
+    }
+
+    let custom_scheme = !matches!(url.scheme(), "http" | "https");
+
+    if custom_scheme && url.host().is_none() && url.path().trim_start_matches('/').is_empty() {
+        return Err(Error::InvalidRedirectUrl(format!(
+            "{} has neither a host nor a path",
+            redirect_url
+        )));
+    }
+
+    Ok(url)
+}
+
+/// Parses the full callback URL or its raw query string, e.g. for CLI tools & desktop apps without a web framework
+///
+/// Callback URLs of custom URI schemes, e.g. `eveauth-myapp://callback?code=...`, are parsed the same way. Fails with
+/// [`Error::UnexpectedResponse`] if the callback has neither an `error` nor a `code` & `state`.
+/// ```
+/// use eve_oauth2::models::CallbackResult;
+///
+/// let callback = eve_oauth2::parse_callback_url("http://localhost:8000/callback?code=abc&state=xyz")?;
+/// assert_eq!(callback, CallbackResult::Success { code: "abc".to_string(), state: "xyz".to_string() });
+///
+/// let callback = eve_oauth2::parse_callback_url("?error=access_denied&state=xyz")?;
+/// assert!(callback.is_access_denied());
+/// # Ok::<(), eve_oauth2::Error>(())
+/// ```
+pub fn parse_callback_url(callback: &str) -> Result<CallbackResult, Error> {
+    let query = match http::Url::parse(callback) {
+        Ok(url) => url.query().unwrap_or_default().to_string(),
+        Err(_) => callback.trim_start_matches('?').to_string(),
+    };
+
+    let mut params = CallbackParams::default();
+
+    for (key, value) in url::form_urlencoded::parse(query.as_bytes()) {
+        match key.as_ref() {
+            "code" => params.code = Some(value.into_owned()),
+            "state" => params.state = Some(value.into_owned()),
+            "error" => params.error = Some(value.into_owned()),
+            "error_description" => params.error_description = Some(value.into_owned()),
+            _ => {}
+        }
+    }
+
+    CallbackResult::try_from(params)
+}
+
+/// Opens the login URL in the system's default browser, enabled with the `browser` feature
+///
+/// Combine it with `loopback::LocalCallbackServer` of the `loopback` feature for an interactive login in desktop & CLI
+/// applications.
+///
+/// ```no_run
+/// # async fn example(client: eve_oauth2::EveSsoClient) -> Result<(), Box<dyn std::error::Error>> {
+/// let auth = client.create_login_url_pkce(
+///     "http://localhost:8080/callback".to_string(),
+///     vec!["publicData".to_string()],
+/// );
+///
+/// if eve_oauth2::open_login_url(&auth).is_err() {
+///     println!("Log in at {}", auth.login_url);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "browser")]
+pub fn open_login_url(auth: &AuthenticationData) -> std::io::Result<()> {
+    open::that_detached(&auth.login_url)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+const EVE_JWT_KEYS_TTL: Duration = Duration::from_secs(10800);
+
+#[cfg(not(target_arch = "wasm32"))]
+static EVE_JWT_KEYS: RwLock<Option<(Instant, String, EveJwtKeys)>> = RwLock::new(None);
+
+/// Returns the cached EVE JWT keys if they were retrieved from the JWKS URI less than 3 hours ago
+pub(crate) fn cached_eve_jwt_keys(jwks_uri: &str) -> Option<EveJwtKeys> {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some((retrieved_at, uri, keys)) = EVE_JWT_KEYS.read().unwrap().as_ref() {
+        if uri == jwks_uri && retrieved_at.elapsed() < EVE_JWT_KEYS_TTL {
+            return Some(keys.clone());
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    let _ = jwks_uri;
+
+    None
+}
+
+pub(crate) fn cache_eve_jwt_keys(jwks_uri: &str, keys: &EveJwtKeys) {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        *EVE_JWT_KEYS.write().unwrap() = Some((Instant::now(), jwks_uri.to_string(), keys.clone()));
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    let _ = (jwks_uri, keys);
+}
+
+/// Default time the EVE Online SSO metadata is cached for
+pub(crate) const SSO_META_DATA_TTL: Duration = Duration::from_secs(86400);
+
+#[cfg(not(target_arch = "wasm32"))]
+static SSO_META_DATA: RwLock<Option<(Instant, EveSsoMetaData)>> = RwLock::new(None);
+
+/// Returns the cached EVE Online SSO metadata if it was retrieved within the TTL
+pub(crate) fn cached_sso_metadata(ttl: Duration) -> Option<EveSsoMetaData> {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some((retrieved_at, metadata)) = SSO_META_DATA.read().unwrap().as_ref() {
+        if retrieved_at.elapsed() < ttl {
+            return Some(metadata.clone());
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    let _ = ttl;
+
+    None
+}
+
+pub(crate) fn cache_sso_metadata(metadata: &EveSsoMetaData) {
+    #[cfg(not(target_arch = "wasm32"))]
     {
-        jwk_n = n;
-        jwk_e = e;
+        *SSO_META_DATA.write().unwrap() = Some((Instant::now(), metadata.clone()));
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    let _ = metadata;
+}
+
+/// `oauth2` client with the authorization & token endpoints of EVE Online SSO set
+pub(crate) type EveOAuthClient =
+    BasicClient<EndpointSet, EndpointNotSet, EndpointNotSet, EndpointNotSet, EndpointSet>;
+
+pub(crate) fn eve_client(client_id: String, client_secret: Option<String>) -> EveOAuthClient {
+    eve_client_with(&SsoEndpoints::default(), client_id, client_secret)
+}
+
+/// Creates the `oauth2` client using the authorization & token endpoints
+pub(crate) fn eve_client_with(
+    endpoints: &SsoEndpoints,
+    client_id: String,
+    client_secret: Option<String>,
+) -> EveOAuthClient {
+    let client = BasicClient::new(ClientId::new(client_id))
+        .set_auth_uri(AuthUrl::from_url(endpoints.authorization_endpoint.clone()))
+        .set_token_uri(TokenUrl::from_url(endpoints.token_endpoint.clone()));
+
+    match client_secret {
+        Some(client_secret) => client.set_client_secret(ClientSecret::new(client_secret)),
+        None => client,
+    }
+}
+
+/// Creates a login URL using the authorization endpoint of the oauth2 client
+pub(crate) fn login_url(
+    client: EveOAuthClient,
+    redirect_url: &str,
+    scopes: impl IntoIterator<Item = impl Into<String>>,
+    state: CsrfToken,
+) -> AuthenticationData {
+    login_url_with(client, redirect_url, scopes, state, false, &[])
+}
+
+/// Creates a PKCE login URL using the authorization endpoint of the oauth2 client
+pub(crate) fn login_url_pkce(
+    client: EveOAuthClient,
+    redirect_url: &str,
+    scopes: impl IntoIterator<Item = impl Into<String>>,
+    state: CsrfToken,
+) -> AuthenticationData {
+    login_url_with(client, redirect_url, scopes, state, true, &[])
+}
+
+/// Creates a login URL with the extra query parameters, using PKCE if pkce is set
+///
+/// Scopes are deduplicated & sorted so the same scopes always result in the same `scope` parameter.
+pub(crate) fn login_url_with(
+    client: EveOAuthClient,
+    redirect_url: &str,
+    scopes: impl IntoIterator<Item = impl Into<String>>,
+    state: CsrfToken,
+    pkce: bool,
+    extra_params: &[(String, String)],
+) -> AuthenticationData {
+    let client = client.set_redirect_uri(redirect_uri(redirect_url));
+
+    let mut request = client
+        .authorize_url(|| state)
+        .add_scopes(convert_scopes(scopes.into_iter().collect::<ScopeSet>()));
+
+    for (name, value) in extra_params {
+        request = request.add_extra_param(name.as_str(), value.as_str());
+    }
+
+    let mut code_verifier = None;
+
+    if pkce {
+        let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+        request = request.set_pkce_challenge(pkce_challenge);
+        code_verifier = Some(pkce_verifier.secret().to_string());
+    }
+
+    let (eve_oauth_url, csrf_token) = request.url();
+
+    #[cfg(feature = "tracing")]
+    if pkce {
+        tracing::debug!("Created EVE SSO PKCE login URL");
     } else {
-        panic!("Failed to get JWT key values!")
+        tracing::debug!("Created EVE SSO login URL");
     }
 
+    metrics::record_login();
+
+    AuthenticationData::new(
+        eve_oauth_url.to_string(),
+        csrf_token.secret().to_string(),
+        code_verifier,
+    )
+}
+
+/// Validates the redirect URL of a login, panics if it's invalid like the login URL functions document
+fn redirect_uri(redirect_url: &str) -> RedirectUrl {
+    match validate_redirect_url(redirect_url) {
+        Ok(url) => RedirectUrl::from_url(url),
+        Err(err) => panic!("Failed to set redirect_uri: {}", err),
+    }
+}
+
+pub(crate) fn convert_scopes(scopes: impl IntoIterator<Item = impl Into<String>>) -> Vec<Scope> {
+    scopes
+        .into_iter()
+        .map(|scope| Scope::new(scope.into()))
+        .collect()
+}
+
+pub(crate) fn decode_token(
+    token: &str,
+    keys: Vec<EveJwtKey>,
+    token_validation: &TokenValidation,
+) -> Result<TokenData<EveJwtClaims>, Error> {
+    decode_token_with_key(token, &decoding_key(keys)?, token_validation)
+}
+
+/// Validates the tokens with a decoding key created once by [`decoding_key`] & returns the result of each token in order
+pub(crate) fn decode_tokens(
+    tokens: &[String],
+    key: &DecodingKey,
+    token_validation: &TokenValidation,
+) -> Vec<Result<TokenData<EveJwtClaims>, Error>> {
+    tokens
+        .iter()
+        .map(|token| decode_token_with_key(token, key, token_validation))
+        .collect()
+}
+
+pub(crate) fn decoding_key(keys: Vec<EveJwtKey>) -> Result<DecodingKey, Error> {
+    let Some(EveJwtKey::RS256 { e, n, .. }) = select_key(keys) else {
+        return Err(Error::MissingJwtKey);
+    };
+
+    DecodingKey::from_rsa_components(&n, &e).map_err(Error::from)
+}
+
+fn decode_token_with_key(
+    token: &str,
+    key: &DecodingKey,
+    token_validation: &TokenValidation,
+) -> Result<TokenData<EveJwtClaims>, Error> {
     let mut validation = Validation::new(jsonwebtoken::Algorithm::RS256);
     validation.set_audience(&["EVE Online"]);
-    validation.set_issuer(&["https://login.eveonline.com"]);
+    validation.set_issuer(&[ISSUER]);
 
-    match jsonwebtoken::decode::<EveJwtClaims>(
-        &token,
-        &DecodingKey::from_rsa_components(&jwk_n, &jwk_e)
-            .expect("Failed to generate decoding key from EveJwtKey"),
-        &validation,
-    ) {
-        Ok(c) => c,
-        Err(err) => match *err.kind() {
-            ErrorKind::InvalidToken => panic!("Token is invalid"),
-            ErrorKind::InvalidIssuer => panic!("Issuer is invalid"),
-            _ => panic!("Unknown token error: {:?}", err),
-        },
+    let result = jsonwebtoken::decode::<EveJwtClaims>(token, key, &validation)
+        .map_err(Error::from)
+        .and_then(|token_data| {
+            token_validation.check(&token_data.claims)?;
+
+            Ok(token_data)
+        });
+
+    #[cfg(feature = "tracing")]
+    match &result {
+        Ok(c) => tracing::debug!(character = %c.claims.sub, "Validated EVE JWT"),
+        Err(err) => tracing::warn!(error = %err, "Failed to validate EVE JWT"),
     }
+
+    result
 }
 
 fn select_key(keys: Vec<EveJwtKey>) -> Option<EveJwtKey> {
@@ -174,3 +703,358 @@ fn select_key(keys: Vec<EveJwtKey>) -> Option<EveJwtKey> {
 
     None
 }
+
+#[cfg(feature = "tracing")]
+pub(crate) fn trace_token_result(operation: &str, result: &Result<EveTokenResponse, Error>) {
+    match result {
+        Ok(token) => tracing::debug!(
+            operation,
+            expires_in = ?token.expires_in(),
+            "EVE SSO token request succeeded"
+        ),
+        Err(err) => tracing::warn!(operation, error = %err, "EVE SSO token request failed"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "testing")]
+    use crate::testing::TestTokenSigner;
+
+    #[test]
+    fn validates_redirect_urls() {
+        for redirect_url in [
+            "http://localhost:8080/callback",
+            "https://example.com/callback?app=1",
+            "eveauth-myapp://callback",
+            "com.example.app:/callback",
+        ] {
+            assert!(
+                validate_redirect_url(redirect_url).is_ok(),
+                "{redirect_url}"
+            );
+        }
+
+        for redirect_url in [
+            "/callback",
+            "https://example.com/callback#fragment",
+            "javascript:alert(1)",
+            "data:text/html,<script>alert(1)</script>",
+            "file:///etc/passwd",
+            "eveauth-myapp:",
+            "eveauth-myapp:///",
+        ] {
+            assert!(
+                matches!(
+                    validate_redirect_url(redirect_url),
+                    Err(Error::InvalidRedirectUrl(_))
+                ),
+                "{redirect_url}"
+            );
+        }
+    }
+
+    #[test]
+    fn login_urls_keep_custom_scheme_redirect_urls() {
+        let login = EveSsoClient::builder("client_id")
+            .build()
+            .create_login_url_pkce("eveauth-myapp://callback", ["publicData"]);
+
+        assert!(login
+            .login_url
+            .contains("redirect_uri=eveauth-myapp%3A%2F%2Fcallback"));
+    }
+
+    #[test]
+    fn authentication_data_expires_with_the_state() {
+        let mut login = create_login_url_pkce(
+            "client_id",
+            "http://localhost:8080/callback",
+            ["publicData"],
+        );
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        assert!(now - login.created_at < 60);
+        assert_eq!(
+            login.expires_at,
+            login.created_at + DEFAULT_STATE_TTL.as_secs()
+        );
+        assert!(!login.is_expired());
+
+        login.expires_at = now - 1;
+        assert!(login.is_expired());
+    }
+
+    #[test]
+    fn authentication_data_round_trips_through_serde() {
+        let login = create_login_url_pkce(
+            "client_id",
+            "http://localhost:8080/callback",
+            ["publicData"],
+        );
+
+        let stored: AuthenticationData =
+            serde_json::from_str(&serde_json::to_string(&login).unwrap()).unwrap();
+
+        assert_eq!(stored.login_url, login.login_url);
+        assert_eq!(stored.state, login.state);
+        assert_eq!(stored.code_verifier, login.code_verifier);
+        assert_eq!(stored.created_at, login.created_at);
+        assert_eq!(stored.expires_at, login.expires_at);
+        assert!(!format!("{:?}", login).contains(login.code_verifier.as_deref().unwrap()));
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn pkce_login_urls_carry_the_challenge_of_the_code_verifier() {
+        use std::collections::HashMap;
+
+        use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        use base64::Engine;
+        use sha2::{Digest, Sha256};
+
+        let login = create_login_url_pkce(
+            "client_id",
+            "http://localhost:8080/callback",
+            ["publicData"],
+        );
+        let params: HashMap<_, _> = http::Url::parse(&login.login_url)
+            .unwrap()
+            .query_pairs()
+            .into_owned()
+            .collect();
+        let code_verifier = login.code_verifier.unwrap();
+
+        assert_eq!(params["code_challenge_method"], "S256");
+        assert_eq!(
+            params["code_challenge"],
+            URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+        );
+        assert_eq!(params["state"], login.state);
+
+        let login = create_login_url(
+            "client_id",
+            "client_secret",
+            "http://localhost:8080/callback",
+            ["publicData"],
+        );
+        assert!(login.code_verifier.is_none());
+        assert!(!login.login_url.contains("code_challenge"));
+    }
+
+    #[test]
+    fn login_urls_request_deduplicated_sorted_scopes() {
+        let scope = |login: AuthenticationData| {
+            http::Url::parse(&login.login_url)
+                .unwrap()
+                .query_pairs()
+                .find(|(name, _)| name == "scope")
+                .map(|(_, scope)| scope.into_owned())
+        };
+        let sorted = "esi-mail.read_mail.v1 esi-wallet.read_character_wallet.v1 publicData";
+
+        for scopes in [
+            vec![
+                "publicData",
+                "esi-wallet.read_character_wallet.v1",
+                "esi-mail.read_mail.v1",
+            ],
+            vec![
+                "esi-wallet.read_character_wallet.v1",
+                "publicData",
+                "esi-mail.read_mail.v1",
+                "publicData",
+            ],
+        ] {
+            let login = create_login_url(
+                "client_id",
+                "client_secret",
+                "http://localhost:8080/callback",
+                scopes.clone(),
+            );
+            assert_eq!(scope(login).as_deref(), Some(sorted));
+
+            let login =
+                create_login_url_pkce("client_id", "http://localhost:8080/callback", scopes);
+            assert_eq!(scope(login).as_deref(), Some(sorted));
+        }
+    }
+
+    #[test]
+    fn login_urls_accept_borrowed_and_owned_parameters() {
+        let (client_id, redirect_url) = (
+            "client_id".to_string(),
+            "http://localhost:8080/callback".to_string(),
+        );
+        let scope = "scope=esi-wallet.read_character_wallet.v1+publicData";
+        let scope_set =
+            scopes::ScopeSet::from_iter(["publicData", "esi-wallet.read_character_wallet.v1"]);
+
+        let logins = [
+            create_login_url(
+                "client_id",
+                "client_secret",
+                "http://localhost:8080/callback",
+                ["esi-wallet.read_character_wallet.v1", "publicData"],
+            ),
+            create_login_url(
+                &client_id,
+                "client_secret".to_string(),
+                &redirect_url,
+                vec![
+                    "esi-wallet.read_character_wallet.v1".to_string(),
+                    "publicData".to_string(),
+                ],
+            ),
+            create_login_url_pkce("client_id", "http://localhost:8080/callback", &scope_set),
+        ];
+
+        for login in logins {
+            assert!(login.login_url.contains("client_id=client_id"));
+            assert!(login
+                .login_url
+                .contains("redirect_uri=http%3A%2F%2Flocalhost%3A8080%2Fcallback"));
+            assert!(login.login_url.contains(scope), "{}", login.login_url);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Failed to set redirect_uri")]
+    fn login_urls_panic_on_forbidden_redirect_urls() {
+        EveSsoClient::builder("client_id")
+            .build()
+            .create_login_url_pkce("javascript:alert(1)", ["publicData"]);
+    }
+
+    #[test]
+    fn parses_callback_urls_and_query_strings() {
+        let success = CallbackResult::Success {
+            code: "a b".to_string(),
+            state: "xyz".to_string(),
+        };
+
+        for callback in [
+            "http://localhost:8080/callback?code=a+b&state=xyz",
+            "eveauth-myapp://callback?state=xyz&code=a%20b&unknown=1",
+            "?code=a+b&state=xyz",
+            "code=a+b&state=xyz",
+        ] {
+            assert_eq!(parse_callback_url(callback).unwrap(), success, "{callback}");
+        }
+
+        let denied = parse_callback_url(
+            "http://localhost:8080/callback?error=access_denied&error_description=Denied&state=xyz",
+        )
+        .unwrap();
+        assert!(denied.is_access_denied());
+        assert_eq!(denied.state(), Some("xyz"));
+
+        for callback in [
+            "http://localhost:8080/callback",
+            "http://localhost:8080/callback?code=abc",
+            "",
+        ] {
+            assert!(
+                matches!(
+                    parse_callback_url(callback),
+                    Err(Error::UnexpectedResponse(_))
+                ),
+                "{callback}"
+            );
+        }
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn decode_unverified_returns_claims_of_tokens_failing_validation() {
+        let signer = TestTokenSigner::shared();
+        let token = signer
+            .token()
+            .character_id(2114794365)
+            .client_id("other_client")
+            .expires_at(1_700_000_000)
+            .sign();
+
+        assert!(matches!(
+            validate_token_with_keys(&token, &signer.jwt_keys(), &TokenValidation::default()),
+            Err(Error::InvalidToken(_))
+        ));
+
+        let token_data = decode_unverified(&token).unwrap();
+        assert_eq!(token_data.header.kid.as_deref(), Some("JWT-Signature-Key"));
+        assert_eq!(token_data.claims.character_id().unwrap().0, 2114794365);
+        assert_eq!(token_data.claims.azp, "other_client");
+        assert_eq!(token_data.claims.exp, 1_700_000_000);
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn decode_unverified_ignores_the_signature_but_not_the_format() {
+        let signer = TestTokenSigner::generate();
+        let token = signer.token().character_id(2114794365).sign();
+
+        assert!(validate_token_with_keys(
+            &token,
+            &TestTokenSigner::shared().jwt_keys(),
+            &TokenValidation::default()
+        )
+        .is_err());
+        assert!(decode_unverified(&token).is_ok());
+
+        assert!(matches!(
+            decode_unverified("not a token"),
+            Err(Error::InvalidToken(_))
+        ));
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn describes_tokens_for_support_tooling() {
+        let token = TestTokenSigner::shared()
+            .token()
+            .character_id(2114794365)
+            .name("Tryn Voss")
+            .scopes([
+                "publicData",
+                "esi-wallet.read_character_wallet.v1",
+                "esi-wallet.read_corporation_wallets.v1",
+            ])
+            .expires_at(1_700_000_000)
+            .sign();
+
+        let description = describe_token(&token).unwrap();
+
+        assert_eq!(description.character_id, Some(2114794365));
+        assert_eq!(description.character_name, "Tryn Voss");
+        assert_eq!(
+            description.scopes.get("wallet").map(Vec::len),
+            Some(2),
+            "{:?}",
+            description.scopes
+        );
+        assert_eq!(
+            description.scopes.get("publicData"),
+            Some(&vec!["publicData".to_string()])
+        );
+        assert_eq!(description.key_id.as_deref(), Some("JWT-Signature-Key"));
+        assert_eq!(description.algorithm, "RS256");
+        assert!(description.is_expired());
+
+        let table = description.to_string();
+        assert!(table.contains("Character name  Tryn Voss\n"), "{}", table);
+        assert!(table.contains("expired "));
+
+        let json = serde_json::to_value(&description).unwrap();
+        assert_eq!(json["expires_at"], 1_700_000_000);
+
+        assert!(matches!(
+            describe_token("not a token"),
+            Err(Error::InvalidToken(_))
+        ));
+    }
+}
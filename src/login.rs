@@ -0,0 +1,163 @@
+use crate::{AuthenticationData, Error, EveSsoClient};
+
+/// Query parameters of the login URL which are set by the builder & can't be added as extra parameters
+const RESERVED_PARAMS: [&str; 7] = [
+    "response_type",
+    "client_id",
+    "redirect_uri",
+    "scope",
+    "state",
+    "code_challenge",
+    "code_challenge_method",
+];
+
+/// Builder for a login URL with extra query parameters, created with [`EveSsoClient::login_url_builder`]
+///
+/// The PKCE flow is used if the client has no client secret unless set with [`LoginUrlBuilder::pkce`].
+///
+/// ```no_run
+/// # fn example(client: eve_oauth2::EveSsoClient) {
+/// let login = client
+///     .login_url_builder("https://example.com/callback")
+///     .scopes(["publicData", "esi-wallet.read_character_wallet.v1"])
+///     .extra_param("utm_source", "discord")
+///     .build();
+///
+/// // Redirect the user to login.login_url & store login.state
+/// # }
+/// ```
+pub struct LoginUrlBuilder<'a> {
+    client: &'a EveSsoClient,
+    redirect_url: String,
+    scopes: Vec<String>,
+    pkce: Option<bool>,
+    extra_params: Vec<(String, String)>,
+}
+
+impl<'a> LoginUrlBuilder<'a> {
+    pub(crate) fn new(client: &'a EveSsoClient, redirect_url: String) -> Self {
+        Self {
+            client,
+            redirect_url,
+            scopes: Vec::new(),
+            pkce: None,
+            extra_params: Vec::new(),
+        }
+    }
+
+    /// Sets the URL EVE Online SSO redirects to after the login, panics on build if it's invalid like
+    /// [`crate::create_login_url`]
+    pub fn redirect_url(mut self, redirect_url: impl Into<String>) -> Self {
+        self.redirect_url = redirect_url.into();
+        self
+    }
+
+    /// Requests the scope
+    pub fn scope(mut self, scope: impl Into<String>) -> Self {
+        self.scopes.push(scope.into());
+        self
+    }
+
+    /// Requests the scopes along with those added before
+    pub fn scopes(mut self, scopes: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.scopes.extend(scopes.into_iter().map(Into::into));
+        self
+    }
+
+    /// Sets whether the PKCE flow is used, the returned data contains the code verifier if it is
+    pub fn pkce(mut self, pkce: bool) -> Self {
+        self.pkce = Some(pkce);
+        self
+    }
+
+    /// Appends a query parameter to the login URL, e.g. a tracking parameter consumed by your own redirect wrapper
+    ///
+    /// Panics if name is one of the OAuth2 parameters set by the builder such as `state` or `scope`.
+    pub fn extra_param(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        let name = name.into();
+
+        assert!(
+            !RESERVED_PARAMS.contains(&name.as_str()),
+            "{} is set by the login URL builder",
+            name
+        );
+
+        self.extra_params.push((name, value.into()));
+        self
+    }
+
+    /// Creates the login URL & state, panics if a scope is unknown & [`crate::scopes::ScopeCheck::Deny`] is set
+    pub fn build(self) -> AuthenticationData {
+        self.try_build().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Creates the login URL & state, fails with [`Error::Config`] if a scope is unknown &
+    /// [`crate::scopes::ScopeCheck::Deny`] is set
+    pub fn try_build(self) -> Result<AuthenticationData, Error> {
+        self.client.login_url_with(
+            &self.redirect_url,
+            self.scopes,
+            self.pkce,
+            &self.extra_params,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client() -> EveSsoClient {
+        EveSsoClient::builder("client_id")
+            .client_secret("client_secret")
+            .build()
+    }
+
+    #[test]
+    fn builds_login_urls_with_extra_params() {
+        let client = client();
+
+        let login = client
+            .login_url_builder("http://localhost:8080/unused")
+            .redirect_url("http://localhost:8080/callback")
+            .scope("publicData")
+            .scopes(["esi-wallet.read_character_wallet.v1"])
+            .extra_param("utm_source", "discord")
+            .extra_param("lang", "de")
+            .build();
+
+        assert!(login.code_verifier.is_none());
+        assert!(login
+            .login_url
+            .contains("redirect_uri=http%3A%2F%2Flocalhost%3A8080%2Fcallback"));
+        assert!(login
+            .login_url
+            .contains("scope=esi-wallet.read_character_wallet.v1+publicData"));
+        assert!(login.login_url.ends_with("&utm_source=discord&lang=de"));
+    }
+
+    #[test]
+    fn pkce_defaults_to_whether_the_client_has_a_secret() {
+        let client = client();
+        let login = client
+            .login_url_builder("http://localhost:8080/callback")
+            .pkce(true)
+            .build();
+        assert!(login.code_verifier.is_some());
+        assert!(login.login_url.contains("code_challenge_method=S256"));
+
+        let client = EveSsoClient::builder("client_id").build();
+        let login = client
+            .login_url_builder("http://localhost:8080/callback")
+            .build();
+        assert!(login.code_verifier.is_some());
+    }
+
+    #[test]
+    #[should_panic(expected = "state is set by the login URL builder")]
+    fn reserved_params_cant_be_added() {
+        let _ = client()
+            .login_url_builder("http://localhost:8080/callback")
+            .extra_param("state", "state");
+    }
+}
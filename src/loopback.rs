@@ -0,0 +1,269 @@
+//! Loopback callback server for desktop & CLI applications, enabled with the `loopback` feature
+//!
+//! [`LocalCallbackServer`] listens on `127.0.0.1` & waits for EVE Online SSO to redirect the browser to the callback
+//! URL, so native applications get the code & state without a web framework.
+//!
+//! ```no_run
+//! # async fn example(client: eve_oauth2::EveSsoClient) -> Result<(), eve_oauth2::Error> {
+//! use eve_oauth2::loopback::LocalCallbackServer;
+//!
+//! let server = LocalCallbackServer::bind(8080).await?;
+//! let auth = client.create_login_url_pkce(server.redirect_url(), vec!["publicData".to_string()]);
+//!
+//! println!("Log in at {}", auth.login_url);
+//!
+//! let code = server.wait_for_code(&auth).await?;
+//! let token = client.get_access_token_pkce(code, auth.code_verifier.unwrap()).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::error::Error;
+use crate::models::CallbackResult;
+use crate::{parse_callback_url, verify_state, AuthenticationData};
+
+const DEFAULT_PATH: &str = "/callback";
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(300);
+/// Time to wait for the request line of a connection, browsers open speculative connections which never send one
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Time to wait before accepting connections again after accepting one failed, e.g. with too many open files
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+const DEFAULT_SUCCESS_PAGE: &str =
+    "<!DOCTYPE html><html><body><p>Logged in, you can close this window.</p></body></html>";
+const DEFAULT_ERROR_PAGE: &str =
+    "<!DOCTYPE html><html><body><p>Login failed, you can close this window.</p></body></html>";
+
+/// Waits for the redirect of EVE Online SSO to `http://127.0.0.1:<port><path>`
+///
+/// The callback URL of your EVE developer application must match [`LocalCallbackServer::redirect_url`], bind port 0
+/// only if your application accepts any port. The loopback IP address is used rather than `localhost`, which may
+/// resolve to `::1` where the server doesn't listen.
+#[derive(Debug)]
+#[must_use]
+pub struct LocalCallbackServer {
+    listener: TcpListener,
+    port: u16,
+    path: String,
+    timeout: Duration,
+    success_page: String,
+    error_page: String,
+}
+
+impl LocalCallbackServer {
+    /// Listens on `127.0.0.1` on the port, port 0 selects a free port
+    ///
+    /// Bind before opening the login URL so the redirect can't arrive before the server listens.
+    pub async fn bind(port: u16) -> Result<Self, Error> {
+        let listener = TcpListener::bind(("127.0.0.1", port))
+            .await
+            .map_err(Error::CallbackServer)?;
+        let port = listener.local_addr().map_err(Error::CallbackServer)?.port();
+
+        Ok(Self {
+            listener,
+            port,
+            path: DEFAULT_PATH.to_string(),
+            timeout: DEFAULT_TIMEOUT,
+            success_page: DEFAULT_SUCCESS_PAGE.to_string(),
+            error_page: DEFAULT_ERROR_PAGE.to_string(),
+        })
+    }
+
+    /// Sets the path of the callback, defaults to `/callback`
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.path = path.into();
+        self
+    }
+
+    /// Sets how long to wait for the redirect, defaults to 5 minutes
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the HTML shown in the browser after a successful login
+    pub fn success_page(mut self, html: impl Into<String>) -> Self {
+        self.success_page = html.into();
+        self
+    }
+
+    /// Sets the HTML shown in the browser when EVE Online SSO redirects with an error, e.g. `access_denied`
+    pub fn error_page(mut self, html: impl Into<String>) -> Self {
+        self.error_page = html.into();
+        self
+    }
+
+    /// Port the server listens on
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Callback URL to create the login URL with
+    pub fn redirect_url(&self) -> String {
+        format!("http://127.0.0.1:{}{}", self.port, self.path)
+    }
+
+    /// Waits for the redirect to the callback carrying the expected state & returns its code & state or error
+    ///
+    /// Requests to other paths, e.g. `/favicon.ico`, are answered with 404. Callbacks with another state, e.g. sent by
+    /// a web page the user has open, are answered with 400 & ignored. Fails with [`Error::CallbackServer`] if no
+    /// redirect arrives before the timeout.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(port = self.port)))]
+    pub async fn wait(&self, expected_state: &str) -> Result<CallbackResult, Error> {
+        tokio::time::timeout(self.timeout, self.accept_callback(expected_state))
+            .await
+            .map_err(|_| {
+                Error::CallbackServer(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "Timed out waiting for the login",
+                ))
+            })?
+    }
+
+    /// Waits for the redirect to the callback, verifies its state against the login & returns the code
+    ///
+    /// Fails with [`Error::Sso`] if the login failed.
+    pub async fn wait_for_code(&self, auth: &AuthenticationData) -> Result<String, Error> {
+        let (code, _) = self.wait(&auth.state).await?.into_result()?;
+
+        Ok(code)
+    }
+
+    async fn accept_callback(&self, expected_state: &str) -> Result<CallbackResult, Error> {
+        loop {
+            let mut stream = match self.listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(_err) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(error = %_err, "Failed to accept callback connection");
+
+                    tokio::time::sleep(ACCEPT_BACKOFF).await;
+                    continue;
+                }
+            };
+
+            let Ok(Some(target)) =
+                tokio::time::timeout(REQUEST_TIMEOUT, read_request_target(&mut stream)).await
+            else {
+                continue;
+            };
+
+            let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+
+            if path != self.path {
+                let _ = respond(&mut stream, "404 Not Found", "").await;
+                continue;
+            }
+
+            let callback = parse_callback_url(query);
+
+            let state_matches = callback
+                .as_ref()
+                .ok()
+                .and_then(CallbackResult::state)
+                .is_some_and(|state| verify_state(expected_state, state));
+
+            if !state_matches {
+                let _ = respond(&mut stream, "400 Bad Request", &self.error_page).await;
+
+                #[cfg(feature = "tracing")]
+                tracing::debug!("Ignored login callback with another state");
+
+                continue;
+            }
+
+            let page = match &callback {
+                Ok(CallbackResult::Success { .. }) => &self.success_page,
+                _ => &self.error_page,
+            };
+            let _ = respond(&mut stream, "200 OK", page).await;
+
+            #[cfg(feature = "tracing")]
+            tracing::debug!("Received login callback");
+
+            return callback;
+        }
+    }
+}
+
+/// Reads the request line & returns its target, e.g. `/callback?code=...&state=...`
+async fn read_request_target(stream: &mut TcpStream) -> Option<String> {
+    let mut buffer = vec![0; 8192];
+    let mut read = 0;
+
+    while !buffer[..read].windows(2).any(|window| window == b"\r\n") {
+        if read == buffer.len() {
+            return None;
+        }
+
+        match stream.read(&mut buffer[read..]).await {
+            Ok(0) | Err(_) => return None,
+            Ok(n) => read += n,
+        }
+    }
+
+    let request = String::from_utf8_lossy(&buffer[..read]);
+    let request_line = request.lines().next()?;
+
+    request_line.split_whitespace().nth(1).map(String::from)
+}
+
+async fn respond(stream: &mut TcpStream, status: &str, body: &str) -> io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+
+    stream.write_all(response.as_bytes()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn send(port: u16, path: &str) {
+        let mut stream = tokio::net::TcpStream::connect(("127.0.0.1", port))
+            .await
+            .unwrap();
+        let request = format!("GET {} HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n", path);
+        stream.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = Vec::new();
+        let _ = stream.read_to_end(&mut response).await;
+    }
+
+    #[tokio::test]
+    async fn ignores_callbacks_with_another_state() {
+        let server = LocalCallbackServer::bind(0).await.unwrap();
+        let port = server.port();
+        assert_eq!(
+            server.redirect_url(),
+            format!("http://127.0.0.1:{}/callback", port)
+        );
+
+        let client = tokio::spawn(async move {
+            send(port, "/favicon.ico").await;
+            send(port, "/callback?code=attacker&state=other").await;
+            send(port, "/callback?code=code&state=expected").await;
+        });
+
+        let (code, state) = server
+            .wait("expected")
+            .await
+            .unwrap()
+            .into_result()
+            .unwrap();
+        assert_eq!(code, "code");
+        assert_eq!(state, "expected");
+
+        client.await.unwrap();
+    }
+}
@@ -0,0 +1,814 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use futures_util::lock::Mutex as AsyncMutex;
+use futures_util::{stream, StreamExt};
+
+use crate::client::EveSsoClient;
+use crate::error::{Error, ErrorClass};
+use crate::events::{AuditEventKind, ManagedTokenEvent};
+use crate::models::{EveJwtClaims, EveTokens};
+use crate::store::TokenStore;
+
+/// Hands out valid access tokens for stored characters, refreshing them when they are near expiry
+///
+/// Wraps an [`EveSsoClient`] & a [`TokenStore`], refreshed token pairs are saved to the store.
+///
+/// EVE Online SSO rotates the refresh token on every refresh, so refreshes are serialized per character: when
+/// several tasks request a token for the same character only the first refreshes, the others wait & reuse the
+/// refreshed tokens. Clones of the manager share these locks.
+///
+/// ```no_run
+/// # async fn example(character_id: u64) -> Result<(), eve_oauth2::Error> {
+/// use eve_oauth2::store::MemoryTokenStore;
+/// use eve_oauth2::{EveSsoClient, TokenManager};
+///
+/// let client = EveSsoClient::new("client_id".to_string(), Some("client_secret".to_string()));
+/// let manager = TokenManager::new(client, MemoryTokenStore::new());
+///
+/// let access_token = manager.get_valid_token(character_id).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct TokenManager<S> {
+    client: EveSsoClient,
+    store: S,
+    refresh_margin: Duration,
+    refresh_locks: Arc<Mutex<HashMap<u64, Arc<AsyncMutex<()>>>>>,
+    evict_on_owner_change: bool,
+    #[cfg(feature = "tokio")]
+    events: tokio::sync::broadcast::Sender<ManagedTokenEvent>,
+}
+
+impl<S: TokenStore> TokenManager<S> {
+    /// Creates a token manager which refreshes access tokens expiring within the next 60 seconds
+    pub fn new(client: EveSsoClient, store: S) -> Self {
+        Self {
+            client,
+            store,
+            refresh_margin: Duration::from_secs(60),
+            refresh_locks: Arc::default(),
+            evict_on_owner_change: true,
+            #[cfg(feature = "tokio")]
+            events: tokio::sync::broadcast::channel(64).0,
+        }
+    }
+
+    /// Sets how long before expiry access tokens are refreshed
+    pub fn refresh_margin(mut self, refresh_margin: Duration) -> Self {
+        self.refresh_margin = refresh_margin;
+        self
+    }
+
+    /// Sets whether the stored tokens of a character are deleted when its owner hash changes, enabled by default
+    ///
+    /// A [`ManagedTokenEvent::OwnerChanged`] event is sent either way.
+    pub fn evict_on_owner_change(mut self, evict_on_owner_change: bool) -> Self {
+        self.evict_on_owner_change = evict_on_owner_change;
+        self
+    }
+
+    /// Returns the client used to refresh tokens
+    pub fn client(&self) -> &EveSsoClient {
+        &self.client
+    }
+
+    /// Returns the store the tokens are kept in
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Subscribes to the events of the managed characters, enabled with the `tokio` feature
+    ///
+    /// Events are sent to all subscribers, including those of clones of the manager. A subscriber which falls more than
+    /// 64 events behind misses the oldest events, see [`tokio::sync::broadcast`].
+    ///
+    /// ```no_run
+    /// # async fn example(manager: eve_oauth2::TokenManager<eve_oauth2::store::MemoryTokenStore>) {
+    /// use eve_oauth2::events::ManagedTokenEvent;
+    ///
+    /// let mut events = manager.subscribe();
+    ///
+    /// while let Ok(event) = events.recv().await {
+    ///     if let ManagedTokenEvent::Invalidated { character_id } = event {
+    ///         println!("Character {} has to log in again", character_id);
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    #[cfg(feature = "tokio")]
+    pub fn subscribe(&self) -> tokio::sync::broadcast::Receiver<ManagedTokenEvent> {
+        self.events.subscribe()
+    }
+
+    /// Returns a valid access token for the character, refreshing the stored tokens if the access token is near expiry
+    ///
+    /// Returns [`Error::CharacterNotFound`] if no tokens are stored for the character.
+    pub async fn get_valid_token(&self, character_id: u64) -> Result<String, Error> {
+        Ok(self
+            .get_valid_tokens(character_id)
+            .await?
+            .into_access_token())
+    }
+
+    /// Returns the stored tokens of the character, refreshing them if the access token is near expiry
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub async fn get_valid_tokens(&self, character_id: u64) -> Result<EveTokens, Error> {
+        self.refresh_within(character_id, Some(self.refresh_margin))
+            .await
+    }
+
+    /// Returns an access token for the character which is valid for at least `min_validity`
+    ///
+    /// Refreshes & stores the tokens when needed. If EVE Online SSO reports the refresh token as revoked or expired,
+    /// the stored tokens are deleted & the error is returned, check it with [`Error::classify`] to have the character
+    /// log in again. Tokens refreshed in the meantime by another instance of your application are returned instead.
+    ///
+    /// ```no_run
+    /// # async fn example(manager: eve_oauth2::TokenManager<eve_oauth2::store::MemoryTokenStore>, character_id: u64) {
+    /// use std::time::Duration;
+    ///
+    /// use eve_oauth2::ErrorClass;
+    ///
+    /// match manager.ensure_valid_token(character_id, Duration::from_secs(120)).await {
+    ///     Ok(access_token) => { /* Call ESI */ }
+    ///     Err(err) if err.classify() == ErrorClass::RevokedOrExpiredGrant => { /* Ask the character to log in again */ }
+    ///     Err(err) => { /* Retry later */ }
+    /// }
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub async fn ensure_valid_token(
+        &self,
+        character_id: u64,
+        min_validity: Duration,
+    ) -> Result<String, Error> {
+        let err = match self.refresh_within(character_id, Some(min_validity)).await {
+            Ok(tokens) => return Ok(tokens.into_access_token()),
+            Err(err) if err.classify() == ErrorClass::RevokedOrExpiredGrant => err,
+            Err(err) => return Err(err),
+        };
+
+        match self.store.get(character_id).await? {
+            Some(tokens) if !tokens.needs_refresh(min_validity) => {
+                return Ok(tokens.into_access_token())
+            }
+            Some(_) => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!("Refresh token was revoked or has expired, deleting stored tokens");
+
+                self.store.delete(character_id).await?;
+
+                self.emit(ManagedTokenEvent::Invalidated { character_id });
+                self.client.audit(
+                    AuditEventKind::TokensDeleted,
+                    Some(character_id),
+                    Some(&err),
+                );
+            }
+            None => {}
+        }
+
+        Err(err)
+    }
+
+    /// Refreshes the stored tokens after ESI rejected the access token, e.g. because it was revoked before expiry
+    ///
+    /// Returns the stored access token without refreshing if it was already replaced, so concurrent requests which
+    /// were rejected with the same token only refresh once.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self, rejected_access_token))
+    )]
+    pub async fn refresh_rejected_token(
+        &self,
+        character_id: u64,
+        rejected_access_token: &str,
+    ) -> Result<String, Error> {
+        let tokens = self
+            .store
+            .get(character_id)
+            .await?
+            .ok_or(Error::CharacterNotFound(character_id))?;
+
+        if tokens.access_token.expose_secret() != rejected_access_token {
+            return Ok(tokens.into_access_token());
+        }
+
+        let lock = self.refresh_lock(character_id);
+
+        Ok(self
+            .refresh_locked(&lock.lock, character_id, tokens.refresh_token.into_secret())
+            .await?
+            .into_access_token())
+    }
+
+    /// Refreshes the stored tokens of all characters whose access token expires within the window
+    ///
+    /// Characters whose tokens don't need a refresh are skipped.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub async fn refresh_expiring(&self, window: Duration) -> Result<RefreshReport, Error> {
+        let mut report = RefreshReport::default();
+
+        for character_id in self.store.characters().await? {
+            let Some(tokens) = self.store.get(character_id).await? else {
+                continue;
+            };
+
+            if tokens.needs_refresh(window) {
+                report.add(
+                    character_id,
+                    self.refresh_within(character_id, Some(window)).await,
+                );
+            }
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            refreshed = report.refreshed.len(),
+            failed = report.failed.len(),
+            "Refreshed expiring tokens"
+        );
+
+        Ok(report)
+    }
+
+    /// Refreshes the stored tokens of every character, running at most `max_concurrency` refreshes at a time
+    ///
+    /// Use this to check all stored refresh tokens are still usable, e.g. after a restart. A low concurrency
+    /// avoids tripping the rate limits of EVE Online SSO.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub async fn refresh_all(&self, max_concurrency: usize) -> Result<RefreshReport, Error> {
+        let characters = self.store.characters().await?;
+
+        let report = stream::iter(characters)
+            .map(|character_id| async move {
+                (character_id, self.refresh_within(character_id, None).await)
+            })
+            .buffer_unordered(max_concurrency.max(1))
+            .fold(
+                RefreshReport::default(),
+                |mut report, (character_id, result)| async move {
+                    report.add(character_id, result);
+                    report
+                },
+            )
+            .await;
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            refreshed = report.refreshed.len(),
+            failed = report.failed.len(),
+            "Refreshed all tokens"
+        );
+
+        Ok(report)
+    }
+
+    /// Spawns a tokio task which refreshes the tokens expiring within the window every interval, enabled with the `tokio` feature
+    ///
+    /// Pick a window larger than the interval so tokens are refreshed before they expire, e.g. an interval of 1 minute
+    /// & a window of 5 minutes. Failed refreshes are emitted as [`crate::events::TokenEvent::RefreshFailed`] events of
+    /// the client. Abort the returned handle to stop the task.
+    #[cfg(feature = "tokio")]
+    pub fn spawn_refresh_scheduler(
+        &self,
+        interval: Duration,
+        window: Duration,
+    ) -> tokio::task::JoinHandle<()>
+    where
+        S: Clone + 'static,
+    {
+        let manager = self.clone();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+            loop {
+                interval.tick().await;
+
+                if let Err(_err) = manager.refresh_expiring(window).await {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(error = %_err, "Failed to scan token store for expiring tokens");
+                }
+            }
+        })
+    }
+
+    /// Refreshes the tokens if they expire within the margin, always refreshes them if the margin is `None`
+    async fn refresh_within(
+        &self,
+        character_id: u64,
+        margin: Option<Duration>,
+    ) -> Result<EveTokens, Error> {
+        let tokens = self
+            .store
+            .get(character_id)
+            .await?
+            .ok_or(Error::CharacterNotFound(character_id))?;
+
+        if margin.is_some_and(|margin| !tokens.needs_refresh(margin)) {
+            return Ok(tokens);
+        }
+
+        let lock = self.refresh_lock(character_id);
+
+        self.refresh_locked(&lock.lock, character_id, tokens.refresh_token.into_secret())
+            .await
+    }
+
+    async fn refresh_locked(
+        &self,
+        lock: &AsyncMutex<()>,
+        character_id: u64,
+        previous_refresh_token: String,
+    ) -> Result<EveTokens, Error> {
+        let _guard = lock.lock().await;
+
+        // Another task may have refreshed the tokens while waiting for the lock
+        let tokens = self
+            .store
+            .get(character_id)
+            .await?
+            .ok_or(Error::CharacterNotFound(character_id))?;
+
+        if tokens.refresh_token.expose_secret() != previous_refresh_token {
+            #[cfg(feature = "tracing")]
+            tracing::debug!("Tokens were refreshed by another task");
+
+            return Ok(tokens);
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!("Refreshing tokens");
+
+        let result = self.client.refresh_stored(&self.store, character_id).await;
+
+        self.emit(match &result {
+            Ok(tokens) => ManagedTokenEvent::Refreshed(tokens.clone()),
+            Err(err) => ManagedTokenEvent::RefreshFailed {
+                character_id,
+                class: err.classify(),
+                error: err.to_string(),
+            },
+        });
+
+        let refreshed = result?;
+
+        match &tokens.owner_hash {
+            Some(owner_hash) if refreshed.owner_hash.as_ref() != Some(owner_hash) => {
+                self.owner_changed(character_id).await?;
+
+                Ok(refreshed)
+            }
+            _ => Ok(refreshed),
+        }
+    }
+
+    /// Checks whether the owner hash of freshly validated claims differs from the owner hash of the stored tokens,
+    /// e.g. when a character logs in again
+    ///
+    /// Returns `true` if the owner changed, the stored tokens are then deleted unless disabled with
+    /// [`TokenManager::evict_on_owner_change`]. Returns `false` if no tokens with an owner hash are stored.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub async fn check_owner(&self, claims: &EveJwtClaims) -> Result<bool, Error> {
+        let character_id = claims.character_id()?.0;
+
+        let Some(tokens) = self.store.get(character_id).await? else {
+            return Ok(false);
+        };
+
+        match &tokens.owner_hash {
+            Some(owner_hash) if claims.owner_changed(owner_hash) => {
+                match self.owner_changed(character_id).await {
+                    Ok(()) | Err(Error::OwnerChanged(_)) => Ok(true),
+                    Err(err) => Err(err),
+                }
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Flags the owner change & evicts the stored tokens if enabled, returning [`Error::OwnerChanged`] when evicted
+    async fn owner_changed(&self, character_id: u64) -> Result<(), Error> {
+        #[cfg(feature = "tracing")]
+        tracing::warn!(character_id, "Character owner changed");
+
+        self.emit(ManagedTokenEvent::OwnerChanged { character_id });
+        self.client
+            .audit(AuditEventKind::OwnerChanged, Some(character_id), None);
+
+        if !self.evict_on_owner_change {
+            return Ok(());
+        }
+
+        self.store.delete(character_id).await?;
+
+        Err(Error::OwnerChanged(character_id))
+    }
+
+    fn emit(&self, event: ManagedTokenEvent) {
+        // Sending only fails if there are no subscribers
+        #[cfg(feature = "tokio")]
+        let _ = self.events.send(event);
+
+        #[cfg(not(feature = "tokio"))]
+        let _ = event;
+    }
+
+    fn refresh_lock(&self, character_id: u64) -> RefreshLock<'_> {
+        let lock = self
+            .refresh_locks
+            .lock()
+            .unwrap()
+            .entry(character_id)
+            .or_default()
+            .clone();
+
+        RefreshLock {
+            locks: &self.refresh_locks,
+            character_id,
+            lock,
+        }
+    }
+}
+
+/// Reference to the refresh lock of a character, removing it from the map when dropped & no other task holds or waits
+/// for it, also when the refreshing future is cancelled
+struct RefreshLock<'a> {
+    locks: &'a Mutex<HashMap<u64, Arc<AsyncMutex<()>>>>,
+    character_id: u64,
+    lock: Arc<AsyncMutex<()>>,
+}
+
+impl Drop for RefreshLock<'_> {
+    fn drop(&mut self) {
+        let mut locks = self.locks.lock().unwrap_or_else(PoisonError::into_inner);
+
+        // The map & this reference hold the only references
+        if Arc::strong_count(&self.lock) == 2 {
+            locks.remove(&self.character_id);
+        }
+    }
+}
+
+/// Result of refreshing the tokens of multiple characters
+#[derive(Debug, Default)]
+#[non_exhaustive]
+pub struct RefreshReport {
+    /// Refreshed tokens of the characters which succeeded
+    pub refreshed: Vec<EveTokens>,
+    /// Ids of the characters which failed to refresh & the error
+    pub failed: Vec<(u64, Error)>,
+}
+
+impl RefreshReport {
+    /// Whether all refreshes succeeded
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    fn add(&mut self, character_id: u64, result: Result<EveTokens, Error>) {
+        match result {
+            Ok(tokens) => self.refreshed.push(tokens),
+            Err(err) => self.failed.push((character_id, err)),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "testing", feature = "tokio", feature = "reqwest"))]
+mod tests {
+    use futures_util::future::join_all;
+    use tokio::sync::broadcast::Receiver;
+    use tokio::time::timeout;
+
+    use super::*;
+    use crate::store::MemoryTokenStore;
+    use crate::testing::MockSso;
+
+    const CHARACTER_ID: u64 = 90000001;
+
+    /// Logs in the mock's character & stores its tokens with an expired access token, so they're refreshed on use
+    async fn manager(sso: &MockSso) -> TokenManager<Arc<MemoryTokenStore>> {
+        let client = sso.client("client_id");
+        let auth = client.create_login_url("http://localhost:8080/callback", ["publicData"]);
+        let (code, _) = sso
+            .authorize(&auth.login_url)
+            .unwrap()
+            .into_result()
+            .unwrap();
+        let token = client.get_access_token(code).await.unwrap();
+
+        let mut tokens = client.validate_token_response(&token).await.unwrap();
+        tokens.expires_at = 0;
+
+        let store = MemoryTokenStore::new();
+        store.save(tokens).await.unwrap();
+
+        TokenManager::new(client, Arc::new(store))
+    }
+
+    async fn stored(
+        manager: &TokenManager<Arc<MemoryTokenStore>>,
+        character_id: u64,
+    ) -> Option<EveTokens> {
+        manager.store().get(character_id).await.unwrap()
+    }
+
+    /// Stores tokens of another character whose refresh token EVE Online SSO rejects
+    async fn save_revoked(
+        manager: &TokenManager<Arc<MemoryTokenStore>>,
+        character_id: u64,
+        expires_at: u64,
+    ) {
+        let tokens = EveTokens {
+            character_id,
+            access_token: "access_token".into(),
+            refresh_token: "revoked".into(),
+            expires_at,
+            scopes: Vec::new(),
+            owner_hash: None,
+        };
+
+        manager.store().save(tokens).await.unwrap();
+    }
+
+    fn received(events: &mut Receiver<ManagedTokenEvent>) -> Vec<ManagedTokenEvent> {
+        std::iter::from_fn(|| events.try_recv().ok()).collect()
+    }
+
+    fn refreshes(events: &[ManagedTokenEvent]) -> usize {
+        events
+            .iter()
+            .filter(|event| matches!(event, ManagedTokenEvent::Refreshed(_)))
+            .count()
+    }
+
+    #[tokio::test]
+    async fn refreshes_expiring_tokens_and_saves_them() {
+        let sso = MockSso::start().unwrap();
+        let manager = manager(&sso).await;
+        let previous = stored(&manager, CHARACTER_ID).await.unwrap();
+
+        let access_token = manager.get_valid_token(CHARACTER_ID).await.unwrap();
+        let refreshed = stored(&manager, CHARACTER_ID).await.unwrap();
+        assert_ne!(access_token, previous.access_token.expose_secret());
+        assert_eq!(refreshed.access_token.expose_secret(), access_token);
+        assert_ne!(refreshed.refresh_token, previous.refresh_token);
+        assert!(!refreshed.needs_refresh(manager.refresh_margin));
+
+        // Valid tokens are returned without refreshing them
+        assert_eq!(
+            manager.get_valid_token(CHARACTER_ID).await.unwrap(),
+            access_token
+        );
+        assert_eq!(stored(&manager, CHARACTER_ID).await.unwrap(), refreshed);
+
+        assert!(matches!(
+            manager.get_valid_token(1).await,
+            Err(Error::CharacterNotFound(1))
+        ));
+    }
+
+    #[tokio::test]
+    async fn refresh_expiring_skips_tokens_outside_the_window() {
+        let sso = MockSso::start().unwrap();
+        let manager = manager(&sso).await;
+        save_revoked(&manager, 2, u64::MAX).await;
+
+        let report = manager
+            .refresh_expiring(Duration::from_secs(300))
+            .await
+            .unwrap();
+
+        assert!(report.is_success());
+        assert_eq!(report.refreshed.len(), 1);
+        assert_eq!(report.refreshed[0].character_id, CHARACTER_ID);
+        assert_eq!(
+            stored(&manager, CHARACTER_ID).await.unwrap(),
+            report.refreshed[0]
+        );
+    }
+
+    #[tokio::test]
+    async fn refresh_scheduler_refreshes_expiring_tokens() {
+        let sso = MockSso::start().unwrap();
+        let manager = manager(&sso).await;
+        let mut events = manager.subscribe();
+
+        let scheduler =
+            manager.spawn_refresh_scheduler(Duration::from_millis(10), Duration::from_secs(300));
+        let event = timeout(Duration::from_secs(10), events.recv()).await;
+        scheduler.abort();
+
+        match event {
+            Ok(Ok(ManagedTokenEvent::Refreshed(tokens))) => {
+                assert_eq!(stored(&manager, CHARACTER_ID).await.unwrap(), tokens)
+            }
+            event => panic!("Expected a refresh, got {:?}", event),
+        }
+    }
+
+    #[tokio::test]
+    async fn refresh_all_reports_each_character() {
+        let sso = MockSso::start().unwrap();
+        let manager = manager(&sso).await;
+        save_revoked(&manager, 2, u64::MAX).await;
+
+        let report = manager.refresh_all(0).await.unwrap();
+
+        assert!(!report.is_success());
+        assert_eq!(report.refreshed.len(), 1);
+        assert_eq!(report.refreshed[0].character_id, CHARACTER_ID);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].0, 2);
+        assert_eq!(
+            report.failed[0].1.classify(),
+            ErrorClass::RevokedOrExpiredGrant
+        );
+        assert!(stored(&manager, 2).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn ensure_valid_token_deletes_revoked_characters() {
+        let sso = MockSso::start().unwrap();
+        let manager = manager(&sso).await;
+        save_revoked(&manager, 2, 0).await;
+        let mut events = manager.subscribe();
+
+        let err = manager
+            .ensure_valid_token(2, Duration::from_secs(120))
+            .await
+            .unwrap_err();
+        assert_eq!(err.classify(), ErrorClass::RevokedOrExpiredGrant);
+        assert_eq!(stored(&manager, 2).await, None);
+        assert!(received(&mut events)
+            .iter()
+            .any(|event| matches!(event, ManagedTokenEvent::Invalidated { character_id: 2 })));
+
+        let access_token = manager
+            .ensure_valid_token(CHARACTER_ID, Duration::from_secs(120))
+            .await
+            .unwrap();
+        let tokens = stored(&manager, CHARACTER_ID).await.unwrap();
+        assert_eq!(tokens.access_token.expose_secret(), access_token);
+        assert!(!tokens.needs_refresh(Duration::from_secs(120)));
+    }
+
+    #[tokio::test]
+    async fn broadcasts_refresh_events_to_subscribers_of_clones() {
+        let sso = MockSso::start().unwrap();
+        let manager = manager(&sso).await;
+        save_revoked(&manager, 2, 0).await;
+        let mut events = manager.clone().subscribe();
+
+        manager.get_valid_token(CHARACTER_ID).await.unwrap();
+        assert!(manager.get_valid_token(2).await.is_err());
+
+        match &received(&mut events)[..] {
+            [ManagedTokenEvent::Refreshed(tokens), ManagedTokenEvent::RefreshFailed {
+                character_id: 2,
+                class: ErrorClass::RevokedOrExpiredGrant,
+                error,
+            }] => {
+                assert_eq!(Some(tokens), stored(&manager, CHARACTER_ID).await.as_ref());
+                assert!(error.contains("invalid_grant"));
+            }
+            events => panic!("Unexpected events {:?}", events),
+        }
+    }
+
+    async fn set_owner_hash(manager: &TokenManager<Arc<MemoryTokenStore>>, owner_hash: &str) {
+        let mut tokens = stored(manager, CHARACTER_ID).await.unwrap();
+        tokens.owner_hash = Some(owner_hash.to_string());
+
+        manager.store().save(tokens).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn evicts_characters_whose_owner_changed_on_refresh() {
+        let sso = MockSso::start().unwrap();
+        let manager = manager(&sso).await;
+        set_owner_hash(&manager, "previous-owner-hash").await;
+        let mut events = manager.subscribe();
+
+        assert!(matches!(
+            manager.get_valid_tokens(CHARACTER_ID).await,
+            Err(Error::OwnerChanged(CHARACTER_ID))
+        ));
+        assert_eq!(stored(&manager, CHARACTER_ID).await, None);
+        assert!(received(&mut events).iter().any(|event| matches!(
+            event,
+            ManagedTokenEvent::OwnerChanged {
+                character_id: CHARACTER_ID
+            }
+        )));
+    }
+
+    #[tokio::test]
+    async fn keeps_characters_whose_owner_changed_if_eviction_is_disabled() {
+        let sso = MockSso::start().unwrap();
+        let manager = manager(&sso).await.evict_on_owner_change(false);
+        set_owner_hash(&manager, "previous-owner-hash").await;
+        let mut events = manager.subscribe();
+
+        let tokens = manager.get_valid_tokens(CHARACTER_ID).await.unwrap();
+        assert_eq!(tokens.owner_hash.as_deref(), Some("test-owner-hash"));
+        assert_eq!(stored(&manager, CHARACTER_ID).await, Some(tokens));
+        assert!(received(&mut events)
+            .iter()
+            .any(|event| matches!(event, ManagedTokenEvent::OwnerChanged { .. })));
+    }
+
+    #[tokio::test]
+    async fn check_owner_compares_claims_with_the_stored_owner_hash() {
+        let sso = MockSso::start().unwrap();
+        let manager = manager(&sso).await;
+        let claims = |owner_hash: &str| {
+            sso.signer()
+                .token()
+                .character_id(CHARACTER_ID)
+                .owner(owner_hash)
+                .claims()
+        };
+
+        assert!(!manager
+            .check_owner(&claims("test-owner-hash"))
+            .await
+            .unwrap());
+        assert!(stored(&manager, CHARACTER_ID).await.is_some());
+
+        assert!(manager
+            .check_owner(&claims("new-owner-hash"))
+            .await
+            .unwrap());
+        assert_eq!(stored(&manager, CHARACTER_ID).await, None);
+
+        assert!(!manager
+            .check_owner(&claims("new-owner-hash"))
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn concurrent_requests_refresh_once() {
+        let sso = MockSso::start().unwrap();
+        let manager = manager(&sso).await;
+        let mut events = manager.subscribe();
+
+        let tokens = join_all((0..8).map(|_| manager.get_valid_token(CHARACTER_ID))).await;
+        let tokens: Vec<String> = tokens.into_iter().map(Result::unwrap).collect();
+
+        assert!(tokens.iter().all(|token| *token == tokens[0]));
+        assert_eq!(refreshes(&received(&mut events)), 1);
+        assert!(manager.refresh_locks.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn cancelled_refreshes_release_their_lock() {
+        let sso = MockSso::start().unwrap();
+        let manager = manager(&sso).await;
+
+        let held = manager.refresh_lock(CHARACTER_ID);
+        let guard = held.lock.lock().await;
+        let mut refresh = Box::pin(manager.get_valid_token(CHARACTER_ID));
+        assert!(timeout(Duration::from_millis(50), &mut refresh)
+            .await
+            .is_err());
+
+        drop(guard);
+        drop(held);
+        assert_eq!(manager.refresh_locks.lock().unwrap().len(), 1);
+
+        drop(refresh);
+        assert!(manager.refresh_locks.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn waiting_requests_reuse_tokens_refreshed_meanwhile() {
+        let sso = MockSso::start().unwrap();
+        let manager = manager(&sso).await;
+        let mut events = manager.subscribe();
+
+        let held = manager.refresh_lock(CHARACTER_ID);
+        let guard = held.lock.lock().await;
+        let mut refresh = Box::pin(manager.get_valid_token(CHARACTER_ID));
+        assert!(timeout(Duration::from_millis(50), &mut refresh)
+            .await
+            .is_err());
+
+        // Another instance of the application refreshes the tokens while the request waits for the lock
+        let refreshed = manager
+            .client()
+            .refresh_stored(manager.store(), CHARACTER_ID)
+            .await
+            .unwrap();
+        drop(guard);
+
+        assert_eq!(refresh.await.unwrap(), refreshed.into_access_token());
+        assert_eq!(refreshes(&received(&mut events)), 0);
+    }
+}
@@ -0,0 +1,246 @@
+//! Metrics emitted with the `metrics` feature, these are no-ops when the feature is disabled
+//!
+//! Counters:
+//! - `eve_oauth2_logins_total`: login URLs created
+//! - `eve_oauth2_code_exchanges_total`: authorization codes exchanged for tokens
+//! - `eve_oauth2_refreshes_total`: refresh token exchanges
+//! - `eve_oauth2_validations_total`: token validations
+//! - `eve_oauth2_failures_total`: failed operations, labeled by `operation` & error `kind`
+//!
+//! Histograms:
+//! - `eve_oauth2_sso_request_duration_seconds`: latency of requests to EVE Online SSO & ESI labeled by `endpoint`
+//!   (`token`, `metadata`, `jwks`, `verify`, `revoke` or `affiliation`), not recorded on wasm targets
+
+#![cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+
+use std::future::Future;
+
+use crate::error::Error;
+use crate::http::Endpoint;
+
+#[derive(Clone, Copy)]
+pub(crate) enum Operation {
+    CodeExchange,
+    Refresh,
+    Validation,
+}
+
+impl Operation {
+    #[cfg(feature = "metrics")]
+    fn counter(self) -> &'static str {
+        match self {
+            Operation::CodeExchange => "eve_oauth2_code_exchanges_total",
+            Operation::Refresh => "eve_oauth2_refreshes_total",
+            Operation::Validation => "eve_oauth2_validations_total",
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    fn label(self) -> &'static str {
+        match self {
+            Operation::CodeExchange => "code_exchange",
+            Operation::Refresh => "refresh",
+            Operation::Validation => "validation",
+        }
+    }
+}
+
+pub(crate) fn record_login() {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!("eve_oauth2_logins_total").increment(1);
+}
+
+pub(crate) fn record<T>(operation: Operation, result: &Result<T, Error>) {
+    #[cfg(feature = "metrics")]
+    {
+        ::metrics::counter!(operation.counter()).increment(1);
+
+        if let Err(err) = result {
+            ::metrics::counter!(
+                "eve_oauth2_failures_total",
+                "operation" => operation.label(),
+                "kind" => err.kind()
+            )
+            .increment(1);
+        }
+    }
+}
+
+/// Records the duration of a request to EVE Online SSO
+pub(crate) async fn timed<T>(endpoint: Endpoint, request: impl Future<Output = T>) -> T {
+    #[cfg(all(feature = "metrics", not(target_arch = "wasm32")))]
+    {
+        let started = std::time::Instant::now();
+        let output = request.await;
+
+        record_duration(endpoint, started);
+
+        output
+    }
+
+    #[cfg(not(all(feature = "metrics", not(target_arch = "wasm32"))))]
+    {
+        let _ = endpoint;
+        request.await
+    }
+}
+
+/// Records the duration of a blocking request to EVE Online SSO
+#[cfg(feature = "blocking")]
+pub(crate) fn timed_blocking<T>(endpoint: Endpoint, request: impl FnOnce() -> T) -> T {
+    #[cfg(all(feature = "metrics", not(target_arch = "wasm32")))]
+    {
+        let started = std::time::Instant::now();
+        let output = request();
+
+        record_duration(endpoint, started);
+
+        output
+    }
+
+    #[cfg(not(all(feature = "metrics", not(target_arch = "wasm32"))))]
+    {
+        let _ = endpoint;
+        request()
+    }
+}
+
+#[cfg(all(feature = "metrics", not(target_arch = "wasm32")))]
+fn record_duration(endpoint: Endpoint, started: std::time::Instant) {
+    ::metrics::histogram!(
+        "eve_oauth2_sso_request_duration_seconds",
+        "endpoint" => endpoint.as_str()
+    )
+    .record(started.elapsed().as_secs_f64());
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+
+    use ::metrics::{
+        Counter, CounterFn, Gauge, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder,
+        SharedString, Unit,
+    };
+
+    use super::*;
+
+    /// Recorder summing the values recorded per metric name & labels
+    #[derive(Clone, Default)]
+    struct TestRecorder(Arc<Mutex<BTreeMap<String, f64>>>);
+
+    struct Handle(Arc<Mutex<BTreeMap<String, f64>>>, String);
+
+    impl CounterFn for Handle {
+        fn increment(&self, value: u64) {
+            *self.0.lock().unwrap().entry(self.1.clone()).or_default() += value as f64;
+        }
+
+        fn absolute(&self, value: u64) {
+            self.0.lock().unwrap().insert(self.1.clone(), value as f64);
+        }
+    }
+
+    impl HistogramFn for Handle {
+        fn record(&self, value: f64) {
+            *self.0.lock().unwrap().entry(self.1.clone()).or_default() += value;
+        }
+    }
+
+    impl TestRecorder {
+        fn handle(&self, key: &Key) -> Arc<Handle> {
+            let labels: Vec<_> = key
+                .labels()
+                .map(|label| format!("{}={}", label.key(), label.value()))
+                .collect();
+
+            Arc::new(Handle(
+                self.0.clone(),
+                format!("{}{{{}}}", key.name(), labels.join(",")),
+            ))
+        }
+
+        fn get(&self, name: &str) -> Option<f64> {
+            self.0.lock().unwrap().get(name).copied()
+        }
+    }
+
+    impl Recorder for TestRecorder {
+        fn describe_counter(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {
+        }
+
+        fn describe_gauge(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+        fn describe_histogram(
+            &self,
+            _key: KeyName,
+            _unit: Option<Unit>,
+            _description: SharedString,
+        ) {
+        }
+
+        fn register_counter(&self, key: &Key, _metadata: &Metadata<'_>) -> Counter {
+            Counter::from_arc(self.handle(key))
+        }
+
+        fn register_gauge(&self, _key: &Key, _metadata: &Metadata<'_>) -> Gauge {
+            Gauge::noop()
+        }
+
+        fn register_histogram(&self, key: &Key, _metadata: &Metadata<'_>) -> Histogram {
+            Histogram::from_arc(self.handle(key))
+        }
+    }
+
+    #[test]
+    fn counts_operations_and_failures_by_kind() {
+        let recorder = TestRecorder::default();
+
+        ::metrics::with_local_recorder(&recorder, || {
+            record_login();
+            record(Operation::Validation, &Ok(()));
+            record::<()>(Operation::Validation, &Err(Error::MissingJwtKey));
+            record::<()>(
+                Operation::Refresh,
+                &Err(Error::sso("invalid_grant".to_string(), None)),
+            );
+        });
+
+        assert_eq!(recorder.get("eve_oauth2_logins_total{}"), Some(1.0));
+        assert_eq!(recorder.get("eve_oauth2_validations_total{}"), Some(2.0));
+        assert_eq!(recorder.get("eve_oauth2_refreshes_total{}"), Some(1.0));
+        assert_eq!(recorder.get("eve_oauth2_code_exchanges_total{}"), None);
+        assert_eq!(
+            recorder.get(&format!(
+                "eve_oauth2_failures_total{{operation=validation,kind={}}}",
+                Error::MissingJwtKey.kind()
+            )),
+            Some(1.0)
+        );
+        assert_eq!(
+            recorder.get(&format!(
+                "eve_oauth2_failures_total{{operation=refresh,kind={}}}",
+                Error::sso("invalid_grant".to_string(), None).kind()
+            )),
+            Some(1.0)
+        );
+    }
+
+    #[test]
+    fn records_request_durations_by_endpoint() {
+        let recorder = TestRecorder::default();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        let output = ::metrics::with_local_recorder(&recorder, || {
+            runtime.block_on(timed(Endpoint::Jwks, async { "keys" }))
+        });
+
+        assert_eq!(output, "keys");
+        assert!(recorder
+            .get("eve_oauth2_sso_request_duration_seconds{endpoint=jwks}")
+            .is_some_and(|seconds| seconds >= 0.0));
+    }
+}
@@ -1,28 +1,103 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, EllipticCurveKeyParameters,
+    EllipticCurveKeyType, Jwk, JwkSet, KeyAlgorithm, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+};
 use serde::{Deserialize, Serialize};
+use web_time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::Error;
+use crate::http::Url;
+use crate::scopes::ScopeSet;
+use crate::secret::{redact, serialize_exposed, Redacted, SecretString};
+use crate::token::EveTokenResponse;
+use crate::{AUTHORIZE_URL, TOKEN_URL};
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Authorization server metadata of EVE Online SSO
+///
+/// Only the endpoints are required, other fields default to empty when CCP removes them & unknown fields are ignored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EveSsoMetaData {
     pub authorization_endpoint: String,
+    #[serde(default)]
     pub code_challenge_methods_supported: Vec<String>,
-    pub issuer: String,
+    pub issuer: Option<String>,
     pub jwks_uri: String,
+    #[serde(default)]
     pub response_types_supported: Vec<String>,
-    pub revocation_endpoint: String,
+    pub revocation_endpoint: Option<String>,
+    #[serde(default)]
     pub revocation_endpoint_auth_methods_supported: Vec<String>,
     pub token_endpoint: String,
+    #[serde(default)]
     pub token_endpoint_auth_methods_supported: Vec<String>,
+    #[serde(default)]
     pub token_endpoint_auth_signing_alg_values_supported: Vec<String>,
 }
 
+/// Endpoints of EVE Online SSO used by [`crate::EveSsoClient`]
+///
+/// Defaults to the well-known EVE Online SSO endpoints, the JWKS URI is looked up from the metadata when `None`.
+/// Use [`crate::EveSsoClient::discover_endpoints`] to use the endpoints published in the metadata instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SsoEndpoints {
+    pub authorization_endpoint: Url,
+    pub token_endpoint: Url,
+    pub revocation_endpoint: Option<Url>,
+    pub jwks_uri: Option<Url>,
+}
+
+impl SsoEndpoints {
+    /// Endpoints published in the EVE Online SSO metadata, fails with [`Error::UnexpectedResponse`] if one isn't a valid URL
+    pub fn from_metadata(metadata: &EveSsoMetaData) -> Result<Self, Error> {
+        Ok(Self {
+            authorization_endpoint: parse_url(&metadata.authorization_endpoint)?,
+            token_endpoint: parse_url(&metadata.token_endpoint)?,
+            revocation_endpoint: metadata
+                .revocation_endpoint
+                .as_deref()
+                .map(parse_url)
+                .transpose()?,
+            jwks_uri: Some(parse_url(&metadata.jwks_uri)?),
+        })
+    }
+}
+
+impl Default for SsoEndpoints {
+    fn default() -> Self {
+        Self {
+            authorization_endpoint: parse_url(AUTHORIZE_URL).expect("Invalid authorization URL"),
+            token_endpoint: parse_url(TOKEN_URL).expect("Invalid token URL"),
+            revocation_endpoint: None,
+            jwks_uri: None,
+        }
+    }
+}
+
+fn parse_url(url: &str) -> Result<Url, Error> {
+    Url::parse(url)
+        .map_err(|err| Error::UnexpectedResponse(format!("Invalid URL {}: {}", url, err)))
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct EveJwtKeys {
-    #[serde(rename = "SkipUnresolvedJsonWebKeys")]
+    #[serde(rename = "SkipUnresolvedJsonWebKeys", default)]
     pub skip_unresolved_json_web_keys: bool,
     pub keys: Vec<EveJwtKey>,
 }
 
+/// Key of the EVE JWT key set, tagged by its `alg`
+///
+/// Keys with an algorithm this crate doesn't know are deserialized as [`EveJwtKey::Unknown`] & skipped during
+/// validation, so new keys added by CCP don't break parsing of the whole key set.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(tag = "alg")]
+#[non_exhaustive]
 pub enum EveJwtKey {
     RS256 {
         e: String,
@@ -39,11 +114,119 @@ pub enum EveJwtKey {
         x: String,
         y: String,
     },
+    /// Key with an algorithm which isn't supported
+    #[serde(other)]
+    Unknown,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Converts the key set to the standard `jsonwebtoken` key set, [`EveJwtKey::Unknown`] keys are skipped
+impl From<EveJwtKeys> for JwkSet {
+    fn from(keys: EveJwtKeys) -> Self {
+        JwkSet {
+            keys: keys.keys.into_iter().filter_map(jwk_from_eve_key).collect(),
+        }
+    }
+}
+
+/// Converts a standard `jsonwebtoken` key set, keys other than RS256 & ES256 (P-256) become [`EveJwtKey::Unknown`]
+impl From<JwkSet> for EveJwtKeys {
+    fn from(set: JwkSet) -> Self {
+        EveJwtKeys {
+            skip_unresolved_json_web_keys: false,
+            keys: set.keys.into_iter().map(EveJwtKey::from).collect(),
+        }
+    }
+}
+
+impl From<Jwk> for EveJwtKey {
+    fn from(jwk: Jwk) -> Self {
+        let kid = jwk.common.key_id.unwrap_or_default();
+        let r#use = match jwk.common.public_key_use {
+            Some(PublicKeyUse::Signature) | None => "sig".to_string(),
+            Some(PublicKeyUse::Encryption) => "enc".to_string(),
+            Some(PublicKeyUse::Other(other)) => other,
+        };
+
+        match (jwk.common.key_algorithm, jwk.algorithm) {
+            (Some(KeyAlgorithm::RS256), AlgorithmParameters::RSA(rsa)) => EveJwtKey::RS256 {
+                e: rsa.e,
+                kid,
+                kty: "RSA".to_string(),
+                n: rsa.n,
+                r#use,
+            },
+            (Some(KeyAlgorithm::ES256), AlgorithmParameters::EllipticCurve(ec))
+                if ec.curve == EllipticCurve::P256 =>
+            {
+                EveJwtKey::ES256 {
+                    crv: "P-256".to_string(),
+                    kid,
+                    kty: "EC".to_string(),
+                    r#use,
+                    x: ec.x,
+                    y: ec.y,
+                }
+            }
+            _ => EveJwtKey::Unknown,
+        }
+    }
+}
+
+fn jwk_from_eve_key(key: EveJwtKey) -> Option<Jwk> {
+    let (kid, r#use, key_algorithm, algorithm) = match key {
+        EveJwtKey::RS256 {
+            e, kid, n, r#use, ..
+        } => (
+            kid,
+            r#use,
+            KeyAlgorithm::RS256,
+            AlgorithmParameters::RSA(RSAKeyParameters {
+                key_type: RSAKeyType::RSA,
+                n,
+                e,
+            }),
+        ),
+        EveJwtKey::ES256 {
+            crv,
+            kid,
+            r#use,
+            x,
+            y,
+            ..
+        } if crv == "P-256" => (
+            kid,
+            r#use,
+            KeyAlgorithm::ES256,
+            AlgorithmParameters::EllipticCurve(EllipticCurveKeyParameters {
+                key_type: EllipticCurveKeyType::EC,
+                curve: EllipticCurve::P256,
+                x,
+                y,
+            }),
+        ),
+        _ => return None,
+    };
+
+    let public_key_use = match r#use.as_str() {
+        "sig" => PublicKeyUse::Signature,
+        "enc" => PublicKeyUse::Encryption,
+        _ => PublicKeyUse::Other(r#use),
+    };
+
+    Some(Jwk {
+        common: CommonParameters {
+            public_key_use: Some(public_key_use),
+            key_algorithm: Some(key_algorithm),
+            key_id: Some(kid),
+            ..Default::default()
+        },
+        algorithm,
+    })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EveJwtClaims {
-    pub scp: Option<String>,
+    pub scp: Option<EveJwtScopes>,
     pub jti: String,
     pub kid: String,
     pub sub: String,
@@ -57,4 +240,1311 @@ pub struct EveJwtClaims {
     pub exp: u64,
     pub iat: u64,
     pub iss: String,
+    /// Claims the crate doesn't know yet, e.g. claims CCP added after the crate was released
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
+}
+
+impl EveJwtClaims {
+    /// Id of the character, parsed from the subject
+    pub fn character_id(&self) -> Result<CharacterId, Error> {
+        CharacterId::from_subject(&self.sub)
+    }
+
+    /// Name of the character
+    pub fn character_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Scopes granted to the token, normalized whether EVE Online SSO returned a single scope or an array
+    pub fn scopes(&self) -> ScopeSet {
+        ScopeSet::from(self)
+    }
+
+    /// Time the token expires
+    pub fn expires_at(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.exp)
+    }
+
+    /// Whether the token has expired
+    pub fn is_expired(&self) -> bool {
+        SystemTime::now() >= self.expires_at()
+    }
+
+    /// Returns a claim the crate doesn't know by name, `None` if the token doesn't have it
+    pub fn extra_claim(&self, name: &str) -> Option<&serde_json::Value> {
+        self.extra.get(name)
+    }
+
+    /// Time the token expires, enabled with the `time` feature
+    #[cfg(feature = "time")]
+    pub fn expires_at_time(&self) -> time::OffsetDateTime {
+        crate::datetime::offset_date_time(Duration::from_secs(self.exp))
+    }
+
+    /// Time the token was issued, enabled with the `time` feature
+    #[cfg(feature = "time")]
+    pub fn issued_at_time(&self) -> time::OffsetDateTime {
+        crate::datetime::offset_date_time(Duration::from_secs(self.iat))
+    }
+
+    /// Time the token expires, enabled with the `chrono` feature
+    #[cfg(feature = "chrono")]
+    pub fn expires_at_chrono(&self) -> chrono::DateTime<chrono::Utc> {
+        crate::datetime::date_time_utc(Duration::from_secs(self.exp))
+    }
+
+    /// Time the token was issued, enabled with the `chrono` feature
+    #[cfg(feature = "chrono")]
+    pub fn issued_at_chrono(&self) -> chrono::DateTime<chrono::Utc> {
+        crate::datetime::date_time_utc(Duration::from_secs(self.iat))
+    }
+
+    /// Whether the owner hash differs from the previously seen owner hash of the character
+    ///
+    /// The owner hash changes when the character is transferred to another account, tokens & account links
+    /// of the previous owner should no longer be trusted.
+    pub fn owner_changed(&self, previous_owner: &str) -> bool {
+        self.owner != previous_owner
+    }
+}
+
+/// EVE Online servers, used to require tokens of a specific server with [`crate::validation::TokenValidation::require_server`]
+///
+/// Serialized & parsed as the lowercase name of the server, e.g. `tranquility`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum EveServer {
+    /// The live server
+    Tranquility,
+    /// The public test server
+    Singularity,
+    /// The Chinese server
+    Serenity,
+}
+
+impl EveServer {
+    /// Value of the `tenant` claim of the server's tokens
+    pub fn tenant(self) -> &'static str {
+        match self {
+            EveServer::Tranquility => "tranquility",
+            EveServer::Singularity => "singularity",
+            EveServer::Serenity => "serenity",
+        }
+    }
+
+    /// Value of the `tier` claim of the server's tokens
+    pub fn tier(self) -> &'static str {
+        match self {
+            EveServer::Tranquility | EveServer::Serenity => "live",
+            EveServer::Singularity => "test",
+        }
+    }
+
+    /// Value of the `region` claim of the server's tokens
+    pub fn region(self) -> &'static str {
+        match self {
+            EveServer::Tranquility | EveServer::Singularity => "world",
+            EveServer::Serenity => "china",
+        }
+    }
+}
+
+impl FromStr for EveServer {
+    type Err = Error;
+
+    /// Parses the case-insensitive name of the server, fails with [`Error::Config`] for unknown servers
+    fn from_str(server: &str) -> Result<Self, Self::Err> {
+        match server.to_ascii_lowercase().as_str() {
+            "tranquility" => Ok(EveServer::Tranquility),
+            "singularity" => Ok(EveServer::Singularity),
+            "serenity" => Ok(EveServer::Serenity),
+            _ => Err(Error::Config(format!(
+                "Unknown EVE server {}, expected tranquility, singularity or serenity",
+                server
+            ))),
+        }
+    }
+}
+
+/// Scopes of an EVE JWT, EVE Online SSO uses a single string for one scope & an array for multiple scopes
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum EveJwtScopes {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+impl EveJwtScopes {
+    pub fn to_vec(&self) -> Vec<String> {
+        match self {
+            EveJwtScopes::Single(scope) => vec![scope.clone()],
+            EveJwtScopes::Multiple(scopes) => scopes.clone(),
+        }
+    }
+}
+
+/// Builder of EVE JWT claims for tests, created with [`EveJwtClaims::test`], enabled with the `test-utils` feature
+///
+/// Defaults to a token of character `90000001` issued to `client_id` on Tranquility without scopes, expiring in
+/// 20 minutes.
+///
+/// ```
+/// use eve_oauth2::models::EveJwtClaims;
+///
+/// let claims = EveJwtClaims::test()
+///     .character_id(2114794365)
+///     .scopes(["esi-wallet.read_character_wallet.v1"])
+///     .build();
+///
+/// assert_eq!(claims.character_id()?.0, 2114794365);
+/// assert!(claims.scopes().contains("esi-wallet.read_character_wallet.v1"));
+/// # Ok::<(), eve_oauth2::Error>(())
+/// ```
+#[cfg(feature = "test-utils")]
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct EveJwtClaimsBuilder {
+    claims: EveJwtClaims,
+}
+
+#[cfg(feature = "test-utils")]
+impl EveJwtClaims {
+    /// Creates a builder of claims for tests, see [`EveJwtClaimsBuilder`]
+    pub fn test() -> EveJwtClaimsBuilder {
+        static JTI: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        let jti = JTI.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+        EveJwtClaimsBuilder {
+            claims: EveJwtClaims {
+                scp: None,
+                jti: format!("test-{}", jti),
+                kid: "JWT-Signature-Key".to_string(),
+                sub: "CHARACTER:EVE:90000001".to_string(),
+                azp: "client_id".to_string(),
+                tenant: EveServer::Tranquility.tenant().to_string(),
+                tier: EveServer::Tranquility.tier().to_string(),
+                region: EveServer::Tranquility.region().to_string(),
+                aud: vec!["client_id".to_string(), "EVE Online".to_string()],
+                name: "Test Character".to_string(),
+                owner: "test-owner-hash".to_string(),
+                exp: now + 1200,
+                iat: now,
+                iss: crate::ISSUER.to_string(),
+                extra: HashMap::new(),
+            },
+        }
+    }
+}
+
+#[cfg(feature = "test-utils")]
+impl EveJwtClaimsBuilder {
+    /// Sets the subject to the character, e.g. `CHARACTER:EVE:2114794365`
+    pub fn character_id(mut self, character_id: u64) -> Self {
+        self.claims.sub = format!("CHARACTER:EVE:{}", character_id);
+        self
+    }
+
+    /// Sets the subject as it is, e.g. to test handling of subjects which aren't characters
+    pub fn subject(mut self, sub: impl Into<String>) -> Self {
+        self.claims.sub = sub.into();
+        self
+    }
+
+    /// Sets the name of the character
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.claims.name = name.into();
+        self
+    }
+
+    /// Sets the owner hash of the character
+    pub fn owner(mut self, owner_hash: impl Into<String>) -> Self {
+        self.claims.owner = owner_hash.into();
+        self
+    }
+
+    /// Sets the granted scopes, a single scope is encoded as a string like EVE Online SSO does
+    pub fn scopes(mut self, scopes: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        let mut scopes: Vec<String> = scopes.into_iter().map(Into::into).collect();
+
+        self.claims.scp = match scopes.len() {
+            0 => None,
+            1 => scopes.pop().map(EveJwtScopes::Single),
+            _ => Some(EveJwtScopes::Multiple(scopes)),
+        };
+        self
+    }
+
+    /// Sets the client_id the token was issued to in the `azp` & `aud` claims
+    pub fn client_id(mut self, client_id: impl Into<String>) -> Self {
+        let client_id = client_id.into();
+
+        self.claims.aud = vec![client_id.clone(), "EVE Online".to_string()];
+        self.claims.azp = client_id;
+        self
+    }
+
+    /// Sets the `tenant`, `tier` & `region` claims to those of the server
+    pub fn server(mut self, server: EveServer) -> Self {
+        self.claims.tenant = server.tenant().to_string();
+        self.claims.tier = server.tier().to_string();
+        self.claims.region = server.region().to_string();
+        self
+    }
+
+    /// Sets the unix timestamp in seconds the token expires at, use a past timestamp to test expired tokens
+    pub fn expires_at(mut self, exp: u64) -> Self {
+        self.claims.exp = exp;
+        self
+    }
+
+    /// Sets the token to expire after the duration from when it was issued
+    pub fn expires_in(mut self, duration: Duration) -> Self {
+        self.claims.exp = self.claims.iat + duration.as_secs();
+        self
+    }
+
+    /// Sets the unix timestamp in seconds the token was issued at
+    pub fn issued_at(mut self, iat: u64) -> Self {
+        self.claims.iat = iat;
+        self
+    }
+
+    /// Adds a claim the crate doesn't know, e.g. to test handling of claims CCP added, see [`EveJwtClaims::extra`]
+    pub fn claim(mut self, name: impl Into<String>, value: impl Into<serde_json::Value>) -> Self {
+        self.claims.extra.insert(name.into(), value.into());
+        self
+    }
+
+    /// Creates the claims
+    pub fn build(self) -> EveJwtClaims {
+        self.claims
+    }
+}
+
+/// Raw query parameters of the callback from EVE Online SSO, convert them into a [`CallbackResult`] with `try_from`
+///
+/// Use it in place of your own `{ code, state }` struct when your framework needs a plain struct to deserialize into.
+/// Its `Debug` output redacts the code.
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CallbackParams {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    /// OAuth2 error code, e.g. `access_denied` when the user denied consent
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+impl fmt::Debug for CallbackParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CallbackParams")
+            .field("code", &redact(&self.code))
+            .field("state", &self.state)
+            .field("error", &self.error)
+            .field("error_description", &self.error_description)
+            .finish()
+    }
+}
+
+impl TryFrom<CallbackParams> for CallbackResult {
+    type Error = Error;
+
+    /// Fails with [`Error::UnexpectedResponse`] if the callback has neither an `error` nor a `code` & `state`
+    fn try_from(params: CallbackParams) -> Result<Self, Error> {
+        match params {
+            CallbackParams {
+                error: Some(error),
+                error_description,
+                state,
+                ..
+            } => Ok(CallbackResult::Error {
+                error,
+                error_description,
+                state,
+            }),
+            CallbackParams {
+                code: Some(code),
+                state: Some(state),
+                ..
+            } => Ok(CallbackResult::Success { code, state }),
+            _ => Err(Error::UnexpectedResponse(
+                "Callback has neither an error nor a code & state".to_string(),
+            )),
+        }
+    }
+}
+
+/// Query parameters of the callback from EVE Online SSO, deserialize your callback route's query into it
+///
+/// EVE Online SSO redirects with `code` & `state` when the user logged in & with `error`, e.g. `access_denied` when
+/// the user denied consent, instead of `code` when the login failed. Its `Debug` output redacts the code.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum CallbackResult {
+    /// The user logged in, verify the state before exchanging the code for a token
+    Success { code: String, state: String },
+    /// The login failed, state is `None` if EVE Online SSO didn't return it
+    Error {
+        error: String,
+        error_description: Option<String>,
+        state: Option<String>,
+    },
+}
+
+impl fmt::Debug for CallbackResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CallbackResult::Success { state, .. } => f
+                .debug_struct("Success")
+                .field("code", &Redacted)
+                .field("state", state)
+                .finish(),
+            CallbackResult::Error {
+                error,
+                error_description,
+                state,
+            } => f
+                .debug_struct("Error")
+                .field("error", error)
+                .field("error_description", error_description)
+                .field("state", state)
+                .finish(),
+        }
+    }
+}
+
+impl CallbackResult {
+    /// State of the login the callback belongs to
+    pub fn state(&self) -> Option<&str> {
+        match self {
+            CallbackResult::Success { state, .. } => Some(state),
+            CallbackResult::Error { state, .. } => state.as_deref(),
+        }
+    }
+
+    /// Whether the user denied consent to your application
+    pub fn is_access_denied(&self) -> bool {
+        matches!(self, CallbackResult::Error { error, .. } if error == "access_denied")
+    }
+
+    /// Returns the code & state of a successful login or the error returned by EVE Online SSO as [`Error::Sso`]
+    pub fn into_result(self) -> Result<(String, String), Error> {
+        match self {
+            CallbackResult::Success { code, state } => Ok((code, state)),
+            CallbackResult::Error {
+                error,
+                error_description,
+                ..
+            } => Err(Error::sso(error, error_description)),
+        }
+    }
+}
+
+/// Access & refresh token pair of a character for storing in your database
+///
+/// expires_at is the unix timestamp in seconds of when the access token expires. The access & refresh token are
+/// [`SecretString`]s, redacted in `Debug` output & wiped from memory with the `zeroize` feature, but serialized in
+/// full so the tokens can be stored.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct EveTokens {
+    pub character_id: u64,
+    #[serde(serialize_with = "serialize_exposed")]
+    pub access_token: SecretString,
+    #[serde(serialize_with = "serialize_exposed")]
+    pub refresh_token: SecretString,
+    pub expires_at: u64,
+    pub scopes: Vec<String>,
+    /// Owner hash of the character when the tokens were obtained, `None` for tokens stored by older versions
+    #[serde(default)]
+    pub owner_hash: Option<String>,
+}
+
+impl fmt::Debug for EveTokens {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EveTokens")
+            .field("character_id", &self.character_id)
+            .field("access_token", &Redacted)
+            .field("refresh_token", &Redacted)
+            .field("expires_at", &self.expires_at)
+            .field("scopes", &self.scopes)
+            .field("owner_hash", &self.owner_hash)
+            .finish()
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl zeroize::ZeroizeOnDrop for EveTokens {}
+
+impl EveTokens {
+    /// Creates the token pair from a token response & the claims returned by validating its access token
+    pub fn new(token: &EveTokenResponse, claims: &EveJwtClaims) -> Result<Self, Error> {
+        let refresh_token = token.refresh_token().ok_or(Error::MissingRefreshToken)?;
+
+        Ok(Self {
+            character_id: parse_character_id(&claims.sub)?,
+            access_token: token.access_token().into(),
+            refresh_token: refresh_token.into(),
+            expires_at: claims.exp,
+            scopes: claims
+                .scp
+                .as_ref()
+                .map(EveJwtScopes::to_vec)
+                .unwrap_or_default(),
+            owner_hash: Some(claims.owner.clone()),
+        })
+    }
+
+    /// Returns the access token, consuming the tokens
+    pub fn into_access_token(self) -> String {
+        self.access_token.into_secret()
+    }
+
+    /// Whether the access token has expired
+    pub fn is_expired(&self) -> bool {
+        self.needs_refresh(Duration::ZERO)
+    }
+
+    /// Whether the access token expires within the provided margin & should be refreshed
+    pub fn needs_refresh(&self, margin: Duration) -> bool {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        now + margin >= Duration::from_secs(self.expires_at)
+    }
+}
+
+/// Id of an EVE character, parsed from the subject of an EVE JWT
+///
+/// ```no_run
+/// # async fn example(token: String) -> Result<(), eve_oauth2::Error> {
+/// use eve_oauth2::models::CharacterId;
+///
+/// let token_data = eve_oauth2::validate_token(token).await?;
+/// let character_id = CharacterId::try_from(&token_data.claims)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct CharacterId(pub u64);
+
+impl CharacterId {
+    /// Parses the character id from the subject of an EVE JWT, e.g. `CHARACTER:EVE:2114794365`
+    ///
+    /// Fails with [`Error::InvalidClaims`] if the subject isn't an EVE character.
+    pub fn from_subject(sub: &str) -> Result<Self, Error> {
+        parse_character_id(sub).map(CharacterId)
+    }
+}
+
+impl TryFrom<&EveJwtClaims> for CharacterId {
+    type Error = Error;
+
+    fn try_from(claims: &EveJwtClaims) -> Result<Self, Self::Error> {
+        Self::from_subject(&claims.sub)
+    }
+}
+
+impl fmt::Display for CharacterId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<u64> for CharacterId {
+    fn from(character_id: u64) -> Self {
+        CharacterId(character_id)
+    }
+}
+
+impl From<CharacterId> for u64 {
+    fn from(character_id: CharacterId) -> Self {
+        character_id.0
+    }
+}
+
+/// Character which logged in, created from the claims of a validated token
+///
+/// expires_at is the unix timestamp in seconds of when the token expires.
+///
+/// ```no_run
+/// # async fn example(token: String) -> Result<(), eve_oauth2::Error> {
+/// use eve_oauth2::models::Character;
+///
+/// let token_data = eve_oauth2::validate_token(token).await?;
+/// let character = Character::try_from(token_data.claims)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Character {
+    pub id: CharacterId,
+    pub name: String,
+    /// Hash of the account owning the character, changes when the character is transferred to another account
+    pub owner_hash: String,
+    pub scopes: ScopeSet,
+    pub expires_at: u64,
+}
+
+impl TryFrom<&EveJwtClaims> for Character {
+    type Error = Error;
+
+    fn try_from(claims: &EveJwtClaims) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: claims.character_id()?,
+            name: claims.name.clone(),
+            owner_hash: claims.owner.clone(),
+            scopes: claims.scopes(),
+            expires_at: claims.exp,
+        })
+    }
+}
+
+impl TryFrom<EveJwtClaims> for Character {
+    type Error = Error;
+
+    fn try_from(claims: EveJwtClaims) -> Result<Self, Self::Error> {
+        Character::try_from(&claims)
+    }
+}
+
+/// Summary of a token for support tooling, created with [`crate::describe_token`]
+///
+/// Displays as a table for printing & serializes to JSON, e.g. for an admin UI. Timestamps are unix timestamps in
+/// seconds, expires_in is negative once the token has expired.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct TokenDescription {
+    /// Character id, `None` if the subject isn't an EVE character
+    pub character_id: Option<u64>,
+    pub character_name: String,
+    pub owner_hash: String,
+    /// Granted scopes grouped by category, e.g. `wallet` for `esi-wallet.read_character_wallet.v1`
+    pub scopes: BTreeMap<String, Vec<String>>,
+    pub issued_at: u64,
+    pub expires_at: u64,
+    /// Seconds until the token expires
+    pub expires_in: i64,
+    pub client_id: String,
+    pub issuer: String,
+    pub tenant: String,
+    pub tier: String,
+    pub region: String,
+    /// Id of the key the token was signed with, from the token's header
+    pub key_id: Option<String>,
+    pub algorithm: String,
+    pub jwt_id: String,
+}
+
+impl TokenDescription {
+    pub(crate) fn new(header: &jsonwebtoken::Header, claims: &EveJwtClaims) -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+
+        let mut scopes = BTreeMap::<String, Vec<String>>::new();
+
+        for scope in claims.scopes() {
+            scopes
+                .entry(scope_category(&scope).to_string())
+                .or_default()
+                .push(scope);
+        }
+
+        Self {
+            character_id: parse_character_id(&claims.sub).ok(),
+            character_name: claims.name.clone(),
+            owner_hash: claims.owner.clone(),
+            scopes,
+            issued_at: claims.iat,
+            expires_at: claims.exp,
+            expires_in: claims.exp as i64 - now as i64,
+            client_id: claims.azp.clone(),
+            issuer: claims.iss.clone(),
+            tenant: claims.tenant.clone(),
+            tier: claims.tier.clone(),
+            region: claims.region.clone(),
+            key_id: header.kid.clone(),
+            algorithm: format!("{:?}", header.alg),
+            jwt_id: claims.jti.clone(),
+        }
+    }
+
+    /// Whether the token has expired
+    pub fn is_expired(&self) -> bool {
+        self.expires_in <= 0
+    }
+}
+
+impl fmt::Display for TokenDescription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let expiry = match self.expires_in {
+            expires_in if expires_in > 0 => format!("in {}", countdown(expires_in.unsigned_abs())),
+            expires_in => format!("expired {} ago", countdown(expires_in.unsigned_abs())),
+        };
+        let scopes = self
+            .scopes
+            .iter()
+            .map(|(category, scopes)| format!("{}: {}", category, scopes.join(" ")))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let rows = [
+            ("Character id", self.character_id.map(|id| id.to_string())),
+            ("Character name", Some(self.character_name.clone())),
+            ("Owner hash", Some(self.owner_hash.clone())),
+            ("Scopes", Some(scopes)),
+            ("Issued at", Some(self.issued_at.to_string())),
+            (
+                "Expires at",
+                Some(format!("{} ({})", self.expires_at, expiry)),
+            ),
+            ("Client id", Some(self.client_id.clone())),
+            ("Issuer", Some(self.issuer.clone())),
+            (
+                "Server",
+                Some(format!("{} {} {}", self.tenant, self.tier, self.region)),
+            ),
+            ("Key id", self.key_id.clone()),
+            ("Algorithm", Some(self.algorithm.clone())),
+            ("JWT id", Some(self.jwt_id.clone())),
+        ];
+
+        let width = rows
+            .iter()
+            .map(|(name, _)| name.len())
+            .max()
+            .unwrap_or_default();
+
+        for (name, value) in rows {
+            let value = value.unwrap_or_else(|| "-".to_string());
+            let mut lines = value.lines();
+
+            writeln!(f, "{:width$}  {}", name, lines.next().unwrap_or_default())?;
+
+            for line in lines {
+                writeln!(f, "{:width$}  {}", "", line)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Category of a scope, e.g. `wallet` for `esi-wallet.read_character_wallet.v1` & `publicData` for itself
+fn scope_category(scope: &str) -> &str {
+    scope
+        .strip_prefix("esi-")
+        .and_then(|scope| scope.split_once('.'))
+        .map_or(scope, |(category, _)| category)
+}
+
+/// Formats a number of seconds as a countdown, e.g. `19m 59s` or `2h 3m`
+fn countdown(seconds: u64) -> String {
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m {}s", seconds / 60, seconds % 60),
+        3600..=86399 => format!("{}h {}m", seconds / 3600, seconds % 3600 / 60),
+        _ => format!("{}d {}h", seconds / 86400, seconds % 86400 / 3600),
+    }
+}
+
+/// Response of ESI's `/verify` endpoint, e.g. `{"CharacterID": 2114794365, "ExpiresOn": "2024-06-01T12:34:56", ...}`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct EsiVerifyResponse {
+    #[serde(rename = "CharacterID")]
+    character_id: u64,
+    character_name: String,
+    expires_on: String,
+    #[serde(default)]
+    scopes: Option<String>,
+    character_owner_hash: String,
+}
+
+impl TryFrom<EsiVerifyResponse> for Character {
+    type Error = Error;
+
+    fn try_from(response: EsiVerifyResponse) -> Result<Self, Self::Error> {
+        let expires_at = parse_esi_timestamp(&response.expires_on).ok_or_else(|| {
+            Error::UnexpectedResponse(format!("Invalid ExpiresOn {}", response.expires_on))
+        })?;
+
+        Ok(Self {
+            id: CharacterId(response.character_id),
+            name: response.character_name,
+            owner_hash: response.character_owner_hash,
+            scopes: response
+                .scopes
+                .as_deref()
+                .unwrap_or_default()
+                .split_whitespace()
+                .collect(),
+            expires_at,
+        })
+    }
+}
+
+/// Parses an ESI timestamp such as `2024-06-01T12:34:56` or `2024-06-01T12:34:56.123Z` into unix seconds
+fn parse_esi_timestamp(timestamp: &str) -> Option<u64> {
+    let (date, time) = timestamp.trim_end_matches('Z').split_once('T')?;
+    let time = time.split('.').next()?;
+
+    let mut date = date.splitn(3, '-').map(str::parse::<u64>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    let mut time = time.splitn(3, ':').map(str::parse::<u64>);
+    let (hour, minute, second) = (time.next()?.ok()?, time.next()?.ok()?, time.next()?.ok()?);
+
+    if year < 1970 || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    // Days since the unix epoch of the proleptic Gregorian calendar, counting years from March
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = (era * 146_097 + day_of_era).checked_sub(719_468)?;
+
+    Some(days * 86_400 + hour * 3_600 + minute * 60 + second)
+}
+
+/// Parses the character id from the subject of an EVE JWT, e.g. `CHARACTER:EVE:2114794365`
+pub(crate) fn parse_character_id(sub: &str) -> Result<u64, Error> {
+    match sub.split(':').collect::<Vec<&str>>()[..] {
+        ["CHARACTER", "EVE", id] => id
+            .parse()
+            .map_err(|_| Error::InvalidClaims(format!("Invalid character id in subject {}", sub))),
+        _ => Err(Error::InvalidClaims(format!(
+            "Subject {} is not an EVE character",
+            sub
+        ))),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    fn tokens() -> EveTokens {
+        EveTokens {
+            character_id: 2114794365,
+            access_token: "access_token".into(),
+            refresh_token: "refresh_token".into(),
+            expires_at: 0,
+            scopes: vec!["publicData".to_string()],
+            owner_hash: None,
+        }
+    }
+
+    #[test]
+    fn tokens_serialize_exposed_secrets_but_debug_redacts_them() {
+        let tokens = tokens();
+
+        let json = serde_json::to_string(&tokens).unwrap();
+        assert!(json.contains(r#""access_token":"access_token""#));
+        assert!(json.contains(r#""refresh_token":"refresh_token""#));
+        assert_eq!(serde_json::from_str::<EveTokens>(&json).unwrap(), tokens);
+
+        let debug = format!("{:?}", tokens);
+        assert!(!debug.contains(r#""access_token""#));
+        assert!(!debug.contains(r#""refresh_token""#));
+    }
+
+    #[cfg(feature = "test-utils")]
+    #[test]
+    fn tokens_are_created_from_a_token_response_and_its_claims() {
+        let claims = EveJwtClaims::test()
+            .character_id(2114794365)
+            .owner("owner-hash")
+            .scopes(["publicData", "esi-wallet.read_character_wallet.v1"])
+            .build();
+        let token = EveTokenResponse::new(
+            "access_token",
+            Some("refresh_token".to_string()),
+            Some(Duration::from_secs(1199)),
+        );
+
+        let tokens = EveTokens::new(&token, &claims).unwrap();
+
+        assert_eq!(tokens.character_id, 2114794365);
+        assert_eq!(tokens.access_token.expose_secret(), "access_token");
+        assert_eq!(tokens.refresh_token.expose_secret(), "refresh_token");
+        assert_eq!(tokens.expires_at, claims.exp);
+        assert_eq!(
+            tokens.scopes,
+            ["publicData", "esi-wallet.read_character_wallet.v1"]
+        );
+        assert_eq!(tokens.owner_hash.as_deref(), Some("owner-hash"));
+
+        let token = EveTokenResponse::new("access_token", None, None);
+        assert!(matches!(
+            EveTokens::new(&token, &claims),
+            Err(Error::MissingRefreshToken)
+        ));
+    }
+
+    #[test]
+    fn tokens_stored_by_older_versions_deserialize() {
+        let tokens: EveTokens = serde_json::from_str(
+            r#"{
+                "character_id": 2114794365,
+                "access_token": "access_token",
+                "refresh_token": "refresh_token",
+                "expires_at": 0,
+                "scopes": ["publicData"]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(tokens.owner_hash, None);
+        assert!(tokens.is_expired());
+        assert_eq!(tokens.into_access_token(), "access_token");
+    }
+
+    #[test]
+    fn tokens_need_refreshing_within_the_margin() {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let tokens = EveTokens {
+            expires_at: now + 120,
+            ..tokens()
+        };
+
+        assert!(!tokens.is_expired());
+        assert!(!tokens.needs_refresh(Duration::from_secs(60)));
+        assert!(tokens.needs_refresh(Duration::from_secs(300)));
+    }
+
+    #[test]
+    fn tokens_can_be_destructured() {
+        let EveTokens {
+            refresh_token,
+            scopes,
+            ..
+        } = tokens();
+
+        assert_eq!(refresh_token.into_secret(), "refresh_token");
+        assert_eq!(scopes, ["publicData"]);
+    }
+
+    pub(crate) const JWKS: &str = r#"{
+        "keys": [
+            {
+                "alg": "RS256",
+                "e": "AQAB",
+                "kid": "JWT-Signature-Key",
+                "kty": "RSA",
+                "n": "nehPQ7FQ1YK-leKyIg-aACZaT-DbTL5V1XpXghtLX_bEC-fwxhdE_4yQKDF6cA-V4c-5kh8wMZbfYw5xxgM9DynhMkVrmQFyYB3QMZwydr922UWs3kLz-nO6vi0ldCn-ffM9odUPRHv9UbhM5bB4SZtCrpr9hWQgJ3FjzWO2KosGQ8acLxLtDQfU_lq0OGzoj_oWwUKaN_OVfu80zGTH7mxVeGMJqWXABKd52ByvYZn3wL_hG60DfDWGV_xfLlHMt_WoKZmrXT4V3BCBmbitJ6lda3oNdNeHUh486iqaL43bMR2K4TzrspGMRUYXcudUQ9TycBQBrUlT85NRY9TeOw",
+                "use": "sig"
+            },
+            {
+                "alg": "ES256",
+                "crv": "P-256",
+                "kid": "8878a23f-b40a-4a2a-a7f0-e0e4aa6fb3e6",
+                "kty": "EC",
+                "use": "sig",
+                "x": "ITcDYJ8WVpDO4QtZ169xXUt7GB1Y6-oMKIwJ3nK1tFU",
+                "y": "ZAJr0c7pFGhpWbG3k_FhTbXSkRL0TR-Pbvq6zJIBtWQ"
+            },
+            {
+                "alg": "EdDSA",
+                "crv": "Ed25519",
+                "kid": "future-key",
+                "kty": "OKP",
+                "use": "sig",
+                "x": "11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo"
+            }
+        ],
+        "SkipUnresolvedJsonWebKeys": true
+    }"#;
+
+    #[test]
+    fn keys_with_unknown_algorithms_dont_break_the_key_set() {
+        let keys: EveJwtKeys = serde_json::from_str(JWKS).unwrap();
+
+        assert!(keys.skip_unresolved_json_web_keys);
+        assert_eq!(keys.keys.len(), 3);
+        assert!(
+            matches!(&keys.keys[0], EveJwtKey::RS256 { kid, .. } if kid == "JWT-Signature-Key")
+        );
+        assert!(matches!(&keys.keys[1], EveJwtKey::ES256 { crv, .. } if crv == "P-256"));
+        assert!(matches!(keys.keys[2], EveJwtKey::Unknown));
+
+        let mut keys = keys.keys;
+        keys.rotate_right(1);
+        assert!(crate::decoding_key(keys).is_ok());
+        assert!(matches!(
+            crate::decoding_key(vec![EveJwtKey::Unknown]),
+            Err(Error::MissingJwtKey)
+        ));
+    }
+
+    #[test]
+    fn key_sets_round_trip_through_jwk_sets() {
+        let keys: EveJwtKeys = serde_json::from_str(JWKS).unwrap();
+        let supported = serde_json::to_value(&keys.keys[..2]).unwrap();
+
+        let set = JwkSet::from(keys);
+        assert_eq!(set.keys.len(), 2);
+        let jwk = set.find("JWT-Signature-Key").unwrap();
+        assert_eq!(jwk.common.key_algorithm, Some(KeyAlgorithm::RS256));
+        assert_eq!(jwk.common.public_key_use, Some(PublicKeyUse::Signature));
+        assert!(matches!(
+            &set.keys[1].algorithm,
+            AlgorithmParameters::EllipticCurve(ec) if ec.curve == EllipticCurve::P256
+        ));
+
+        let keys = EveJwtKeys::from(set);
+        assert!(!keys.skip_unresolved_json_web_keys);
+        assert_eq!(serde_json::to_value(&keys.keys).unwrap(), supported);
+    }
+
+    #[test]
+    fn unsupported_jwks_become_unknown_keys() {
+        let set: JwkSet = serde_json::from_str(
+            r#"{
+                "keys": [
+                    {"kty": "oct", "alg": "HS256", "kid": "secret", "k": "c2VjcmV0"},
+                    {
+                        "kty": "EC",
+                        "alg": "ES384",
+                        "crv": "P-384",
+                        "kid": "p384",
+                        "x": "ITcDYJ8WVpDO4QtZ169xXUt7GB1Y6-oMKIwJ3nK1tFU",
+                        "y": "ZAJr0c7pFGhpWbG3k_FhTbXSkRL0TR-Pbvq6zJIBtWQ"
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let keys = EveJwtKeys::from(set);
+        assert!(keys
+            .keys
+            .iter()
+            .all(|key| matches!(key, EveJwtKey::Unknown)));
+        assert!(JwkSet::from(keys).keys.is_empty());
+    }
+
+    #[test]
+    fn metadata_only_requires_the_endpoints() {
+        let metadata: EveSsoMetaData = serde_json::from_str(
+            r#"{
+                "authorization_endpoint": "https://login.eveonline.com/v2/oauth/authorize",
+                "jwks_uri": "https://login.eveonline.com/oauth/jwks",
+                "token_endpoint": "https://login.eveonline.com/v2/oauth/token",
+                "device_authorization_endpoint": "https://login.eveonline.com/v2/oauth/device"
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(metadata.jwks_uri, "https://login.eveonline.com/oauth/jwks");
+        assert!(metadata.issuer.is_none());
+        assert!(metadata.revocation_endpoint.is_none());
+        assert!(metadata.code_challenge_methods_supported.is_empty());
+        assert!(metadata.token_endpoint_auth_methods_supported.is_empty());
+
+        let endpoints = SsoEndpoints::from_metadata(&metadata).unwrap();
+        assert_eq!(endpoints.token_endpoint.path(), "/v2/oauth/token");
+        assert!(endpoints.revocation_endpoint.is_none());
+
+        assert!(serde_json::from_str::<EveSsoMetaData>(
+            r#"{
+                "authorization_endpoint": "https://login.eveonline.com/v2/oauth/authorize",
+                "jwks_uri": "https://login.eveonline.com/oauth/jwks"
+            }"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn key_sets_without_skip_unresolved_json_web_keys_parse() {
+        let keys: EveJwtKeys = serde_json::from_str(r#"{"keys": []}"#).unwrap();
+
+        assert!(!keys.skip_unresolved_json_web_keys);
+        assert!(keys.keys.is_empty());
+    }
+
+    #[test]
+    fn parses_esi_timestamps() {
+        assert_eq!(parse_esi_timestamp("1970-01-01T00:00:00"), Some(0));
+        assert_eq!(parse_esi_timestamp("2024-06-01T12:34:56"), Some(1717245296));
+        assert_eq!(
+            parse_esi_timestamp("2024-06-01T12:34:56Z"),
+            Some(1717245296)
+        );
+        assert_eq!(
+            parse_esi_timestamp("2024-06-01T12:34:56.123Z"),
+            Some(1717245296)
+        );
+        assert_eq!(parse_esi_timestamp("2024-02-29T00:00:00"), Some(1709164800));
+        assert_eq!(parse_esi_timestamp("2000-03-01T00:00:00"), Some(951868800));
+    }
+
+    #[test]
+    fn rejects_invalid_esi_timestamps() {
+        assert_eq!(parse_esi_timestamp(""), None);
+        assert_eq!(parse_esi_timestamp("2024-06-01"), None);
+        assert_eq!(parse_esi_timestamp("2024-06-01 12:34:56"), None);
+        assert_eq!(parse_esi_timestamp("2024-13-01T12:34:56"), None);
+        assert_eq!(parse_esi_timestamp("2024-06-00T12:34:56"), None);
+        assert_eq!(parse_esi_timestamp("1969-12-31T23:59:59"), None);
+        assert_eq!(parse_esi_timestamp("2024-06-01T12:34"), None);
+        assert_eq!(parse_esi_timestamp("not a timestamp"), None);
+    }
+
+    #[test]
+    fn character_ids_are_parsed_from_character_subjects() {
+        assert_eq!(
+            CharacterId::from_subject("CHARACTER:EVE:2114794365").unwrap(),
+            CharacterId(2114794365)
+        );
+        assert_eq!(CharacterId::from(2114794365).to_string(), "2114794365");
+        assert_eq!(u64::from(CharacterId(2114794365)), 2114794365);
+        assert_eq!(serde_json::to_string(&CharacterId(1)).unwrap(), "1");
+
+        for sub in [
+            "CORPORATION:EVE:98000001",
+            "CHARACTER:EVE:not-a-number",
+            "CHARACTER:EVE",
+            "CHARACTER:EVE:1:2",
+        ] {
+            assert!(
+                matches!(CharacterId::from_subject(sub), Err(Error::InvalidClaims(_))),
+                "{sub}"
+            );
+        }
+    }
+
+    #[cfg(feature = "test-utils")]
+    #[test]
+    fn characters_are_created_from_claims() {
+        let claims = EveJwtClaims::test()
+            .character_id(2114794365)
+            .name("Test Character")
+            .owner("owner-hash")
+            .scopes(["publicData", "esi-wallet.read_character_wallet.v1"])
+            .expires_at(1_700_000_000)
+            .build();
+
+        let character = Character::try_from(&claims).unwrap();
+        assert_eq!(character.id, CharacterId(2114794365));
+        assert_eq!(character.name, "Test Character");
+        assert_eq!(character.owner_hash, "owner-hash");
+        assert_eq!(character.scopes, claims.scopes());
+        assert_eq!(character.expires_at, 1_700_000_000);
+        assert_eq!(Character::try_from(claims).unwrap(), character);
+
+        let claims = EveJwtClaims::test()
+            .subject("CORPORATION:EVE:98000001")
+            .build();
+        assert!(matches!(
+            Character::try_from(claims),
+            Err(Error::InvalidClaims(_))
+        ));
+    }
+
+    #[cfg(feature = "test-utils")]
+    #[test]
+    fn claims_helpers() {
+        let claims = EveJwtClaims::test()
+            .character_id(2114794365)
+            .name("Test Character")
+            .scopes(["publicData"])
+            .expires_at(1_700_000_000)
+            .build();
+
+        assert_eq!(claims.character_id().unwrap(), CharacterId(2114794365));
+        assert_eq!(claims.character_name(), "Test Character");
+        assert_eq!(claims.scopes().to_vec(), ["publicData"]);
+        assert_eq!(
+            claims.expires_at(),
+            UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+        );
+        assert!(claims.is_expired());
+
+        let claims = EveJwtClaims::test()
+            .scopes(["publicData", "esi-wallet.read_character_wallet.v1"])
+            .build();
+        assert_eq!(claims.scopes().len(), 2);
+        assert!(!claims.is_expired());
+        assert!(EveJwtClaims::test().build().scopes().is_empty());
+    }
+
+    #[test]
+    fn callback_results_of_successful_and_failed_logins() {
+        let success: CallbackResult =
+            serde_json::from_str(r#"{"code": "code", "state": "state"}"#).unwrap();
+        assert_eq!(success.state(), Some("state"));
+        assert!(!success.is_access_denied());
+        assert_eq!(
+            success.into_result().unwrap(),
+            ("code".to_string(), "state".to_string())
+        );
+
+        let denied: CallbackResult = serde_json::from_str(
+            r#"{"error": "access_denied", "error_description": "The user denied consent"}"#,
+        )
+        .unwrap();
+        assert_eq!(denied.state(), None);
+        assert!(denied.is_access_denied());
+        match denied.into_result() {
+            Err(Error::Sso {
+                error,
+                error_description,
+                ..
+            }) => {
+                assert_eq!(error, "access_denied");
+                assert_eq!(
+                    error_description.as_deref(),
+                    Some("The user denied consent")
+                );
+            }
+            result => panic!("Expected an SSO error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn callback_params_convert_into_callback_results() {
+        let params: CallbackParams =
+            serde_json::from_str(r#"{"code": "code", "state": "state"}"#).unwrap();
+        assert_eq!(
+            CallbackResult::try_from(params).unwrap(),
+            CallbackResult::Success {
+                code: "code".to_string(),
+                state: "state".to_string()
+            }
+        );
+
+        // An error takes precedence over a code
+        let params = CallbackParams {
+            code: Some("code".to_string()),
+            state: Some("state".to_string()),
+            error: Some("access_denied".to_string()),
+            error_description: None,
+        };
+        assert!(CallbackResult::try_from(params).unwrap().is_access_denied());
+
+        for params in [
+            CallbackParams::default(),
+            CallbackParams {
+                code: Some("code".to_string()),
+                ..Default::default()
+            },
+        ] {
+            assert!(matches!(
+                CallbackResult::try_from(params),
+                Err(Error::UnexpectedResponse(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn debug_redacts_codes_and_tokens() {
+        let params = CallbackParams {
+            code: Some("secret_code".to_string()),
+            state: Some("state".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            format!("{:?}", params),
+            r#"CallbackParams { code: Some(***redacted***), state: Some("state"), error: None, error_description: None }"#
+        );
+
+        let result = CallbackResult::Success {
+            code: "secret_code".to_string(),
+            state: "state".to_string(),
+        };
+        assert_eq!(
+            format!("{:?}", result),
+            r#"Success { code: ***redacted***, state: "state" }"#
+        );
+
+        let tokens = crate::store::tests::tokens("secret_refresh_token");
+        let debug = format!("{:?}", tokens);
+        assert!(!debug.contains("access_token\""));
+        assert!(!debug.contains("secret_refresh_token"));
+        assert!(debug.contains("2114794365"));
+    }
+
+    #[test]
+    fn scopes_are_grouped_by_category() {
+        assert_eq!(
+            scope_category("esi-wallet.read_character_wallet.v1"),
+            "wallet"
+        );
+        assert_eq!(scope_category("esi-ui.open_window.v1"), "ui");
+        assert_eq!(scope_category("publicData"), "publicData");
+    }
+
+    #[test]
+    fn countdowns_use_the_two_largest_units() {
+        assert_eq!(countdown(59), "59s");
+        assert_eq!(countdown(1199), "19m 59s");
+        assert_eq!(countdown(7380), "2h 3m");
+        assert_eq!(countdown(90000), "1d 1h");
+    }
+
+    #[cfg(feature = "test-utils")]
+    #[test]
+    fn claims_builder_sets_the_claims() {
+        let claims = EveJwtClaims::test()
+            .character_id(2114794365)
+            .client_id("my_client")
+            .server(EveServer::Singularity)
+            .issued_at(1_700_000_000)
+            .expires_in(Duration::from_secs(60))
+            .scopes(["publicData"])
+            .build();
+
+        assert_eq!(claims.sub, "CHARACTER:EVE:2114794365");
+        assert_eq!(claims.azp, "my_client");
+        assert_eq!(claims.aud, ["my_client", "EVE Online"]);
+        assert_eq!(claims.tenant, EveServer::Singularity.tenant());
+        assert_eq!(claims.exp, 1_700_000_060);
+        assert!(matches!(&claims.scp, Some(EveJwtScopes::Single(scope)) if scope == "publicData"));
+
+        let claims = EveJwtClaims::test()
+            .scopes(["publicData", "esi-assets.read_assets.v1"])
+            .build();
+        assert!(matches!(&claims.scp, Some(EveJwtScopes::Multiple(scopes)) if scopes.len() == 2));
+        assert_ne!(claims.jti, EveJwtClaims::test().build().jti);
+    }
+
+    #[cfg(feature = "test-utils")]
+    #[test]
+    fn unknown_claims_are_preserved() {
+        let claims = EveJwtClaims::test()
+            .claim("sso_version", 3)
+            .claim("amr", serde_json::json!(["password"]))
+            .build();
+
+        let json = serde_json::to_value(&claims).unwrap();
+        assert_eq!(json["sso_version"], 3);
+
+        let claims: EveJwtClaims = serde_json::from_value(json).unwrap();
+        assert_eq!(
+            claims.extra_claim("sso_version"),
+            Some(&serde_json::json!(3))
+        );
+        assert_eq!(
+            claims.extra_claim("amr"),
+            Some(&serde_json::json!(["password"]))
+        );
+        assert_eq!(claims.extra_claim("unknown"), None);
+        // Known claims stay in their fields
+        assert_eq!(claims.extra.len(), 2);
+    }
 }
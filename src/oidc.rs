@@ -0,0 +1,192 @@
+//! Interop with the `openidconnect` crate, enabled with the `openidconnect` feature
+//!
+//! Exposes the EVE Online SSO endpoints, keys & claims as `openidconnect` types so its client & claims machinery
+//! can be used alongside this crate. EVE Online SSO doesn't issue ID tokens, the returned access token is a JWT
+//! which is validated with [`crate::validate_token`] or [`crate::EveSsoClient::validate_token`].
+//!
+//! ```no_run
+//! # fn example(client_id: String, client_secret: String, keys: eve_oauth2::models::EveJwtKeys) {
+//! use eve_oauth2::oidc;
+//!
+//! let client = oidc::core_client(client_id, Some(client_secret), &keys);
+//! # }
+//! ```
+
+use openidconnect::core::{
+    CoreClient, CoreGenderClaim, CoreJsonWebKey, CoreJsonWebKeySet, CoreJwsSigningAlgorithm,
+    CoreProviderMetadata, CoreResponseType, CoreSubjectIdentifierType,
+};
+use openidconnect::{
+    AuthUrl, ClientId, ClientSecret, EmptyAdditionalProviderMetadata, EndUserName, EndpointNotSet,
+    EndpointSet, IssuerUrl, JsonWebKeySetUrl, ResponseTypes, StandardClaims, SubjectIdentifier,
+    TokenUrl,
+};
+
+use crate::error::Error;
+use crate::models::{EveJwtClaims, EveJwtKey, EveJwtKeys, EveSsoMetaData};
+use crate::{AUTHORIZE_URL, ISSUER, TOKEN_URL};
+
+/// Issuer of EVE Online SSO tokens
+pub fn issuer_url() -> IssuerUrl {
+    IssuerUrl::new(ISSUER.to_string()).expect("Invalid EVE Online SSO issuer")
+}
+
+/// Creates an `openidconnect` client for EVE Online SSO using the EVE JWT keys
+///
+/// The `openid` scope isn't requested as EVE Online SSO doesn't support it. client_secret is `None` for PKCE clients.
+pub fn core_client(
+    client_id: impl Into<String>,
+    client_secret: Option<String>,
+    keys: &EveJwtKeys,
+) -> CoreClient<EndpointSet, EndpointNotSet, EndpointNotSet, EndpointNotSet, EndpointSet> {
+    let client = CoreClient::new(ClientId::new(client_id.into()), issuer_url(), keys.into())
+        .set_auth_uri(
+            AuthUrl::new(AUTHORIZE_URL.to_string()).expect("Invalid authorization endpoint URL"),
+        )
+        .set_token_uri(TokenUrl::new(TOKEN_URL.to_string()).expect("Invalid token endpoint URL"))
+        .disable_openid_scope();
+
+    match client_secret {
+        Some(client_secret) => client.set_client_secret(ClientSecret::new(client_secret)),
+        None => client,
+    }
+}
+
+/// Converts the EVE Online SSO metadata & keys to `openidconnect` provider metadata
+///
+/// Use with `CoreClient::from_provider_metadata` to create a client from the discovered endpoints. Returns
+/// [`Error::UnexpectedResponse`] if the metadata contains an invalid URL.
+pub fn provider_metadata(
+    metadata: &EveSsoMetaData,
+    keys: &EveJwtKeys,
+) -> Result<CoreProviderMetadata, Error> {
+    let issuer = match &metadata.issuer {
+        Some(issuer) if issuer.starts_with("http") => {
+            IssuerUrl::new(issuer.clone()).map_err(invalid_url)?
+        }
+        // EVE Online SSO has used its host without a scheme as issuer
+        Some(issuer) => IssuerUrl::new(format!("https://{}", issuer)).map_err(invalid_url)?,
+        None => issuer_url(),
+    };
+
+    let response_types = metadata
+        .response_types_supported
+        .iter()
+        .filter_map(|response_type| match response_type.as_str() {
+            "code" => Some(ResponseTypes::new(vec![CoreResponseType::Code])),
+            "token" => Some(ResponseTypes::new(vec![CoreResponseType::Token])),
+            _ => None,
+        })
+        .collect();
+
+    let provider_metadata = CoreProviderMetadata::new(
+        issuer,
+        AuthUrl::new(metadata.authorization_endpoint.clone()).map_err(invalid_url)?,
+        JsonWebKeySetUrl::new(metadata.jwks_uri.clone()).map_err(invalid_url)?,
+        response_types,
+        vec![CoreSubjectIdentifierType::Public],
+        vec![CoreJwsSigningAlgorithm::RsaSsaPkcs1V15Sha256],
+        EmptyAdditionalProviderMetadata {},
+    )
+    .set_token_endpoint(Some(
+        TokenUrl::new(metadata.token_endpoint.clone()).map_err(invalid_url)?,
+    ))
+    .set_jwks(keys.into());
+
+    Ok(provider_metadata)
+}
+
+/// Converts the RS256 & ES256 keys, unknown keys are skipped
+impl From<&EveJwtKeys> for CoreJsonWebKeySet {
+    fn from(keys: &EveJwtKeys) -> Self {
+        CoreJsonWebKeySet::new(keys.keys.iter().filter_map(core_json_web_key).collect())
+    }
+}
+
+/// Maps the subject & character name to the standard claims
+impl From<&EveJwtClaims> for StandardClaims<CoreGenderClaim> {
+    fn from(claims: &EveJwtClaims) -> Self {
+        StandardClaims::new(SubjectIdentifier::new(claims.sub.clone()))
+            .set_name(Some(EndUserName::new(claims.name.clone()).into()))
+    }
+}
+
+/// Round-trips the key through its JWK JSON, the `openidconnect` key constructors need the decoded components &
+/// don't expose the curve type
+fn core_json_web_key(key: &EveJwtKey) -> Option<CoreJsonWebKey> {
+    if let EveJwtKey::Unknown = key {
+        return None;
+    }
+
+    serde_json::to_value(key)
+        .and_then(serde_json::from_value)
+        .ok()
+}
+
+fn invalid_url(err: openidconnect::url::ParseError) -> Error {
+    Error::UnexpectedResponse(format!("Invalid URL in EVE Online SSO metadata: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use openidconnect::JsonWebKey;
+
+    fn metadata(issuer: Option<&str>) -> EveSsoMetaData {
+        serde_json::from_value(serde_json::json!({
+            "authorization_endpoint": "https://login.eveonline.com/v2/oauth/authorize",
+            "issuer": issuer,
+            "jwks_uri": "https://login.eveonline.com/oauth/jwks",
+            "response_types_supported": ["code", "token", "id_token"],
+            "token_endpoint": "https://login.eveonline.com/v2/oauth/token",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn converts_the_known_keys() {
+        let keys: EveJwtKeys = serde_json::from_str(crate::models::tests::JWKS).unwrap();
+        let key_set = CoreJsonWebKeySet::from(&keys);
+
+        let kids: Vec<&str> = key_set
+            .keys()
+            .iter()
+            .filter_map(|key| key.key_id())
+            .map(|kid| kid.as_str())
+            .collect();
+        assert_eq!(
+            kids,
+            ["JWT-Signature-Key", "8878a23f-b40a-4a2a-a7f0-e0e4aa6fb3e6"]
+        );
+    }
+
+    #[test]
+    fn provider_metadata_uses_the_endpoints_and_issuer_of_the_metadata() {
+        let keys: EveJwtKeys = serde_json::from_str(crate::models::tests::JWKS).unwrap();
+
+        for issuer in [None, Some("login.eveonline.com"), Some(ISSUER)] {
+            let provider_metadata = provider_metadata(&metadata(issuer), &keys).unwrap();
+
+            assert_eq!(provider_metadata.issuer(), &issuer_url());
+            assert_eq!(
+                provider_metadata.token_endpoint().map(|url| url.as_str()),
+                Some("https://login.eveonline.com/v2/oauth/token")
+            );
+            assert_eq!(provider_metadata.response_types_supported().len(), 2);
+            assert_eq!(provider_metadata.jwks().keys().len(), 2);
+        }
+    }
+
+    #[test]
+    fn provider_metadata_rejects_invalid_urls() {
+        let keys: EveJwtKeys = serde_json::from_str(crate::models::tests::JWKS).unwrap();
+        let mut metadata = metadata(None);
+        metadata.token_endpoint = "not a url".to_string();
+
+        assert!(matches!(
+            provider_metadata(&metadata, &keys),
+            Err(Error::UnexpectedResponse(_))
+        ));
+    }
+}
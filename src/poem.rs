@@ -0,0 +1,212 @@
+//! Integration with the poem web framework, enabled with the `poem` feature
+//!
+//! Add an [`EveSsoClient`] to your app's data & take [`EveClaims`] as a parameter of protected handlers.
+//! Wrap endpoints in [`RequireScopes`] to require scopes of every request to them.
+//!
+//! ```no_run
+//! use eve_oauth2::poem::{EveClaims, RequireScopes};
+//! use eve_oauth2::EveSsoClient;
+//! use poem::{get, handler, EndpointExt, Route};
+//!
+//! #[handler]
+//! fn character(EveClaims(claims): EveClaims) -> String {
+//!     claims.name
+//! }
+//!
+//! let client = EveSsoClient::new("client_id".to_string(), None);
+//! let app = Route::new()
+//!     .at(
+//!         "/wallet",
+//!         get(character).with(RequireScopes::new(["esi-wallet.read_character_wallet.v1"])),
+//!     )
+//!     .data(client);
+//! ```
+
+use std::fmt;
+use std::sync::Arc;
+
+use ::poem::error::ResponseError;
+use ::poem::http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use ::poem::http::StatusCode;
+use ::poem::{Endpoint, FromRequest, IntoResponse, Middleware, Request, RequestBody, Response};
+
+use crate::error::{Error, ErrorClass};
+use crate::http;
+use crate::models::EveJwtClaims;
+use crate::validation::TokenValidation;
+use crate::EveSsoClient;
+
+/// Extracts the bearer token of the Authorization header & validates it with the [`EveSsoClient`] of the app's data
+///
+/// Requests without a valid token are rejected with 401 Unauthorized. Claims validated by [`RequireScopes`] are
+/// reused instead of validating the token again, other [`EveJwtClaims`] in the request's data are ignored.
+#[derive(Debug, Clone)]
+pub struct EveClaims(pub EveJwtClaims);
+
+impl<'a> FromRequest<'a> for EveClaims {
+    async fn from_request(req: &'a Request, _body: &mut RequestBody) -> ::poem::Result<Self> {
+        if let Some(ValidatedClaims(claims)) = req.data::<ValidatedClaims>() {
+            return Ok(EveClaims(claims.clone()));
+        }
+
+        Ok(EveClaims(validate(req, &TokenValidation::default()).await?))
+    }
+}
+
+/// Claims validated by [`RequireScopes`], other middleware can't insert this type into the request's data
+#[derive(Debug, Clone)]
+struct ValidatedClaims(EveJwtClaims);
+
+/// Middleware rejecting requests without a valid token granting the scopes with 401 Unauthorized or 403 Forbidden
+///
+/// The validated claims are available to the handlers through [`EveClaims`].
+#[derive(Debug, Clone)]
+pub struct RequireScopes {
+    validation: Arc<TokenValidation>,
+}
+
+impl RequireScopes {
+    /// Creates the middleware requiring the scopes, an empty list only requires a valid token
+    pub fn new(scopes: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            validation: Arc::new(TokenValidation::new().require_scopes(scopes)),
+        }
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for RequireScopes {
+    type Output = RequireScopesEndpoint<E>;
+
+    fn transform(&self, endpoint: E) -> Self::Output {
+        RequireScopesEndpoint {
+            endpoint,
+            validation: self.validation.clone(),
+        }
+    }
+}
+
+/// Endpoint created by [`RequireScopes`]
+pub struct RequireScopesEndpoint<E> {
+    endpoint: E,
+    validation: Arc<TokenValidation>,
+}
+
+impl<E: Endpoint> Endpoint for RequireScopesEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, mut req: Request) -> ::poem::Result<Self::Output> {
+        let claims = validate(&req, &self.validation).await?;
+
+        req.set_data(ValidatedClaims(claims));
+
+        self.endpoint
+            .call(req)
+            .await
+            .map(IntoResponse::into_response)
+    }
+}
+
+/// Error of requests which couldn't be authenticated
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum EveAuthError {
+    /// The request has no bearer token in the Authorization header, responds with 401 Unauthorized
+    MissingToken,
+    /// No [`EveSsoClient`] was added to the app's data, responds with 500 Internal Server Error
+    MissingClient,
+    /// Validating the token failed, responds with 403 Forbidden if the token is missing required scopes,
+    /// 503 Service Unavailable if EVE Online SSO couldn't be reached to retrieve the EVE JWT keys & 401 Unauthorized
+    /// otherwise
+    InvalidToken(Error),
+}
+
+impl fmt::Display for EveAuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EveAuthError::MissingToken => write!(f, "Missing bearer token"),
+            EveAuthError::MissingClient => write!(f, "No EveSsoClient was added to the app's data"),
+            EveAuthError::InvalidToken(err @ Error::MissingScopes(_)) => write!(f, "{}", err),
+            EveAuthError::InvalidToken(_) => write!(f, "Invalid bearer token"),
+        }
+    }
+}
+
+impl std::error::Error for EveAuthError {}
+
+impl ResponseError for EveAuthError {
+    fn status(&self) -> StatusCode {
+        match self {
+            EveAuthError::MissingToken => StatusCode::UNAUTHORIZED,
+            EveAuthError::MissingClient => StatusCode::INTERNAL_SERVER_ERROR,
+            EveAuthError::InvalidToken(Error::MissingScopes(_)) => StatusCode::FORBIDDEN,
+            EveAuthError::InvalidToken(err) => match err.classify() {
+                ErrorClass::Transient | ErrorClass::RateLimited => StatusCode::SERVICE_UNAVAILABLE,
+                _ => StatusCode::UNAUTHORIZED,
+            },
+        }
+    }
+
+    fn as_response(&self) -> Response {
+        let mut response = Response::builder().status(self.status());
+
+        match self {
+            EveAuthError::InvalidToken(Error::MissingScopes(missing_scopes)) => {
+                response =
+                    response.header(WWW_AUTHENTICATE, http::insufficient_scope(missing_scopes));
+            }
+            _ if self.status() == StatusCode::UNAUTHORIZED => {
+                response = response.header(WWW_AUTHENTICATE, "Bearer");
+            }
+            _ => {}
+        }
+
+        response.body(self.to_string())
+    }
+}
+
+async fn validate(
+    req: &Request,
+    validation: &TokenValidation,
+) -> Result<EveJwtClaims, EveAuthError> {
+    let client = req
+        .data::<EveSsoClient>()
+        .ok_or(EveAuthError::MissingClient)?;
+    let token = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(http::bearer_token)
+        .ok_or(EveAuthError::MissingToken)?;
+
+    client
+        .validate_token_with(token, validation)
+        .await
+        .map(|token_data| token_data.claims)
+        .map_err(EveAuthError::InvalidToken)
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use super::*;
+
+    async fn extract(req: Request) -> ::poem::Result<EveClaims> {
+        EveClaims::from_request(&req, &mut RequestBody::default()).await
+    }
+
+    #[tokio::test]
+    async fn claims_extractor_only_reuses_claims_validated_by_require_scopes() {
+        let client = EveSsoClient::new("client_id", None);
+        let claims = EveJwtClaims::test().client_id("client_id").build();
+
+        let mut req = Request::default();
+        req.set_data(client.clone());
+        req.set_data(claims.clone());
+        let err = extract(req).await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::UNAUTHORIZED);
+
+        let mut req = Request::default();
+        req.set_data(client);
+        req.set_data(ValidatedClaims(claims));
+        assert_eq!(extract(req).await.unwrap().0.azp, "client_id");
+    }
+}
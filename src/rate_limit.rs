@@ -0,0 +1,148 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// Client-side rate limiter for requests to EVE Online SSO, enabled with the `tokio` feature
+///
+/// Bulk refresh jobs can send more requests than EVE Online SSO tolerates. Set a limiter with
+/// [`crate::EveSsoClient::rate_limiter`] to delay requests once the rate is exceeded, including requests sent by a
+/// [`crate::TokenManager`] using the client & retries. Up to burst requests are sent immediately after a quiet period.
+/// Clones share the same limit, e.g. to apply one limit to several clients.
+///
+/// ```no_run
+/// use eve_oauth2::{EveSsoClient, RateLimiter};
+///
+/// let client = EveSsoClient::builder("client_id")
+///     .rate_limiter(RateLimiter::new(10).burst(20))
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    /// Time at which the next request is due if requests are sent at exactly the rate
+    next_due: Arc<Mutex<Option<Instant>>>,
+    interval: Duration,
+    burst: u32,
+}
+
+impl RateLimiter {
+    /// Creates a limiter allowing requests_per_second requests per second with a burst of 1
+    ///
+    /// Panics if requests_per_second is 0.
+    pub fn new(requests_per_second: u32) -> Self {
+        assert!(requests_per_second > 0, "requests_per_second must not be 0");
+
+        Self {
+            next_due: Arc::default(),
+            interval: Duration::from_secs(1) / requests_per_second,
+            burst: 1,
+        }
+    }
+
+    /// Sets how many requests can be sent immediately after a quiet period, at least 1
+    pub fn burst(mut self, burst: u32) -> Self {
+        self.burst = burst.max(1);
+        self
+    }
+
+    /// Waits until a request can be sent without exceeding the rate
+    pub(crate) async fn acquire(&self) {
+        let now = Instant::now();
+
+        let ready_at = {
+            let mut next_due = self.next_due.lock().unwrap();
+            let due = next_due.map_or(now, |due| due.max(now));
+            *next_due = Some(due + self.interval);
+
+            due.checked_sub(self.interval.saturating_mul(self.burst - 1))
+                .map_or(now, |ready_at| ready_at.max(now))
+        };
+
+        if ready_at > now {
+            tokio::time::sleep_until(ready_at).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{Endpoint, StatusCode};
+    use crate::retry::tests::{request, ScriptedHttpClient};
+    use crate::EveSsoClient;
+
+    /// Acquires the limiter count times & returns when each acquire returned relative to the start
+    async fn acquire(rate_limiter: &RateLimiter, count: usize) -> Vec<Duration> {
+        let start = Instant::now();
+        let mut acquired_after = Vec::new();
+
+        for _ in 0..count {
+            rate_limiter.acquire().await;
+            acquired_after.push(start.elapsed());
+        }
+
+        acquired_after
+    }
+
+    fn millis(millis: &[u64]) -> Vec<Duration> {
+        millis.iter().copied().map(Duration::from_millis).collect()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn spaces_requests_at_the_rate() {
+        let rate_limiter = RateLimiter::new(10);
+
+        assert_eq!(acquire(&rate_limiter, 4).await, millis(&[0, 100, 200, 300]));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sends_a_burst_after_a_quiet_period() {
+        let rate_limiter = RateLimiter::new(10).burst(3);
+
+        assert_eq!(
+            acquire(&rate_limiter, 5).await,
+            millis(&[0, 0, 0, 100, 200])
+        );
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert_eq!(acquire(&rate_limiter, 4).await, millis(&[0, 0, 0, 100]));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn clones_share_the_limit() {
+        let rate_limiter = RateLimiter::new(2);
+        let clone = rate_limiter.clone();
+
+        assert_eq!(acquire(&rate_limiter, 1).await, millis(&[0]));
+        assert_eq!(acquire(&clone, 2).await, millis(&[500, 1000]));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn client_requests_are_rate_limited() {
+        let http_client = ScriptedHttpClient::new([Ok(StatusCode::OK); 3]);
+        let client = EveSsoClient::builder("client_id")
+            .http_client(http_client.clone())
+            .rate_limiter(RateLimiter::new(4))
+            .build();
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            client.execute(request(), Endpoint::Jwks).await.unwrap();
+        }
+
+        let sent_after: Vec<_> = http_client
+            .sent_at
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|sent_at| *sent_at - start)
+            .collect();
+        assert_eq!(sent_after, millis(&[0, 250, 500]));
+    }
+
+    #[test]
+    #[should_panic(expected = "requests_per_second must not be 0")]
+    fn rejects_a_rate_of_zero() {
+        RateLimiter::new(0);
+    }
+}
@@ -0,0 +1,286 @@
+use std::collections::BTreeMap;
+
+use jsonwebtoken::TokenData;
+
+use crate::client::EveSsoClient;
+use crate::error::Error;
+use crate::models::EveJwtClaims;
+use crate::token::EveTokenResponse;
+use crate::{decode_unverified, AuthenticationData};
+
+/// Separates the application name from the state of the client in the states of registry logins
+const STATE_SEPARATOR: char = '.';
+
+/// Routes logins of several EVE developer applications through the right [`EveSsoClient`]
+///
+/// Each client is registered under a name, e.g. `main` & `recruitment`. Login URLs created by the registry carry the
+/// name in front of the state, so the callback is exchanged with the client the login was started with. Tokens are
+/// validated with the client whose client_id matches their `azp` claim.
+///
+/// ```no_run
+/// # async fn example(code: String, state: String) -> Result<(), eve_oauth2::Error> {
+/// use eve_oauth2::{EveSsoClient, EveSsoRegistry};
+///
+/// let registry = EveSsoRegistry::new()
+///     .register("main", EveSsoClient::new("main_id".to_string(), Some("main_secret".to_string())))
+///     .register("recruitment", EveSsoClient::new("recruitment_id".to_string(), None));
+///
+/// let auth = registry.create_login_url(
+///     "recruitment",
+///     "http://localhost:8000/callback".to_string(),
+///     vec!["publicData".to_string()],
+/// )?;
+/// // Store auth.state & auth.code_verifier in the session, then in the callback:
+/// let token = registry.get_access_token(&state, code, auth.code_verifier).await?;
+/// let (app, token_data) = registry.validate_token(token.access_token()).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct EveSsoRegistry {
+    clients: BTreeMap<String, EveSsoClient>,
+}
+
+impl EveSsoRegistry {
+    /// Creates an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the client under the name, replacing any client previously registered under it
+    ///
+    /// Panics if the name is empty or contains a `.`, which separates it from the state.
+    pub fn register(mut self, name: impl Into<String>, client: EveSsoClient) -> Self {
+        let name = name.into();
+
+        assert!(
+            !name.is_empty() && !name.contains(STATE_SEPARATOR),
+            "Invalid EVE application name {:?}",
+            name
+        );
+
+        self.clients.insert(name, client);
+        self
+    }
+
+    /// Returns the client registered under the name
+    pub fn client(&self, name: &str) -> Option<&EveSsoClient> {
+        self.clients.get(name)
+    }
+
+    /// Names of the registered applications in sorted order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.clients.keys().map(String::as_str)
+    }
+
+    /// Generates a login URL for the application with its name embedded in the state
+    ///
+    /// The PKCE flow is used if the application's client has no client secret, store the state & code verifier like
+    /// any other login. Fails with [`Error::InvalidState`] if no application is registered under the name & with
+    /// [`Error::Config`] if a scope is unknown & the client's [`crate::scopes::ScopeCheck::Deny`] is set.
+    pub fn create_login_url(
+        &self,
+        name: &str,
+        redirect_url: impl AsRef<str>,
+        scopes: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Result<AuthenticationData, Error> {
+        let client = self.require_client(name)?;
+        let prefix = format!("{}{}", name, STATE_SEPARATOR);
+
+        client.create_prefixed_login_url(&prefix, redirect_url, scopes)
+    }
+
+    /// Returns the name & client of the application a login was started with from the state of its callback
+    ///
+    /// Fails with [`Error::InvalidState`] if the state wasn't created by the registry or its application isn't
+    /// registered.
+    pub fn resolve_state(&self, state: &str) -> Result<(&str, &EveSsoClient), Error> {
+        let (name, _) = state.split_once(STATE_SEPARATOR).ok_or_else(|| {
+            Error::InvalidState("State doesn't contain an EVE application name".to_string())
+        })?;
+
+        self.clients
+            .get_key_value(name)
+            .map(|(name, client)| (name.as_str(), client))
+            .ok_or_else(|| Error::InvalidState(format!("No EVE application named {}", name)))
+    }
+
+    /// Verifies a signed state with the state signer of the application the login was started with & returns the
+    /// application's name, see [`EveSsoClient::verify_signed_state`]
+    pub fn verify_signed_state(&self, state: &str) -> Result<&str, Error> {
+        let (name, client) = self.resolve_state(state)?;
+
+        client.verify_signed_state(&state[name.len() + STATE_SEPARATOR.len_utf8()..])?;
+
+        Ok(name)
+    }
+
+    /// Retrieves the access token with the client of the application the login was started with
+    ///
+    /// Pass the code verifier of the login if it was created with PKCE. Verify the state of the callback against the
+    /// state stored in the session before calling this.
+    pub async fn get_access_token(
+        &self,
+        state: &str,
+        code: impl Into<String>,
+        code_verifier: Option<String>,
+    ) -> Result<EveTokenResponse, Error> {
+        let (_, client) = self.resolve_state(state)?;
+
+        match code_verifier {
+            Some(code_verifier) => client.get_access_token_pkce(code, code_verifier).await,
+            None => client.get_access_token(code).await,
+        }
+    }
+
+    /// Validates an access token with the client of the application it was issued to & returns the application's name
+    /// alongside the claims
+    ///
+    /// Fails with [`Error::InvalidClaims`] if the token was issued to an application which isn't registered.
+    pub async fn validate_token(
+        &self,
+        token: impl Into<String>,
+    ) -> Result<(&str, TokenData<EveJwtClaims>), Error> {
+        let token = token.into();
+        let azp = decode_unverified(&token)?.claims.azp;
+        let (name, client) = self
+            .clients
+            .iter()
+            .find(|(_, client)| client.client_id() == azp)
+            .ok_or_else(|| {
+                Error::InvalidClaims(format!("Token was issued to unknown client {}", azp))
+            })?;
+
+        Ok((name, client.validate_token(token).await?))
+    }
+
+    fn require_client(&self, name: &str) -> Result<&EveSsoClient, Error> {
+        self.clients
+            .get(name)
+            .ok_or_else(|| Error::InvalidState(format!("No EVE application named {}", name)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{BoxFuture, HttpClient, HttpError, HttpRequest, HttpResponse};
+    use crate::state::StateSigner;
+
+    const REDIRECT_URL: &str = "http://localhost:8000/callback";
+
+    /// Fails every request, the tests only need clients which don't send any
+    struct OfflineHttpClient;
+
+    impl HttpClient for OfflineHttpClient {
+        fn execute(&self, _request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, HttpError>> {
+            Box::pin(std::future::ready(Err(HttpError::new("Offline"))))
+        }
+    }
+
+    fn client(client_id: &str, client_secret: Option<&str>) -> EveSsoClient {
+        EveSsoClient::with_http_client(
+            client_id,
+            client_secret.map(str::to_string),
+            OfflineHttpClient,
+        )
+    }
+
+    fn registry() -> EveSsoRegistry {
+        EveSsoRegistry::new()
+            .register("main", client("main_id", Some("main_secret")))
+            .register(
+                "recruitment",
+                client("recruitment_id", None)
+                    .state_signer(StateSigner::new("0123456789abcdef0123456789abcdef")),
+            )
+    }
+
+    #[test]
+    fn login_states_resolve_to_their_application() {
+        let registry = registry();
+        assert_eq!(
+            registry.names().collect::<Vec<_>>(),
+            ["main", "recruitment"]
+        );
+
+        let login = registry
+            .create_login_url("main", REDIRECT_URL, ["publicData"])
+            .unwrap();
+        assert!(login.state.starts_with("main."));
+        assert!(login.code_verifier.is_none());
+        assert!(login.login_url.contains("client_id=main_id"));
+
+        let (name, client) = registry.resolve_state(&login.state).unwrap();
+        assert_eq!(name, "main");
+        assert_eq!(client.client_id(), "main_id");
+
+        let login = registry
+            .create_login_url("recruitment", REDIRECT_URL, ["publicData"])
+            .unwrap();
+        assert!(login.code_verifier.is_some());
+        assert_eq!(
+            registry.verify_signed_state(&login.state).unwrap(),
+            "recruitment"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_applications_and_states() {
+        let registry = registry();
+
+        assert!(matches!(
+            registry.create_login_url("unknown", REDIRECT_URL, ["publicData"]),
+            Err(Error::InvalidState(_))
+        ));
+        for state in ["state", "unknown.state"] {
+            assert!(matches!(
+                registry.resolve_state(state),
+                Err(Error::InvalidState(_))
+            ));
+        }
+        assert!(matches!(
+            registry.verify_signed_state("recruitment.tampered"),
+            Err(Error::InvalidState(_))
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid EVE application name")]
+    fn names_cant_contain_the_separator() {
+        let _ = EveSsoRegistry::new().register("main.app", client("client_id", None));
+    }
+
+    #[cfg(all(feature = "testing", feature = "reqwest"))]
+    #[tokio::test]
+    async fn routes_callbacks_and_tokens_to_their_application() {
+        let sso = crate::testing::MockSso::start().unwrap();
+        let registry = EveSsoRegistry::new()
+            .register("main", sso.client("main_id"))
+            .register("recruitment", sso.client("recruitment_id"));
+
+        let login = registry
+            .create_login_url("recruitment", REDIRECT_URL, ["publicData"])
+            .unwrap();
+        let (code, state) = sso
+            .authorize(&login.login_url)
+            .unwrap()
+            .into_result()
+            .unwrap();
+        assert_eq!(state, login.state);
+
+        let token = registry
+            .get_access_token(&state, code, login.code_verifier)
+            .await
+            .unwrap();
+        let (name, token_data) = registry.validate_token(token.access_token()).await.unwrap();
+        assert_eq!(name, "recruitment");
+        assert_eq!(token_data.claims.azp, "recruitment_id");
+
+        let token = sso.signer().token().client_id("other_id").sign();
+        assert!(matches!(
+            registry.validate_token(token).await,
+            Err(Error::InvalidClaims(_))
+        ));
+    }
+}
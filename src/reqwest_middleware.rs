@@ -0,0 +1,146 @@
+//! Middleware authenticating ESI requests sent with reqwest-middleware, enabled with the `reqwest-middleware` feature
+//!
+//! ```no_run
+//! # async fn example(manager: eve_oauth2::TokenManager<eve_oauth2::store::MemoryTokenStore>, character_id: u64) -> Result<(), Box<dyn std::error::Error>> {
+//! use eve_oauth2::reqwest_middleware::EveAuthMiddleware;
+//!
+//! let client = reqwest_middleware::ClientBuilder::new(reqwest::Client::new())
+//!     .with(EveAuthMiddleware::new(manager, character_id))
+//!     .build();
+//!
+//! let wallet = client
+//!     .get(format!("https://esi.evetech.net/latest/characters/{}/wallet/", character_id))
+//!     .send()
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use ::http::Extensions;
+use ::reqwest_middleware::{Middleware, Next, Result};
+use reqwest::{Request, Response};
+
+use crate::http::header::AUTHORIZATION;
+use crate::http::{HeaderValue, StatusCode};
+use crate::models::CharacterId;
+use crate::store::TokenStore;
+use crate::{TokenManager, ESI_HOST};
+
+/// Adds a valid access token of the character to requests sent to ESI
+///
+/// The token is taken from the [`TokenManager`], which refreshes it when it's near expiry. Requests rejected by ESI
+/// with 401 Unauthorized are retried once with a refreshed token if their body can be cloned. Insert a
+/// [`CharacterId`] into the extensions of a request, e.g. with `RequestBuilder::with_extension`, to send it on
+/// behalf of another character.
+///
+/// Only https requests to `esi.evetech.net` are authenticated so tokens aren't leaked to other hosts or sent in
+/// cleartext, use [`EveAuthMiddleware::hosts`] to authenticate requests to other hosts such as a proxy in front of ESI.
+pub struct EveAuthMiddleware<S> {
+    manager: TokenManager<S>,
+    character_id: u64,
+    hosts: Vec<String>,
+}
+
+impl<S: TokenStore> EveAuthMiddleware<S> {
+    /// Creates the middleware authenticating requests as the character
+    pub fn new(manager: TokenManager<S>, character_id: u64) -> Self {
+        Self {
+            manager,
+            character_id,
+            hosts: vec![ESI_HOST.to_string()],
+        }
+    }
+
+    /// Sets the hosts requests are authenticated for, replacing `esi.evetech.net`
+    pub fn hosts(mut self, hosts: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.hosts = hosts.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Whether the request is sent over https to one of the hosts
+    fn authenticates(&self, url: &reqwest::Url) -> bool {
+        url.scheme() == "https"
+            && url
+                .host_str()
+                .is_some_and(|host| self.hosts.iter().any(|allowed| allowed == host))
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait::async_trait(?Send))]
+impl<S: TokenStore + 'static> Middleware for EveAuthMiddleware<S> {
+    async fn handle(
+        &self,
+        mut req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> Result<Response> {
+        if !self.authenticates(req.url()) {
+            return next.run(req, extensions).await;
+        }
+
+        let character_id = extensions
+            .get::<CharacterId>()
+            .map_or(self.character_id, |character_id| character_id.0);
+
+        let access_token = self
+            .manager
+            .get_valid_token(character_id)
+            .await
+            .map_err(::reqwest_middleware::Error::middleware)?;
+
+        set_bearer(&mut req, &access_token)?;
+
+        let retry = req.try_clone();
+        let response = next.clone().run(req, extensions).await?;
+
+        let Some(mut retry) = retry.filter(|_| response.status() == StatusCode::UNAUTHORIZED)
+        else {
+            return Ok(response);
+        };
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(character_id, "ESI rejected the access token, refreshing it");
+
+        let access_token = self
+            .manager
+            .refresh_rejected_token(character_id, &access_token)
+            .await
+            .map_err(::reqwest_middleware::Error::middleware)?;
+
+        set_bearer(&mut retry, &access_token)?;
+
+        next.run(retry, extensions).await
+    }
+}
+
+fn set_bearer(req: &mut Request, access_token: &str) -> Result<()> {
+    let mut value = HeaderValue::from_str(&format!("Bearer {}", access_token))
+        .map_err(::reqwest_middleware::Error::middleware)?;
+    value.set_sensitive(true);
+
+    req.headers_mut().insert(AUTHORIZATION, value);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryTokenStore;
+    use crate::EveSsoClient;
+
+    #[test]
+    fn authenticates_only_https_requests_to_esi() {
+        let manager = TokenManager::new(
+            EveSsoClient::new("client_id", None),
+            MemoryTokenStore::new(),
+        );
+        let middleware = EveAuthMiddleware::new(manager, 2114794365);
+        let url = |url: &str| reqwest::Url::parse(url).unwrap();
+
+        assert!(middleware.authenticates(&url("https://esi.evetech.net/latest/status/")));
+        assert!(!middleware.authenticates(&url("http://esi.evetech.net/latest/status/")));
+        assert!(!middleware.authenticates(&url("https://example.com/latest/status/")));
+    }
+}
@@ -0,0 +1,380 @@
+//! Retry policies for requests to EVE Online SSO
+//!
+//! Requests aren't retried by default. Set a [`RetryPolicy`] with [`crate::EveSsoClient::retry_policy`] to decide
+//! per [`Endpoint`] whether & when a failed request is sent again, e.g. no retries in interactive login flows but
+//! aggressive retries in batch jobs refreshing many tokens.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use eve_oauth2::http::{BoxFuture, Endpoint, HttpError, HttpResponse};
+//! use eve_oauth2::retry::RetryPolicy;
+//!
+//! /// Retries JWKS requests which failed to connect once after a second
+//! struct RetryJwks;
+//!
+//! impl RetryPolicy for RetryJwks {
+//!     fn retry_delay(
+//!         &self,
+//!         endpoint: Endpoint,
+//!         attempt: u32,
+//!         result: Result<&HttpResponse, &HttpError>,
+//!     ) -> Option<Duration> {
+//!         (endpoint == Endpoint::Jwks && attempt == 1 && result.is_err()).then_some(Duration::from_secs(1))
+//!     }
+//!
+//!     fn sleep(&self, delay: Duration) -> BoxFuture<'static, ()> {
+//!         // Sleep using your async runtime of choice
+//!         # let _ = delay;
+//!         # unimplemented!()
+//!     }
+//! }
+//! ```
+
+use std::time::Duration;
+
+use crate::http::header::RETRY_AFTER;
+#[cfg(feature = "tokio")]
+use crate::http::StatusCode;
+use crate::http::{BoxFuture, Endpoint, HttpError, HttpResponse};
+
+/// Decides whether a request to EVE Online SSO is retried & how long to wait before sending it again
+pub trait RetryPolicy: Send + Sync {
+    /// Returns the delay before the request is retried or `None` to return the result
+    ///
+    /// Called after every attempt, including successful ones, attempt starts at 1. Non-success status codes are
+    /// passed as a response, errors mean the request couldn't be completed.
+    fn retry_delay(
+        &self,
+        endpoint: Endpoint,
+        attempt: u32,
+        result: Result<&HttpResponse, &HttpError>,
+    ) -> Option<Duration>;
+
+    /// Waits for the delay returned by [`RetryPolicy::retry_delay`] using the application's async runtime
+    fn sleep(&self, delay: Duration) -> BoxFuture<'static, ()>;
+}
+
+/// Policy which never retries requests, used by default
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoRetry;
+
+impl RetryPolicy for NoRetry {
+    fn retry_delay(
+        &self,
+        _endpoint: Endpoint,
+        _attempt: u32,
+        _result: Result<&HttpResponse, &HttpError>,
+    ) -> Option<Duration> {
+        None
+    }
+
+    fn sleep(&self, _delay: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(std::future::ready(()))
+    }
+}
+
+/// Policy retrying failed requests with exponentially increasing delays, sleeps using tokio
+///
+/// Requests which couldn't be completed & responses with status 429 or 5xx are retried, honoring the Retry-After
+/// header. Requests to the token endpoint are only retried on status 429 & 503 as EVE Online SSO didn't process them,
+/// authorization codes can only be exchanged once & refresh tokens may have been rotated by a lost response.
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use eve_oauth2::retry::ExponentialBackoff;
+/// use eve_oauth2::EveSsoClient;
+///
+/// let client = EveSsoClient::builder("client_id")
+///     .retry_policy(ExponentialBackoff::new(5).max_delay(Duration::from_secs(60)))
+///     .build();
+/// ```
+#[cfg(feature = "tokio")]
+#[derive(Debug, Clone)]
+pub struct ExponentialBackoff {
+    max_retries: u32,
+    initial_delay: Duration,
+    max_delay: Duration,
+}
+
+#[cfg(feature = "tokio")]
+impl ExponentialBackoff {
+    /// Creates a policy retrying up to max_retries times, waiting 500ms before the first retry & at most 30 seconds
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+
+    /// Sets the delay before the first retry, it's doubled for every following retry
+    pub fn initial_delay(mut self, delay: Duration) -> Self {
+        self.initial_delay = delay;
+        self
+    }
+
+    /// Sets the longest delay between retries, also caps delays requested with the Retry-After header
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl RetryPolicy for ExponentialBackoff {
+    fn retry_delay(
+        &self,
+        endpoint: Endpoint,
+        attempt: u32,
+        result: Result<&HttpResponse, &HttpError>,
+    ) -> Option<Duration> {
+        if attempt > self.max_retries {
+            return None;
+        }
+
+        let retry_after = match result {
+            Ok(response) if !is_retryable_status(endpoint, response.status_code) => return None,
+            Ok(response) => retry_after(response),
+            Err(_) if endpoint == Endpoint::Token => return None,
+            Err(_) => None,
+        };
+
+        let backoff = self
+            .initial_delay
+            .saturating_mul(2u32.saturating_pow(attempt - 1));
+
+        Some(retry_after.unwrap_or(backoff).min(self.max_delay))
+    }
+
+    fn sleep(&self, delay: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(tokio::time::sleep(delay))
+    }
+}
+
+#[cfg(feature = "tokio")]
+fn is_retryable_status(endpoint: Endpoint, status_code: StatusCode) -> bool {
+    match endpoint {
+        Endpoint::Token => matches!(
+            status_code,
+            StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+        ),
+        _ => status_code == StatusCode::TOO_MANY_REQUESTS || status_code.is_server_error(),
+    }
+}
+
+/// Reads the delay in seconds of the Retry-After header of a response
+pub fn retry_after(response: &HttpResponse) -> Option<Duration> {
+    response
+        .headers
+        .get(RETRY_AFTER)
+        .and_then(|retry_after| retry_after.to_str().ok())
+        .and_then(|retry_after| retry_after.trim().parse().ok())
+        .map(Duration::from_secs)
+}
+
+#[cfg(all(test, feature = "tokio"))]
+pub(crate) mod tests {
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::http::{HeaderMap, HttpClient, HttpRequest, Method, Url};
+    use crate::EveSsoClient;
+
+    /// HTTP client returning the scripted results in order & recording when each request was sent
+    #[derive(Clone, Default)]
+    pub(crate) struct ScriptedHttpClient {
+        results: Arc<Mutex<VecDeque<Result<StatusCode, &'static str>>>>,
+        pub(crate) sent_at: Arc<Mutex<Vec<tokio::time::Instant>>>,
+    }
+
+    impl ScriptedHttpClient {
+        pub(crate) fn new(
+            results: impl IntoIterator<Item = Result<StatusCode, &'static str>>,
+        ) -> Self {
+            Self {
+                results: Arc::new(Mutex::new(results.into_iter().collect())),
+                sent_at: Arc::default(),
+            }
+        }
+    }
+
+    impl HttpClient for ScriptedHttpClient {
+        fn execute(&self, _request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, HttpError>> {
+            self.sent_at
+                .lock()
+                .unwrap()
+                .push(tokio::time::Instant::now());
+            let result = self
+                .results
+                .lock()
+                .unwrap()
+                .pop_front()
+                .unwrap_or(Ok(StatusCode::OK));
+
+            Box::pin(std::future::ready(
+                result
+                    .map(|status_code| response(status_code, None))
+                    .map_err(HttpError::new),
+            ))
+        }
+    }
+
+    pub(crate) fn request() -> HttpRequest {
+        HttpRequest {
+            method: Method::GET,
+            url: Url::parse("https://login.eveonline.com/oauth/jwks").unwrap(),
+            headers: HeaderMap::new(),
+            body: Vec::new(),
+        }
+    }
+
+    fn response(status_code: StatusCode, retry_after: Option<&str>) -> HttpResponse {
+        let mut headers = HeaderMap::new();
+        if let Some(retry_after) = retry_after {
+            headers.insert(RETRY_AFTER, retry_after.parse().unwrap());
+        }
+
+        HttpResponse {
+            status_code,
+            headers,
+            body: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn exponential_backoff_doubles_the_delay_up_to_the_max() {
+        let policy = ExponentialBackoff::new(5)
+            .initial_delay(Duration::from_millis(100))
+            .max_delay(Duration::from_millis(500));
+        let unavailable = response(StatusCode::SERVICE_UNAVAILABLE, None);
+
+        let delays: Vec<_> = (1..=6)
+            .map(|attempt| policy.retry_delay(Endpoint::Jwks, attempt, Ok(&unavailable)))
+            .collect();
+
+        assert_eq!(
+            delays,
+            [
+                Some(Duration::from_millis(100)),
+                Some(Duration::from_millis(200)),
+                Some(Duration::from_millis(400)),
+                Some(Duration::from_millis(500)),
+                Some(Duration::from_millis(500)),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn exponential_backoff_honors_retry_after_up_to_the_max() {
+        let policy = ExponentialBackoff::new(3).max_delay(Duration::from_secs(10));
+
+        let rate_limited = response(StatusCode::TOO_MANY_REQUESTS, Some(" 3 "));
+        assert_eq!(
+            policy.retry_delay(Endpoint::Token, 1, Ok(&rate_limited)),
+            Some(Duration::from_secs(3))
+        );
+
+        let rate_limited = response(StatusCode::TOO_MANY_REQUESTS, Some("120"));
+        assert_eq!(
+            policy.retry_delay(Endpoint::Metadata, 1, Ok(&rate_limited)),
+            Some(Duration::from_secs(10))
+        );
+
+        let rate_limited = response(
+            StatusCode::TOO_MANY_REQUESTS,
+            Some("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        assert_eq!(retry_after(&rate_limited), None);
+        assert_eq!(
+            policy.retry_delay(Endpoint::Metadata, 1, Ok(&rate_limited)),
+            Some(Duration::from_millis(500))
+        );
+    }
+
+    #[test]
+    fn token_requests_are_only_retried_if_sso_didnt_process_them() {
+        let policy = ExponentialBackoff::new(3);
+        let err = HttpError::new("connection reset");
+
+        for status_code in [
+            StatusCode::TOO_MANY_REQUESTS,
+            StatusCode::SERVICE_UNAVAILABLE,
+        ] {
+            assert!(policy
+                .retry_delay(Endpoint::Token, 1, Ok(&response(status_code, None)))
+                .is_some());
+        }
+        for status_code in [StatusCode::INTERNAL_SERVER_ERROR, StatusCode::BAD_GATEWAY] {
+            assert!(policy
+                .retry_delay(Endpoint::Token, 1, Ok(&response(status_code, None)))
+                .is_none());
+            assert!(policy
+                .retry_delay(Endpoint::Jwks, 1, Ok(&response(status_code, None)))
+                .is_some());
+        }
+        assert!(policy.retry_delay(Endpoint::Token, 1, Err(&err)).is_none());
+        assert!(policy.retry_delay(Endpoint::Jwks, 1, Err(&err)).is_some());
+
+        for status_code in [
+            StatusCode::OK,
+            StatusCode::BAD_REQUEST,
+            StatusCode::NOT_FOUND,
+        ] {
+            assert!(policy
+                .retry_delay(Endpoint::Jwks, 1, Ok(&response(status_code, None)))
+                .is_none());
+        }
+        assert!(NoRetry.retry_delay(Endpoint::Jwks, 1, Err(&err)).is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn client_retries_requests_after_the_backoff() {
+        let http_client = ScriptedHttpClient::new([
+            Err("connection refused"),
+            Ok(StatusCode::BAD_GATEWAY),
+            Ok(StatusCode::OK),
+        ]);
+        let client = EveSsoClient::builder("client_id")
+            .http_client(http_client.clone())
+            .retry_policy(ExponentialBackoff::new(5).initial_delay(Duration::from_secs(1)))
+            .build();
+        let start = tokio::time::Instant::now();
+
+        let response = client.execute(request(), Endpoint::Jwks).await.unwrap();
+
+        assert_eq!(response.status_code, StatusCode::OK);
+        let sent_after: Vec<_> = http_client
+            .sent_at
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|sent_at| *sent_at - start)
+            .collect();
+        assert_eq!(
+            sent_after,
+            [
+                Duration::ZERO,
+                Duration::from_secs(1),
+                Duration::from_secs(3)
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn client_returns_the_last_result_once_retries_are_exhausted() {
+        let http_client = ScriptedHttpClient::new([Ok(StatusCode::SERVICE_UNAVAILABLE); 3]);
+        let client = EveSsoClient::builder("client_id")
+            .http_client(http_client.clone())
+            .retry_policy(ExponentialBackoff::new(2))
+            .build();
+
+        let response = client.execute(request(), Endpoint::Token).await.unwrap();
+
+        assert_eq!(response.status_code, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(http_client.sent_at.lock().unwrap().len(), 3);
+    }
+}
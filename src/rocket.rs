@@ -0,0 +1,152 @@
+//! Integration with the Rocket web framework, enabled with the `rocket` feature
+//!
+//! Manage an [`EveSsoClient`] & take [`ValidatedEveToken`] as a parameter of handlers requiring an authenticated
+//! EVE character.
+//!
+//! ```no_run
+//! use eve_oauth2::rocket::ValidatedEveToken;
+//! use eve_oauth2::EveSsoClient;
+//!
+//! #[rocket::get("/character")]
+//! fn character(token: ValidatedEveToken) -> String {
+//!     token.claims.name
+//! }
+//!
+//! let client = EveSsoClient::new("client_id".to_string(), None);
+//! let rocket = rocket::build()
+//!     .manage(client)
+//!     .mount("/", rocket::routes![character]);
+//! ```
+
+use ::rocket::http::Status;
+use ::rocket::request::{FromRequest, Outcome, Request};
+
+use crate::error::{Error, ErrorClass};
+use crate::http;
+use crate::models::EveJwtClaims;
+use crate::secret::Redacted;
+use crate::EveSsoClient;
+
+/// Request guard validating the bearer token of the Authorization header with the managed [`EveSsoClient`]
+///
+/// Requests without a valid token fail with 401 Unauthorized, 503 Service Unavailable if EVE Online SSO couldn't be
+/// reached to retrieve the EVE JWT keys. Take `Result<ValidatedEveToken, EveAuthError>` to handle failures yourself.
+/// Its `Debug` output redacts the token.
+#[derive(Clone)]
+pub struct ValidatedEveToken {
+    /// The access token the claims were validated from
+    pub token: String,
+    pub claims: EveJwtClaims,
+}
+
+impl std::fmt::Debug for ValidatedEveToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ValidatedEveToken")
+            .field("token", &Redacted)
+            .field("claims", &self.claims)
+            .finish()
+    }
+}
+
+#[::rocket::async_trait]
+impl<'r> FromRequest<'r> for ValidatedEveToken {
+    type Error = EveAuthError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(client) = req.rocket().state::<EveSsoClient>() else {
+            return Outcome::Error((Status::InternalServerError, EveAuthError::MissingClient));
+        };
+
+        let Some(token) = req
+            .headers()
+            .get_one("Authorization")
+            .and_then(http::bearer_token)
+        else {
+            return Outcome::Error((Status::Unauthorized, EveAuthError::MissingToken));
+        };
+
+        match client.validate_token(token).await {
+            Ok(token_data) => Outcome::Success(ValidatedEveToken {
+                token: token.to_string(),
+                claims: token_data.claims,
+            }),
+            Err(err) => {
+                let status = match err.classify() {
+                    ErrorClass::Transient | ErrorClass::RateLimited => Status::ServiceUnavailable,
+                    _ => Status::Unauthorized,
+                };
+
+                Outcome::Error((status, EveAuthError::InvalidToken(err)))
+            }
+        }
+    }
+}
+
+/// Error of requests which couldn't be authenticated
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum EveAuthError {
+    /// The request has no bearer token in the Authorization header
+    MissingToken,
+    /// No [`EveSsoClient`] is managed by Rocket
+    MissingClient,
+    /// Validating the token failed
+    InvalidToken(Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use ::rocket::local::asynchronous::Client;
+    use ::rocket::{Build, Rocket};
+
+    use super::*;
+
+    #[::rocket::get("/character")]
+    fn character(token: ValidatedEveToken) -> String {
+        token.claims.name
+    }
+
+    async fn get(rocket: Rocket<Build>, authorization: Option<&str>) -> (Status, Option<String>) {
+        let client = Client::untracked(rocket.mount("/", ::rocket::routes![character]))
+            .await
+            .unwrap();
+        let mut request = client.get("/character");
+        if let Some(authorization) = authorization {
+            request.add_header(::rocket::http::Header::new(
+                "Authorization",
+                authorization.to_string(),
+            ));
+        }
+
+        let response = request.dispatch().await;
+        (response.status(), response.into_string().await)
+    }
+
+    #[::rocket::async_test]
+    async fn fails_without_a_managed_client_or_bearer_token() {
+        let (status, _) = get(::rocket::build(), Some("Bearer token")).await;
+        assert_eq!(status, Status::InternalServerError);
+
+        let rocket = ::rocket::build().manage(EveSsoClient::new("client_id", None));
+        assert_eq!(get(rocket, None).await.0, Status::Unauthorized);
+
+        let rocket = ::rocket::build().manage(EveSsoClient::new("client_id", None));
+        assert_eq!(get(rocket, Some("Basic abc")).await.0, Status::Unauthorized);
+    }
+
+    #[cfg(all(feature = "testing", feature = "reqwest"))]
+    #[::rocket::async_test]
+    async fn validates_bearer_tokens_with_the_managed_client() {
+        let sso = crate::testing::MockSso::start().unwrap();
+        let token = sso.signer().token().name("Test Character").sign();
+
+        let rocket = ::rocket::build().manage(sso.client("client_id"));
+        let (status, body) = get(rocket, Some(&format!("Bearer {}", token))).await;
+        assert_eq!(status, Status::Ok);
+        assert_eq!(body.as_deref(), Some("Test Character"));
+
+        let rocket = ::rocket::build().manage(sso.client("other_client"));
+        let (status, _) = get(rocket, Some(&format!("Bearer {}", token))).await;
+        assert_eq!(status, Status::Unauthorized);
+    }
+}
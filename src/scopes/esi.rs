@@ -0,0 +1,271 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+macro_rules! esi_scopes {
+    ($($variant:ident => $scope:literal,)*) => {
+        /// Scopes of the EVE Swagger Interface (ESI)
+        ///
+        /// Converts into the scope string wherever scopes are passed as strings, e.g.
+        /// `vec![EsiScope::WalletReadCharacterWallet.into()]`.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+        #[serde(try_from = "String", into = "String")]
+        #[non_exhaustive]
+        pub enum EsiScope {
+            $(
+                #[doc = concat!("`", $scope, "`")]
+                $variant,
+            )*
+        }
+
+        impl EsiScope {
+            /// All known ESI scopes
+            pub const ALL: &'static [EsiScope] = &[$(EsiScope::$variant,)*];
+
+            /// Returns the scope string, e.g. `esi-wallet.read_character_wallet.v1`
+            pub const fn as_str(self) -> &'static str {
+                match self {
+                    $(EsiScope::$variant => $scope,)*
+                }
+            }
+        }
+
+        impl FromStr for EsiScope {
+            type Err = UnknownScope;
+
+            fn from_str(scope: &str) -> Result<Self, Self::Err> {
+                match scope {
+                    $($scope => Ok(EsiScope::$variant),)*
+                    _ => Err(UnknownScope(scope.to_string())),
+                }
+            }
+        }
+    };
+}
+
+// Scopes listed in the ESI specification at https://esi.evetech.net/latest/swagger.json
+esi_scopes! {
+    PublicData => "publicData",
+    AlliancesReadContacts => "esi-alliances.read_contacts.v1",
+    AssetsReadAssets => "esi-assets.read_assets.v1",
+    AssetsReadCorporationAssets => "esi-assets.read_corporation_assets.v1",
+    BookmarksReadCharacterBookmarks => "esi-bookmarks.read_character_bookmarks.v1",
+    BookmarksReadCorporationBookmarks => "esi-bookmarks.read_corporation_bookmarks.v1",
+    CalendarReadCalendarEvents => "esi-calendar.read_calendar_events.v1",
+    CalendarRespondCalendarEvents => "esi-calendar.respond_calendar_events.v1",
+    CharactersReadAgentsResearch => "esi-characters.read_agents_research.v1",
+    CharactersReadBlueprints => "esi-characters.read_blueprints.v1",
+    CharactersReadContacts => "esi-characters.read_contacts.v1",
+    CharactersReadCorporationRoles => "esi-characters.read_corporation_roles.v1",
+    CharactersReadFatigue => "esi-characters.read_fatigue.v1",
+    CharactersReadFwStats => "esi-characters.read_fw_stats.v1",
+    CharactersReadLoyalty => "esi-characters.read_loyalty.v1",
+    CharactersReadMedals => "esi-characters.read_medals.v1",
+    CharactersReadNotifications => "esi-characters.read_notifications.v1",
+    CharactersReadOpportunities => "esi-characters.read_opportunities.v1",
+    CharactersReadStandings => "esi-characters.read_standings.v1",
+    CharactersReadTitles => "esi-characters.read_titles.v1",
+    CharactersWriteContacts => "esi-characters.write_contacts.v1",
+    CharacterstatsRead => "esi-characterstats.read.v1",
+    ClonesReadClones => "esi-clones.read_clones.v1",
+    ClonesReadImplants => "esi-clones.read_implants.v1",
+    ContractsReadCharacterContracts => "esi-contracts.read_character_contracts.v1",
+    ContractsReadCorporationContracts => "esi-contracts.read_corporation_contracts.v1",
+    CorporationsReadBlueprints => "esi-corporations.read_blueprints.v1",
+    CorporationsReadContacts => "esi-corporations.read_contacts.v1",
+    CorporationsReadContainerLogs => "esi-corporations.read_container_logs.v1",
+    CorporationsReadCorporationMembership => "esi-corporations.read_corporation_membership.v1",
+    CorporationsReadDivisions => "esi-corporations.read_divisions.v1",
+    CorporationsReadFacilities => "esi-corporations.read_facilities.v1",
+    CorporationsReadFwStats => "esi-corporations.read_fw_stats.v1",
+    CorporationsReadMedals => "esi-corporations.read_medals.v1",
+    CorporationsReadStandings => "esi-corporations.read_standings.v1",
+    CorporationsReadStarbases => "esi-corporations.read_starbases.v1",
+    CorporationsReadStructures => "esi-corporations.read_structures.v1",
+    CorporationsReadTitles => "esi-corporations.read_titles.v1",
+    CorporationsTrackMembers => "esi-corporations.track_members.v1",
+    FittingsReadFittings => "esi-fittings.read_fittings.v1",
+    FittingsWriteFittings => "esi-fittings.write_fittings.v1",
+    FleetsReadFleet => "esi-fleets.read_fleet.v1",
+    FleetsWriteFleet => "esi-fleets.write_fleet.v1",
+    IndustryReadCharacterJobs => "esi-industry.read_character_jobs.v1",
+    IndustryReadCharacterMining => "esi-industry.read_character_mining.v1",
+    IndustryReadCorporationJobs => "esi-industry.read_corporation_jobs.v1",
+    IndustryReadCorporationMining => "esi-industry.read_corporation_mining.v1",
+    KillmailsReadCorporationKillmails => "esi-killmails.read_corporation_killmails.v1",
+    KillmailsReadKillmails => "esi-killmails.read_killmails.v1",
+    LocationReadLocation => "esi-location.read_location.v1",
+    LocationReadOnline => "esi-location.read_online.v1",
+    LocationReadShipType => "esi-location.read_ship_type.v1",
+    MailOrganizeMail => "esi-mail.organize_mail.v1",
+    MailReadMail => "esi-mail.read_mail.v1",
+    MailSendMail => "esi-mail.send_mail.v1",
+    MarketsReadCharacterOrders => "esi-markets.read_character_orders.v1",
+    MarketsReadCorporationOrders => "esi-markets.read_corporation_orders.v1",
+    MarketsStructureMarkets => "esi-markets.structure_markets.v1",
+    PlanetsManagePlanets => "esi-planets.manage_planets.v1",
+    PlanetsReadCustomsOffices => "esi-planets.read_customs_offices.v1",
+    SearchSearchStructures => "esi-search.search_structures.v1",
+    SkillsReadSkillqueue => "esi-skills.read_skillqueue.v1",
+    SkillsReadSkills => "esi-skills.read_skills.v1",
+    UiOpenWindow => "esi-ui.open_window.v1",
+    UiWriteWaypoint => "esi-ui.write_waypoint.v1",
+    UniverseReadStructures => "esi-universe.read_structures.v1",
+    WalletReadCharacterWallet => "esi-wallet.read_character_wallet.v1",
+    WalletReadCorporationWallets => "esi-wallet.read_corporation_wallets.v1",
+}
+
+/// Whether the scope is a known ESI scope, used by [`crate::scopes!`] to validate scopes at compile time
+#[doc(hidden)]
+pub const fn is_esi_scope(scope: &str) -> bool {
+    let mut i = 0;
+
+    while i < EsiScope::ALL.len() {
+        if str_eq(EsiScope::ALL[i].as_str(), scope) {
+            return true;
+        }
+
+        i += 1;
+    }
+
+    false
+}
+
+const fn str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+
+    if a.len() != b.len() {
+        return false;
+    }
+
+    let mut i = 0;
+
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+
+        i += 1;
+    }
+
+    true
+}
+
+impl fmt::Display for EsiScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<EsiScope> for String {
+    fn from(scope: EsiScope) -> Self {
+        scope.as_str().to_string()
+    }
+}
+
+impl TryFrom<String> for EsiScope {
+    type Error = UnknownScope;
+
+    fn try_from(scope: String) -> Result<Self, Self::Error> {
+        scope.parse()
+    }
+}
+
+/// Error returned when parsing a scope which isn't a known ESI scope, displays the closest known scope if it's likely a
+/// typo
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownScope(pub String);
+
+/// Largest number of edits between an unknown scope & a known scope for the known scope to be suggested
+const MAX_SUGGESTION_DISTANCE: usize = 3;
+
+impl UnknownScope {
+    /// Returns the known ESI scope closest to the unknown scope if it's likely a typo of it
+    ///
+    /// ```
+    /// use eve_oauth2::scopes::{EsiScope, UnknownScope};
+    ///
+    /// let err = "esi-wallet.read_character_walet.v1".parse::<EsiScope>().unwrap_err();
+    /// assert_eq!(err.suggestion(), Some(EsiScope::WalletReadCharacterWallet));
+    /// ```
+    pub fn suggestion(&self) -> Option<EsiScope> {
+        EsiScope::ALL
+            .iter()
+            .map(|scope| (edit_distance(&self.0, scope.as_str()), *scope))
+            .filter(|(distance, _)| *distance <= MAX_SUGGESTION_DISTANCE)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, scope)| scope)
+    }
+}
+
+impl fmt::Display for UnknownScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.suggestion() {
+            Some(suggestion) => write!(
+                f,
+                "Unknown ESI scope {}, did you mean {}?",
+                self.0, suggestion
+            ),
+            None => write!(f, "Unknown ESI scope {}", self.0),
+        }
+    }
+}
+
+/// Levenshtein distance between two scopes
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.as_bytes();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a) in a.bytes().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+impl std::error::Error for UnknownScope {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_scope_round_trips_through_its_string() {
+        for scope in EsiScope::ALL {
+            assert_eq!(scope.as_str().parse::<EsiScope>(), Ok(*scope));
+            assert_eq!(String::from(*scope), scope.to_string());
+            assert!(is_esi_scope(scope.as_str()));
+        }
+    }
+
+    #[test]
+    fn unknown_scopes_fail_to_parse() {
+        assert_eq!(
+            "esi-wallet.read_character_wallet.v2".parse::<EsiScope>(),
+            Err(UnknownScope(
+                "esi-wallet.read_character_wallet.v2".to_string()
+            ))
+        );
+        assert!(!is_esi_scope("esi-wallet.read_character_wallet"));
+        assert!(!is_esi_scope(""));
+    }
+
+    #[test]
+    fn serializes_as_the_scope_string() {
+        let json = serde_json::to_string(&EsiScope::PublicData).unwrap();
+        assert_eq!(json, r#""publicData""#);
+        assert_eq!(
+            serde_json::from_str::<EsiScope>(r#""esi-assets.read_assets.v1""#).unwrap(),
+            EsiScope::AssetsReadAssets
+        );
+        assert!(serde_json::from_str::<EsiScope>(r#""esi-assets.read_asets.v1""#).is_err());
+    }
+}
@@ -0,0 +1,385 @@
+//! Helpers for working with the ESI scopes granted to a character
+
+mod esi;
+mod presets;
+
+#[doc(hidden)]
+pub use esi::is_esi_scope;
+pub use esi::{EsiScope, UnknownScope};
+pub use presets::ScopePreset;
+
+use std::collections::BTreeSet;
+use std::convert::Infallible;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::models::{EveJwtClaims, EveJwtScopes, EveTokens};
+
+/// Creates a [`ScopeSet`] from scope literals, checking at compile time that every scope is a known ESI scope
+///
+/// ```
+/// let scopes = eve_oauth2::scopes!["publicData", "esi-wallet.read_character_wallet.v1"];
+///
+/// assert!(scopes.contains("publicData"));
+/// ```
+///
+/// Typos fail to compile:
+///
+/// ```compile_fail
+/// let scopes = eve_oauth2::scopes!["esi-wallet.read_character_walet.v1"];
+/// ```
+#[macro_export]
+macro_rules! scopes {
+    ($($scope:literal),* $(,)?) => {{
+        $(
+            const _: () = ::core::assert!(
+                $crate::scopes::is_esi_scope($scope),
+                ::core::concat!("Unknown ESI scope ", $scope)
+            );
+        )*
+
+        <$crate::scopes::ScopeSet as ::core::iter::FromIterator<&str>>::from_iter([$($scope),*])
+    }};
+}
+
+/// How the scopes of login URLs are checked against the known ESI scopes, see [`crate::EveSsoClient::scope_check`]
+///
+/// A misspelled scope otherwise only fails on EVE Online SSO's consent page with a vague error.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ScopeCheck {
+    /// Scopes aren't checked
+    #[default]
+    Off,
+    /// Unknown scopes are logged as a warning with the `tracing` feature, without it this is the same as `Off`
+    Warn,
+    /// Creating a login URL with unknown scopes fails with [`Error::Config`], the infallible login URL functions panic
+    Deny,
+}
+
+/// Checks that all scopes are known ESI scopes, e.g. when loading the scopes of your application on startup
+///
+/// Fails with [`Error::Config`] naming the unknown scopes & the known scopes they're likely typos of.
+///
+/// ```
+/// use eve_oauth2::scopes::validate_scopes;
+///
+/// assert!(validate_scopes(["publicData", "esi-wallet.read_character_wallet.v1"]).is_ok());
+/// assert!(validate_scopes(["esi-wallet.read_character_walet.v1"]).is_err());
+/// ```
+pub fn validate_scopes(scopes: impl IntoIterator<Item = impl AsRef<str>>) -> Result<(), Error> {
+    let unknown: Vec<String> = scopes
+        .into_iter()
+        .filter_map(|scope| scope.as_ref().parse::<EsiScope>().err())
+        .map(|err| err.to_string())
+        .collect();
+
+    if unknown.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Config(unknown.join("; ")))
+    }
+}
+
+/// Applies the scope check to the scopes of a login URL, failing only with [`ScopeCheck::Deny`]
+pub(crate) fn check_scopes(check: ScopeCheck, scopes: &ScopeSet) -> Result<(), Error> {
+    if check == ScopeCheck::Off {
+        return Ok(());
+    }
+
+    match validate_scopes(scopes) {
+        Err(err) if check == ScopeCheck::Deny => Err(err),
+        #[cfg(feature = "tracing")]
+        Err(err) => {
+            tracing::warn!(error = %err, "Login URL requests unknown scopes");
+
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Returns the required scopes which aren't among the granted scopes, in the order they are required
+///
+/// The granted scopes can be taken from the stored [`crate::models::EveTokens`] or the `scp` claim of a validated token.
+pub fn missing_scopes(granted: &[String], required: &[String]) -> Vec<String> {
+    let mut missing: Vec<String> = Vec::new();
+
+    for scope in required {
+        if !granted.contains(scope) && !missing.contains(scope) {
+            missing.push(scope.clone());
+        }
+    }
+
+    missing
+}
+
+/// Set of ESI scopes, kept in sorted order without duplicates
+///
+/// Displays as the space separated list used in the `scope` parameter of the authorize URL.
+///
+/// ```
+/// use eve_oauth2::scopes::ScopeSet;
+///
+/// let granted: ScopeSet = ["publicData", "esi-wallet.read_character_wallet.v1"].into_iter().collect();
+/// let required: ScopeSet = ["esi-assets.read_assets.v1"].into_iter().collect();
+///
+/// let missing = required.difference(&granted);
+/// assert_eq!(missing.to_string(), "esi-assets.read_assets.v1");
+///
+/// let login_scopes: Vec<String> = granted.union(&required).into();
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ScopeSet(BTreeSet<String>);
+
+impl ScopeSet {
+    /// Creates an empty scope set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a scope, returns whether it wasn't already in the set
+    pub fn insert(&mut self, scope: impl Into<String>) -> bool {
+        self.0.insert(scope.into())
+    }
+
+    /// Whether the set contains the scope
+    pub fn contains(&self, scope: &str) -> bool {
+        self.0.contains(scope)
+    }
+
+    /// Whether all scopes of this set are in the other set
+    pub fn is_subset(&self, other: &ScopeSet) -> bool {
+        self.0.is_subset(&other.0)
+    }
+
+    /// Returns the scopes in either set
+    pub fn union(&self, other: &ScopeSet) -> ScopeSet {
+        ScopeSet(self.0.union(&other.0).cloned().collect())
+    }
+
+    /// Returns the scopes in this set which aren't in the other set
+    pub fn difference(&self, other: &ScopeSet) -> ScopeSet {
+        ScopeSet(self.0.difference(&other.0).cloned().collect())
+    }
+
+    /// Returns the scopes in both sets
+    pub fn intersection(&self, other: &ScopeSet) -> ScopeSet {
+        ScopeSet(self.0.intersection(&other.0).cloned().collect())
+    }
+
+    /// Number of scopes in the set
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether the set contains no scopes
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Iterates over the scopes in sorted order
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(String::as_str)
+    }
+
+    /// Returns the scopes in sorted order
+    pub fn to_vec(&self) -> Vec<String> {
+        self.0.iter().cloned().collect()
+    }
+}
+
+impl fmt::Display for ScopeSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, scope) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+
+            f.write_str(scope)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for ScopeSet {
+    type Err = Infallible;
+
+    /// Parses a space separated list of scopes such as the `scope` parameter of the authorize URL
+    fn from_str(scopes: &str) -> Result<Self, Self::Err> {
+        Ok(scopes.split_whitespace().collect())
+    }
+}
+
+impl<T: Into<String>> FromIterator<T> for ScopeSet {
+    fn from_iter<I: IntoIterator<Item = T>>(scopes: I) -> Self {
+        ScopeSet(scopes.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: Into<String>> Extend<T> for ScopeSet {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, scopes: I) {
+        self.0.extend(scopes.into_iter().map(Into::into));
+    }
+}
+
+impl IntoIterator for ScopeSet {
+    type Item = String;
+    type IntoIter = std::collections::btree_set::IntoIter<String>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a ScopeSet {
+    type Item = &'a String;
+    type IntoIter = std::collections::btree_set::Iter<'a, String>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl From<&EveJwtClaims> for ScopeSet {
+    /// Scopes granted to a validated token
+    fn from(claims: &EveJwtClaims) -> Self {
+        claims
+            .scp
+            .as_ref()
+            .map(EveJwtScopes::to_vec)
+            .unwrap_or_default()
+            .into_iter()
+            .collect()
+    }
+}
+
+impl From<&EveTokens> for ScopeSet {
+    fn from(tokens: &EveTokens) -> Self {
+        tokens.scopes.iter().cloned().collect()
+    }
+}
+
+impl From<Vec<String>> for ScopeSet {
+    fn from(scopes: Vec<String>) -> Self {
+        scopes.into_iter().collect()
+    }
+}
+
+impl From<ScopeSet> for Vec<String> {
+    fn from(scopes: ScopeSet) -> Self {
+        scopes.0.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scope_sets_are_sorted_without_duplicates() {
+        let mut scopes: ScopeSet = ["publicData", "esi-wallet.read_character_wallet.v1"]
+            .into_iter()
+            .collect();
+
+        assert!(!scopes.insert("publicData"));
+        assert!(scopes.insert("esi-assets.read_assets.v1"));
+
+        assert_eq!(scopes.len(), 3);
+        assert_eq!(
+            scopes.to_string(),
+            "esi-assets.read_assets.v1 esi-wallet.read_character_wallet.v1 publicData"
+        );
+        assert_eq!(
+            "publicData  esi-assets.read_assets.v1 publicData"
+                .parse::<ScopeSet>()
+                .unwrap()
+                .to_vec(),
+            ["esi-assets.read_assets.v1", "publicData"]
+        );
+        assert!("".parse::<ScopeSet>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn set_operations() {
+        let granted: ScopeSet = ["publicData", "esi-wallet.read_character_wallet.v1"]
+            .into_iter()
+            .collect();
+        let required: ScopeSet = ["publicData", "esi-assets.read_assets.v1"]
+            .into_iter()
+            .collect();
+
+        assert_eq!(
+            required.difference(&granted).to_vec(),
+            ["esi-assets.read_assets.v1"]
+        );
+        assert_eq!(required.intersection(&granted).to_vec(), ["publicData"]);
+        assert_eq!(granted.union(&required).len(), 3);
+        assert!(!required.is_subset(&granted));
+        assert!(required.is_subset(&granted.union(&required)));
+        assert!(ScopeSet::new().is_subset(&granted));
+    }
+
+    #[test]
+    fn converts_from_tokens_claims_and_vecs() {
+        let tokens = crate::store::tests::tokens("refresh_token");
+        let scopes = ScopeSet::from(&tokens);
+        assert_eq!(scopes.to_vec(), {
+            let mut expected = tokens.scopes.clone();
+            expected.sort();
+            expected
+        });
+
+        let scopes = ScopeSet::from(vec!["publicData".to_string(), "publicData".to_string()]);
+        assert_eq!(Vec::<String>::from(scopes), ["publicData"]);
+
+        let serialized = serde_json::to_string(&ScopeSet::from_iter(["b", "a"])).unwrap();
+        assert_eq!(serialized, r#"["a","b"]"#);
+        assert_eq!(
+            serde_json::from_str::<ScopeSet>(r#"["b","a","b"]"#)
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn scopes_macro_builds_a_scope_set() {
+        let scopes = crate::scopes![
+            "publicData",
+            "esi-wallet.read_character_wallet.v1",
+            "publicData",
+        ];
+
+        assert_eq!(
+            scopes.to_vec(),
+            ["esi-wallet.read_character_wallet.v1", "publicData"]
+        );
+        assert!(crate::scopes![].is_empty());
+    }
+
+    #[test]
+    fn missing_scopes_keep_the_required_order() {
+        let granted = vec!["publicData".to_string()];
+        let required = vec![
+            "esi-wallet.read_character_wallet.v1".to_string(),
+            "publicData".to_string(),
+            "esi-assets.read_assets.v1".to_string(),
+            "esi-wallet.read_character_wallet.v1".to_string(),
+        ];
+
+        assert_eq!(
+            missing_scopes(&granted, &required),
+            [
+                "esi-wallet.read_character_wallet.v1",
+                "esi-assets.read_assets.v1"
+            ]
+        );
+        assert!(missing_scopes(&required, &granted).is_empty());
+    }
+}
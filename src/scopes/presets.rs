@@ -0,0 +1,149 @@
+use crate::scopes::{EsiScope, ScopeSet};
+
+/// Curated sets of scopes for common kinds of EVE tools
+///
+/// ```
+/// use eve_oauth2::scopes::{ScopePreset, ScopeSet};
+///
+/// let scopes = ScopePreset::WalletAndAssets
+///     .scope_set()
+///     .union(&ScopePreset::Industry.scope_set());
+///
+/// let login_scopes: Vec<String> = scopes.into();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ScopePreset {
+    /// Wallet, assets, market orders & contracts of a character
+    WalletAndAssets,
+    /// Industry jobs, mining ledger, blueprints, planetary interaction & skills of a character
+    Industry,
+    /// Fleet management along with the location, ship & online status of a character
+    Fleet,
+    /// Reading, sending & organizing mail
+    Mail,
+    /// Skills, skill queue, clones, implants & character statistics
+    CharacterSheet,
+    /// Membership, roles, wallets, assets & structures of a corporation, requires the character to have the corporation roles
+    CorporationAudit,
+}
+
+impl ScopePreset {
+    /// Returns the scopes of the preset
+    pub const fn scopes(self) -> &'static [EsiScope] {
+        match self {
+            ScopePreset::WalletAndAssets => &[
+                EsiScope::WalletReadCharacterWallet,
+                EsiScope::AssetsReadAssets,
+                EsiScope::MarketsReadCharacterOrders,
+                EsiScope::ContractsReadCharacterContracts,
+            ],
+            ScopePreset::Industry => &[
+                EsiScope::IndustryReadCharacterJobs,
+                EsiScope::IndustryReadCharacterMining,
+                EsiScope::CharactersReadBlueprints,
+                EsiScope::PlanetsManagePlanets,
+                EsiScope::SkillsReadSkills,
+                EsiScope::AssetsReadAssets,
+            ],
+            ScopePreset::Fleet => &[
+                EsiScope::FleetsReadFleet,
+                EsiScope::FleetsWriteFleet,
+                EsiScope::LocationReadLocation,
+                EsiScope::LocationReadShipType,
+                EsiScope::LocationReadOnline,
+            ],
+            ScopePreset::Mail => &[
+                EsiScope::MailReadMail,
+                EsiScope::MailSendMail,
+                EsiScope::MailOrganizeMail,
+            ],
+            ScopePreset::CharacterSheet => &[
+                EsiScope::SkillsReadSkills,
+                EsiScope::SkillsReadSkillqueue,
+                EsiScope::ClonesReadClones,
+                EsiScope::ClonesReadImplants,
+                EsiScope::CharacterstatsRead,
+            ],
+            ScopePreset::CorporationAudit => &[
+                EsiScope::CorporationsReadCorporationMembership,
+                EsiScope::CorporationsTrackMembers,
+                EsiScope::CharactersReadCorporationRoles,
+                EsiScope::WalletReadCorporationWallets,
+                EsiScope::AssetsReadCorporationAssets,
+                EsiScope::CorporationsReadStructures,
+            ],
+        }
+    }
+
+    /// Returns the scopes of the preset as a [`ScopeSet`]
+    pub fn scope_set(self) -> ScopeSet {
+        self.scopes().iter().copied().collect()
+    }
+}
+
+impl From<ScopePreset> for ScopeSet {
+    fn from(preset: ScopePreset) -> Self {
+        preset.scope_set()
+    }
+}
+
+impl From<ScopePreset> for Vec<String> {
+    fn from(preset: ScopePreset) -> Self {
+        preset.scope_set().into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRESETS: [ScopePreset; 6] = [
+        ScopePreset::WalletAndAssets,
+        ScopePreset::Industry,
+        ScopePreset::Fleet,
+        ScopePreset::Mail,
+        ScopePreset::CharacterSheet,
+        ScopePreset::CorporationAudit,
+    ];
+
+    #[test]
+    fn presets_contain_known_scopes_without_duplicates() {
+        for preset in PRESETS {
+            let scopes = preset.scope_set();
+
+            assert!(!scopes.is_empty());
+            assert_eq!(scopes.len(), preset.scopes().len(), "{preset:?}");
+            assert!(crate::scopes::validate_scopes(&scopes).is_ok());
+        }
+    }
+
+    #[test]
+    fn presets_convert_into_scope_strings() {
+        let scopes: Vec<String> = ScopePreset::Mail.into();
+
+        assert_eq!(
+            scopes,
+            [
+                "esi-mail.organize_mail.v1",
+                "esi-mail.read_mail.v1",
+                "esi-mail.send_mail.v1"
+            ]
+        );
+        assert_eq!(
+            ScopeSet::from(ScopePreset::Mail),
+            ScopePreset::Mail.scope_set()
+        );
+    }
+
+    #[test]
+    fn presets_combine_into_one_scope_set() {
+        let scopes = ScopePreset::WalletAndAssets
+            .scope_set()
+            .union(&ScopePreset::Industry.scope_set());
+
+        // Both presets include the assets scope
+        assert_eq!(scopes.len(), 9);
+        assert!(scopes.contains("esi-assets.read_assets.v1"));
+    }
+}
@@ -0,0 +1,127 @@
+use std::fmt;
+
+use serde::{Deserialize, Deserializer, Serializer};
+
+/// Printed in place of secrets & tokens by the `Debug` implementations of the crate's types
+pub(crate) const REDACTED: &str = "***redacted***";
+
+/// Debug placeholder of a redacted value, wrap optional values with [`redact`] to keep whether they're set
+pub(crate) struct Redacted;
+
+impl fmt::Debug for Redacted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+/// Redacts an optional value, `None` stays visible
+pub(crate) fn redact<T>(value: &Option<T>) -> Option<Redacted> {
+    value.as_ref().map(|_| Redacted)
+}
+
+/// Secret such as the client secret of your EVE developer application
+///
+/// The secret is only exposed where it's sent to EVE Online SSO. Its `Debug` output is redacted & it implements
+/// neither `Display` nor `Serialize`, so it can't end up in logs by accident.
+///
+/// ```
+/// use eve_oauth2::SecretString;
+///
+/// let secret = SecretString::from("client_secret");
+/// assert_eq!(format!("{:?}", secret), "***redacted***");
+/// assert_eq!(secret.expose_secret(), "client_secret");
+/// ```
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct SecretString(WipedString);
+
+impl SecretString {
+    pub fn new(secret: impl Into<String>) -> Self {
+        Self(WipedString(secret.into()))
+    }
+
+    /// Returns the secret, only call this where the secret is needed
+    pub fn expose_secret(&self) -> &str {
+        &self.0 .0
+    }
+
+    /// Returns the secret, consuming it, only call this where the secret is needed
+    pub fn into_secret(mut self) -> String {
+        std::mem::take(&mut self.0 .0)
+    }
+}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl zeroize::ZeroizeOnDrop for SecretString {}
+
+/// String wiped from memory when dropped, enabled with the `zeroize` feature
+///
+/// Only this private type implements `Drop`, so the public types holding secrets can be destructured & have their
+/// fields moved out of regardless of the feature.
+#[derive(Clone, Default, PartialEq, Eq)]
+struct WipedString(String);
+
+#[cfg(feature = "zeroize")]
+impl Drop for WipedString {
+    fn drop(&mut self) {
+        zeroize::Zeroize::zeroize(&mut self.0);
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(secret: String) -> Self {
+        Self::new(secret)
+    }
+}
+
+impl From<&str> for SecretString {
+    fn from(secret: &str) -> Self {
+        Self::new(secret)
+    }
+}
+
+impl<'de> Deserialize<'de> for SecretString {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Self::new)
+    }
+}
+
+/// Serializes the exposed secret, for fields of types which are stored rather than logged, e.g. [`crate::models::EveTokens`]
+pub(crate) fn serialize_exposed<S: Serializer>(
+    secret: &SecretString,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(secret.expose_secret())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debug_never_shows_the_secret() {
+        let secret = SecretString::from("client_secret".to_string());
+
+        assert_eq!(format!("{:?}", secret), REDACTED);
+        assert_eq!(format!("{:?}", Some(&secret)), "Some(***redacted***)");
+        assert_eq!(
+            format!("{:?}", redact(&Some("code"))),
+            "Some(***redacted***)"
+        );
+        assert_eq!(format!("{:?}", redact::<&str>(&None)), "None");
+    }
+
+    #[test]
+    fn exposes_the_secret_where_its_needed() {
+        let secret: SecretString = serde_json::from_str(r#""client_secret""#).unwrap();
+
+        assert_eq!(secret, SecretString::new("client_secret"));
+        assert_eq!(secret.expose_secret(), "client_secret");
+        assert_eq!(secret.into_secret(), "client_secret");
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use web_time::Instant;
+
+use crate::error::Error;
+use crate::http::BoxFuture;
+use crate::state::{PendingLogin, StateStore};
+
+/// Thread-safe in-memory [`StateStore`], only usable when the callback is handled by the same instance
+///
+/// Expired states are removed whenever a state is stored. The store holds at most
+/// [`MemoryStateStore::DEFAULT_MAX_STATES`] states by default so unauthenticated logins can't exhaust memory, storing
+/// more fails with [`Error::StateStore`] until states expire or are taken.
+#[derive(Debug)]
+pub struct MemoryStateStore {
+    states: Mutex<HashMap<String, (Instant, PendingLogin)>>,
+    max_states: usize,
+}
+
+impl Default for MemoryStateStore {
+    fn default() -> Self {
+        Self {
+            states: Mutex::default(),
+            max_states: Self::DEFAULT_MAX_STATES,
+        }
+    }
+}
+
+impl MemoryStateStore {
+    /// Default maximum number of pending logins held at once
+    pub const DEFAULT_MAX_STATES: usize = 10_000;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum number of pending logins held at once
+    pub fn max_states(mut self, max_states: usize) -> Self {
+        self.max_states = max_states;
+        self
+    }
+}
+
+impl StateStore for MemoryStateStore {
+    fn put(
+        &self,
+        state: String,
+        login: PendingLogin,
+        ttl: Duration,
+    ) -> BoxFuture<'_, Result<(), Error>> {
+        let now = Instant::now();
+        let mut states = self.states.lock().unwrap();
+
+        states.retain(|_, (expires_at, _)| *expires_at > now);
+
+        let result = if states.len() < self.max_states || states.contains_key(&state) {
+            states.insert(state, (now + ttl, login));
+
+            Ok(())
+        } else {
+            Err(Error::StateStore(
+                "Too many pending logins, try again later".into(),
+            ))
+        };
+
+        Box::pin(async { result })
+    }
+
+    fn take(&self, state: String) -> BoxFuture<'_, Result<Option<PendingLogin>, Error>> {
+        let login = self
+            .states
+            .lock()
+            .unwrap()
+            .remove(&state)
+            .filter(|(expires_at, _)| *expires_at > Instant::now())
+            .map(|(_, login)| login);
+
+        Box::pin(async move { Ok(login) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn states_can_be_taken_once() {
+        let store = MemoryStateStore::new();
+        let login = PendingLogin::new(Some("code_verifier".to_string()));
+
+        store
+            .put("state".to_string(), login.clone(), Duration::from_secs(600))
+            .await
+            .unwrap();
+
+        assert_eq!(store.take("state".to_string()).await.unwrap(), Some(login));
+        assert_eq!(store.take("state".to_string()).await.unwrap(), None);
+        assert_eq!(store.take("unknown".to_string()).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn expired_states_cant_be_taken() {
+        let store = MemoryStateStore::new().max_states(1);
+
+        store
+            .put(
+                "expired".to_string(),
+                PendingLogin::default(),
+                Duration::ZERO,
+            )
+            .await
+            .unwrap();
+        assert_eq!(store.take("expired".to_string()).await.unwrap(), None);
+
+        // Expired states don't count towards the maximum
+        store
+            .put(
+                "expired".to_string(),
+                PendingLogin::default(),
+                Duration::ZERO,
+            )
+            .await
+            .unwrap();
+        store
+            .put(
+                "state".to_string(),
+                PendingLogin::default(),
+                Duration::from_secs(600),
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejects_states_beyond_max_states() {
+        let store = MemoryStateStore::new().max_states(1);
+        let ttl = Duration::from_secs(600);
+
+        store
+            .put("first".to_string(), PendingLogin::default(), ttl)
+            .await
+            .unwrap();
+        let result = store
+            .put("second".to_string(), PendingLogin::default(), ttl)
+            .await;
+        assert!(matches!(result, Err(Error::StateStore(_))));
+
+        store.take("first".to_string()).await.unwrap().unwrap();
+        store
+            .put("second".to_string(), PendingLogin::default(), ttl)
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,135 @@
+//! CSRF states of the login flow
+//!
+//! Store states in a [`StateStore`] which expires them & lets each be used once, using
+//! [`crate::EveSsoClient::create_login_url_with_store`] & [`crate::EveSsoClient::verify_callback`].
+//!
+//! Applications which can't keep server-side state, e.g. serverless handlers, can sign the state with a key
+//! instead of storing it. Set a [`StateSigner`] on the [`crate::EveSsoClient`] to use signed states for its login URLs
+//! & verify the state of the callback with [`crate::EveSsoClient::verify_signed_state`].
+
+mod memory;
+#[cfg(feature = "redis")]
+mod redis;
+mod signer;
+
+#[cfg(feature = "redis")]
+pub use self::redis::RedisStateStore;
+pub use memory::MemoryStateStore;
+pub use signer::StateSigner;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::http::BoxFuture;
+use crate::secret::redact;
+
+/// Default time a state is valid, the user has to complete the login within this time
+pub(crate) const DEFAULT_STATE_TTL: Duration = Duration::from_secs(600);
+
+/// Compares the state stored when creating the login URL with the state of the callback in constant time
+///
+/// Returns `false` if the expected state is empty, e.g. because the session holding it has expired.
+/// ```
+/// use eve_oauth2::verify_state;
+///
+/// assert!(verify_state("abc", "abc"));
+/// assert!(!verify_state("abc", "abd"));
+/// assert!(!verify_state("", ""));
+/// ```
+pub fn verify_state(expected: &str, received: &str) -> bool {
+    let (expected, received) = (expected.as_bytes(), received.as_bytes());
+
+    if expected.is_empty() || expected.len() != received.len() {
+        return false;
+    }
+
+    expected
+        .iter()
+        .zip(received)
+        .fold(0, |diff, (a, b)| diff | (a ^ b))
+        == 0
+}
+
+/// Data of a login which was started but not completed yet, stored under its state, its `Debug` output redacts the code
+/// verifier
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct PendingLogin {
+    /// PKCE code verifier of the login, `None` for logins using the client secret
+    pub code_verifier: Option<String>,
+}
+
+impl std::fmt::Debug for PendingLogin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PendingLogin")
+            .field("code_verifier", &redact(&self.code_verifier))
+            .finish()
+    }
+}
+
+impl PendingLogin {
+    pub fn new(code_verifier: Option<String>) -> Self {
+        Self { code_verifier }
+    }
+}
+
+/// Asynchronous storage of the [`PendingLogin`]s of states, each state expires & can only be taken once
+///
+/// Wrap errors of your storage backend in [`Error::StateStore`].
+pub trait StateStore: Send + Sync {
+    /// Stores the pending login under the state, it expires after the ttl
+    fn put(
+        &self,
+        state: String,
+        login: PendingLogin,
+        ttl: Duration,
+    ) -> BoxFuture<'_, Result<(), Error>>;
+
+    /// Removes & returns the pending login of the state, `None` if the state is unknown, expired or was already taken
+    fn take(&self, state: String) -> BoxFuture<'_, Result<Option<PendingLogin>, Error>>;
+}
+
+impl<S: StateStore + ?Sized> StateStore for Arc<S> {
+    fn put(
+        &self,
+        state: String,
+        login: PendingLogin,
+        ttl: Duration,
+    ) -> BoxFuture<'_, Result<(), Error>> {
+        (**self).put(state, login, ttl)
+    }
+
+    fn take(&self, state: String) -> BoxFuture<'_, Result<Option<PendingLogin>, Error>> {
+        (**self).take(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_state_requires_equal_non_empty_states() {
+        assert!(verify_state("state", "state"));
+        assert!(!verify_state("state", "statf"));
+        assert!(!verify_state("state", "stat"));
+        assert!(!verify_state("state", "state "));
+        assert!(!verify_state("state", ""));
+        assert!(!verify_state("", ""));
+    }
+
+    #[test]
+    fn debug_redacts_the_code_verifier() {
+        assert_eq!(
+            format!("{:?}", PendingLogin::new(Some("code_verifier".to_string()))),
+            "PendingLogin { code_verifier: Some(***redacted***) }"
+        );
+        assert_eq!(
+            format!("{:?}", PendingLogin::new(None)),
+            "PendingLogin { code_verifier: None }"
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use std::time::Duration;
+
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+
+use crate::error::Error;
+use crate::http::BoxFuture;
+use crate::state::{PendingLogin, StateStore};
+
+/// [`StateStore`] storing pending logins as JSON in Redis, enabled with the `redis` feature
+///
+/// States are stored under `{key_prefix}{state}` with the default prefix `eve_oauth2:states:`, so the callback can be
+/// handled by any instance of your application. Redis expires the states & `GETDEL` takes them atomically, which
+/// requires Redis 6.2 or later.
+#[derive(Clone)]
+pub struct RedisStateStore {
+    connection: ConnectionManager,
+    key_prefix: String,
+}
+
+impl RedisStateStore {
+    /// Connects to Redis using the provided client
+    pub async fn new(client: redis::Client) -> Result<Self, Error> {
+        let connection = ConnectionManager::new(client).await.map_err(store_error)?;
+
+        Ok(Self::with_connection_manager(connection))
+    }
+
+    /// Uses an existing connection manager, e.g. one shared with the rest of your application
+    pub fn with_connection_manager(connection: ConnectionManager) -> Self {
+        Self {
+            connection,
+            key_prefix: "eve_oauth2:states:".to_string(),
+        }
+    }
+
+    /// Sets the prefix of the keys the states are stored under
+    pub fn key_prefix(mut self, key_prefix: impl Into<String>) -> Self {
+        self.key_prefix = key_prefix.into();
+        self
+    }
+
+    fn key(&self, state: &str) -> String {
+        format!("{}{}", self.key_prefix, state)
+    }
+}
+
+impl StateStore for RedisStateStore {
+    fn put(
+        &self,
+        state: String,
+        login: PendingLogin,
+        ttl: Duration,
+    ) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async move {
+            let mut connection = self.connection.clone();
+            let value = serde_json::to_string(&login).map_err(store_error)?;
+            let ttl = (ttl.as_millis() as u64).max(1);
+
+            connection
+                .pset_ex::<_, _, ()>(self.key(&state), value, ttl)
+                .await
+                .map_err(store_error)
+        })
+    }
+
+    fn take(&self, state: String) -> BoxFuture<'_, Result<Option<PendingLogin>, Error>> {
+        Box::pin(async move {
+            let mut connection = self.connection.clone();
+
+            let login: Option<String> = connection
+                .get_del(self.key(&state))
+                .await
+                .map_err(store_error)?;
+
+            login
+                .map(|login| serde_json::from_str(&login).map_err(store_error))
+                .transpose()
+        })
+    }
+}
+
+fn store_error(err: impl std::error::Error + Send + Sync + 'static) -> Error {
+    Error::StateStore(Box::new(err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Store with a unique key prefix in the Redis server at `REDIS_URL`, `redis://127.0.0.1/` by default
+    async fn store(name: &str) -> RedisStateStore {
+        let url = std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1/".to_string());
+        let client = redis::Client::open(url).unwrap();
+        let prefix = format!("eve_oauth2:tests:{}:{}:", name, std::process::id());
+
+        RedisStateStore::new(client)
+            .await
+            .unwrap()
+            .key_prefix(prefix)
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a Redis server at REDIS_URL"]
+    async fn states_can_be_taken_once() {
+        let store = store("states").await;
+        let login = PendingLogin::new(Some("code_verifier".to_string()));
+
+        store
+            .put("state".to_string(), login.clone(), Duration::from_secs(60))
+            .await
+            .unwrap();
+
+        assert_eq!(store.take("state".to_string()).await.unwrap(), Some(login));
+        assert_eq!(store.take("state".to_string()).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a Redis server at REDIS_URL"]
+    async fn states_expire_after_the_ttl() {
+        let store = store("expiry").await;
+
+        store
+            .put(
+                "state".to_string(),
+                PendingLogin::default(),
+                Duration::from_millis(10),
+            )
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        assert_eq!(store.take("state".to_string()).await.unwrap(), None);
+    }
+}
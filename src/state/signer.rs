@@ -0,0 +1,235 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use oauth2::CsrfToken;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use web_time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::Error;
+use crate::state::DEFAULT_STATE_TTL;
+
+/// Signs & verifies expiring state tokens with HMAC-SHA256 using a key supplied by your application
+///
+/// The state is a compact JWT containing a random nonce & its expiry, so the callback can be verified without
+/// storing anything. Use a random key of at least 32 bytes & keep it secret, anyone with the key can forge states.
+///
+/// A valid signature only proves the state was issued by your application, not to the browser completing the login.
+/// An attacker can start a login, stop at the callback & send the callback URL to a victim, whose browser then
+/// completes the login with the attacker's character (login CSRF). Bind the state to the browser, e.g. by setting it
+/// in an HttpOnly cookie when redirecting to the login URL & comparing the cookie with the state in the callback using
+/// [`crate::verify_state`], as the `axum` feature's router does.
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use eve_oauth2::state::StateSigner;
+/// use eve_oauth2::EveSsoClient;
+///
+/// let client = EveSsoClient::builder("client_id")
+///     .client_secret("client_secret")
+///     .state_signer(StateSigner::new(b"your 32 byte or longer secret key").ttl(Duration::from_secs(300)))
+///     .build();
+/// ```
+#[derive(Clone)]
+pub struct StateSigner {
+    key: Vec<u8>,
+    ttl: Duration,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StateClaims {
+    nonce: String,
+    exp: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    data: Option<serde_json::Value>,
+}
+
+impl StateSigner {
+    /// Creates a signer using the key, states expire after 10 minutes
+    pub fn new(key: impl Into<Vec<u8>>) -> Self {
+        Self {
+            key: key.into(),
+            ttl: DEFAULT_STATE_TTL,
+        }
+    }
+
+    /// Sets how long states are valid, the user has to complete the login within this time
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Creates a new signed state token
+    pub fn sign(&self) -> String {
+        self.sign_claims(None).expect("Failed to sign state")
+    }
+
+    /// Creates a new signed state token carrying the payload, e.g. where to send the user after the login
+    ///
+    /// The payload is signed but not encrypted, the user can read it so don't include secrets. Keep it small as the
+    /// state is part of the login URL.
+    pub fn sign_with_payload<T: Serialize>(&self, payload: &T) -> Result<String, Error> {
+        let data = serde_json::to_value(payload).map_err(|err| {
+            Error::InvalidState(format!("Failed to serialize state payload: {}", err))
+        })?;
+
+        self.sign_claims(Some(data))
+    }
+
+    /// Verifies the signature & expiry of a state token, fails with [`Error::InvalidState`]
+    pub fn verify(&self, state: &str) -> Result<(), Error> {
+        self.verify_claims(state).map(|_| ())
+    }
+
+    /// Verifies a state token created with [`StateSigner::sign_with_payload`] & returns its payload
+    pub fn verify_with_payload<T: DeserializeOwned>(&self, state: &str) -> Result<T, Error> {
+        let data = self
+            .verify_claims(state)?
+            .data
+            .ok_or_else(|| Error::InvalidState("State has no payload".to_string()))?;
+
+        serde_json::from_value(data)
+            .map_err(|err| Error::InvalidState(format!("State payload is invalid: {}", err)))
+    }
+
+    fn sign_claims(&self, data: Option<serde_json::Value>) -> Result<String, Error> {
+        let expires_at = SystemTime::now() + self.ttl;
+
+        let claims = StateClaims {
+            nonce: CsrfToken::new_random().secret().to_string(),
+            exp: expires_at
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            data,
+        };
+
+        jsonwebtoken::encode(
+            &Header::new(Algorithm::HS256),
+            &claims,
+            &EncodingKey::from_secret(&self.key),
+        )
+        .map_err(|err| Error::InvalidState(format!("Failed to sign state: {}", err)))
+    }
+
+    fn verify_claims(&self, state: &str) -> Result<StateClaims, Error> {
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.leeway = 0;
+        validation.validate_aud = false;
+        validation.required_spec_claims = HashSet::from(["exp".to_string()]);
+
+        jsonwebtoken::decode::<StateClaims>(
+            state,
+            &DecodingKey::from_secret(&self.key),
+            &validation,
+        )
+        .map(|token_data| token_data.claims)
+        .map_err(|err| match err.kind() {
+            ErrorKind::ExpiredSignature => Error::InvalidState("State has expired".to_string()),
+            _ => Error::InvalidState("State signature is invalid".to_string()),
+        })
+    }
+}
+
+impl std::fmt::Debug for StateSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StateSigner")
+            .field("ttl", &self.ttl)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    const KEY: &[u8] = b"0123456789abcdef0123456789abcdef";
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Payload {
+        return_to: String,
+    }
+
+    #[test]
+    fn verifies_signed_states() {
+        let signer = StateSigner::new(KEY);
+
+        let state = signer.sign();
+        assert!(signer.verify(&state).is_ok());
+        assert_ne!(signer.sign(), state);
+    }
+
+    #[test]
+    fn rejects_states_signed_with_another_key_or_tampered_with() {
+        let signer = StateSigner::new(KEY);
+        let state = signer.sign();
+
+        let other = StateSigner::new(b"another 32 byte or longer secret key".to_vec());
+        assert!(matches!(other.verify(&state), Err(Error::InvalidState(_))));
+
+        let (unsigned, signature) = state.rsplit_once('.').unwrap();
+        let tampered = format!(
+            "{}.{}",
+            unsigned,
+            signature.chars().rev().collect::<String>()
+        );
+        assert!(matches!(
+            signer.verify(&tampered),
+            Err(Error::InvalidState(_))
+        ));
+        assert!(matches!(
+            signer.verify("state"),
+            Err(Error::InvalidState(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_expired_states() {
+        let signer = StateSigner::new(KEY);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let claims = StateClaims {
+            nonce: "nonce".to_string(),
+            exp: now - 1,
+            data: None,
+        };
+        let state = jsonwebtoken::encode(
+            &Header::new(Algorithm::HS256),
+            &claims,
+            &EncodingKey::from_secret(KEY),
+        )
+        .unwrap();
+
+        match signer.verify(&state) {
+            Err(Error::InvalidState(message)) => assert_eq!(message, "State has expired"),
+            result => panic!("Expected an expired state, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn verifies_payloads() {
+        let signer = StateSigner::new(KEY);
+        let payload = Payload {
+            return_to: "/dashboard".to_string(),
+        };
+
+        let state = signer.sign_with_payload(&payload).unwrap();
+        assert_eq!(
+            signer.verify_with_payload::<Payload>(&state).unwrap(),
+            payload
+        );
+
+        let state = signer.sign();
+        assert!(matches!(
+            signer.verify_with_payload::<Payload>(&state),
+            Err(Error::InvalidState(_))
+        ));
+    }
+}
@@ -0,0 +1,315 @@
+use std::sync::Arc;
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+use crate::error::Error;
+use crate::http::BoxFuture;
+use crate::models::EveTokens;
+use crate::store::TokenStore;
+
+const NONCE_LEN: usize = 12;
+const ACCESS_TOKEN_FIELD: &[u8] = b"access_token";
+const REFRESH_TOKEN_FIELD: &[u8] = b"refresh_token";
+
+/// Provides the 256-bit AES key used by [`EncryptedTokenStore`]
+///
+/// Implement this to retrieve the key from a secrets manager, a fixed key can be passed to
+/// [`EncryptedTokenStore::new`] instead.
+pub trait KeyProvider: Send + Sync {
+    /// Returns the key to encrypt & decrypt tokens with
+    fn key(&self) -> BoxFuture<'_, Result<[u8; 32], Error>>;
+}
+
+impl KeyProvider for [u8; 32] {
+    fn key(&self) -> BoxFuture<'_, Result<[u8; 32], Error>> {
+        let key = *self;
+
+        Box::pin(async move { Ok(key) })
+    }
+}
+
+/// [`TokenStore`] wrapper encrypting the access & refresh tokens with AES-256-GCM before they reach the inner store,
+/// enabled with the `encryption` feature
+///
+/// Tokens are stored as base64 of a random nonce followed by the ciphertext, bound to the field & character id so
+/// encrypted tokens can't be swapped between characters or between the access & refresh token. Character ids, expiry
+/// & scopes are stored unencrypted.
+///
+/// ```no_run
+/// use eve_oauth2::store::{EncryptedTokenStore, MemoryTokenStore};
+///
+/// let key = [0u8; 32]; // Load your application key from configuration
+/// let store = EncryptedTokenStore::new(MemoryTokenStore::new(), key);
+/// ```
+#[derive(Clone)]
+pub struct EncryptedTokenStore<S> {
+    inner: S,
+    key_provider: Arc<dyn KeyProvider>,
+}
+
+impl<S: TokenStore> EncryptedTokenStore<S> {
+    /// Wraps the inner store, encrypting tokens with the provided key
+    pub fn new(inner: S, key: [u8; 32]) -> Self {
+        Self::with_key_provider(inner, key)
+    }
+
+    /// Wraps the inner store, encrypting tokens with the key returned by the key provider for every operation
+    pub fn with_key_provider(inner: S, key_provider: impl KeyProvider + 'static) -> Self {
+        Self {
+            inner,
+            key_provider: Arc::new(key_provider),
+        }
+    }
+
+    /// Returns the inner store
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    async fn cipher(&self) -> Result<Aes256Gcm, Error> {
+        let key = self.key_provider.key().await?;
+
+        Ok(Aes256Gcm::new(&key.into()))
+    }
+
+    async fn encrypt(&self, mut tokens: EveTokens) -> Result<EveTokens, Error> {
+        let cipher = self.cipher().await?;
+        let id = tokens.character_id;
+
+        tokens.access_token = encrypt(
+            &cipher,
+            tokens.access_token.expose_secret(),
+            &aad(ACCESS_TOKEN_FIELD, id),
+        )?
+        .into();
+        tokens.refresh_token = encrypt(
+            &cipher,
+            tokens.refresh_token.expose_secret(),
+            &aad(REFRESH_TOKEN_FIELD, id),
+        )?
+        .into();
+
+        Ok(tokens)
+    }
+
+    async fn decrypt(&self, mut tokens: EveTokens) -> Result<EveTokens, Error> {
+        let cipher = self.cipher().await?;
+        let id = tokens.character_id;
+
+        tokens.access_token = decrypt(
+            &cipher,
+            tokens.access_token.expose_secret(),
+            &aad(ACCESS_TOKEN_FIELD, id),
+        )?
+        .into();
+        tokens.refresh_token = decrypt(
+            &cipher,
+            tokens.refresh_token.expose_secret(),
+            &aad(REFRESH_TOKEN_FIELD, id),
+        )?
+        .into();
+
+        Ok(tokens)
+    }
+}
+
+impl<S: TokenStore> TokenStore for EncryptedTokenStore<S> {
+    fn get(&self, character_id: u64) -> BoxFuture<'_, Result<Option<EveTokens>, Error>> {
+        Box::pin(async move {
+            match self.inner.get(character_id).await? {
+                Some(tokens) => Ok(Some(self.decrypt(tokens).await?)),
+                None => Ok(None),
+            }
+        })
+    }
+
+    fn save(&self, tokens: EveTokens) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async move { self.inner.save(self.encrypt(tokens).await?).await })
+    }
+
+    fn delete(&self, character_id: u64) -> BoxFuture<'_, Result<(), Error>> {
+        self.inner.delete(character_id)
+    }
+
+    fn characters(&self) -> BoxFuture<'_, Result<Vec<u64>, Error>> {
+        self.inner.characters()
+    }
+
+    /// Encryption uses a random nonce, so the stored refresh token is decrypted & compared before the
+    /// inner store's `replace` is called with the stored ciphertext, keeping the inner store's atomicity
+    fn replace(
+        &self,
+        tokens: EveTokens,
+        previous_refresh_token: String,
+    ) -> BoxFuture<'_, Result<bool, Error>> {
+        Box::pin(async move {
+            let Some(current) = self.inner.get(tokens.character_id).await? else {
+                return Ok(false);
+            };
+
+            let stored_refresh_token = current.refresh_token.expose_secret().to_string();
+
+            if self.decrypt(current).await?.refresh_token.expose_secret() != previous_refresh_token
+            {
+                return Ok(false);
+            }
+
+            self.inner
+                .replace(self.encrypt(tokens).await?, stored_refresh_token)
+                .await
+        })
+    }
+}
+
+/// Associated data binding a ciphertext to the token field & the character id
+fn aad(field: &[u8], character_id: u64) -> Vec<u8> {
+    let mut aad = field.to_vec();
+    aad.extend(character_id.to_be_bytes());
+
+    aad
+}
+
+fn encrypt(cipher: &Aes256Gcm, token: &str, aad: &[u8]) -> Result<String, Error> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: token.as_bytes(),
+                aad,
+            },
+        )
+        .map_err(|_| Error::TokenStore("Failed to encrypt token".into()))?;
+
+    let mut value = nonce.to_vec();
+    value.extend(ciphertext);
+
+    Ok(STANDARD.encode(value))
+}
+
+fn decrypt(cipher: &Aes256Gcm, token: &str, aad: &[u8]) -> Result<String, Error> {
+    let value = STANDARD
+        .decode(token)
+        .map_err(|err| Error::TokenStore(Box::new(err)))?;
+
+    if value.len() < NONCE_LEN {
+        return Err(Error::TokenStore("Encrypted token is too short".into()));
+    }
+
+    let (nonce, ciphertext) = value.split_at(NONCE_LEN);
+
+    let token = cipher
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .map_err(|_| {
+            Error::TokenStore("Failed to decrypt token, the encryption key may have changed".into())
+        })?;
+
+    String::from_utf8(token).map_err(|err| Error::TokenStore(Box::new(err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::tests::tokens;
+    use crate::store::MemoryTokenStore;
+
+    const KEY: [u8; 32] = [7; 32];
+
+    #[tokio::test]
+    async fn encrypts_tokens_in_the_inner_store() {
+        let store = EncryptedTokenStore::new(MemoryTokenStore::new(), KEY);
+        store.save(tokens("refresh_token")).await.unwrap();
+
+        let stored = store.inner.get(2114794365).await.unwrap().unwrap();
+        assert_ne!(stored.access_token.expose_secret(), "access_token");
+        assert_ne!(stored.refresh_token.expose_secret(), "refresh_token");
+        assert_eq!(stored.scopes, ["publicData"]);
+
+        assert_eq!(
+            store.get(2114794365).await.unwrap(),
+            Some(tokens("refresh_token"))
+        );
+    }
+
+    #[tokio::test]
+    async fn detects_swapped_or_tampered_tokens() {
+        let store = EncryptedTokenStore::new(MemoryTokenStore::new(), KEY);
+        store.save(tokens("refresh_token")).await.unwrap();
+        let stored = store.inner.get(2114794365).await.unwrap().unwrap();
+
+        let mut swapped = stored.clone();
+        swapped.access_token = stored.refresh_token.clone();
+        swapped.refresh_token = stored.access_token.clone();
+        store.inner.save(swapped).await.unwrap();
+        assert!(matches!(
+            store.get(2114794365).await,
+            Err(Error::TokenStore(_))
+        ));
+
+        let mut other_character = stored.clone();
+        other_character.character_id = 90000001;
+        store.inner.save(other_character).await.unwrap();
+        assert!(matches!(
+            store.get(90000001).await,
+            Err(Error::TokenStore(_))
+        ));
+
+        let mut tampered = STANDARD
+            .decode(stored.refresh_token.expose_secret())
+            .unwrap();
+        *tampered.last_mut().unwrap() ^= 1;
+        let mut tampered_tokens = stored;
+        tampered_tokens.refresh_token = STANDARD.encode(tampered).into();
+        store.inner.save(tampered_tokens).await.unwrap();
+        assert!(matches!(
+            store.get(2114794365).await,
+            Err(Error::TokenStore(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn fails_to_decrypt_with_another_key() {
+        let inner = Arc::new(MemoryTokenStore::new());
+        EncryptedTokenStore::new(inner.clone(), KEY)
+            .save(tokens("refresh_token"))
+            .await
+            .unwrap();
+
+        let store = EncryptedTokenStore::new(inner, [8; 32]);
+        assert!(matches!(
+            store.get(2114794365).await,
+            Err(Error::TokenStore(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn replaces_tokens_only_if_the_decrypted_refresh_token_matches() {
+        let store = EncryptedTokenStore::new(MemoryTokenStore::new(), KEY);
+        store.save(tokens("first")).await.unwrap();
+
+        assert!(!store
+            .replace(tokens("second"), "other".to_string())
+            .await
+            .unwrap());
+        assert!(store
+            .replace(tokens("second"), "first".to_string())
+            .await
+            .unwrap());
+        assert!(!store
+            .replace(tokens("third"), "first".to_string())
+            .await
+            .unwrap());
+
+        assert_eq!(store.get(2114794365).await.unwrap(), Some(tokens("second")));
+    }
+}
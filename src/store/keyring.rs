@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use keyring::Entry;
+
+use crate::error::Error;
+use crate::http::BoxFuture;
+use crate::models::EveTokens;
+use crate::store::TokenStore;
+
+const CHARACTERS_ENTRY: &str = "characters";
+
+/// [`TokenStore`] storing tokens in the OS credential store, enabled with the `keyring` feature
+///
+/// Uses the Keychain on macOS, the Credential Manager (DPAPI) on Windows & the Secret Service on Linux.
+/// Each character's tokens are stored as JSON in their own credential under the provided service name, alongside
+/// a `characters` credential listing the stored character ids as the credential store can't be enumerated.
+///
+/// Credential store calls can block, e.g. on an unlock prompt, so they run on tokio's blocking thread pool & the store
+/// must be used inside a tokio runtime. Credentials holding only a refresh token, as written by older versions, are
+/// loaded with an empty access token, no scopes or owner hash & `expires_at` set to 0 so they are refreshed before use,
+/// e.g. with [`crate::EveSsoClient::refresh_stored`].
+#[derive(Debug, Clone)]
+pub struct KeyringTokenStore {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    service: String,
+    tokens: Mutex<HashMap<u64, EveTokens>>,
+}
+
+impl KeyringTokenStore {
+    /// Creates a store using the service name to identify your application's credentials, e.g. `"my-eve-tool"`
+    pub fn new(service: impl Into<String>) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                service: service.into(),
+                tokens: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// Runs the credential store calls on tokio's blocking thread pool, holding the lock of the cached tokens
+    fn run<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Inner, &mut HashMap<u64, EveTokens>) -> Result<T, Error> + Send + 'static,
+    ) -> BoxFuture<'_, Result<T, Error>> {
+        let inner = self.inner.clone();
+
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || f(&inner, &mut inner.tokens.lock().unwrap()))
+                .await
+                .map_err(store_error)?
+        })
+    }
+}
+
+impl Inner {
+    fn entry(&self, user: &str) -> Result<Entry, Error> {
+        Entry::new(&self.service, user).map_err(store_error)
+    }
+
+    fn load(&self, character_id: u64) -> Result<Option<EveTokens>, Error> {
+        let credential = match self.entry(&character_id.to_string())?.get_password() {
+            Ok(credential) => credential,
+            Err(keyring::Error::NoEntry) => return Ok(None),
+            Err(err) => return Err(store_error(err)),
+        };
+
+        Ok(Some(parse_credential(character_id, credential)))
+    }
+
+    fn get_tokens(
+        &self,
+        tokens: &HashMap<u64, EveTokens>,
+        character_id: u64,
+    ) -> Result<Option<EveTokens>, Error> {
+        match tokens.get(&character_id) {
+            Some(tokens) => Ok(Some(tokens.clone())),
+            None => self.load(character_id),
+        }
+    }
+
+    fn save_tokens(
+        &self,
+        tokens: &mut HashMap<u64, EveTokens>,
+        new: EveTokens,
+    ) -> Result<(), Error> {
+        let credential = serde_json::to_string(&new).map_err(store_error)?;
+
+        self.entry(&new.character_id.to_string())?
+            .set_password(&credential)
+            .map_err(store_error)?;
+
+        let mut characters = self.load_characters()?;
+
+        if !characters.contains(&new.character_id) {
+            characters.push(new.character_id);
+            self.save_characters(&characters)?;
+        }
+
+        tokens.insert(new.character_id, new);
+
+        Ok(())
+    }
+
+    fn delete_tokens(
+        &self,
+        tokens: &mut HashMap<u64, EveTokens>,
+        character_id: u64,
+    ) -> Result<(), Error> {
+        delete_credential(&self.entry(&character_id.to_string())?)?;
+
+        let mut characters = self.load_characters()?;
+
+        if characters.contains(&character_id) {
+            characters.retain(|id| *id != character_id);
+            self.save_characters(&characters)?;
+        }
+
+        tokens.remove(&character_id);
+
+        Ok(())
+    }
+
+    fn replace_tokens(
+        &self,
+        tokens: &mut HashMap<u64, EveTokens>,
+        new: EveTokens,
+        previous_refresh_token: String,
+    ) -> Result<bool, Error> {
+        match self.get_tokens(tokens, new.character_id)? {
+            Some(current) if current.refresh_token.expose_secret() == previous_refresh_token => {
+                self.save_tokens(tokens, new)?;
+
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    fn load_characters(&self) -> Result<Vec<u64>, Error> {
+        let characters = match self.entry(CHARACTERS_ENTRY)?.get_password() {
+            Ok(characters) => characters,
+            Err(keyring::Error::NoEntry) => return Ok(Vec::new()),
+            Err(err) => return Err(store_error(err)),
+        };
+
+        characters
+            .split(',')
+            .filter(|id| !id.is_empty())
+            .map(|id| {
+                id.parse()
+                    .map_err(|_| Error::TokenStore(format!("Invalid character id {:?}", id).into()))
+            })
+            .collect()
+    }
+
+    fn save_characters(&self, characters: &[u64]) -> Result<(), Error> {
+        let entry = self.entry(CHARACTERS_ENTRY)?;
+
+        if characters.is_empty() {
+            return delete_credential(&entry);
+        }
+
+        let characters = characters
+            .iter()
+            .map(u64::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+
+        entry.set_password(&characters).map_err(store_error)
+    }
+}
+
+impl TokenStore for KeyringTokenStore {
+    fn get(&self, character_id: u64) -> BoxFuture<'_, Result<Option<EveTokens>, Error>> {
+        self.run(move |inner, tokens| inner.get_tokens(tokens, character_id))
+    }
+
+    fn save(&self, tokens: EveTokens) -> BoxFuture<'_, Result<(), Error>> {
+        self.run(move |inner, cached| inner.save_tokens(cached, tokens))
+    }
+
+    fn delete(&self, character_id: u64) -> BoxFuture<'_, Result<(), Error>> {
+        self.run(move |inner, tokens| inner.delete_tokens(tokens, character_id))
+    }
+
+    fn characters(&self) -> BoxFuture<'_, Result<Vec<u64>, Error>> {
+        self.run(|inner, _tokens| inner.load_characters())
+    }
+
+    fn replace(
+        &self,
+        tokens: EveTokens,
+        previous_refresh_token: String,
+    ) -> BoxFuture<'_, Result<bool, Error>> {
+        self.run(move |inner, cached| inner.replace_tokens(cached, tokens, previous_refresh_token))
+    }
+}
+
+/// Parses the tokens stored as JSON, or the bare refresh token stored by older versions
+fn parse_credential(character_id: u64, credential: String) -> EveTokens {
+    if let Ok(tokens) = serde_json::from_str(&credential) {
+        return tokens;
+    }
+
+    EveTokens {
+        character_id,
+        access_token: Default::default(),
+        refresh_token: credential.into(),
+        expires_at: 0,
+        scopes: Vec::new(),
+        owner_hash: None,
+    }
+}
+
+fn delete_credential(entry: &Entry) -> Result<(), Error> {
+    match entry.delete_credential() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(err) => Err(store_error(err)),
+    }
+}
+
+fn store_error(err: impl std::error::Error + Send + Sync + 'static) -> Error {
+    Error::TokenStore(Box::new(err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_stored_tokens_and_legacy_refresh_tokens() {
+        let tokens = EveTokens {
+            character_id: 2114794365,
+            access_token: "access_token".into(),
+            refresh_token: "refresh_token".into(),
+            expires_at: 1700000000,
+            scopes: vec!["publicData".to_string()],
+            owner_hash: Some("owner_hash".to_string()),
+        };
+        let credential = serde_json::to_string(&tokens).unwrap();
+
+        assert_eq!(parse_credential(2114794365, credential), tokens);
+
+        let legacy = parse_credential(2114794365, "refresh_token".to_string());
+        assert_eq!(legacy.refresh_token.expose_secret(), "refresh_token");
+        assert_eq!(legacy.expires_at, 0);
+        assert_eq!(legacy.owner_hash, None);
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use crate::error::Error;
+use crate::http::BoxFuture;
+use crate::models::EveTokens;
+use crate::store::TokenStore;
+
+/// Thread-safe in-memory [`TokenStore`], tokens are lost when the application stops
+///
+/// Useful for tests & small bots, use a persistent store for anything else.
+#[derive(Debug, Default)]
+pub struct MemoryTokenStore {
+    tokens: RwLock<HashMap<u64, EveTokens>>,
+}
+
+impl MemoryTokenStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl TokenStore for MemoryTokenStore {
+    fn get(&self, character_id: u64) -> BoxFuture<'_, Result<Option<EveTokens>, Error>> {
+        let tokens = self.tokens.read().unwrap().get(&character_id).cloned();
+
+        Box::pin(async move { Ok(tokens) })
+    }
+
+    fn save(&self, tokens: EveTokens) -> BoxFuture<'_, Result<(), Error>> {
+        self.tokens
+            .write()
+            .unwrap()
+            .insert(tokens.character_id, tokens);
+
+        Box::pin(async { Ok(()) })
+    }
+
+    fn delete(&self, character_id: u64) -> BoxFuture<'_, Result<(), Error>> {
+        self.tokens.write().unwrap().remove(&character_id);
+
+        Box::pin(async { Ok(()) })
+    }
+
+    fn characters(&self) -> BoxFuture<'_, Result<Vec<u64>, Error>> {
+        let characters = self.tokens.read().unwrap().keys().copied().collect();
+
+        Box::pin(async move { Ok(characters) })
+    }
+
+    fn replace(
+        &self,
+        tokens: EveTokens,
+        previous_refresh_token: String,
+    ) -> BoxFuture<'_, Result<bool, Error>> {
+        let mut stored = self.tokens.write().unwrap();
+
+        let replaced = match stored.get(&tokens.character_id) {
+            Some(current) if current.refresh_token.expose_secret() == previous_refresh_token => {
+                stored.insert(tokens.character_id, tokens);
+                true
+            }
+            _ => false,
+        };
+
+        Box::pin(async move { Ok(replaced) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::tests::tokens;
+
+    #[tokio::test]
+    async fn replaces_tokens_only_if_the_refresh_token_matches() {
+        let store = MemoryTokenStore::new();
+        assert!(!store
+            .replace(tokens("second"), "first".to_string())
+            .await
+            .unwrap());
+        assert_eq!(store.get(2114794365).await.unwrap(), None);
+
+        store.save(tokens("first")).await.unwrap();
+        assert!(store
+            .replace(tokens("second"), "first".to_string())
+            .await
+            .unwrap());
+        assert!(!store
+            .replace(tokens("third"), "first".to_string())
+            .await
+            .unwrap());
+
+        let stored = store.get(2114794365).await.unwrap().unwrap();
+        assert_eq!(stored.refresh_token.expose_secret(), "second");
+    }
+}
@@ -0,0 +1,187 @@
+//! Storage of character tokens
+//!
+//! Implement [`TokenStore`] for your storage backend to use it with the token helpers of [`crate::EveSsoClient`],
+//! or use one of the provided implementations.
+
+#[cfg(feature = "encryption")]
+mod encrypted;
+#[cfg(feature = "keyring")]
+mod keyring;
+mod memory;
+#[cfg(feature = "sqlx-postgres")]
+mod postgres;
+#[cfg(feature = "redis")]
+mod redis;
+#[cfg(feature = "sled")]
+mod sled;
+
+#[cfg(feature = "keyring")]
+pub use self::keyring::KeyringTokenStore;
+#[cfg(feature = "sqlx-postgres")]
+pub use self::postgres::PgTokenStore;
+#[cfg(feature = "redis")]
+pub use self::redis::RedisTokenStore;
+#[cfg(feature = "sled")]
+pub use self::sled::SledTokenStore;
+#[cfg(feature = "encryption")]
+pub use encrypted::{EncryptedTokenStore, KeyProvider};
+pub use memory::MemoryTokenStore;
+
+use std::sync::Arc;
+
+use crate::error::Error;
+use crate::http::BoxFuture;
+use crate::models::EveTokens;
+
+/// Asynchronous storage of the [`EveTokens`] of characters, keyed by character id
+///
+/// Wrap errors of your storage backend in [`Error::TokenStore`].
+///
+/// ```no_run
+/// use eve_oauth2::http::BoxFuture;
+/// use eve_oauth2::models::EveTokens;
+/// use eve_oauth2::store::TokenStore;
+/// use eve_oauth2::Error;
+///
+/// struct MyTokenStore;
+///
+/// impl TokenStore for MyTokenStore {
+///     fn get(&self, character_id: u64) -> BoxFuture<'_, Result<Option<EveTokens>, Error>> {
+///         Box::pin(async move {
+///             // Retrieve the tokens from your database
+///             # unimplemented!()
+///         })
+///     }
+///     # fn save(&self, tokens: EveTokens) -> BoxFuture<'_, Result<(), Error>> { unimplemented!() }
+///     # fn delete(&self, character_id: u64) -> BoxFuture<'_, Result<(), Error>> { unimplemented!() }
+///     # fn characters(&self) -> BoxFuture<'_, Result<Vec<u64>, Error>> { unimplemented!() }
+/// }
+/// ```
+pub trait TokenStore: Send + Sync {
+    /// Returns the tokens of the character, `None` if no tokens are stored for the character
+    fn get(&self, character_id: u64) -> BoxFuture<'_, Result<Option<EveTokens>, Error>>;
+
+    /// Stores the tokens, replacing any tokens previously stored for the character
+    fn save(&self, tokens: EveTokens) -> BoxFuture<'_, Result<(), Error>>;
+
+    /// Deletes the tokens of the character, does nothing if no tokens are stored for the character
+    fn delete(&self, character_id: u64) -> BoxFuture<'_, Result<(), Error>>;
+
+    /// Returns the ids of all characters with stored tokens
+    fn characters(&self) -> BoxFuture<'_, Result<Vec<u64>, Error>>;
+
+    /// Replaces the tokens of a character only if the stored refresh token still matches `previous_refresh_token`
+    ///
+    /// Returns `false` without saving if the tokens were replaced or deleted in the meantime, e.g. by another
+    /// instance of your application refreshing the same character. The default implementation isn't atomic,
+    /// override it if your storage backend supports compare-and-swap.
+    fn replace(
+        &self,
+        tokens: EveTokens,
+        previous_refresh_token: String,
+    ) -> BoxFuture<'_, Result<bool, Error>> {
+        Box::pin(async move {
+            match self.get(tokens.character_id).await? {
+                Some(current)
+                    if current.refresh_token.expose_secret() == previous_refresh_token =>
+                {
+                    self.save(tokens).await?;
+
+                    Ok(true)
+                }
+                _ => Ok(false),
+            }
+        })
+    }
+}
+
+impl<S: TokenStore + ?Sized> TokenStore for Arc<S> {
+    fn get(&self, character_id: u64) -> BoxFuture<'_, Result<Option<EveTokens>, Error>> {
+        (**self).get(character_id)
+    }
+
+    fn save(&self, tokens: EveTokens) -> BoxFuture<'_, Result<(), Error>> {
+        (**self).save(tokens)
+    }
+
+    fn delete(&self, character_id: u64) -> BoxFuture<'_, Result<(), Error>> {
+        (**self).delete(character_id)
+    }
+
+    fn characters(&self) -> BoxFuture<'_, Result<Vec<u64>, Error>> {
+        (**self).characters()
+    }
+
+    fn replace(
+        &self,
+        tokens: EveTokens,
+        previous_refresh_token: String,
+    ) -> BoxFuture<'_, Result<bool, Error>> {
+        (**self).replace(tokens, previous_refresh_token)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Store relying on the default implementation of [`TokenStore::replace`]
+    struct DefaultReplace(MemoryTokenStore);
+
+    impl TokenStore for DefaultReplace {
+        fn get(&self, character_id: u64) -> BoxFuture<'_, Result<Option<EveTokens>, Error>> {
+            self.0.get(character_id)
+        }
+
+        fn save(&self, tokens: EveTokens) -> BoxFuture<'_, Result<(), Error>> {
+            self.0.save(tokens)
+        }
+
+        fn delete(&self, character_id: u64) -> BoxFuture<'_, Result<(), Error>> {
+            self.0.delete(character_id)
+        }
+
+        fn characters(&self) -> BoxFuture<'_, Result<Vec<u64>, Error>> {
+            self.0.characters()
+        }
+    }
+
+    /// Tokens of a test character with the refresh token, shared by the tests of the stores
+    pub(crate) fn tokens(refresh_token: &str) -> EveTokens {
+        EveTokens {
+            character_id: 2114794365,
+            access_token: "access_token".into(),
+            refresh_token: refresh_token.into(),
+            expires_at: 0,
+            scopes: vec!["publicData".to_string()],
+            owner_hash: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn default_replace_only_saves_if_the_refresh_token_matches() {
+        let store = Arc::new(DefaultReplace(MemoryTokenStore::new()));
+        assert!(!store
+            .replace(tokens("second"), "first".to_string())
+            .await
+            .unwrap());
+        assert_eq!(store.characters().await.unwrap(), Vec::<u64>::new());
+
+        store.save(tokens("first")).await.unwrap();
+        assert!(store
+            .replace(tokens("second"), "first".to_string())
+            .await
+            .unwrap());
+        assert!(!store
+            .replace(tokens("third"), "first".to_string())
+            .await
+            .unwrap());
+
+        store.delete(2114794365).await.unwrap();
+        assert!(!store
+            .replace(tokens("third"), "second".to_string())
+            .await
+            .unwrap());
+        assert_eq!(store.get(2114794365).await.unwrap(), None);
+    }
+}
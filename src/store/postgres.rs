@@ -0,0 +1,223 @@
+use sqlx::migrate::Migrator;
+use sqlx::{PgPool, Row};
+
+use crate::error::Error;
+use crate::http::BoxFuture;
+use crate::models::EveTokens;
+use crate::store::TokenStore;
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// [`TokenStore`] storing tokens in the `eve_oauth2_tokens` Postgres table, enabled with the `sqlx-postgres` feature
+///
+/// Call [`PgTokenStore::migrate`] on startup to create the table. Replacing refresh tokens is atomic so multiple
+/// instances of your application can share the store.
+///
+/// ```no_run
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// use eve_oauth2::store::{PgTokenStore, TokenStore};
+///
+/// let pool = sqlx::PgPool::connect("postgres://localhost/my_app").await?;
+/// let store = PgTokenStore::new(pool);
+/// store.migrate().await?;
+///
+/// let characters = store.characters().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct PgTokenStore {
+    pool: PgPool,
+}
+
+impl PgTokenStore {
+    /// Uses the provided connection pool, e.g. one shared with the rest of your application
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Runs the bundled migrations which create the `eve_oauth2_tokens` table
+    ///
+    /// The migrations are recorded in sqlx's `_sqlx_migrations` table, if you run your own sqlx migrations on the
+    /// same database call `set_ignore_missing(true)` on your migrator so it ignores the migrations of this crate.
+    pub async fn migrate(&self) -> Result<(), Error> {
+        let mut migrator = Migrator {
+            migrations: MIGRATOR.migrations.clone(),
+            ..Migrator::DEFAULT
+        };
+        migrator.set_ignore_missing(true);
+
+        migrator.run(&self.pool).await.map_err(store_error)
+    }
+}
+
+impl TokenStore for PgTokenStore {
+    fn get(&self, character_id: u64) -> BoxFuture<'_, Result<Option<EveTokens>, Error>> {
+        Box::pin(async move {
+            let row = sqlx::query(
+                "SELECT character_id, access_token, refresh_token, expires_at, scopes, owner_hash
+                FROM eve_oauth2_tokens WHERE character_id = $1",
+            )
+            .bind(character_id as i64)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(store_error)?;
+
+            row.map(|row| {
+                Ok::<_, sqlx::Error>(EveTokens {
+                    character_id: row.try_get::<i64, _>("character_id")? as u64,
+                    access_token: row.try_get::<String, _>("access_token")?.into(),
+                    refresh_token: row.try_get::<String, _>("refresh_token")?.into(),
+                    expires_at: row.try_get::<i64, _>("expires_at")? as u64,
+                    scopes: row.try_get("scopes")?,
+                    owner_hash: row.try_get("owner_hash")?,
+                })
+            })
+            .transpose()
+            .map_err(store_error)
+        })
+    }
+
+    fn save(&self, tokens: EveTokens) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async move {
+            sqlx::query(
+                "INSERT INTO eve_oauth2_tokens (character_id, access_token, refresh_token, expires_at, scopes, owner_hash)
+                VALUES ($1, $2, $3, $4, $5, $6)
+                ON CONFLICT (character_id) DO UPDATE SET
+                    access_token = EXCLUDED.access_token,
+                    refresh_token = EXCLUDED.refresh_token,
+                    expires_at = EXCLUDED.expires_at,
+                    scopes = EXCLUDED.scopes,
+                    owner_hash = EXCLUDED.owner_hash,
+                    updated_at = now()",
+            )
+            .bind(tokens.character_id as i64)
+            .bind(tokens.access_token.expose_secret())
+            .bind(tokens.refresh_token.expose_secret())
+            .bind(tokens.expires_at as i64)
+            .bind(&tokens.scopes)
+            .bind(&tokens.owner_hash)
+            .execute(&self.pool)
+            .await
+            .map_err(store_error)?;
+
+            Ok(())
+        })
+    }
+
+    fn delete(&self, character_id: u64) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async move {
+            sqlx::query("DELETE FROM eve_oauth2_tokens WHERE character_id = $1")
+                .bind(character_id as i64)
+                .execute(&self.pool)
+                .await
+                .map_err(store_error)?;
+
+            Ok(())
+        })
+    }
+
+    fn characters(&self) -> BoxFuture<'_, Result<Vec<u64>, Error>> {
+        Box::pin(async move {
+            let characters: Vec<i64> =
+                sqlx::query_scalar("SELECT character_id FROM eve_oauth2_tokens")
+                    .fetch_all(&self.pool)
+                    .await
+                    .map_err(store_error)?;
+
+            Ok(characters.into_iter().map(|id| id as u64).collect())
+        })
+    }
+
+    fn replace(
+        &self,
+        tokens: EveTokens,
+        previous_refresh_token: String,
+    ) -> BoxFuture<'_, Result<bool, Error>> {
+        Box::pin(async move {
+            let result = sqlx::query(
+                "UPDATE eve_oauth2_tokens SET
+                    access_token = $2,
+                    refresh_token = $3,
+                    expires_at = $4,
+                    scopes = $5,
+                    owner_hash = $6,
+                    updated_at = now()
+                WHERE character_id = $1 AND refresh_token = $7",
+            )
+            .bind(tokens.character_id as i64)
+            .bind(tokens.access_token.expose_secret())
+            .bind(tokens.refresh_token.expose_secret())
+            .bind(tokens.expires_at as i64)
+            .bind(&tokens.scopes)
+            .bind(&tokens.owner_hash)
+            .bind(previous_refresh_token)
+            .execute(&self.pool)
+            .await
+            .map_err(store_error)?;
+
+            Ok(result.rows_affected() == 1)
+        })
+    }
+}
+
+fn store_error(err: impl std::error::Error + Send + Sync + 'static) -> Error {
+    Error::TokenStore(Box::new(err))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use sqlx::postgres::PgPoolOptions;
+
+    use super::*;
+    use crate::store::tests::tokens;
+
+    /// Store backed by a pool which can't connect, so queries fail quickly without a database
+    fn unreachable_store() -> PgTokenStore {
+        let pool = PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(500))
+            .connect_lazy("postgres://eve_oauth2@127.0.0.1:1/eve_oauth2")
+            .unwrap();
+
+        PgTokenStore::new(pool)
+    }
+
+    #[test]
+    fn migrations_add_owner_hash_after_creating_table() {
+        let migrations: Vec<_> = MIGRATOR.migrations.iter().collect();
+
+        assert_eq!(migrations.len(), 2);
+        assert!(migrations[0].version < migrations[1].version);
+        assert!(migrations[0]
+            .sql
+            .contains("CREATE TABLE IF NOT EXISTS eve_oauth2_tokens"));
+        assert!(migrations[1]
+            .sql
+            .contains("ADD COLUMN IF NOT EXISTS owner_hash TEXT"));
+    }
+
+    #[tokio::test]
+    async fn save_surfaces_connection_errors() {
+        let store = unreachable_store();
+
+        let result = store.save(tokens("refresh_token")).await;
+
+        assert!(matches!(result, Err(Error::TokenStore(_))));
+    }
+
+    #[tokio::test]
+    async fn replace_surfaces_connection_errors() {
+        let store = unreachable_store();
+
+        let result = store
+            .replace(
+                tokens("refresh_token"),
+                "previous_refresh_token".to_string(),
+            )
+            .await;
+
+        assert!(matches!(result, Err(Error::TokenStore(_))));
+    }
+}
@@ -0,0 +1,223 @@
+use std::time::Duration;
+
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, AsyncIter, Script};
+
+use crate::error::Error;
+use crate::http::BoxFuture;
+use crate::models::EveTokens;
+use crate::store::TokenStore;
+
+/// Replaces the tokens only if the stored refresh token matches the previous refresh token
+const REPLACE_SCRIPT: &str = r#"
+local current = redis.call('GET', KEYS[1])
+if not current or cjson.decode(current)['refresh_token'] ~= ARGV[2] then
+    return 0
+end
+if tonumber(ARGV[3]) > 0 then
+    redis.call('SET', KEYS[1], ARGV[1], 'EX', ARGV[3])
+else
+    redis.call('SET', KEYS[1], ARGV[1])
+end
+return 1
+"#;
+
+/// [`TokenStore`] storing tokens as JSON in Redis, enabled with the `redis` feature
+///
+/// Tokens are stored under `{key_prefix}{character_id}`, the default prefix is `eve_oauth2:tokens:`.
+/// Replacing refresh tokens is atomic so multiple instances of your application can share the store.
+#[derive(Clone)]
+pub struct RedisTokenStore {
+    connection: ConnectionManager,
+    key_prefix: String,
+    ttl: Option<Duration>,
+}
+
+impl RedisTokenStore {
+    /// Connects to Redis using the provided client
+    pub async fn new(client: redis::Client) -> Result<Self, Error> {
+        let connection = ConnectionManager::new(client).await.map_err(store_error)?;
+
+        Ok(Self::with_connection_manager(connection))
+    }
+
+    /// Uses an existing connection manager, e.g. one shared with the rest of your application
+    pub fn with_connection_manager(connection: ConnectionManager) -> Self {
+        Self {
+            connection,
+            key_prefix: "eve_oauth2:tokens:".to_string(),
+            ttl: None,
+        }
+    }
+
+    /// Sets the prefix of the keys the tokens are stored under
+    pub fn key_prefix(mut self, key_prefix: impl Into<String>) -> Self {
+        self.key_prefix = key_prefix.into();
+        self
+    }
+
+    /// Expires stored tokens when they haven't been saved for the provided duration, tokens don't expire by default
+    ///
+    /// The TTL is reset every time the tokens of a character are saved, e.g. after a refresh.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    fn key(&self, character_id: u64) -> String {
+        format!("{}{}", self.key_prefix, character_id)
+    }
+
+    fn ttl_seconds(&self) -> u64 {
+        self.ttl.map(|ttl| ttl.as_secs().max(1)).unwrap_or(0)
+    }
+}
+
+impl TokenStore for RedisTokenStore {
+    fn get(&self, character_id: u64) -> BoxFuture<'_, Result<Option<EveTokens>, Error>> {
+        Box::pin(async move {
+            let mut connection = self.connection.clone();
+
+            let tokens: Option<String> = connection
+                .get(self.key(character_id))
+                .await
+                .map_err(store_error)?;
+
+            tokens
+                .map(|tokens| serde_json::from_str(&tokens).map_err(store_error))
+                .transpose()
+        })
+    }
+
+    fn save(&self, tokens: EveTokens) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async move {
+            let mut connection = self.connection.clone();
+            let key = self.key(tokens.character_id);
+            let value = serde_json::to_string(&tokens).map_err(store_error)?;
+
+            match self.ttl_seconds() {
+                0 => connection.set::<_, _, ()>(key, value).await,
+                ttl => connection.set_ex::<_, _, ()>(key, value, ttl).await,
+            }
+            .map_err(store_error)
+        })
+    }
+
+    fn delete(&self, character_id: u64) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async move {
+            let mut connection = self.connection.clone();
+
+            connection
+                .del::<_, ()>(self.key(character_id))
+                .await
+                .map_err(store_error)
+        })
+    }
+
+    fn characters(&self) -> BoxFuture<'_, Result<Vec<u64>, Error>> {
+        Box::pin(async move {
+            let mut connection = self.connection.clone();
+            let pattern = format!("{}*", self.key_prefix);
+
+            let mut keys: AsyncIter<String> =
+                connection.scan_match(pattern).await.map_err(store_error)?;
+
+            let mut characters = Vec::new();
+
+            while let Some(key) = keys.next_item().await {
+                if let Ok(character_id) = key[self.key_prefix.len()..].parse() {
+                    characters.push(character_id);
+                }
+            }
+
+            Ok(characters)
+        })
+    }
+
+    fn replace(
+        &self,
+        tokens: EveTokens,
+        previous_refresh_token: String,
+    ) -> BoxFuture<'_, Result<bool, Error>> {
+        Box::pin(async move {
+            let mut connection = self.connection.clone();
+            let value = serde_json::to_string(&tokens).map_err(store_error)?;
+
+            let replaced: i32 = Script::new(REPLACE_SCRIPT)
+                .key(self.key(tokens.character_id))
+                .arg(value)
+                .arg(previous_refresh_token)
+                .arg(self.ttl_seconds())
+                .invoke_async(&mut connection)
+                .await
+                .map_err(store_error)?;
+
+            Ok(replaced == 1)
+        })
+    }
+}
+
+fn store_error(err: impl std::error::Error + Send + Sync + 'static) -> Error {
+    Error::TokenStore(Box::new(err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::tests::tokens;
+
+    /// Store with a unique key prefix in the Redis server at `REDIS_URL`, `redis://127.0.0.1/` by default
+    async fn store(name: &str) -> RedisTokenStore {
+        let url = std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1/".to_string());
+        let client = redis::Client::open(url).unwrap();
+        let prefix = format!("eve_oauth2:tests:{}:{}:", name, std::process::id());
+
+        RedisTokenStore::new(client)
+            .await
+            .unwrap()
+            .key_prefix(prefix)
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a Redis server at REDIS_URL"]
+    async fn saves_gets_lists_and_deletes_tokens() {
+        let store = store("crud").await;
+
+        assert_eq!(store.get(2114794365).await.unwrap(), None);
+
+        store.save(tokens("refresh_token")).await.unwrap();
+        assert_eq!(
+            store.get(2114794365).await.unwrap(),
+            Some(tokens("refresh_token"))
+        );
+        assert_eq!(store.characters().await.unwrap(), [2114794365]);
+
+        store.delete(2114794365).await.unwrap();
+        assert_eq!(store.get(2114794365).await.unwrap(), None);
+        assert!(store.characters().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a Redis server at REDIS_URL"]
+    async fn replace_only_replaces_the_previous_refresh_token() {
+        let store = store("replace").await.ttl(Duration::from_secs(60));
+
+        assert!(!store
+            .replace(tokens("second"), "first".to_string())
+            .await
+            .unwrap());
+
+        store.save(tokens("first")).await.unwrap();
+        assert!(!store
+            .replace(tokens("second"), "other".to_string())
+            .await
+            .unwrap());
+        assert!(store
+            .replace(tokens("second"), "first".to_string())
+            .await
+            .unwrap());
+        assert_eq!(store.get(2114794365).await.unwrap(), Some(tokens("second")));
+
+        store.delete(2114794365).await.unwrap();
+    }
+}
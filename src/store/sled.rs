@@ -0,0 +1,206 @@
+use std::path::Path;
+
+use crate::error::Error;
+use crate::http::BoxFuture;
+use crate::models::EveTokens;
+use crate::store::TokenStore;
+
+/// [`TokenStore`] storing tokens in an embedded sled database, enabled with the `sled` feature
+///
+/// Tokens are stored as JSON in the `eve_oauth2_tokens` tree & flushed to disk after every change.
+#[derive(Clone, Debug)]
+pub struct SledTokenStore {
+    tree: sled::Tree,
+}
+
+impl SledTokenStore {
+    /// Opens or creates the sled database at the provided path
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let db = sled::open(path).map_err(store_error)?;
+
+        Self::new(&db)
+    }
+
+    /// Uses the `eve_oauth2_tokens` tree of an existing sled database, e.g. one shared with the rest of your application
+    pub fn new(db: &sled::Db) -> Result<Self, Error> {
+        let tree = db.open_tree("eve_oauth2_tokens").map_err(store_error)?;
+
+        Ok(Self { tree })
+    }
+
+    async fn flush(&self) -> Result<(), Error> {
+        self.tree.flush_async().await.map_err(store_error)?;
+
+        Ok(())
+    }
+}
+
+impl TokenStore for SledTokenStore {
+    fn get(&self, character_id: u64) -> BoxFuture<'_, Result<Option<EveTokens>, Error>> {
+        let tokens = self
+            .tree
+            .get(character_id.to_be_bytes())
+            .map_err(store_error)
+            .and_then(|tokens| tokens.map(|tokens| deserialize(&tokens)).transpose());
+
+        Box::pin(async move { tokens })
+    }
+
+    fn save(&self, tokens: EveTokens) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async move {
+            let value = serde_json::to_vec(&tokens).map_err(store_error)?;
+
+            self.tree
+                .insert(tokens.character_id.to_be_bytes(), value)
+                .map_err(store_error)?;
+
+            self.flush().await
+        })
+    }
+
+    fn delete(&self, character_id: u64) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async move {
+            self.tree
+                .remove(character_id.to_be_bytes())
+                .map_err(store_error)?;
+
+            self.flush().await
+        })
+    }
+
+    fn characters(&self) -> BoxFuture<'_, Result<Vec<u64>, Error>> {
+        let characters = self
+            .tree
+            .iter()
+            .keys()
+            .map(|key| {
+                let key = key.map_err(store_error)?;
+                let key = key.as_ref().try_into().map_err(|_| {
+                    Error::TokenStore(format!("Invalid character id key {:?}", key).into())
+                })?;
+
+                Ok(u64::from_be_bytes(key))
+            })
+            .collect();
+
+        Box::pin(async move { characters })
+    }
+
+    fn replace(
+        &self,
+        tokens: EveTokens,
+        previous_refresh_token: String,
+    ) -> BoxFuture<'_, Result<bool, Error>> {
+        Box::pin(async move {
+            let key = tokens.character_id.to_be_bytes();
+
+            let Some(current) = self.tree.get(key).map_err(store_error)? else {
+                return Ok(false);
+            };
+
+            if deserialize(&current)?.refresh_token.expose_secret() != previous_refresh_token {
+                return Ok(false);
+            }
+
+            let value = serde_json::to_vec(&tokens).map_err(store_error)?;
+
+            let replaced = self
+                .tree
+                .compare_and_swap(key, Some(current), Some(value))
+                .map_err(store_error)?
+                .is_ok();
+
+            self.flush().await?;
+
+            Ok(replaced)
+        })
+    }
+}
+
+fn deserialize(tokens: &[u8]) -> Result<EveTokens, Error> {
+    serde_json::from_slice(tokens).map_err(store_error)
+}
+
+fn store_error(err: impl std::error::Error + Send + Sync + 'static) -> Error {
+    Error::TokenStore(Box::new(err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::tests::tokens;
+
+    fn temporary_store() -> SledTokenStore {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+
+        SledTokenStore::new(&db).unwrap()
+    }
+
+    #[tokio::test]
+    async fn saves_gets_lists_and_deletes_tokens() {
+        let store = temporary_store();
+        let other = EveTokens {
+            character_id: 90000001,
+            ..tokens("other_refresh_token")
+        };
+
+        store.save(tokens("refresh_token")).await.unwrap();
+        store.save(other.clone()).await.unwrap();
+
+        assert_eq!(
+            store.get(2114794365).await.unwrap(),
+            Some(tokens("refresh_token"))
+        );
+        let mut characters = store.characters().await.unwrap();
+        characters.sort_unstable();
+        assert_eq!(characters, [90000001, 2114794365]);
+
+        store.delete(2114794365).await.unwrap();
+        assert_eq!(store.get(2114794365).await.unwrap(), None);
+        assert_eq!(store.characters().await.unwrap(), [90000001]);
+    }
+
+    #[tokio::test]
+    async fn replace_only_replaces_the_previous_refresh_token() {
+        let store = temporary_store();
+
+        assert!(!store
+            .replace(tokens("rotated"), "refresh_token".to_string())
+            .await
+            .unwrap());
+        assert_eq!(store.get(2114794365).await.unwrap(), None);
+
+        store.save(tokens("refresh_token")).await.unwrap();
+
+        assert!(!store
+            .replace(tokens("rotated"), "other_refresh_token".to_string())
+            .await
+            .unwrap());
+        assert!(store
+            .replace(tokens("rotated"), "refresh_token".to_string())
+            .await
+            .unwrap());
+        assert_eq!(
+            store.get(2114794365).await.unwrap(),
+            Some(tokens("rotated"))
+        );
+    }
+
+    #[tokio::test]
+    async fn tokens_persist_across_reopening() {
+        let path = std::env::temp_dir().join(format!("eve_oauth2-sled-{}", std::process::id()));
+
+        let store = SledTokenStore::open(&path).unwrap();
+        store.save(tokens("refresh_token")).await.unwrap();
+        drop(store);
+
+        let store = SledTokenStore::open(&path).unwrap();
+        assert_eq!(
+            store.get(2114794365).await.unwrap(),
+            Some(tokens("refresh_token"))
+        );
+        drop(store);
+
+        std::fs::remove_dir_all(path).unwrap();
+    }
+}
@@ -0,0 +1,257 @@
+//! Tauri commands for desktop applications, enabled with the `tauri` feature
+//!
+//! Manage an [`EveSsoTauri`] & register the commands with the invoke handler of your app. Logins use PKCE, so no
+//! client secret is shipped with the app, & refresh tokens are stored in the OS credential store with the
+//! [`KeyringTokenStore`]. Refresh tokens never leave the Rust side, the frontend only receives characters & access
+//! tokens.
+//!
+//! ```no_run
+//! use eve_oauth2::tauri::EveSsoTauri;
+//! use eve_oauth2::EveSsoClient;
+//!
+//! fn setup<R: tauri::Runtime>(builder: tauri::Builder<R>) -> tauri::Builder<R> {
+//!     let client = EveSsoClient::builder("client_id").build();
+//!
+//!     builder
+//!         .manage(EveSsoTauri::new(client, "my-eve-tool"))
+//!         .invoke_handler(tauri::generate_handler![
+//!             eve_oauth2::tauri::eve_sso_login,
+//!             eve_oauth2::tauri::eve_sso_start_login,
+//!             eve_oauth2::tauri::eve_sso_handle_callback,
+//!             eve_oauth2::tauri::eve_sso_characters,
+//!             eve_oauth2::tauri::eve_sso_access_token,
+//!             eve_oauth2::tauri::eve_sso_logout,
+//!         ])
+//! }
+//! ```
+//!
+//! The frontend then logs in a character with `invoke("eve_sso_login", { scopes: ["publicData"] })`.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::State;
+
+use crate::error::Error;
+use crate::loopback::LocalCallbackServer;
+use crate::models::{Character, EveTokens};
+use crate::store::{KeyringTokenStore, TokenStore};
+use crate::{open_login_url, parse_callback_url, EveSsoClient, EveTokenResponse};
+
+const DEFAULT_PORT: u16 = 8080;
+
+/// Margin before the expiry of an access token at which [`eve_sso_access_token`] refreshes it
+const REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// State of the EVE Online SSO commands, add it to your app with `tauri::Builder::manage`
+pub struct EveSsoTauri {
+    client: EveSsoClient,
+    store: KeyringTokenStore,
+    port: u16,
+    deep_link: Option<String>,
+    /// Code verifiers of logins started with [`eve_sso_start_login`], keyed by state
+    pending: Mutex<HashMap<String, String>>,
+}
+
+impl EveSsoTauri {
+    /// Creates the state, storing refresh tokens in the OS credential store under the service name
+    pub fn new(client: EveSsoClient, service: impl Into<String>) -> Self {
+        Self {
+            client,
+            store: KeyringTokenStore::new(service),
+            port: DEFAULT_PORT,
+            deep_link: None,
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sets the port of the loopback callback used by [`eve_sso_login`], defaults to 8080
+    ///
+    /// The callback URL of your EVE developer application must be `http://127.0.0.1:<port>/callback`.
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Sets the deep link EVE Online SSO redirects to for logins started with [`eve_sso_start_login`], e.g.
+    /// `my-eve-tool://callback`
+    ///
+    /// Register the scheme with the deep link plugin of Tauri & pass the URLs it receives to
+    /// [`eve_sso_handle_callback`].
+    pub fn deep_link(mut self, redirect_url: impl Into<String>) -> Self {
+        self.deep_link = Some(redirect_url.into());
+        self
+    }
+
+    /// Client used to log in & refresh tokens
+    pub fn client(&self) -> &EveSsoClient {
+        &self.client
+    }
+
+    /// Store holding the tokens of logged in characters
+    pub fn store(&self) -> &KeyringTokenStore {
+        &self.store
+    }
+
+    /// Validates the token, stores the character's tokens & returns the character
+    async fn save(&self, token: EveTokenResponse) -> Result<Character, Error> {
+        let token_data = self.client.validate_token(token.access_token()).await?;
+
+        self.store
+            .save(EveTokens::new(&token, &token_data.claims)?)
+            .await?;
+
+        Character::try_from(&token_data.claims)
+    }
+}
+
+/// Error returned to the frontend, `kind` is the [`Error::kind`] of the error
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandError {
+    pub kind: &'static str,
+    pub message: String,
+}
+
+impl From<Error> for CommandError {
+    fn from(err: Error) -> Self {
+        Self {
+            kind: err.kind(),
+            message: err.to_string(),
+        }
+    }
+}
+
+/// Logs in a character in the system browser with a loopback callback, stores its tokens & returns the character
+#[tauri::command]
+pub async fn eve_sso_login(
+    sso: State<'_, EveSsoTauri>,
+    scopes: Vec<String>,
+) -> Result<Character, CommandError> {
+    let server = LocalCallbackServer::bind(sso.port).await?;
+    let auth = sso
+        .client
+        .try_create_login_url_pkce(server.redirect_url(), scopes)?;
+
+    open_login_url(&auth).map_err(Error::CallbackServer)?;
+
+    let code = server.wait_for_code(&auth).await?;
+    let code_verifier = auth.code_verifier.unwrap_or_default();
+    let token = sso
+        .client
+        .get_access_token_pkce(code, code_verifier)
+        .await?;
+
+    Ok(sso.save(token).await?)
+}
+
+/// Opens the login in the system browser, redirecting to the deep link set with [`EveSsoTauri::deep_link`]
+///
+/// Returns the login URL, pass the deep link the app is opened with to [`eve_sso_handle_callback`].
+#[tauri::command]
+pub async fn eve_sso_start_login(
+    sso: State<'_, EveSsoTauri>,
+    scopes: Vec<String>,
+) -> Result<String, CommandError> {
+    let redirect_url = sso
+        .deep_link
+        .clone()
+        .ok_or_else(|| Error::InvalidState("No deep link is set".to_string()))?;
+    let auth = sso.client.try_create_login_url_pkce(redirect_url, scopes)?;
+
+    sso.pending.lock().unwrap().insert(
+        auth.state.clone(),
+        auth.code_verifier.clone().unwrap_or_default(),
+    );
+
+    open_login_url(&auth).map_err(Error::CallbackServer)?;
+
+    Ok(auth.login_url)
+}
+
+/// Completes a login started with [`eve_sso_start_login`] from the deep link, stores the character's tokens &
+/// returns the character
+#[tauri::command]
+pub async fn eve_sso_handle_callback(
+    sso: State<'_, EveSsoTauri>,
+    url: String,
+) -> Result<Character, CommandError> {
+    let (code, state) = parse_callback_url(&url)?.into_result()?;
+    let code_verifier =
+        sso.pending.lock().unwrap().remove(&state).ok_or_else(|| {
+            Error::InvalidState("No login was started with the state".to_string())
+        })?;
+    let token = sso
+        .client
+        .get_access_token_pkce(code, code_verifier)
+        .await?;
+
+    Ok(sso.save(token).await?)
+}
+
+/// Returns the ids of the characters with stored tokens
+#[tauri::command]
+pub async fn eve_sso_characters(sso: State<'_, EveSsoTauri>) -> Result<Vec<u64>, CommandError> {
+    Ok(sso.store.characters().await?)
+}
+
+/// Returns an access token of the character, refreshing it if it expires within a minute
+#[tauri::command]
+pub async fn eve_sso_access_token(
+    sso: State<'_, EveSsoTauri>,
+    character_id: u64,
+) -> Result<String, CommandError> {
+    let tokens = sso
+        .store
+        .get(character_id)
+        .await?
+        .ok_or(Error::CharacterNotFound(character_id))?;
+
+    if !tokens.needs_refresh(REFRESH_MARGIN) {
+        return Ok(tokens.into_access_token());
+    }
+
+    let tokens = sso.client.refresh_stored(&sso.store, character_id).await?;
+
+    Ok(tokens.into_access_token())
+}
+
+/// Revokes the refresh token of the character & deletes its stored tokens
+///
+/// The tokens are deleted even if the revocation fails, the error is returned afterwards.
+#[tauri::command]
+pub async fn eve_sso_logout(
+    sso: State<'_, EveSsoTauri>,
+    character_id: u64,
+) -> Result<(), CommandError> {
+    let Some(tokens) = sso.store.get(character_id).await? else {
+        return Ok(());
+    };
+
+    let revoked = sso
+        .client
+        .revoke_token(tokens.refresh_token.into_secret())
+        .await;
+    sso.store.delete(character_id).await?;
+
+    Ok(revoked?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_errors_carry_the_kind_and_message() {
+        let err = CommandError::from(Error::InvalidState("No login was started".to_string()));
+
+        assert_eq!(
+            serde_json::to_value(err).unwrap(),
+            serde_json::json!({
+                "kind": "invalid_state",
+                "message": "State is invalid: No login was started",
+            })
+        );
+    }
+}
@@ -0,0 +1,920 @@
+//! Mock EVE Online SSO for integration tests, enabled with the `testing` feature
+//!
+//! [`MockSso`] serves the authorize, token, revocation, metadata & JWKS endpoints on a local port & signs access
+//! tokens with a generated RSA key, so the full login, code exchange & validation flow runs without EVE Online SSO.
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! use eve_oauth2::testing::MockSso;
+//!
+//! let sso = MockSso::start()?;
+//! let client = sso.client("client_id");
+//!
+//! let auth = client.create_login_url(
+//!     "http://localhost:8080/callback".to_string(),
+//!     vec!["esi-wallet.read_character_wallet.v1".to_string()],
+//! );
+//! let (code, state) = sso.authorize(&auth.login_url)?.into_result()?;
+//! assert_eq!(state, auth.state);
+//!
+//! let token = client.get_access_token(code).await?;
+//! let token_data = client.validate_token(token.access_token()).await?;
+//! assert_eq!(token_data.claims.name, "Test Character");
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use jsonwebtoken::{EncodingKey, Header};
+use rand::distributions::{Alphanumeric, DistString};
+use rsa::pkcs1::EncodeRsaPrivateKey;
+use rsa::traits::PublicKeyParts;
+use rsa::RsaPrivateKey;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::error::Error;
+use crate::http::Url;
+use crate::models::{
+    CallbackResult, EveJwtClaims, EveJwtClaimsBuilder, EveJwtKey, EveJwtKeys, EveServer,
+    EveSsoMetaData, SsoEndpoints,
+};
+use crate::{parse_callback_url, ISSUER};
+
+const KEY_ID: &str = "JWT-Signature-Key";
+const ACCESS_TOKEN_LIFETIME: u64 = 1199;
+
+static SHARED_SIGNER: OnceLock<TestTokenSigner> = OnceLock::new();
+
+/// Character logged in by the [`MockSso`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockCharacter {
+    pub id: u64,
+    pub name: String,
+    pub owner_hash: String,
+}
+
+impl Default for MockCharacter {
+    fn default() -> Self {
+        Self {
+            id: 90000001,
+            name: "Test Character".to_string(),
+            owner_hash: "test-owner-hash".to_string(),
+        }
+    }
+}
+
+/// Local mock of EVE Online SSO, stopped when dropped
+///
+/// Every login is granted for the [`MockCharacter`] with the requested scopes, authorization codes can be exchanged
+/// once & refresh tokens are rotated on every refresh. Refreshes may request a subset of the granted scopes. PKCE code challenges are verified, the client secret isn't.
+///
+/// Access tokens are signed by [`TestTokenSigner::shared`], its key is generated once per process & takes a moment
+/// in debug builds.
+pub struct MockSso {
+    addr: SocketAddr,
+    state: Arc<MockState>,
+    shutdown: Arc<AtomicBool>,
+}
+
+impl MockSso {
+    /// Starts the mock on a random local port, logging in the default [`MockCharacter`]
+    pub fn start() -> io::Result<Self> {
+        Self::start_with(MockCharacter::default())
+    }
+
+    /// Starts the mock on a random local port, logging in the character
+    pub fn start_with(character: MockCharacter) -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(MockState {
+            url: format!("http://{}", addr),
+            character,
+            signer: TestTokenSigner::shared(),
+            codes: Mutex::new(HashMap::new()),
+            refresh_tokens: Mutex::new(HashMap::new()),
+        });
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let server_state = state.clone();
+        let server_shutdown = shutdown.clone();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if server_shutdown.load(Ordering::SeqCst) {
+                    break;
+                }
+
+                if let Ok(stream) = stream {
+                    let state = server_state.clone();
+
+                    thread::spawn(move || state.handle(stream));
+                }
+            }
+        });
+
+        Ok(Self {
+            addr,
+            state,
+            shutdown,
+        })
+    }
+
+    /// Base URL of the mock, e.g. `http://127.0.0.1:49152`
+    pub fn url(&self) -> &str {
+        &self.state.url
+    }
+
+    /// Endpoints of the mock, pass them to [`crate::EveSsoClientBuilder::endpoints`]
+    pub fn endpoints(&self) -> SsoEndpoints {
+        SsoEndpoints::from_metadata(&self.metadata()).expect("Invalid mock SSO URL")
+    }
+
+    /// Metadata served by the mock at `/.well-known/oauth-authorization-server`
+    pub fn metadata(&self) -> EveSsoMetaData {
+        self.state.metadata()
+    }
+
+    /// EVE JWT keys served by the mock at `/oauth/jwks`
+    pub fn jwt_keys(&self) -> EveJwtKeys {
+        self.state.signer.jwt_keys()
+    }
+
+    /// Signer of the mock's access tokens, e.g. to mint tokens with custom claims the mock's keys validate
+    pub fn signer(&self) -> &TestTokenSigner {
+        &self.state.signer
+    }
+
+    /// Creates a client using the endpoints of the mock with the client secret `secret`
+    #[cfg(feature = "reqwest")]
+    pub fn client(&self, client_id: impl Into<String>) -> crate::EveSsoClient {
+        crate::EveSsoClient::builder(client_id)
+            .client_secret("secret")
+            .endpoints(self.endpoints())
+            .build()
+    }
+
+    /// Logs in with the login URL as a user would & returns the callback EVE Online SSO redirects to
+    ///
+    /// Fails with [`Error::Sso`] if the login URL is invalid, e.g. misses the client_id or redirect_uri.
+    pub fn authorize(&self, login_url: &str) -> Result<CallbackResult, Error> {
+        let url =
+            Url::parse(login_url).map_err(|err| Error::UnexpectedResponse(err.to_string()))?;
+        let params = url.query_pairs().into_owned().collect();
+
+        match self.state.authorize(&params) {
+            Ok(callback_url) => parse_callback_url(&callback_url),
+            Err(response) => Err(Error::sso(
+                response.error.to_string(),
+                Some(response.description),
+            )),
+        }
+    }
+}
+
+impl Drop for MockSso {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+
+        // Wakes up the server thread waiting for a connection so it sees the shutdown
+        let _ = TcpStream::connect(self.addr);
+    }
+}
+
+struct MockState {
+    url: String,
+    character: MockCharacter,
+    signer: TestTokenSigner,
+    codes: Mutex<HashMap<String, Grant>>,
+    refresh_tokens: Mutex<HashMap<String, Grant>>,
+}
+
+#[derive(Clone)]
+struct Grant {
+    client_id: String,
+    scopes: Vec<String>,
+    code_challenge: Option<String>,
+}
+
+struct OAuthError {
+    error: &'static str,
+    description: String,
+}
+
+impl OAuthError {
+    fn new(error: &'static str, description: impl Into<String>) -> Self {
+        Self {
+            error,
+            description: description.into(),
+        }
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: HashMap<String, String>,
+    authorization: Option<String>,
+    form: HashMap<String, String>,
+}
+
+struct Response {
+    status: u16,
+    location: Option<String>,
+    body: String,
+}
+
+impl Response {
+    fn json(status: u16, body: serde_json::Value) -> Self {
+        Self {
+            status,
+            location: None,
+            body: body.to_string(),
+        }
+    }
+
+    fn error(status: u16, error: OAuthError) -> Self {
+        Self::json(
+            status,
+            json!({ "error": error.error, "error_description": error.description }),
+        )
+    }
+}
+
+impl MockState {
+    fn handle(&self, mut stream: TcpStream) {
+        let response = match read_request(&mut stream) {
+            Ok(request) => self.route(request),
+            Err(_) => return,
+        };
+
+        let reason = match response.status {
+            200 => "OK",
+            302 => "Found",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            _ => "Not Found",
+        };
+        let location = response
+            .location
+            .map(|location| format!("Location: {}\r\n", location))
+            .unwrap_or_default();
+
+        let _ = write!(
+            stream,
+            "HTTP/1.1 {} {}\r\n{}Content-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            response.status,
+            reason,
+            location,
+            response.body.len(),
+            response.body
+        );
+    }
+
+    fn route(&self, request: Request) -> Response {
+        match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/.well-known/oauth-authorization-server") => Response::json(
+                200,
+                serde_json::to_value(self.metadata()).unwrap_or_default(),
+            ),
+            ("GET", "/oauth/jwks") => Response::json(
+                200,
+                serde_json::to_value(self.signer.jwt_keys()).unwrap_or_default(),
+            ),
+            ("GET", "/v2/oauth/authorize") => match self.authorize(&request.query) {
+                Ok(callback_url) => Response {
+                    status: 302,
+                    location: Some(callback_url),
+                    body: String::new(),
+                },
+                Err(error) => Response::error(400, error),
+            },
+            ("POST", "/v2/oauth/token") => match self.token(&request) {
+                Ok(token) => Response::json(200, token),
+                Err(
+                    error @ OAuthError {
+                        error: "invalid_client",
+                        ..
+                    },
+                ) => Response::error(401, error),
+                Err(error) => Response::error(400, error),
+            },
+            ("POST", "/v2/oauth/revoke") => {
+                if let Some(token) = request.form.get("token") {
+                    self.refresh_tokens.lock().unwrap().remove(token);
+                }
+
+                Response::json(200, json!({}))
+            }
+            _ => Response::json(404, json!({ "error": "not_found" })),
+        }
+    }
+
+    fn metadata(&self) -> EveSsoMetaData {
+        EveSsoMetaData {
+            authorization_endpoint: format!("{}/v2/oauth/authorize", self.url),
+            code_challenge_methods_supported: vec!["S256".to_string()],
+            issuer: Some(ISSUER.to_string()),
+            jwks_uri: format!("{}/oauth/jwks", self.url),
+            response_types_supported: vec!["code".to_string()],
+            revocation_endpoint: Some(format!("{}/v2/oauth/revoke", self.url)),
+            revocation_endpoint_auth_methods_supported: vec![
+                "client_secret_basic".to_string(),
+                "client_secret_post".to_string(),
+            ],
+            token_endpoint: format!("{}/v2/oauth/token", self.url),
+            token_endpoint_auth_methods_supported: vec![
+                "client_secret_basic".to_string(),
+                "client_secret_post".to_string(),
+            ],
+            token_endpoint_auth_signing_alg_values_supported: vec!["RS256".to_string()],
+        }
+    }
+
+    /// Grants the login & returns the callback URL with the authorization code
+    fn authorize(&self, params: &HashMap<String, String>) -> Result<String, OAuthError> {
+        if params.get("response_type").map(String::as_str) != Some("code") {
+            return Err(OAuthError::new(
+                "unsupported_response_type",
+                "response_type must be code",
+            ));
+        }
+
+        let client_id = params
+            .get("client_id")
+            .ok_or_else(|| OAuthError::new("invalid_request", "Missing client_id"))?;
+        let redirect_uri = params
+            .get("redirect_uri")
+            .ok_or_else(|| OAuthError::new("invalid_request", "Missing redirect_uri"))?;
+        let mut callback_url = crate::validate_redirect_url(redirect_uri)
+            .map_err(|_| OAuthError::new("invalid_request", "Invalid redirect_uri"))?;
+
+        let code_challenge = match params.get("code_challenge_method").map(String::as_str) {
+            None | Some("S256") => params.get("code_challenge").cloned(),
+            Some(_) => {
+                return Err(OAuthError::new(
+                    "invalid_request",
+                    "Only the S256 code challenge method is supported",
+                ))
+            }
+        };
+
+        let code = random_string();
+
+        self.codes.lock().unwrap().insert(
+            code.clone(),
+            Grant {
+                client_id: client_id.clone(),
+                scopes: params
+                    .get("scope")
+                    .map(|scopes| scopes.split_whitespace().map(String::from).collect())
+                    .unwrap_or_default(),
+                code_challenge,
+            },
+        );
+
+        {
+            let mut query = callback_url.query_pairs_mut();
+            query.append_pair("code", &code);
+
+            if let Some(state) = params.get("state") {
+                query.append_pair("state", state);
+            }
+        }
+
+        Ok(callback_url.to_string())
+    }
+
+    fn token(&self, request: &Request) -> Result<serde_json::Value, OAuthError> {
+        let (client_id, client_secret) = client_credentials(request)?;
+
+        // Scopes of the access token, refreshes may request a subset of the granted scopes
+        let mut access_scopes = None;
+
+        let grant = match request.form.get("grant_type").map(String::as_str) {
+            Some("authorization_code") => {
+                let code = request
+                    .form
+                    .get("code")
+                    .ok_or_else(|| OAuthError::new("invalid_request", "Missing code"))?;
+                let grant = self.codes.lock().unwrap().remove(code).ok_or_else(|| {
+                    OAuthError::new("invalid_grant", "Invalid authorization code")
+                })?;
+
+                match (&grant.code_challenge, request.form.get("code_verifier")) {
+                    (Some(challenge), Some(verifier))
+                        if URL_SAFE_NO_PAD.encode(Sha256::digest(verifier)) == *challenge => {}
+                    (Some(_), _) => {
+                        return Err(OAuthError::new("invalid_grant", "Invalid code verifier"))
+                    }
+                    (None, _) if client_secret.is_none() => {
+                        return Err(OAuthError::new("invalid_client", "Missing client secret"))
+                    }
+                    (None, _) => {}
+                }
+
+                grant
+            }
+            Some("refresh_token") => {
+                let refresh_token = request
+                    .form
+                    .get("refresh_token")
+                    .ok_or_else(|| OAuthError::new("invalid_request", "Missing refresh_token"))?;
+
+                let mut refresh_tokens = self.refresh_tokens.lock().unwrap();
+                let grant = refresh_tokens
+                    .get(refresh_token)
+                    .ok_or_else(|| OAuthError::new("invalid_grant", "Invalid refresh token"))?;
+
+                if let Some(scope) = request.form.get("scope") {
+                    let scopes: Vec<String> = scope.split_whitespace().map(String::from).collect();
+
+                    if scopes.iter().any(|scope| !grant.scopes.contains(scope)) {
+                        return Err(OAuthError::new(
+                            "invalid_scope",
+                            "The requested scope exceeds the granted scopes",
+                        ));
+                    }
+
+                    access_scopes = Some(scopes);
+                }
+
+                refresh_tokens
+                    .remove(refresh_token)
+                    .expect("Refresh token was checked")
+            }
+            _ => {
+                return Err(OAuthError::new(
+                    "unsupported_grant_type",
+                    "grant_type must be authorization_code or refresh_token",
+                ))
+            }
+        };
+
+        if grant.client_id != client_id {
+            return Err(OAuthError::new(
+                "invalid_grant",
+                "The grant was issued to another client",
+            ));
+        }
+
+        let access_token = self.signer.sign(&character_claims(
+            &self.character,
+            &grant.client_id,
+            access_scopes.unwrap_or_else(|| grant.scopes.clone()),
+        ));
+        let refresh_token = random_string();
+
+        self.refresh_tokens
+            .lock()
+            .unwrap()
+            .insert(refresh_token.clone(), grant);
+
+        Ok(json!({
+            "access_token": access_token,
+            "expires_in": ACCESS_TOKEN_LIFETIME,
+            "token_type": "Bearer",
+            "refresh_token": refresh_token,
+        }))
+    }
+}
+
+/// Signs EVE JWTs with a local RSA key, validate them with [`crate::validate_token_with_keys`] & its
+/// [`TestTokenSigner::jwt_keys`] without sending any requests
+///
+/// ```no_run
+/// use eve_oauth2::testing::TestTokenSigner;
+/// use eve_oauth2::validation::TokenValidation;
+///
+/// let signer = TestTokenSigner::shared();
+/// let token = signer
+///     .token()
+///     .character_id(2114794365)
+///     .scopes(["esi-wallet.read_character_wallet.v1"])
+///     .sign();
+///
+/// let token_data =
+///     eve_oauth2::validate_token_with_keys(&token, &signer.jwt_keys(), &TokenValidation::default())?;
+/// assert_eq!(token_data.claims.character_id()?.0, 2114794365);
+/// # Ok::<(), eve_oauth2::Error>(())
+/// ```
+#[derive(Clone)]
+pub struct TestTokenSigner {
+    encoding_key: EncodingKey,
+    n: String,
+    e: String,
+}
+
+impl TestTokenSigner {
+    /// Generates a new 2048 bit RSA key, which takes a moment in debug builds
+    pub fn generate() -> Self {
+        let key = RsaPrivateKey::new(&mut rand::thread_rng(), 2048)
+            .expect("Failed to generate the test RSA key");
+        let der = key
+            .to_pkcs1_der()
+            .expect("Failed to encode the test RSA key");
+
+        Self {
+            encoding_key: EncodingKey::from_rsa_der(der.as_bytes()),
+            n: URL_SAFE_NO_PAD.encode(key.n().to_bytes_be()),
+            e: URL_SAFE_NO_PAD.encode(key.e().to_bytes_be()),
+        }
+    }
+
+    /// Signer generated once per process, which also signs the tokens of every [`MockSso`]
+    pub fn shared() -> Self {
+        SHARED_SIGNER.get_or_init(Self::generate).clone()
+    }
+
+    /// EVE JWT keys to validate the signed tokens with
+    pub fn jwt_keys(&self) -> EveJwtKeys {
+        EveJwtKeys {
+            skip_unresolved_json_web_keys: true,
+            keys: vec![EveJwtKey::RS256 {
+                e: self.e.clone(),
+                kid: KEY_ID.to_string(),
+                kty: "RSA".to_string(),
+                n: self.n.clone(),
+                r#use: "sig".to_string(),
+            }],
+        }
+    }
+
+    /// Creates a token with the defaults of [`EveJwtClaims::test`]
+    pub fn token(&self) -> TestToken<'_> {
+        TestToken {
+            signer: self,
+            claims: EveJwtClaims::test(),
+        }
+    }
+
+    /// Signs the claims as they are
+    pub fn sign(&self, claims: &EveJwtClaims) -> String {
+        let mut header = Header::new(jsonwebtoken::Algorithm::RS256);
+        header.kid = Some(KEY_ID.to_string());
+
+        jsonwebtoken::encode(&header, claims, &self.encoding_key)
+            .expect("Failed to sign the test token")
+    }
+}
+
+/// Token signed by a [`TestTokenSigner`], created with [`TestTokenSigner::token`]
+///
+/// The setters are those of [`EveJwtClaimsBuilder`].
+#[must_use]
+pub struct TestToken<'a> {
+    signer: &'a TestTokenSigner,
+    claims: EveJwtClaimsBuilder,
+}
+
+impl TestToken<'_> {
+    /// See [`EveJwtClaimsBuilder::character_id`]
+    pub fn character_id(mut self, character_id: u64) -> Self {
+        self.claims = self.claims.character_id(character_id);
+        self
+    }
+
+    /// See [`EveJwtClaimsBuilder::subject`]
+    pub fn subject(mut self, sub: impl Into<String>) -> Self {
+        self.claims = self.claims.subject(sub);
+        self
+    }
+
+    /// See [`EveJwtClaimsBuilder::name`]
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.claims = self.claims.name(name);
+        self
+    }
+
+    /// See [`EveJwtClaimsBuilder::owner`]
+    pub fn owner(mut self, owner_hash: impl Into<String>) -> Self {
+        self.claims = self.claims.owner(owner_hash);
+        self
+    }
+
+    /// See [`EveJwtClaimsBuilder::scopes`]
+    pub fn scopes(mut self, scopes: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.claims = self.claims.scopes(scopes);
+        self
+    }
+
+    /// See [`EveJwtClaimsBuilder::client_id`]
+    pub fn client_id(mut self, client_id: impl Into<String>) -> Self {
+        self.claims = self.claims.client_id(client_id);
+        self
+    }
+
+    /// See [`EveJwtClaimsBuilder::server`]
+    pub fn server(mut self, server: EveServer) -> Self {
+        self.claims = self.claims.server(server);
+        self
+    }
+
+    /// See [`EveJwtClaimsBuilder::expires_at`]
+    pub fn expires_at(mut self, exp: u64) -> Self {
+        self.claims = self.claims.expires_at(exp);
+        self
+    }
+
+    /// See [`EveJwtClaimsBuilder::expires_in`]
+    pub fn expires_in(mut self, duration: Duration) -> Self {
+        self.claims = self.claims.expires_in(duration);
+        self
+    }
+
+    /// See [`EveJwtClaimsBuilder::claim`]
+    pub fn claim(mut self, name: impl Into<String>, value: impl Into<serde_json::Value>) -> Self {
+        self.claims = self.claims.claim(name, value);
+        self
+    }
+
+    /// Claims which will be signed
+    pub fn claims(&self) -> EveJwtClaims {
+        self.claims.clone().build()
+    }
+
+    /// Signs the token
+    pub fn sign(self) -> String {
+        self.signer.sign(&self.claims.build())
+    }
+}
+
+fn character_claims(
+    character: &MockCharacter,
+    client_id: &str,
+    scopes: Vec<String>,
+) -> EveJwtClaims {
+    EveJwtClaims::test()
+        .character_id(character.id)
+        .name(character.name.clone())
+        .owner(character.owner_hash.clone())
+        .client_id(client_id)
+        .scopes(scopes)
+        .expires_in(Duration::from_secs(ACCESS_TOKEN_LIFETIME))
+        .build()
+}
+
+fn random_string() -> String {
+    Alphanumeric.sample_string(&mut rand::thread_rng(), 32)
+}
+
+/// Returns the client_id & client_secret from HTTP Basic authentication or the request body
+fn client_credentials(request: &Request) -> Result<(String, Option<String>), OAuthError> {
+    if let Some(credentials) = request
+        .authorization
+        .as_deref()
+        .and_then(|authorization| authorization.strip_prefix("Basic "))
+    {
+        let credentials = STANDARD
+            .decode(credentials)
+            .ok()
+            .and_then(|credentials| String::from_utf8(credentials).ok())
+            .ok_or_else(|| OAuthError::new("invalid_client", "Invalid Authorization header"))?;
+        let (client_id, client_secret) = credentials.split_once(':').unwrap_or((&credentials, ""));
+
+        return Ok((
+            decode_component(client_id),
+            Some(decode_component(client_secret)),
+        ));
+    }
+
+    let client_id = request
+        .form
+        .get("client_id")
+        .ok_or_else(|| OAuthError::new("invalid_client", "Missing client credentials"))?;
+
+    Ok((
+        client_id.clone(),
+        request.form.get("client_secret").cloned(),
+    ))
+}
+
+/// Decodes a form-urlencoded client credential of HTTP Basic authentication
+fn decode_component(component: &str) -> String {
+    url::form_urlencoded::parse(format!("c={}", component).as_bytes())
+        .next()
+        .map(|(_, value)| value.into_owned())
+        .unwrap_or_default()
+}
+
+fn read_request(stream: &mut TcpStream) -> io::Result<Request> {
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default();
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    let mut authorization = None;
+    let mut content_length = 0;
+
+    loop {
+        let mut line = String::new();
+
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            match name.trim().to_ascii_lowercase().as_str() {
+                "authorization" => authorization = Some(value.trim().to_string()),
+                "content-length" => content_length = value.trim().parse().unwrap_or_default(),
+                _ => {}
+            }
+        }
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    Ok(Request {
+        method,
+        path: path.to_string(),
+        query: url::form_urlencoded::parse(query.as_bytes())
+            .into_owned()
+            .collect(),
+        authorization,
+        form: url::form_urlencoded::parse(&body).into_owned().collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validation::TokenValidation;
+    #[cfg(feature = "reqwest")]
+    use crate::EveSsoClient;
+
+    #[cfg(feature = "reqwest")]
+    const REDIRECT_URL: &str = "http://localhost:8080/callback";
+
+    #[cfg(feature = "reqwest")]
+    fn sso_error(result: Result<impl std::fmt::Debug, Error>) -> String {
+        match result {
+            Err(Error::Sso { error, .. }) => error,
+            result => panic!("Expected an SSO error, got {:?}", result),
+        }
+    }
+
+    #[cfg(feature = "reqwest")]
+    #[tokio::test]
+    async fn logs_in_the_mock_character_with_the_requested_scopes() {
+        let sso = MockSso::start_with(MockCharacter {
+            id: 2114794365,
+            name: "Other Character".to_string(),
+            owner_hash: "other-owner-hash".to_string(),
+        })
+        .unwrap();
+        let client = sso.client("client_id");
+
+        let auth = client.create_login_url(REDIRECT_URL, ["publicData"]);
+        let (code, state) = sso
+            .authorize(&auth.login_url)
+            .unwrap()
+            .into_result()
+            .unwrap();
+        assert_eq!(state, auth.state);
+
+        let token = client.get_access_token(code.clone()).await.unwrap();
+        let claims = client
+            .validate_token(token.access_token())
+            .await
+            .unwrap()
+            .claims;
+        assert_eq!(claims.character_id().unwrap().0, 2114794365);
+        assert_eq!(claims.name, "Other Character");
+        assert_eq!(claims.owner, "other-owner-hash");
+        assert_eq!(claims.scopes().to_vec(), ["publicData"]);
+
+        // Codes can only be exchanged once
+        assert_eq!(
+            sso_error(client.get_access_token(code).await),
+            "invalid_grant"
+        );
+    }
+
+    #[cfg(feature = "reqwest")]
+    #[tokio::test]
+    async fn rotates_refresh_tokens() {
+        let sso = MockSso::start().unwrap();
+        let client = sso.client("client_id");
+
+        let auth = client.create_login_url(REDIRECT_URL, ["publicData"]);
+        let (code, _) = sso
+            .authorize(&auth.login_url)
+            .unwrap()
+            .into_result()
+            .unwrap();
+        let token = client.get_access_token(code).await.unwrap();
+        let refresh_token = token.refresh_token().unwrap().to_string();
+
+        let refreshed = client.refresh(refresh_token.clone()).await.unwrap();
+        assert_ne!(refreshed.refresh_token(), Some(refresh_token.as_str()));
+        assert_eq!(
+            sso_error(client.refresh(refresh_token).await),
+            "invalid_grant"
+        );
+    }
+
+    #[cfg(feature = "reqwest")]
+    #[tokio::test]
+    async fn verifies_pkce_code_verifiers() {
+        let sso = MockSso::start().unwrap();
+        let client = EveSsoClient::builder("client_id")
+            .endpoints(sso.endpoints())
+            .build();
+
+        let auth = client.create_login_url_pkce(REDIRECT_URL, ["publicData"]);
+        let (code, _) = sso
+            .authorize(&auth.login_url)
+            .unwrap()
+            .into_result()
+            .unwrap();
+        assert_eq!(
+            sso_error(
+                client
+                    .get_access_token_pkce(code, "wrong code verifier".to_string())
+                    .await
+            ),
+            "invalid_grant"
+        );
+    }
+
+    #[cfg(feature = "reqwest")]
+    #[test]
+    fn rejects_invalid_login_urls() {
+        let sso = MockSso::start().unwrap();
+
+        let login_url = format!("{}/v2/oauth/authorize?response_type=code", sso.url());
+        assert_eq!(sso_error(sso.authorize(&login_url)), "invalid_request");
+    }
+
+    #[test]
+    fn signed_tokens_validate_with_the_keys_of_the_signer() {
+        let signer = TestTokenSigner::shared();
+        let token = signer
+            .token()
+            .character_id(2114794365)
+            .scopes(["publicData"])
+            .claim("custom", "value")
+            .sign();
+
+        let claims = crate::validate_token_with_keys(
+            &token,
+            &signer.jwt_keys(),
+            &TokenValidation::default(),
+        )
+        .unwrap()
+        .claims;
+        assert_eq!(claims.character_id().unwrap().0, 2114794365);
+        assert_eq!(claims.scopes().to_vec(), ["publicData"]);
+        assert_eq!(claims.extra_claim("custom"), Some(&json!("value")));
+
+        let header = jsonwebtoken::decode_header(&token).unwrap();
+        assert_eq!(header.kid.as_deref(), Some(KEY_ID));
+        assert_eq!(header.alg, jsonwebtoken::Algorithm::RS256);
+    }
+
+    #[test]
+    fn tokens_of_other_signers_fail_validation() {
+        let token = TestTokenSigner::generate().token().sign();
+
+        assert!(matches!(
+            crate::validate_token_with_keys(
+                &token,
+                &TestTokenSigner::shared().jwt_keys(),
+                &TokenValidation::default()
+            ),
+            Err(Error::InvalidToken(_))
+        ));
+    }
+
+    #[test]
+    fn sign_uses_the_claims_as_they_are() {
+        let signer = TestTokenSigner::shared();
+        let claims = signer.token().expires_at(1_700_000_000).claims();
+
+        let token = signer.sign(&claims);
+        let decoded = crate::decode_unverified(&token).unwrap().claims;
+        assert_eq!(decoded.jti, claims.jti);
+        assert_eq!(decoded.exp, 1_700_000_000);
+        assert!(crate::validate_token_with_keys(
+            &token,
+            &signer.jwt_keys(),
+            &TokenValidation::default()
+        )
+        .is_err());
+    }
+}
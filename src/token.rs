@@ -0,0 +1,244 @@
+use std::fmt;
+use std::time::Duration;
+
+use oauth2::basic::BasicTokenResponse;
+use oauth2::TokenResponse;
+use web_time::SystemTime;
+
+#[cfg(any(feature = "time", feature = "chrono"))]
+use crate::datetime;
+use crate::secret::{redact, Redacted, SecretString};
+
+/// Token response returned by EVE Online SSO which records when the token was obtained
+///
+/// Its `Debug` output redacts the access & refresh token.
+#[derive(Clone)]
+pub struct EveTokenResponse {
+    access_token: SecretString,
+    refresh_token: Option<SecretString>,
+    expires_in: Option<Duration>,
+    scopes: Option<Vec<String>>,
+    obtained_at: SystemTime,
+}
+
+impl fmt::Debug for EveTokenResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EveTokenResponse")
+            .field("access_token", &Redacted)
+            .field("refresh_token", &redact(&self.refresh_token))
+            .field("expires_in", &self.expires_in)
+            .field("scopes", &self.scopes)
+            .field("obtained_at", &self.obtained_at)
+            .finish()
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl zeroize::ZeroizeOnDrop for EveTokenResponse {}
+
+impl EveTokenResponse {
+    /// Creates a token response which was obtained just now, e.g. to test code handling token responses
+    pub fn new(
+        access_token: impl Into<String>,
+        refresh_token: Option<String>,
+        expires_in: Option<Duration>,
+    ) -> Self {
+        Self {
+            access_token: SecretString::new(access_token),
+            refresh_token: refresh_token.map(SecretString::from),
+            expires_in,
+            scopes: None,
+            obtained_at: SystemTime::now(),
+        }
+    }
+
+    /// Sets the time the token response was obtained
+    pub fn with_obtained_at(mut self, obtained_at: SystemTime) -> Self {
+        self.obtained_at = obtained_at;
+        self
+    }
+
+    /// Sets the scopes returned alongside the token
+    pub fn with_scopes(mut self, scopes: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.scopes = Some(scopes.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// The access token, a JWT which can be validated with [`crate::validate_token`]
+    pub fn access_token(&self) -> &str {
+        self.access_token.expose_secret()
+    }
+
+    /// The refresh token, EVE Online SSO rotates refresh tokens so store it in place of the previous one
+    pub fn refresh_token(&self) -> Option<&str> {
+        self.refresh_token.as_ref().map(SecretString::expose_secret)
+    }
+
+    /// Lifetime of the access token, `None` if EVE Online SSO didn't return an `expires_in`
+    pub fn expires_in(&self) -> Option<Duration> {
+        self.expires_in
+    }
+
+    /// Scopes returned alongside the token, `None` if EVE Online SSO didn't return them
+    pub fn scopes(&self) -> Option<&[String]> {
+        self.scopes.as_deref()
+    }
+
+    /// Time the token was obtained from EVE Online SSO
+    pub fn obtained_at(&self) -> SystemTime {
+        self.obtained_at
+    }
+
+    /// Time the access token expires, `None` if EVE Online SSO didn't return an `expires_in`
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        self.expires_in
+            .map(|expires_in| self.obtained_at + expires_in)
+    }
+
+    /// Time the token was obtained from EVE Online SSO, enabled with the `time` feature
+    #[cfg(feature = "time")]
+    pub fn obtained_at_time(&self) -> time::OffsetDateTime {
+        datetime::offset_date_time(datetime::since_epoch(self.obtained_at))
+    }
+
+    /// Time the access token expires, enabled with the `time` feature
+    #[cfg(feature = "time")]
+    pub fn expires_at_time(&self) -> Option<time::OffsetDateTime> {
+        self.expires_at()
+            .map(|expires_at| datetime::offset_date_time(datetime::since_epoch(expires_at)))
+    }
+
+    /// Time the token was obtained from EVE Online SSO, enabled with the `chrono` feature
+    #[cfg(feature = "chrono")]
+    pub fn obtained_at_chrono(&self) -> chrono::DateTime<chrono::Utc> {
+        datetime::date_time_utc(datetime::since_epoch(self.obtained_at))
+    }
+
+    /// Time the access token expires, enabled with the `chrono` feature
+    #[cfg(feature = "chrono")]
+    pub fn expires_at_chrono(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.expires_at()
+            .map(|expires_at| datetime::date_time_utc(datetime::since_epoch(expires_at)))
+    }
+
+    /// Whether the access token has expired
+    pub fn is_expired(&self) -> bool {
+        self.needs_refresh(Duration::ZERO)
+    }
+
+    /// Whether the access token expires within the provided margin & should be refreshed
+    pub fn needs_refresh(&self, margin: Duration) -> bool {
+        match self.expires_at() {
+            Some(expires_at) => SystemTime::now() + margin >= expires_at,
+            None => false,
+        }
+    }
+
+    /// Converts the `oauth2` token response, keeping `oauth2` types out of the public API
+    pub(crate) fn from_oauth2(token: BasicTokenResponse) -> Self {
+        Self {
+            access_token: token.access_token().secret().as_str().into(),
+            refresh_token: token
+                .refresh_token()
+                .map(|refresh_token| refresh_token.secret().as_str().into()),
+            expires_in: token.expires_in(),
+            scopes: token
+                .scopes()
+                .map(|scopes| scopes.iter().map(|scope| scope.to_string()).collect()),
+            obtained_at: SystemTime::now(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expiry_is_relative_to_when_the_token_was_obtained() {
+        let obtained_at = SystemTime::now() - Duration::from_secs(1000);
+        let token = EveTokenResponse::new("access_token", None, Some(Duration::from_secs(1199)))
+            .with_obtained_at(obtained_at);
+
+        assert_eq!(
+            token.expires_at(),
+            Some(obtained_at + Duration::from_secs(1199))
+        );
+        assert!(!token.is_expired());
+        assert!(!token.needs_refresh(Duration::from_secs(60)));
+        assert!(token.needs_refresh(Duration::from_secs(300)));
+
+        let token = token.with_obtained_at(obtained_at - Duration::from_secs(200));
+        assert!(token.is_expired());
+    }
+
+    #[cfg(all(feature = "time", feature = "chrono"))]
+    #[test]
+    fn timestamps_convert_to_time_and_chrono() {
+        let obtained_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let token = EveTokenResponse::new("access_token", None, Some(Duration::from_secs(1199)))
+            .with_obtained_at(obtained_at);
+
+        assert_eq!(token.obtained_at_time().unix_timestamp(), 1_700_000_000);
+        assert_eq!(
+            token.expires_at_time().map(|time| time.unix_timestamp()),
+            Some(1_700_001_199)
+        );
+        assert_eq!(token.obtained_at_chrono().timestamp(), 1_700_000_000);
+        assert_eq!(
+            token.expires_at_chrono().map(|time| time.timestamp()),
+            Some(1_700_001_199)
+        );
+    }
+
+    #[test]
+    fn tokens_without_expires_in_never_expire() {
+        let token = EveTokenResponse::new("access_token", None, None)
+            .with_obtained_at(SystemTime::UNIX_EPOCH);
+
+        assert_eq!(token.expires_at(), None);
+        assert!(!token.is_expired());
+        assert!(!token.needs_refresh(Duration::from_secs(300)));
+    }
+
+    #[test]
+    fn converts_oauth2_token_responses() {
+        let token: BasicTokenResponse = serde_json::from_str(
+            r#"{
+                "access_token": "access_token",
+                "token_type": "Bearer",
+                "expires_in": 1199,
+                "refresh_token": "refresh_token",
+                "scope": "publicData esi-wallet.read_character_wallet.v1"
+            }"#,
+        )
+        .unwrap();
+
+        let token = EveTokenResponse::from_oauth2(token);
+
+        assert_eq!(token.access_token(), "access_token");
+        assert_eq!(token.refresh_token(), Some("refresh_token"));
+        assert_eq!(token.expires_in(), Some(Duration::from_secs(1199)));
+        assert_eq!(
+            token.scopes().unwrap(),
+            ["publicData", "esi-wallet.read_character_wallet.v1"]
+        );
+        assert!(token.obtained_at().elapsed().unwrap() < Duration::from_secs(60));
+    }
+
+    #[test]
+    fn debug_redacts_the_tokens() {
+        let token = EveTokenResponse::new(
+            "secret_access_token",
+            Some("secret_refresh_token".to_string()),
+            None,
+        )
+        .with_scopes(["publicData"]);
+
+        let debug = format!("{:?}", token);
+
+        assert!(!debug.contains("secret_access_token"));
+        assert!(!debug.contains("secret_refresh_token"));
+        assert!(debug.contains("publicData"));
+    }
+}
@@ -0,0 +1,522 @@
+//! Framework-agnostic tower middleware, enabled with the `tower` feature
+//!
+//! [`AuthenticateEsi`] authenticates outgoing ESI requests of tower-based HTTP clients. [`RequireEveAuth`] &
+//! [`RequireScopes`] authenticate incoming requests.
+//!
+//! [`RequireEveAuth`] is meant for services accepting EVE JWTs issued to other applications, e.g. from a frontend or another service, without
+//! running the login flow themselves. Add [`RequireScopes`] inside [`RequireEveAuth`] to require scopes of some routes.
+//!
+//! ```no_run
+//! use axum::{routing::get, Extension, Router};
+//! use eve_oauth2::models::EveJwtClaims;
+//! use eve_oauth2::tower::{RequireEveAuth, RequireScopes};
+//! use eve_oauth2::validation::TokenValidation;
+//! use eve_oauth2::EveSsoClient;
+//!
+//! async fn character(Extension(claims): Extension<EveJwtClaims>) -> String {
+//!     claims.name
+//! }
+//!
+//! let client = EveSsoClient::new("client_id".to_string(), None);
+//! let app: Router = Router::new()
+//!     .route("/wallet", get(character))
+//!     .route_layer(RequireScopes::new(["esi-wallet.read_character_wallet.v1"]))
+//!     .route("/character", get(character))
+//!     .layer(RequireEveAuth::new(client).validation(TokenValidation::new().allow_any_client_id()));
+//! ```
+
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::error::{Error, ErrorClass};
+use crate::http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use crate::http::{self, BoxFuture, HeaderValue, StatusCode};
+use crate::models::{CharacterId, EveJwtClaims};
+use crate::scopes::ScopeSet;
+use crate::store::TokenStore;
+use crate::validation::TokenValidation;
+use crate::{EveSsoClient, TokenManager, ESI_HOST};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Layer validating the bearer token of the Authorization header of every request with the client
+///
+/// The validated [`crate::models::EveJwtClaims`] are inserted into the request's extensions. Requests without a
+/// valid token are answered with an empty 401 Unauthorized response, 403 Forbidden if the token is missing required
+/// scopes & 503 Service Unavailable if EVE Online SSO couldn't be reached to retrieve the EVE JWT keys.
+#[derive(Clone)]
+pub struct RequireEveAuth {
+    client: EveSsoClient,
+    validation: Arc<TokenValidation>,
+}
+
+impl RequireEveAuth {
+    /// Creates the layer validating tokens with the client & its default validation options
+    pub fn new(client: EveSsoClient) -> Self {
+        Self {
+            client,
+            validation: Arc::new(TokenValidation::default()),
+        }
+    }
+
+    /// Sets the validation options, e.g. to require scopes or accept tokens issued to any EVE application
+    pub fn validation(mut self, validation: TokenValidation) -> Self {
+        self.validation = Arc::new(validation);
+        self
+    }
+}
+
+impl<S> Layer<S> for RequireEveAuth {
+    type Service = RequireEveAuthService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequireEveAuthService {
+            inner,
+            client: self.client.clone(),
+            validation: self.validation.clone(),
+        }
+    }
+}
+
+/// Service created by [`RequireEveAuth`]
+#[derive(Clone)]
+pub struct RequireEveAuthService<S> {
+    inner: S,
+    client: EveSsoClient,
+    validation: Arc<TokenValidation>,
+}
+
+impl<S, ReqBody, ResBody> Service<::http::Request<ReqBody>> for RequireEveAuthService<S>
+where
+    S: Service<::http::Request<ReqBody>, Response = ::http::Response<ResBody>>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send,
+    ReqBody: Send + 'static,
+    ResBody: Default,
+{
+    type Response = ::http::Response<ResBody>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: ::http::Request<ReqBody>) -> Self::Future {
+        // Use the service which was polled ready & leave the clone for the next request
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        let client = self.client.clone();
+        let validation = self.validation.clone();
+        let token = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(http::bearer_token)
+            .map(str::to_string);
+
+        Box::pin(async move {
+            let Some(token) = token else {
+                return Ok(error_response(StatusCode::UNAUTHORIZED));
+            };
+
+            match client.validate_token_with(token, &validation).await {
+                Ok(token_data) => {
+                    req.extensions_mut()
+                        .insert(ValidatedClaims(token_data.claims.clone()));
+                    req.extensions_mut().insert(token_data.claims);
+
+                    inner.call(req).await
+                }
+                Err(Error::MissingScopes(missing_scopes)) => Ok(forbidden(&missing_scopes)),
+                Err(err) => match err.classify() {
+                    ErrorClass::Transient | ErrorClass::RateLimited => {
+                        Ok(error_response(StatusCode::SERVICE_UNAVAILABLE))
+                    }
+                    _ => Ok(error_response(StatusCode::UNAUTHORIZED)),
+                },
+            }
+        })
+    }
+}
+
+/// Claims validated by [`RequireEveAuth`], other layers can't insert this type into the request's extensions unlike
+/// [`EveJwtClaims`] so extractors only reuse claims validated by this crate
+#[derive(Debug, Clone)]
+pub(crate) struct ValidatedClaims(pub(crate) EveJwtClaims);
+
+/// Layer rejecting requests whose token doesn't grant the scopes with an empty 403 Forbidden response
+///
+/// Relies on the claims inserted into the request's extensions by [`RequireEveAuth`], so add it inside that layer.
+/// Requests without claims are answered with 401 Unauthorized. The missing scopes are listed in the
+/// `WWW-Authenticate` header as described by RFC 6750.
+#[derive(Debug, Clone)]
+pub struct RequireScopes {
+    scopes: Arc<ScopeSet>,
+}
+
+impl RequireScopes {
+    /// Creates the layer requiring the scopes, accepts a [`ScopeSet`] or any list of scopes
+    pub fn new(scopes: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            scopes: Arc::new(scopes.into_iter().collect()),
+        }
+    }
+}
+
+impl<S> Layer<S> for RequireScopes {
+    type Service = RequireScopesService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequireScopesService {
+            inner,
+            scopes: self.scopes.clone(),
+        }
+    }
+}
+
+/// Service created by [`RequireScopes`]
+#[derive(Debug, Clone)]
+pub struct RequireScopesService<S> {
+    inner: S,
+    scopes: Arc<ScopeSet>,
+}
+
+impl<S, ReqBody, ResBody> Service<::http::Request<ReqBody>> for RequireScopesService<S>
+where
+    S: Service<::http::Request<ReqBody>, Response = ::http::Response<ResBody>>,
+    S::Future: Send + 'static,
+    ResBody: Default,
+{
+    type Response = ::http::Response<ResBody>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ::http::Request<ReqBody>) -> Self::Future {
+        let Some(claims) = req.extensions().get::<EveJwtClaims>() else {
+            return Box::pin(async { Ok(error_response(StatusCode::UNAUTHORIZED)) });
+        };
+
+        let missing_scopes = self.scopes.difference(&claims.scopes());
+
+        if !missing_scopes.is_empty() {
+            let missing_scopes = missing_scopes.to_vec();
+
+            return Box::pin(async move { Ok(forbidden(&missing_scopes)) });
+        }
+
+        Box::pin(self.inner.call(req))
+    }
+}
+
+/// Layer adding a valid access token of the character to outgoing requests to ESI, e.g. of a hyper client
+///
+/// The token is taken from the [`TokenManager`] for every request, which refreshes it when it's near expiry. When ESI
+/// rejects a token with 401 Unauthorized it's refreshed with [`TokenManager::refresh_rejected_token`] so following
+/// requests use a new token, the rejected request isn't retried as request bodies can't be cloned. Insert a
+/// [`CharacterId`] into the extensions of a request to send it on behalf of another character.
+///
+/// Only https requests to `esi.evetech.net` are authenticated so tokens aren't leaked to other hosts or sent in
+/// cleartext, use [`AuthenticateEsi::hosts`] to authenticate requests to other hosts such as a proxy in front of ESI.
+pub struct AuthenticateEsi<T> {
+    manager: TokenManager<T>,
+    character_id: u64,
+    hosts: Arc<Vec<String>>,
+}
+
+impl<T: TokenStore> AuthenticateEsi<T> {
+    /// Creates the layer authenticating requests as the character
+    pub fn new(manager: TokenManager<T>, character_id: u64) -> Self {
+        Self {
+            manager,
+            character_id,
+            hosts: Arc::new(vec![ESI_HOST.to_string()]),
+        }
+    }
+
+    /// Sets the hosts requests are authenticated for, replacing `esi.evetech.net`
+    pub fn hosts(mut self, hosts: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.hosts = Arc::new(hosts.into_iter().map(Into::into).collect());
+        self
+    }
+}
+
+impl<T: Clone> Clone for AuthenticateEsi<T> {
+    fn clone(&self) -> Self {
+        Self {
+            manager: self.manager.clone(),
+            character_id: self.character_id,
+            hosts: self.hosts.clone(),
+        }
+    }
+}
+
+impl<S, T: Clone> Layer<S> for AuthenticateEsi<T> {
+    type Service = AuthenticateEsiService<S, T>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AuthenticateEsiService {
+            inner,
+            manager: self.manager.clone(),
+            character_id: self.character_id,
+            hosts: self.hosts.clone(),
+        }
+    }
+}
+
+/// Service created by [`AuthenticateEsi`]
+#[derive(Clone)]
+pub struct AuthenticateEsiService<S, T> {
+    inner: S,
+    manager: TokenManager<T>,
+    character_id: u64,
+    hosts: Arc<Vec<String>>,
+}
+
+impl<S, T, ReqBody, ResBody> Service<::http::Request<ReqBody>> for AuthenticateEsiService<S, T>
+where
+    S: Service<::http::Request<ReqBody>, Response = ::http::Response<ResBody>>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send,
+    S::Error: Into<BoxError>,
+    T: TokenStore + Clone + 'static,
+    ReqBody: Send + 'static,
+    ResBody: Send,
+{
+    type Response = ::http::Response<ResBody>;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, mut req: ::http::Request<ReqBody>) -> Self::Future {
+        // Use the service which was polled ready & leave the clone for the next request
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        if !authenticates(&self.hosts, req.uri()) {
+            return Box::pin(async move { inner.call(req).await.map_err(Into::into) });
+        }
+
+        let manager = self.manager.clone();
+        let character_id = req
+            .extensions()
+            .get::<CharacterId>()
+            .map_or(self.character_id, |character_id| character_id.0);
+
+        Box::pin(async move {
+            let access_token = manager.get_valid_token(character_id).await?;
+
+            let mut authorization = HeaderValue::from_str(&format!("Bearer {}", access_token))?;
+            authorization.set_sensitive(true);
+
+            req.headers_mut().insert(AUTHORIZATION, authorization);
+
+            let response = inner.call(req).await.map_err(Into::into)?;
+
+            if response.status() == StatusCode::UNAUTHORIZED {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(character_id, "ESI rejected the access token, refreshing it");
+
+                manager
+                    .refresh_rejected_token(character_id, &access_token)
+                    .await?;
+            }
+
+            Ok(response)
+        })
+    }
+}
+
+/// Whether the request is sent over https to one of the hosts
+fn authenticates(hosts: &[String], uri: &::http::Uri) -> bool {
+    uri.scheme_str() == Some("https")
+        && uri
+            .host()
+            .is_some_and(|host| hosts.iter().any(|allowed| allowed == host))
+}
+
+fn forbidden<B: Default>(missing_scopes: &[String]) -> ::http::Response<B> {
+    let mut response = error_response(StatusCode::FORBIDDEN);
+
+    if let Ok(challenge) = HeaderValue::from_str(&http::insufficient_scope(missing_scopes)) {
+        response.headers_mut().insert(WWW_AUTHENTICATE, challenge);
+    }
+
+    response
+}
+
+fn error_response<B: Default>(status: StatusCode) -> ::http::Response<B> {
+    let mut response = ::http::Response::new(B::default());
+    *response.status_mut() = status;
+
+    if status == StatusCode::UNAUTHORIZED {
+        response
+            .headers_mut()
+            .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::future::{ready, Ready};
+
+    use super::*;
+
+    /// Service answering with the name of the character in the claims of the request
+    #[derive(Clone)]
+    struct CharacterName;
+
+    impl Service<::http::Request<()>> for CharacterName {
+        type Response = ::http::Response<String>;
+        type Error = Infallible;
+        type Future = Ready<Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, req: ::http::Request<()>) -> Self::Future {
+            let name = req
+                .extensions()
+                .get::<EveJwtClaims>()
+                .map(|claims| claims.name.clone())
+                .unwrap_or_default();
+
+            ready(Ok(::http::Response::new(name)))
+        }
+    }
+
+    fn request(authorization: Option<&str>) -> ::http::Request<()> {
+        let mut req = ::http::Request::new(());
+        if let Some(authorization) = authorization {
+            req.headers_mut()
+                .insert(AUTHORIZATION, authorization.parse().unwrap());
+        }
+
+        req
+    }
+
+    #[tokio::test]
+    async fn require_eve_auth_rejects_requests_without_a_bearer_token() {
+        let mut service =
+            RequireEveAuth::new(EveSsoClient::new("client_id", None)).layer(CharacterName);
+
+        for authorization in [None, Some("Basic abc"), Some("Bearer ")] {
+            let response = service.call(request(authorization)).await.unwrap();
+
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+            assert_eq!(response.headers()[WWW_AUTHENTICATE], "Bearer");
+            assert!(response.body().is_empty());
+        }
+    }
+
+    #[cfg(all(feature = "testing", feature = "reqwest"))]
+    #[tokio::test]
+    async fn require_eve_auth_inserts_the_claims_of_valid_tokens() {
+        let sso = crate::testing::MockSso::start().unwrap();
+        let token = sso
+            .signer()
+            .token()
+            .name("Test Character")
+            .scopes(["publicData"])
+            .sign();
+        let authorization = format!("Bearer {}", token);
+
+        let mut service = RequireEveAuth::new(sso.client("client_id")).layer(CharacterName);
+        let response = service.call(request(Some(&authorization))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body(), "Test Character");
+
+        let mut service = RequireEveAuth::new(sso.client("other_client")).layer(CharacterName);
+        let response = service.call(request(Some(&authorization))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let mut service = RequireEveAuth::new(sso.client("client_id"))
+            .validation(
+                TokenValidation::default().require_scopes(["esi-wallet.read_character_wallet.v1"]),
+            )
+            .layer(CharacterName);
+        let response = service.call(request(Some(&authorization))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[cfg(feature = "test-utils")]
+    #[tokio::test]
+    async fn require_scopes_lists_the_missing_scopes() {
+        let mut service = RequireScopes::new([
+            "esi-wallet.read_character_wallet.v1",
+            "esi-assets.read_assets.v1",
+            "publicData",
+        ])
+        .layer(CharacterName);
+        let claims = |scopes: &[&str]| {
+            let mut req = request(None);
+            req.extensions_mut().insert(
+                EveJwtClaims::test()
+                    .name("Test Character")
+                    .scopes(scopes.iter().copied())
+                    .build(),
+            );
+            req
+        };
+
+        let response = service.call(claims(&["publicData"])).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(
+            response.headers()[WWW_AUTHENTICATE],
+            r#"Bearer error="insufficient_scope", scope="esi-assets.read_assets.v1 esi-wallet.read_character_wallet.v1""#
+        );
+
+        let response = service
+            .call(claims(&[
+                "publicData",
+                "esi-assets.read_assets.v1",
+                "esi-wallet.read_character_wallet.v1",
+            ]))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body(), "Test Character");
+
+        let response = service.call(request(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn authenticates_only_https_requests_to_esi() {
+        let hosts = [ESI_HOST.to_string()];
+        let uri = |uri: &str| uri.parse::<::http::Uri>().unwrap();
+
+        assert!(authenticates(
+            &hosts,
+            &uri("https://esi.evetech.net/latest/status/")
+        ));
+        assert!(!authenticates(
+            &hosts,
+            &uri("http://esi.evetech.net/latest/status/")
+        ));
+        assert!(!authenticates(
+            &hosts,
+            &uri("https://example.com/latest/status/")
+        ));
+        assert!(!authenticates(&hosts, &uri("/latest/status/")));
+    }
+}
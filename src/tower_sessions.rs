@@ -0,0 +1,175 @@
+//! Integration with tower-sessions, enabled with the `tower-sessions` feature
+//!
+//! Keeps the state of a login in the user's session instead of a [`crate::state::StateStore`].
+//!
+//! ```no_run
+//! # async fn example(session: tower_sessions::Session, client: eve_oauth2::EveSsoClient, code: String, state: String) -> Result<(), eve_oauth2::Error> {
+//! use eve_oauth2::tower_sessions::{save_login, verify_login};
+//!
+//! // In the login route
+//! let login = client.create_login_url("http://localhost:8000/callback", ["publicData"]);
+//! save_login(&session, &login).await?;
+//!
+//! // In the callback route
+//! let pending = verify_login(&session, &state).await?;
+//! let token = match pending.code_verifier {
+//!     Some(code_verifier) => client.get_access_token_pkce(code, code_verifier).await?,
+//!     None => client.get_access_token(code).await?,
+//! };
+//! # Ok(())
+//! # }
+//! ```
+
+use std::time::Duration;
+
+use ::tower_sessions::Session;
+use serde::{Deserialize, Serialize};
+use web_time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::Error;
+use crate::models::Character;
+use crate::state::{verify_state, PendingLogin};
+use crate::AuthenticationData;
+
+const LOGIN_KEY: &str = "eve_oauth2.login";
+const CHARACTER_KEY: &str = "eve_oauth2.character";
+
+#[derive(Serialize, Deserialize)]
+struct SessionLogin {
+    state: String,
+    code_verifier: Option<String>,
+    expires_at: u64,
+}
+
+/// Stores the state & code verifier of the login in the session, it expires at the login's `expires_at`
+pub async fn save_login(session: &Session, login: &AuthenticationData) -> Result<(), Error> {
+    let login = SessionLogin {
+        state: login.state.clone(),
+        code_verifier: login.code_verifier.clone(),
+        expires_at: login.expires_at,
+    };
+
+    session
+        .insert(LOGIN_KEY, login)
+        .await
+        .map_err(session_error)
+}
+
+/// Removes the login from the session & verifies the state of the callback against it
+///
+/// Fails with [`Error::InvalidState`] if the session has no login, the login has expired or the state doesn't match.
+pub async fn verify_login(session: &Session, state: &str) -> Result<PendingLogin, Error> {
+    let login: SessionLogin = session
+        .remove(LOGIN_KEY)
+        .await
+        .map_err(session_error)?
+        .ok_or_else(|| Error::InvalidState("Session has no login".to_string()))?;
+
+    if login.expires_at <= unix_time() {
+        return Err(Error::InvalidState("State has expired".to_string()));
+    }
+
+    if !verify_state(&login.state, state) {
+        return Err(Error::InvalidState(
+            "State doesn't match the login".to_string(),
+        ));
+    }
+
+    Ok(PendingLogin::new(login.code_verifier))
+}
+
+/// Stores the logged in character in the session
+pub async fn save_character(session: &Session, character: &Character) -> Result<(), Error> {
+    session
+        .insert(CHARACTER_KEY, character)
+        .await
+        .map_err(session_error)
+}
+
+/// Returns the character stored in the session with [`save_character`], `None` if no character logged in
+pub async fn character(session: &Session) -> Result<Option<Character>, Error> {
+    session.get(CHARACTER_KEY).await.map_err(session_error)
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs()
+}
+
+fn session_error(err: ::tower_sessions::session::Error) -> Error {
+    Error::StateStore(Box::new(err))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use ::tower_sessions::MemoryStore;
+
+    use super::*;
+    use crate::models::CharacterId;
+    use crate::EveSsoClient;
+
+    fn session() -> Session {
+        Session::new(None, Arc::new(MemoryStore::default()), None)
+    }
+
+    fn login() -> AuthenticationData {
+        EveSsoClient::builder("client_id")
+            .build()
+            .create_login_url_pkce("http://localhost:8000/callback", ["publicData"])
+    }
+
+    #[tokio::test]
+    async fn verifies_the_saved_login_once() {
+        let session = session();
+        let login = login();
+        save_login(&session, &login).await.unwrap();
+
+        let pending = verify_login(&session, &login.state).await.unwrap();
+        assert_eq!(pending.code_verifier, login.code_verifier);
+
+        assert!(matches!(
+            verify_login(&session, &login.state).await,
+            Err(Error::InvalidState(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_other_states_and_expired_logins() {
+        let session = session();
+        let mut login = login();
+        save_login(&session, &login).await.unwrap();
+
+        assert!(matches!(
+            verify_login(&session, "other state").await,
+            Err(Error::InvalidState(_))
+        ));
+
+        login.expires_at = unix_time() - 1;
+        save_login(&session, &login).await.unwrap();
+        assert!(matches!(
+            verify_login(&session, &login.state).await,
+            Err(Error::InvalidState(message)) if message == "State has expired"
+        ));
+    }
+
+    #[tokio::test]
+    async fn stores_the_logged_in_character() {
+        let session = session();
+        assert_eq!(character(&session).await.unwrap(), None);
+
+        let logged_in = Character {
+            id: CharacterId(2114794365),
+            name: "Test Character".to_string(),
+            owner_hash: "owner-hash".to_string(),
+            scopes: ["publicData"].into_iter().collect(),
+            expires_at: 1_700_000_000,
+        };
+        save_character(&session, &logged_in).await.unwrap();
+
+        assert_eq!(character(&session).await.unwrap(), Some(logged_in));
+    }
+}
@@ -0,0 +1,304 @@
+//! Options for validating the claims of EVE JWTs in addition to the signature, expiry, audience & issuer
+
+use std::borrow::Cow;
+
+use crate::error::Error;
+use crate::models::{EveJwtClaims, EveJwtScopes, EveServer};
+use crate::scopes::missing_scopes;
+
+/// Additional checks applied to the claims of a token after its signature was verified
+///
+/// ```no_run
+/// # async fn example(token: String) -> Result<(), eve_oauth2::Error> {
+/// use eve_oauth2::validation::TokenValidation;
+/// use eve_oauth2::validate_token_with;
+///
+/// let validation = TokenValidation::new().require_scopes(["esi-wallet.read_character_wallet.v1"]);
+/// let token_data = validate_token_with(token, &validation).await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// When validating with an [`crate::EveSsoClient`] the `azp` claim must match the client's client_id, so tokens issued
+/// to other EVE applications are rejected. Use [`TokenValidation::allow_any_client_id`] to accept them.
+#[derive(Debug, Clone)]
+pub struct TokenValidation {
+    required_scopes: Vec<String>,
+    client_id: Option<String>,
+    check_client_id: bool,
+    tenant: Option<String>,
+    tier: Option<String>,
+    region: Option<String>,
+}
+
+impl Default for TokenValidation {
+    fn default() -> Self {
+        Self {
+            required_scopes: Vec::new(),
+            client_id: None,
+            check_client_id: true,
+            tenant: None,
+            tier: None,
+            region: None,
+        }
+    }
+}
+
+impl TokenValidation {
+    /// Creates validation options without additional checks
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requires the `azp` claim to match the client_id, overriding the client_id of the validating client
+    pub fn require_client_id(mut self, client_id: impl Into<String>) -> Self {
+        self.client_id = Some(client_id.into());
+        self.check_client_id = true;
+        self
+    }
+
+    /// Accepts tokens issued to any EVE application, e.g. for APIs which accept tokens of other applications
+    pub fn allow_any_client_id(mut self) -> Self {
+        self.client_id = None;
+        self.check_client_id = false;
+        self
+    }
+
+    /// Requires the `tenant`, `tier` & `region` claims to match the server, e.g. to only accept Tranquility tokens
+    pub fn require_server(self, server: EveServer) -> Self {
+        self.require_tenant(server.tenant())
+            .require_tier(server.tier())
+            .require_region(server.region())
+    }
+
+    /// Requires the `tenant` claim to match, e.g. `tranquility`
+    pub fn require_tenant(mut self, tenant: impl Into<String>) -> Self {
+        self.tenant = Some(tenant.into());
+        self
+    }
+
+    /// Requires the `tier` claim to match, e.g. `live`
+    pub fn require_tier(mut self, tier: impl Into<String>) -> Self {
+        self.tier = Some(tier.into());
+        self
+    }
+
+    /// Requires the `region` claim to match, e.g. `world`
+    pub fn require_region(mut self, region: impl Into<String>) -> Self {
+        self.region = Some(region.into());
+        self
+    }
+
+    /// Requires the client_id of the validating client unless another client_id is required or the check is disabled
+    pub(crate) fn with_default_client_id(&self, client_id: &str) -> Cow<'_, TokenValidation> {
+        if !self.check_client_id || self.client_id.is_some() || client_id.is_empty() {
+            return Cow::Borrowed(self);
+        }
+
+        Cow::Owned(self.clone().require_client_id(client_id))
+    }
+
+    /// Requires the token to be granted all of the scopes, otherwise validation fails with [`Error::MissingScopes`]
+    pub fn require_scopes<I, T>(mut self, scopes: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.required_scopes
+            .extend(scopes.into_iter().map(Into::into));
+        self
+    }
+
+    /// Checks the claims of a token whose signature was verified
+    pub(crate) fn check(&self, claims: &EveJwtClaims) -> Result<(), Error> {
+        if let Some(client_id) = self.client_id.as_ref().filter(|_| self.check_client_id) {
+            if &claims.azp != client_id {
+                return Err(Error::InvalidClaims(format!(
+                    "Token was issued to client {}, expected {}",
+                    claims.azp, client_id
+                )));
+            }
+        }
+
+        check_claim("tenant", &claims.tenant, &self.tenant)?;
+        check_claim("tier", &claims.tier, &self.tier)?;
+        check_claim("region", &claims.region, &self.region)?;
+
+        if !self.required_scopes.is_empty() {
+            let granted = claims
+                .scp
+                .as_ref()
+                .map(EveJwtScopes::to_vec)
+                .unwrap_or_default();
+
+            let missing = missing_scopes(&granted, &self.required_scopes);
+
+            if !missing.is_empty() {
+                return Err(Error::MissingScopes(missing));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn check_claim(name: &str, value: &str, required: &Option<String>) -> Result<(), Error> {
+    match required {
+        Some(required) if value != required => Err(Error::InvalidClaims(format!(
+            "Token {} is {}, expected {}",
+            name, value, required
+        ))),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_scopes_lists_exactly_the_missing_scopes() {
+        let claims = EveJwtClaims::test()
+            .scopes(["publicData", "esi-wallet.read_character_wallet.v1"])
+            .build();
+
+        let validation = TokenValidation::new()
+            .require_scopes(["publicData", "esi-mail.read_mail.v1"])
+            .require_scopes([
+                "esi-wallet.read_character_wallet.v1",
+                "esi-skills.read_skills.v1",
+            ]);
+        let Err(Error::MissingScopes(missing)) = validation.check(&claims) else {
+            panic!("expected missing scopes");
+        };
+        assert_eq!(
+            missing,
+            ["esi-mail.read_mail.v1", "esi-skills.read_skills.v1"]
+        );
+
+        assert!(TokenValidation::new()
+            .require_scopes(["esi-wallet.read_character_wallet.v1"])
+            .check(&claims)
+            .is_ok());
+    }
+
+    #[test]
+    fn required_scopes_accept_a_single_scope_claim_and_reject_no_scopes() {
+        let validation = TokenValidation::new().require_scopes(["publicData"]);
+
+        assert!(validation
+            .check(&EveJwtClaims::test().scopes(["publicData"]).build())
+            .is_ok());
+        assert!(matches!(
+            validation.check(&EveJwtClaims::test().build()),
+            Err(Error::MissingScopes(missing)) if missing == ["publicData"]
+        ));
+    }
+
+    #[test]
+    fn client_id_defaults_to_the_validating_client() {
+        let claims = EveJwtClaims::test().client_id("other_client").build();
+
+        let validation = TokenValidation::new()
+            .with_default_client_id("client_id")
+            .into_owned();
+        assert!(matches!(
+            validation.check(&claims),
+            Err(Error::InvalidClaims(_))
+        ));
+        assert!(validation
+            .check(&EveJwtClaims::test().client_id("client_id").build())
+            .is_ok());
+
+        let validation = TokenValidation::new()
+            .require_client_id("other_client")
+            .with_default_client_id("client_id")
+            .into_owned();
+        assert!(validation.check(&claims).is_ok());
+
+        let validation = TokenValidation::new()
+            .allow_any_client_id()
+            .with_default_client_id("client_id")
+            .into_owned();
+        assert!(validation.check(&claims).is_ok());
+
+        let validation = TokenValidation::new()
+            .allow_any_client_id()
+            .require_client_id("client_id");
+        assert!(validation.check(&claims).is_err());
+    }
+
+    #[cfg(all(feature = "testing", feature = "reqwest"))]
+    #[tokio::test]
+    async fn client_rejects_tokens_issued_to_other_clients() {
+        use crate::testing::MockSso;
+
+        let sso = MockSso::start().unwrap();
+        let client = sso.client("client_id");
+        let token = sso.signer().token().client_id("other_client").sign();
+
+        let err = client.validate_token(token.clone()).await.unwrap_err();
+        assert!(matches!(err, Error::InvalidClaims(_)), "{:?}", err);
+
+        let token_data = client
+            .validate_token_with(token, &TokenValidation::new().allow_any_client_id())
+            .await
+            .unwrap();
+        assert_eq!(token_data.claims.azp, "other_client");
+    }
+
+    #[test]
+    fn require_server_checks_tenant_tier_and_region() {
+        let validation = TokenValidation::new().require_server(EveServer::Tranquility);
+
+        assert!(validation
+            .check(&EveJwtClaims::test().server(EveServer::Tranquility).build())
+            .is_ok());
+        assert!(matches!(
+            validation.check(&EveJwtClaims::test().server(EveServer::Singularity).build()),
+            Err(Error::InvalidClaims(message)) if message.contains("singularity")
+        ));
+        assert!(matches!(
+            validation.check(&EveJwtClaims::test().server(EveServer::Serenity).build()),
+            Err(Error::InvalidClaims(_))
+        ));
+
+        assert_eq!(
+            "Tranquility".parse::<EveServer>().unwrap(),
+            EveServer::Tranquility
+        );
+        assert!(matches!(
+            "duality".parse::<EveServer>(),
+            Err(Error::Config(_))
+        ));
+    }
+
+    #[test]
+    fn tenant_tier_and_region_are_checked_independently() {
+        let mut claims = EveJwtClaims::test().build();
+        claims.tier = "test".to_string();
+
+        assert!(TokenValidation::new()
+            .require_tenant("tranquility")
+            .require_region("world")
+            .check(&claims)
+            .is_ok());
+        assert!(matches!(
+            TokenValidation::new().require_tier("live").check(&claims),
+            Err(Error::InvalidClaims(message)) if message.contains("tier")
+        ));
+
+        claims.region = "china".to_string();
+        assert!(matches!(
+            TokenValidation::new().require_region("world").check(&claims),
+            Err(Error::InvalidClaims(message)) if message.contains("region")
+        ));
+        assert!(matches!(
+            TokenValidation::new().require_tenant("serenity").check(&claims),
+            Err(Error::InvalidClaims(message)) if message.contains("tenant")
+        ));
+
+        // Claims aren't checked unless required
+        assert!(TokenValidation::new().check(&claims).is_ok());
+    }
+}
@@ -0,0 +1,192 @@
+//! Integration with the warp web framework, enabled with the `warp` feature
+//!
+//! [`eve_claims`] extracts & validates the bearer token & passes the claims to your handler. Recover from its
+//! [`EveAuthRejection`] with [`handle_rejection`] to respond with 401 Unauthorized.
+//!
+//! ```no_run
+//! use eve_oauth2::models::EveJwtClaims;
+//! use eve_oauth2::warp::{eve_claims, handle_rejection};
+//! use eve_oauth2::EveSsoClient;
+//! use warp::Filter;
+//!
+//! let client = EveSsoClient::new("client_id".to_string(), None);
+//!
+//! let routes = warp::path("character")
+//!     .and(eve_claims(client))
+//!     .map(|claims: EveJwtClaims| claims.name)
+//!     .recover(handle_rejection);
+//! ```
+
+use ::warp::http::header::WWW_AUTHENTICATE;
+use ::warp::http::StatusCode;
+use ::warp::reject::{Reject, Rejection};
+use ::warp::reply::{self, Reply};
+use ::warp::Filter;
+
+use crate::error::{Error, ErrorClass};
+use crate::http;
+use crate::models::EveJwtClaims;
+use crate::EveSsoClient;
+
+/// Filter validating the bearer token of the Authorization header with the client & extracting its claims
+///
+/// Requests without a valid token are rejected with an [`EveAuthRejection`].
+pub fn eve_claims(
+    client: EveSsoClient,
+) -> impl Filter<Extract = (EveJwtClaims,), Error = Rejection> + Clone {
+    ::warp::header::optional::<String>("authorization").and_then(
+        move |authorization: Option<String>| {
+            let client = client.clone();
+
+            async move {
+                let token = authorization
+                    .as_deref()
+                    .and_then(http::bearer_token)
+                    .ok_or_else(|| ::warp::reject::custom(EveAuthRejection::MissingToken))?;
+
+                client
+                    .validate_token(token)
+                    .await
+                    .map(|token_data| token_data.claims)
+                    .map_err(|err| ::warp::reject::custom(EveAuthRejection::InvalidToken(err)))
+            }
+        },
+    )
+}
+
+/// Rejection of requests which couldn't be authenticated
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum EveAuthRejection {
+    /// The request has no bearer token in the Authorization header
+    MissingToken,
+    /// Validating the token failed
+    InvalidToken(Error),
+}
+
+impl Reject for EveAuthRejection {}
+
+impl EveAuthRejection {
+    /// Status of the response, 503 Service Unavailable if EVE Online SSO couldn't be reached to retrieve the
+    /// EVE JWT keys & 401 Unauthorized otherwise
+    pub fn status(&self) -> StatusCode {
+        match self {
+            EveAuthRejection::InvalidToken(err) => match err.classify() {
+                ErrorClass::Transient | ErrorClass::RateLimited => StatusCode::SERVICE_UNAVAILABLE,
+                _ => StatusCode::UNAUTHORIZED,
+            },
+            EveAuthRejection::MissingToken => StatusCode::UNAUTHORIZED,
+        }
+    }
+}
+
+impl Reply for EveAuthRejection {
+    fn into_response(self) -> reply::Response {
+        response(&self)
+    }
+}
+
+/// Recovers from an [`EveAuthRejection`] with its response, other rejections are passed on
+pub async fn handle_rejection(rejection: Rejection) -> Result<reply::Response, Rejection> {
+    match rejection.find::<EveAuthRejection>() {
+        Some(auth_rejection) => Ok(response(auth_rejection)),
+        None => Err(rejection),
+    }
+}
+
+fn response(rejection: &EveAuthRejection) -> reply::Response {
+    let status = rejection.status();
+    let message = match rejection {
+        EveAuthRejection::MissingToken => "Missing bearer token",
+        EveAuthRejection::InvalidToken(_) if status == StatusCode::UNAUTHORIZED => {
+            "Invalid bearer token"
+        }
+        EveAuthRejection::InvalidToken(_) => "Failed to validate the token",
+    };
+
+    let reply = reply::with_status(message, status);
+
+    if status == StatusCode::UNAUTHORIZED {
+        reply::with_header(reply, WWW_AUTHENTICATE, "Bearer").into_response()
+    } else {
+        reply.into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn routes(
+        client: EveSsoClient,
+    ) -> impl Filter<Extract = (reply::Response,), Error = std::convert::Infallible> + Clone {
+        ::warp::path("character")
+            .and(eve_claims(client))
+            .map(|claims: EveJwtClaims| claims.name.into_response())
+            .recover(handle_rejection)
+            .unify()
+            .recover(|_| async {
+                Ok::<_, std::convert::Infallible>(StatusCode::NOT_FOUND.into_response())
+            })
+            .unify()
+    }
+
+    #[test]
+    fn rejections_respond_with_401_or_503() {
+        assert_eq!(
+            EveAuthRejection::MissingToken.status(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            EveAuthRejection::InvalidToken(Error::MissingJwtKey).status(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            EveAuthRejection::InvalidToken(Error::sso("server_error".to_string(), None)).status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_requests_without_a_bearer_token() {
+        let routes = routes(EveSsoClient::new("client_id", None));
+
+        for authorization in [None, Some("Basic abc")] {
+            let mut request = ::warp::test::request().path("/character");
+            if let Some(authorization) = authorization {
+                request = request.header("authorization", authorization);
+            }
+
+            let response = request.reply(&routes).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+            assert_eq!(response.headers()[WWW_AUTHENTICATE], "Bearer");
+            assert_eq!(response.body(), "Missing bearer token");
+        }
+
+        let response = ::warp::test::request().path("/other").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[cfg(all(feature = "testing", feature = "reqwest"))]
+    #[tokio::test]
+    async fn extracts_the_claims_of_valid_tokens() {
+        let sso = crate::testing::MockSso::start().unwrap();
+        let token = sso.signer().token().name("Test Character").sign();
+
+        let response = ::warp::test::request()
+            .path("/character")
+            .header("authorization", format!("Bearer {}", token))
+            .reply(&routes(sso.client("client_id")))
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body(), "Test Character");
+
+        let response = ::warp::test::request()
+            .path("/character")
+            .header("authorization", format!("Bearer {}", token))
+            .reply(&routes(sso.client("other_client")))
+            .await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.body(), "Invalid bearer token");
+    }
+}